@@ -23,6 +23,31 @@ fn main() {
         (97, 9),
         (106, 9),
         (115, 9),
+        (124, 10),
+        (134, 1),
+        (135, 1),
+        (136, 1),
+        (137, 1),
+        (138, 1),
+        (139, 9),
+        (148, 9),
+        (157, 8),
+        (165, 1),
+        (166, 9),
+        (175, 1),
+        (176, 1),
+        (177, 1),
+        (178, 8),
+        (186, 9),
+        (195, 8),
+        (203, 2),
+        (205, 9),
+        (214, 9),
+        (223, 1),
+        (224, 9),
+        (233, 1),
+        (234, 1),
+        (235, 1),
     ];
 
     let mut code = String::new();
@@ -2,6 +2,15 @@
 
 use crate::{concat::*, version_info};
 
+/// A recommended virtual address for mapping the [`BootInfo`](crate::BootInfo) at a fixed
+/// location.
+///
+/// The address is the start of level 4 page table entry 510, i.e. directly below the last
+/// 512 GiB of the address space, which are often used by higher-half kernels. Use
+/// [`BootloaderConfig::new_with_fixed_boot_info`] to create a config that maps the boot info
+/// at this address.
+pub const RECOMMENDED_BOOT_INFO_ADDR: u64 = 0xffff_ff00_0000_0000;
+
 /// Allows configuring the bootloader behavior.
 ///
 /// TODO: describe use together with `entry_point` macro
@@ -35,29 +44,243 @@ pub struct BootloaderConfig {
         note = "The frame buffer is now configured through the `BootConfig` struct when creating the bootable disk image"
     )]
     pub frame_buffer: FrameBuffer,
+
+    /// Whether the bootloader should mask all interrupt lines of the legacy 8259 PIC before
+    /// jumping to the kernel.
+    ///
+    /// The firmware might leave some PIC lines unmasked, with interrupt vectors that collide
+    /// with CPU exceptions. Masking them prevents spurious interrupts at wrong vectors if the
+    /// kernel enables interrupts before remapping the PIC.
+    ///
+    /// Defaults to `true`.
+    pub mask_pic: bool,
+
+    /// Whether the bootloader should read the current date and time and report it in
+    /// [`BootInfo::boot_time`](crate::BootInfo::boot_time).
+    ///
+    /// The time is queried from the UEFI runtime services or read from the CMOS real-time
+    /// clock on BIOS systems.
+    ///
+    /// Defaults to `false`.
+    pub boot_time: bool,
+
+    /// Whether the bootloader should load the kernel's segments at the physical addresses
+    /// given in their ELF program headers (`p_paddr`).
+    ///
+    /// By default, the bootloader places the segments in arbitrary free frames. Kernels that
+    /// are linked to run from a fixed physical address can set this option to make the
+    /// bootloader reserve the requested frames and map each segment to them. The bootloader
+    /// panics if the requested physical memory is not usable or already in use. The memory
+    /// must lie above 1MiB and, on BIOS systems, behind the bootloader stages that are loaded
+    /// there, so a load address of 2MiB or higher is recommended.
+    ///
+    /// Defaults to `false`.
+    pub load_at_physical_addresses: bool,
+
+    /// Whether the bootloader should enable 5-level paging (LA57) before jumping to the kernel.
+    ///
+    /// The bootloader still sets up the kernel's address space using a level 4 page table.
+    /// This table is referenced by entries 0 and 511 of a new level 5 table, so that all
+    /// mappings keep their 48-bit virtual addresses. The kernel can use the remaining level 5
+    /// entries to extend its address space. The bootloader panics if the CPU doesn't support
+    /// 5-level paging.
+    ///
+    /// Defaults to `false`.
+    pub five_level_paging: bool,
+
+    /// Whether the bootloader should reserve a zeroed frame below 1 MiB for the real-mode
+    /// trampoline code that is needed to start additional CPUs (APs).
+    ///
+    /// The startup IPI can only start execution at a 4 KiB-aligned address below 1 MiB, which
+    /// is difficult to allocate before the kernel's frame allocator is ready. The reserved
+    /// frame is marked as [`Bootloader`](crate::info::MemoryRegionKind::Bootloader) memory in
+    /// the memory map and its physical address is reported in
+    /// [`BootInfo::ap_trampoline_frame`](crate::BootInfo::ap_trampoline_frame). The frame is
+    /// not mapped into the kernel address space.
+    ///
+    /// Defaults to `false`.
+    pub ap_trampoline_frame: bool,
+
+    /// Overrides the entry point of the kernel with the given virtual address.
+    ///
+    /// By default, the bootloader jumps to the entry point given in the ELF header. The
+    /// override is given as link-time address, i.e. the bootloader applies the same
+    /// relocation offset as for the ELF entry point if the kernel is position independent.
+    /// The address must lie in an executable segment of the kernel, otherwise the bootloader
+    /// panics.
+    ///
+    /// Defaults to `None`, i.e. the ELF entry point is used.
+    pub entry_point: Option<u64>,
+
+    /// Whether the bootloader should map a canary page below the guard page of the kernel
+    /// stack.
+    ///
+    /// The canary page is filled with a known pattern. A stack overflow that skips the
+    /// guard page, e.g. because of a large stack frame, overwrites the pattern instead of
+    /// unrelated memory, so debugging tools of the kernel can detect the overflow by checking
+    /// the pattern. The address of the canary page and the pattern are reported in
+    /// [`BootInfo::kernel_stack_canary_addr`](crate::BootInfo::kernel_stack_canary_addr) and
+    /// [`BootInfo::kernel_stack_canary_pattern`](crate::BootInfo::kernel_stack_canary_pattern).
+    ///
+    /// If the kernel stack is mapped at a fixed address, the canary page is placed at that
+    /// address, followed by the guard page and the stack.
+    ///
+    /// Defaults to `false`.
+    pub kernel_stack_canary: bool,
+
+    /// Whether the bootloader should allocate and map zeroed frames for the zero-initialized
+    /// parts of the kernel's load segments, e.g. the `.bss` section.
+    ///
+    /// If set to `false`, the pages of these parts that aren't backed by the kernel file are
+    /// left unmapped, which saves memory during boot if the kernel has large `.bss` sections.
+    /// The kernel is then responsible for mapping them before their first use, e.g. in its
+    /// page fault handler, so it must not access them before setting this up. A page that
+    /// contains both initialized and zero-initialized data is always mapped. If
+    /// [`load_at_physical_addresses`](Self::load_at_physical_addresses) is enabled, the
+    /// frames of the unmapped pages are still reserved and zeroed, so the kernel can map them
+    /// at the physical addresses of its segments.
+    ///
+    /// The number of frames that were allocated for zero-initialized memory is reported in
+    /// [`BootInfo::kernel_bss_frames`](crate::BootInfo::kernel_bss_frames).
+    ///
+    /// Defaults to `true`.
+    pub preallocate_bss: bool,
+
+    /// Additional page table flags that are set in the mappings of the kernel image, the
+    /// kernel stack (including the optional canary page), and the framebuffer.
+    ///
+    /// This allows kernels that store their own metadata in the bits of page table entries
+    /// that are ignored by the CPU to start with specific values in these bits. Only the
+    /// bits in [`AVAILABLE_PAGE_FLAGS`](Self::AVAILABLE_PAGE_FLAGS), i.e. bits 9 to 11 and
+    /// 52 to 62, may be set, otherwise the bootloader rejects the config. Note that bits 59
+    /// to 62 are interpreted as protection key if the kernel enables protection keys.
+    ///
+    /// Defaults to `0`.
+    pub extra_page_flags: u64,
+
+    /// The minimum amount of usable memory that the kernel requires, in bytes.
+    ///
+    /// After creating the memory map, the bootloader sums up the sizes of all usable memory
+    /// regions, including the regions that the bootloader itself used for the kernel and
+    /// its page tables. If the total is smaller than this value, the bootloader halts with an
+    /// "insufficient memory" error instead of starting the kernel.
+    ///
+    /// Defaults to `None`, i.e. no check is performed.
+    pub minimum_memory_bytes: Option<u64>,
+
+    /// Additional `CR4` flags that the bootloader sets right before jumping to the kernel.
+    ///
+    /// This allows kernels to start with security features such as SMEP and SMAP already
+    /// enabled. Only the bits in [`SUPPORTED_CR4_FLAGS`](Self::SUPPORTED_CR4_FLAGS) may be
+    /// set, otherwise the bootloader rejects the config. Flags that the CPU doesn't support
+    /// according to CPUID are skipped with a warning.
+    ///
+    /// The flags are set after switching to the kernel's page tables, so they don't affect
+    /// the bootloader itself. The flags that were actually set are reported in
+    /// [`BootInfo::enabled_cr4_flags`](crate::BootInfo::enabled_cr4_flags).
+    ///
+    /// Defaults to [`DEFAULT_CR4_FLAGS`](Self::DEFAULT_CR4_FLAGS), i.e. SMEP and SMAP are
+    /// enabled if the CPU supports them. Note that kernels must then use the `stac` and `clac`
+    /// instructions around accesses to user memory. To opt out, clear the corresponding bits.
+    pub cr4_flags: u64,
+
+    /// The initial value of the `FS` base register of the kernel.
+    ///
+    /// If set, the bootloader writes the value to the `IA32_FS_BASE` MSR right before jumping
+    /// to the kernel. The value must be a canonical 48-bit address, otherwise the bootloader
+    /// rejects the config. It is reported in [`BootInfo::fs_base`](crate::BootInfo::fs_base).
+    ///
+    /// Defaults to `None`, i.e. the register is left as set up by the firmware.
+    pub fs_base: Option<u64>,
+
+    /// The initial value of the `GS` base register of the kernel.
+    ///
+    /// If set, the bootloader writes the value to the `IA32_GS_BASE` MSR right before jumping
+    /// to the kernel, e.g. to point it to per-CPU data of the bootstrap processor. Note that
+    /// the `IA32_KERNEL_GS_BASE` MSR used by `swapgs` is not modified. The value must be a
+    /// canonical 48-bit address, otherwise the bootloader rejects the config. It is reported
+    /// in [`BootInfo::gs_base`](crate::BootInfo::gs_base).
+    ///
+    /// Defaults to `None`, i.e. the register is left as set up by the firmware.
+    pub gs_base: Option<u64>,
 }
 
 impl BootloaderConfig {
+    /// Returns the version of the `bootloader_api` crate that this config was created with.
+    pub fn api_version(&self) -> ApiVersion {
+        self.version
+    }
+
     pub(crate) const UUID: [u8; 16] = [
         0x74, 0x3C, 0xA9, 0x61, 0x09, 0x36, 0x46, 0xA0, 0xBB, 0x55, 0x5C, 0x15, 0x89, 0x15, 0x25,
         0x3D,
     ];
     #[doc(hidden)]
-    pub const SERIALIZED_LEN: usize = 124;
+    pub const SERIALIZED_LEN: usize = 236;
+
+    /// The page table entry bits that are ignored by the CPU and can be set through
+    /// [`extra_page_flags`](Self::extra_page_flags): bits 9 to 11 and 52 to 62.
+    pub const AVAILABLE_PAGE_FLAGS: u64 = 0x7ff0_0000_0000_0e00;
+
+    /// The `CR4` flags that can be set through [`cr4_flags`](Self::cr4_flags): `UMIP`
+    /// (bit 11), `FSGSBASE` (bit 16), `SMEP` (bit 20), and `SMAP` (bit 21).
+    pub const SUPPORTED_CR4_FLAGS: u64 = (1 << 11) | (1 << 16) | (1 << 20) | (1 << 21);
+
+    /// The default value of [`cr4_flags`](Self::cr4_flags): `SMEP` (bit 20) and `SMAP`
+    /// (bit 21).
+    pub const DEFAULT_CR4_FLAGS: u64 = (1 << 20) | (1 << 21);
 
     /// Creates a new default configuration with the following values:
     ///
     /// - `kernel_stack_size`: 80kiB
     /// - `mappings`: See [`Mappings::new_default()`]
+    /// - `mask_pic`: `true`
+    /// - `boot_time`: `false`
+    /// - `load_at_physical_addresses`: `false`
+    /// - `five_level_paging`: `false`
+    /// - `ap_trampoline_frame`: `false`
+    /// - `entry_point`: `None`
+    /// - `kernel_stack_canary`: `false`
+    /// - `preallocate_bss`: `true`
+    /// - `extra_page_flags`: `0`
+    /// - `minimum_memory_bytes`: `None`
+    /// - `cr4_flags`: [`DEFAULT_CR4_FLAGS`](Self::DEFAULT_CR4_FLAGS)
+    /// - `fs_base`: `None`
+    /// - `gs_base`: `None`
     pub const fn new_default() -> Self {
         Self {
             kernel_stack_size: 80 * 1024,
             version: ApiVersion::new_default(),
             mappings: Mappings::new_default(),
             frame_buffer: FrameBuffer::new_default(),
+            mask_pic: true,
+            boot_time: false,
+            load_at_physical_addresses: false,
+            five_level_paging: false,
+            ap_trampoline_frame: false,
+            entry_point: Option::None,
+            kernel_stack_canary: false,
+            preallocate_bss: true,
+            extra_page_flags: 0,
+            minimum_memory_bytes: Option::None,
+            cr4_flags: Self::DEFAULT_CR4_FLAGS,
+            fs_base: Option::None,
+            gs_base: Option::None,
         }
     }
 
+    /// Creates a new default configuration that maps the [`BootInfo`](crate::BootInfo) at
+    /// [`RECOMMENDED_BOOT_INFO_ADDR`].
+    ///
+    /// This allows kernels to access the boot info at an address that is known at compile
+    /// time. The bootloader panics if the address collides with another mapping, e.g. with a
+    /// segment of the kernel executable.
+    pub const fn new_with_fixed_boot_info() -> Self {
+        let mut config = Self::new_default();
+        config.mappings.boot_info = Mapping::FixedAddress(RECOMMENDED_BOOT_INFO_ADDR);
+        config
+    }
+
     /// Serializes the configuration to a byte array.
     ///
     /// This is used by the [`crate::entry_point`] macro to store the configuration in a
@@ -68,6 +291,19 @@ impl BootloaderConfig {
             mappings,
             kernel_stack_size,
             frame_buffer,
+            mask_pic,
+            boot_time,
+            load_at_physical_addresses,
+            five_level_paging,
+            ap_trampoline_frame,
+            entry_point,
+            kernel_stack_canary,
+            preallocate_bss,
+            extra_page_flags,
+            minimum_memory_bytes,
+            cr4_flags,
+            fs_base,
+            gs_base,
         } = self;
         let ApiVersion {
             version_major,
@@ -85,6 +321,17 @@ impl BootloaderConfig {
             dynamic_range_start,
             dynamic_range_end,
             ramdisk_memory,
+            framebuffer_scratch,
+            physical_memory_cache_mode,
+            physical_memory_max_len,
+            identity_mapped_range,
+            boot_info_read_only,
+            physical_memory_mmio_cache_mode,
+            framebuffer_in_physical_memory,
+            ramdisk_eager_pages,
+            kernel_stack_placement,
+            boot_info_placement,
+            physical_memory_placement,
         } = mappings;
         let FrameBuffer {
             minimum_framebuffer_height,
@@ -143,13 +390,141 @@ impl BootloaderConfig {
             },
         );
 
-        concat_115_9(
+        let buf = concat_115_9(
             buf,
             match minimum_framebuffer_width {
                 Option::None => [0; 9],
                 Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
             },
-        )
+        );
+
+        let buf = concat_124_10(
+            buf,
+            match framebuffer_scratch {
+                Option::None => [0; 10],
+                Option::Some(m) => concat_1_9([1], m.serialize()),
+            },
+        );
+
+        let buf = concat_134_1(buf, [physical_memory_cache_mode.serialize()]);
+
+        let buf = concat_135_1(buf, [(*mask_pic) as u8]);
+
+        let buf = concat_136_1(buf, [(*boot_time) as u8]);
+
+        let buf = concat_137_1(buf, [(*load_at_physical_addresses) as u8]);
+
+        let buf = concat_138_1(buf, [(*five_level_paging) as u8]);
+
+        let buf = concat_139_9(
+            buf,
+            match physical_memory_max_len {
+                Option::None => [0; 9],
+                Option::Some(len) => concat_1_8([1], len.to_le_bytes()),
+            },
+        );
+
+        let (identity_mapped_start, identity_mapped_len) = match identity_mapped_range {
+            Option::None => ([0; 9], [0; 8]),
+            Option::Some((start, len)) => (concat_1_8([1], start.to_le_bytes()), len.to_le_bytes()),
+        };
+        let buf = concat_148_9(buf, identity_mapped_start);
+        let buf = concat_157_8(buf, identity_mapped_len);
+
+        let buf = concat_165_1(buf, [(*ap_trampoline_frame) as u8]);
+
+        let buf = concat_166_9(
+            buf,
+            match entry_point {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_175_1(buf, [(*boot_info_read_only) as u8]);
+
+        let buf = concat_176_1(buf, [(*kernel_stack_canary) as u8]);
+
+        let buf = concat_177_1(buf, [(*preallocate_bss) as u8]);
+
+        let buf = concat_178_8(buf, extra_page_flags.to_le_bytes());
+
+        let buf = concat_186_9(
+            buf,
+            match minimum_memory_bytes {
+                Option::None => [0; 9],
+                Option::Some(len) => concat_1_8([1], len.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_195_8(buf, cr4_flags.to_le_bytes());
+
+        let buf = concat_203_2(
+            buf,
+            match physical_memory_mmio_cache_mode {
+                Option::None => [0; 2],
+                Option::Some(mode) => [1, mode.serialize()],
+            },
+        );
+
+        let buf = concat_205_9(
+            buf,
+            match fs_base {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_214_9(
+            buf,
+            match gs_base {
+                Option::None => [0; 9],
+                Option::Some(addr) => concat_1_8([1], addr.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_223_1(buf, [(*framebuffer_in_physical_memory) as u8]);
+
+        let buf = concat_224_9(
+            buf,
+            match ramdisk_eager_pages {
+                Option::None => [0; 9],
+                Option::Some(pages) => concat_1_8([1], pages.to_le_bytes()),
+            },
+        );
+
+        let buf = concat_233_1(buf, [kernel_stack_placement.serialize()]);
+
+        let buf = concat_234_1(buf, [boot_info_placement.serialize()]);
+
+        concat_235_1(buf, [physical_memory_placement.serialize()])
+    }
+
+    /// Reads the API version from a config byte array that was created using
+    /// [`Self::serialize`].
+    ///
+    /// In contrast to [`Self::deserialize`], this also works for configs that were serialized
+    /// by a different version of this crate, since all versions start with the same UUID
+    /// followed by the API version. This allows tools to report version mismatches.
+    #[doc(hidden)]
+    pub fn deserialize_api_version(serialized: &[u8]) -> Option<ApiVersion> {
+        if serialized.len() < Self::UUID.len() + 7 {
+            return Option::None;
+        }
+        let (uuid, s) = split_array_ref(serialized);
+        if uuid != &Self::UUID {
+            return Option::None;
+        }
+        let (&major, s) = split_array_ref(s);
+        let (&minor, s) = split_array_ref(s);
+        let (&patch, s) = split_array_ref(s);
+        let (&[pre], _) = split_array_ref(s);
+        Option::Some(ApiVersion {
+            version_major: u16::from_le_bytes(major),
+            version_minor: u16::from_le_bytes(minor),
+            version_patch: u16::from_le_bytes(patch),
+            pre_release: pre != 0,
+        })
     }
 
     /// Tries to deserialize a config byte array that was created using [`Self::serialize`].
@@ -194,7 +569,7 @@ impl BootloaderConfig {
 
         let (&kernel_stack_size, s) = split_array_ref(s);
 
-        let (mappings, s) = {
+        let (mut mappings, s) = {
             let (&kernel_stack, s) = split_array_ref(s);
             let (&boot_info, s) = split_array_ref(s);
             let (&framebuffer, s) = split_array_ref(s);
@@ -239,6 +614,22 @@ impl BootloaderConfig {
                     _ => return Err("invalid dynamic range end value"),
                 },
                 ramdisk_memory: Mapping::deserialize(&ramdisk_memory)?,
+                // stored after the frame buffer config, deserialized below
+                framebuffer_scratch: Option::None,
+                physical_memory_cache_mode: CacheMode::Default,
+                physical_memory_max_len: Option::None,
+                identity_mapped_range: Option::None,
+                boot_info_read_only: false,
+                // stored after the CR4 flags, deserialized below
+                physical_memory_mmio_cache_mode: Option::None,
+                // stored after the FS and GS base, deserialized below
+                framebuffer_in_physical_memory: false,
+                // stored after the framebuffer flag, deserialized below
+                ramdisk_eager_pages: Option::None,
+                // stored at the end, deserialized below
+                kernel_stack_placement: DynamicPlacement::Anywhere,
+                boot_info_placement: DynamicPlacement::Anywhere,
+                physical_memory_placement: DynamicPlacement::Anywhere,
             };
             (mappings, s)
         };
@@ -264,6 +655,241 @@ impl BootloaderConfig {
             (frame_buffer, s)
         };
 
+        let s = {
+            let (&framebuffer_scratch_some, s) = split_array_ref(s);
+            let (&framebuffer_scratch, s) = split_array_ref(s);
+            mappings.framebuffer_scratch = match framebuffer_scratch_some {
+                [0] if framebuffer_scratch == [0; 9] => Option::None,
+                [1] => Option::Some(Mapping::deserialize(&framebuffer_scratch)?),
+                _ => return Err("invalid framebuffer scratch value"),
+            };
+            s
+        };
+
+        let s = {
+            let (&[physical_memory_cache_mode], s) = split_array_ref(s);
+            mappings.physical_memory_cache_mode =
+                CacheMode::deserialize(physical_memory_cache_mode)?;
+            s
+        };
+
+        let (mask_pic, s) = {
+            let (&[mask_pic], s) = split_array_ref(s);
+            let mask_pic = match mask_pic {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid mask_pic value"),
+            };
+            (mask_pic, s)
+        };
+
+        let (boot_time, s) = {
+            let (&[boot_time], s) = split_array_ref(s);
+            let boot_time = match boot_time {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid boot_time value"),
+            };
+            (boot_time, s)
+        };
+
+        let (load_at_physical_addresses, s) = {
+            let (&[load_at_physical_addresses], s) = split_array_ref(s);
+            let load_at_physical_addresses = match load_at_physical_addresses {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid load_at_physical_addresses value"),
+            };
+            (load_at_physical_addresses, s)
+        };
+
+        let (five_level_paging, s) = {
+            let (&[five_level_paging], s) = split_array_ref(s);
+            let five_level_paging = match five_level_paging {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid five_level_paging value"),
+            };
+            (five_level_paging, s)
+        };
+
+        let s = {
+            let (&physical_memory_max_len_some, s) = split_array_ref(s);
+            let (&physical_memory_max_len, s) = split_array_ref(s);
+            mappings.physical_memory_max_len = match physical_memory_max_len_some {
+                [0] if physical_memory_max_len == [0; 8] => Option::None,
+                [1] => Option::Some(u64::from_le_bytes(physical_memory_max_len)),
+                _ => return Err("invalid physical_memory_max_len value"),
+            };
+            s
+        };
+
+        let s = {
+            let (&identity_mapped_range_some, s) = split_array_ref(s);
+            let (&identity_mapped_start, s) = split_array_ref(s);
+            let (&identity_mapped_len, s) = split_array_ref(s);
+            mappings.identity_mapped_range = match identity_mapped_range_some {
+                [0] if identity_mapped_start == [0; 8] && identity_mapped_len == [0; 8] => {
+                    Option::None
+                }
+                [1] => Option::Some((
+                    u64::from_le_bytes(identity_mapped_start),
+                    u64::from_le_bytes(identity_mapped_len),
+                )),
+                _ => return Err("invalid identity_mapped_range value"),
+            };
+            s
+        };
+
+        let (ap_trampoline_frame, s) = {
+            let (&[ap_trampoline_frame], s) = split_array_ref(s);
+            let ap_trampoline_frame = match ap_trampoline_frame {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid ap_trampoline_frame value"),
+            };
+            (ap_trampoline_frame, s)
+        };
+
+        let (entry_point, s) = {
+            let (&entry_point_some, s) = split_array_ref(s);
+            let (&entry_point, s) = split_array_ref(s);
+            let entry_point = match entry_point_some {
+                [0] if entry_point == [0; 8] => Option::None,
+                [1] => Option::Some(u64::from_le_bytes(entry_point)),
+                _ => return Err("invalid entry_point value"),
+            };
+            (entry_point, s)
+        };
+
+        let s = {
+            let (&[boot_info_read_only], s) = split_array_ref(s);
+            mappings.boot_info_read_only = match boot_info_read_only {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid boot_info_read_only value"),
+            };
+            s
+        };
+
+        let (kernel_stack_canary, s) = {
+            let (&[kernel_stack_canary], s) = split_array_ref(s);
+            let kernel_stack_canary = match kernel_stack_canary {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid kernel_stack_canary value"),
+            };
+            (kernel_stack_canary, s)
+        };
+
+        let (preallocate_bss, s) = {
+            let (&[preallocate_bss], s) = split_array_ref(s);
+            let preallocate_bss = match preallocate_bss {
+                1 => true,
+                0 => false,
+                _ => return Err("invalid preallocate_bss value"),
+            };
+            (preallocate_bss, s)
+        };
+
+        let (extra_page_flags, s) = {
+            let (&extra_page_flags, s) = split_array_ref(s);
+            let extra_page_flags = u64::from_le_bytes(extra_page_flags);
+            if extra_page_flags & !Self::AVAILABLE_PAGE_FLAGS != 0 {
+                return Err("invalid extra_page_flags value");
+            }
+            (extra_page_flags, s)
+        };
+
+        let (minimum_memory_bytes, s) = {
+            let (&minimum_memory_bytes_some, s) = split_array_ref(s);
+            let (&minimum_memory_bytes, s) = split_array_ref(s);
+            let minimum_memory_bytes = match minimum_memory_bytes_some {
+                [0] if minimum_memory_bytes == [0; 8] => Option::None,
+                [1] => Option::Some(u64::from_le_bytes(minimum_memory_bytes)),
+                _ => return Err("invalid minimum_memory_bytes value"),
+            };
+            (minimum_memory_bytes, s)
+        };
+
+        let (cr4_flags, s) = {
+            let (&cr4_flags, s) = split_array_ref(s);
+            let cr4_flags = u64::from_le_bytes(cr4_flags);
+            if cr4_flags & !Self::SUPPORTED_CR4_FLAGS != 0 {
+                return Err("invalid cr4_flags value");
+            }
+            (cr4_flags, s)
+        };
+
+        let s = {
+            let (&[mmio_cache_mode_some, mmio_cache_mode], s) = split_array_ref(s);
+            mappings.physical_memory_mmio_cache_mode = match mmio_cache_mode_some {
+                0 if mmio_cache_mode == 0 => Option::None,
+                1 => Option::Some(CacheMode::deserialize(mmio_cache_mode)?),
+                _ => return Err("invalid physical_memory_mmio_cache_mode value"),
+            };
+            s
+        };
+
+        let (fs_base, s) = {
+            let (&fs_base_some, s) = split_array_ref(s);
+            let (&fs_base, s) = split_array_ref(s);
+            let fs_base = match fs_base_some {
+                [0] if fs_base == [0; 8] => Option::None,
+                [1] if is_canonical(u64::from_le_bytes(fs_base)) => {
+                    Option::Some(u64::from_le_bytes(fs_base))
+                }
+                _ => return Err("invalid fs_base value"),
+            };
+            (fs_base, s)
+        };
+
+        let (gs_base, s) = {
+            let (&gs_base_some, s) = split_array_ref(s);
+            let (&gs_base, s) = split_array_ref(s);
+            let gs_base = match gs_base_some {
+                [0] if gs_base == [0; 8] => Option::None,
+                [1] if is_canonical(u64::from_le_bytes(gs_base)) => {
+                    Option::Some(u64::from_le_bytes(gs_base))
+                }
+                _ => return Err("invalid gs_base value"),
+            };
+            (gs_base, s)
+        };
+
+        let s = {
+            let (&[framebuffer_in_physical_memory], s) = split_array_ref(s);
+            mappings.framebuffer_in_physical_memory = match framebuffer_in_physical_memory {
+                0 => false,
+                1 => true,
+                _ => return Err("invalid framebuffer_in_physical_memory value"),
+            };
+            s
+        };
+
+        let s = {
+            let (&ramdisk_eager_pages_some, s) = split_array_ref(s);
+            let (&ramdisk_eager_pages, s) = split_array_ref(s);
+            mappings.ramdisk_eager_pages = match ramdisk_eager_pages_some {
+                [0] if ramdisk_eager_pages == [0; 8] => Option::None,
+                [1] => Option::Some(u64::from_le_bytes(ramdisk_eager_pages)),
+                _ => return Err("invalid ramdisk_eager_pages value"),
+            };
+            s
+        };
+
+        let s = {
+            let (&[kernel_stack_placement], s) = split_array_ref(s);
+            let (&[boot_info_placement], s) = split_array_ref(s);
+            let (&[physical_memory_placement], s) = split_array_ref(s);
+            mappings.kernel_stack_placement =
+                DynamicPlacement::deserialize(kernel_stack_placement)?;
+            mappings.boot_info_placement = DynamicPlacement::deserialize(boot_info_placement)?;
+            mappings.physical_memory_placement =
+                DynamicPlacement::deserialize(physical_memory_placement)?;
+            s
+        };
+
         if !s.is_empty() {
             return Err("unexpected rest");
         }
@@ -273,6 +899,19 @@ impl BootloaderConfig {
             kernel_stack_size: u64::from_le_bytes(kernel_stack_size),
             mappings,
             frame_buffer,
+            mask_pic,
+            boot_time,
+            load_at_physical_addresses,
+            five_level_paging,
+            ap_trampoline_frame,
+            entry_point,
+            kernel_stack_canary,
+            preallocate_bss,
+            extra_page_flags,
+            minimum_memory_bytes,
+            cr4_flags,
+            fs_base,
+            gs_base,
         })
     }
 
@@ -283,6 +922,35 @@ impl BootloaderConfig {
             mappings: Mappings::random(),
             kernel_stack_size: rand::random(),
             frame_buffer: FrameBuffer::random(),
+            mask_pic: rand::random(),
+            boot_time: rand::random(),
+            load_at_physical_addresses: rand::random(),
+            five_level_paging: rand::random(),
+            ap_trampoline_frame: rand::random(),
+            entry_point: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            kernel_stack_canary: rand::random(),
+            preallocate_bss: rand::random(),
+            extra_page_flags: rand::random::<u64>() & Self::AVAILABLE_PAGE_FLAGS,
+            minimum_memory_bytes: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            cr4_flags: rand::random::<u64>() & Self::SUPPORTED_CR4_FLAGS,
+            fs_base: if rand::random() {
+                Option::Some(rand::random::<u64>() & 0x7fff_ffff_ffff)
+            } else {
+                Option::None
+            },
+            gs_base: if rand::random() {
+                Option::Some(rand::random::<u64>() | 0xffff_8000_0000_0000)
+            } else {
+                Option::None
+            },
         }
     }
 }
@@ -399,11 +1067,133 @@ pub struct Mappings {
     pub dynamic_range_start: Option<u64>,
     /// The highest virtual address for dynamic addresses.
     ///
+    /// Dynamic mappings are only placed in level 4 page table entries (i.e. 512 GiB blocks)
+    /// that lie completely inside the range given by [`Self::dynamic_range_start`] and this
+    /// field. The bootloader panics if a dynamic mapping doesn't fit into this range.
+    ///
     /// Defaults to `0xffff_ffff_ffff_f000`.
     pub dynamic_range_end: Option<u64>,
     /// Virtual address to map ramdisk image, if present on disk
     /// Defaults to dynamic
     pub ramdisk_memory: Mapping,
+    /// Allocates an off-screen scratch buffer with the same byte length as the frame buffer
+    /// and maps it at the given address. This is useful for double-buffered graphics.
+    ///
+    /// The backing frames are taken from the frame allocator, so they are reported as
+    /// [`Bootloader`](crate::info::MemoryRegionKind::Bootloader) memory in the memory map.
+    /// The virtual start address is passed to the kernel in
+    /// [`BootInfo::framebuffer_scratch`](crate::BootInfo::framebuffer_scratch).
+    ///
+    /// Defaults to `None`, i.e. no scratch buffer is allocated.
+    pub framebuffer_scratch: Option<Mapping>,
+    /// The caching attributes that should be applied to the pages of the
+    /// [physical memory mapping](Self::physical_memory).
+    ///
+    /// If a value other than [`CacheMode::Default`] is set, the bootloader programs the
+    /// `IA32_PAT` register with its power-on default value before mapping the physical
+    /// memory, so that the page table flags select the requested memory type. Kernels that
+    /// start additional CPUs need to program the PAT of these CPUs the same way.
+    ///
+    /// Defaults to [`CacheMode::Default`].
+    pub physical_memory_cache_mode: CacheMode,
+    /// Limits the [physical memory mapping](Self::physical_memory) to the given number of
+    /// bytes, starting at physical address `0`.
+    ///
+    /// The bootloader maps `min(max_phys_addr, physical_memory_max_len)` bytes, rounded up to
+    /// the next 2 MiB boundary. This is useful for kernels that only need a small window of
+    /// physical memory, e.g. for device access, as it reduces the page table overhead and
+    /// boot time. The actually mapped length is reported in
+    /// [`BootInfo::physical_memory_len`](crate::BootInfo::physical_memory_len). The value
+    /// must not be zero.
+    ///
+    /// Defaults to `None`, i.e. the complete physical memory is mapped.
+    pub physical_memory_max_len: Option<u64>,
+    /// A physical memory range that should be identity-mapped into the kernel address space,
+    /// given as `(start, len)`.
+    ///
+    /// This is useful for code that needs to run at a known address with paging enabled, e.g.
+    /// trampoline code for starting additional CPUs. The range is extended to page
+    /// boundaries and mapped as writable and executable. The bootloader panics if the range
+    /// collides with another mapping of the kernel address space. The mapped range is
+    /// reported in [`BootInfo::identity_map_addr`](crate::BootInfo::identity_map_addr) and
+    /// [`BootInfo::identity_map_len`](crate::BootInfo::identity_map_len).
+    ///
+    /// Defaults to `None`, i.e. no identity mapping is created.
+    pub identity_mapped_range: Option<(u64, u64)>,
+    /// Maps the [`crate::BootInfo`] struct and the memory regions array read-only into the
+    /// kernel address space.
+    ///
+    /// This allows kernels that treat the boot info as immutable to catch accidental writes,
+    /// which cause a page fault. The entry point then receives a `&'static BootInfo`, so this
+    /// option is set through the `read_only_boot_info` parameter of the
+    /// [`entry_point`](crate::entry_point) macro. Setting it in the config passed to the
+    /// default form of the macro is a compile-time error.
+    ///
+    /// Defaults to `false`.
+    pub boot_info_read_only: bool,
+    /// The caching attributes of the parts of the [physical memory mapping](Self::physical_memory)
+    /// that contain no RAM, e.g. the memory-mapped I/O ranges of devices.
+    ///
+    /// The physical memory is mapped with 2 MiB pages. Pages that don't overlap any RAM
+    /// region of the memory map, i.e. that lie in a gap of the memory map or only contain
+    /// reserved and MMIO regions, use this cache mode instead of
+    /// [`physical_memory_cache_mode`](Self::physical_memory_cache_mode). This prevents
+    /// kernels from accidentally caching device registers that they access through the
+    /// physical memory mapping. The `IA32_PAT` register is programmed as described for
+    /// `physical_memory_cache_mode`.
+    ///
+    /// Defaults to `None`, i.e. all pages use `physical_memory_cache_mode`.
+    pub physical_memory_mmio_cache_mode: Option<CacheMode>,
+    /// Lets the kernel access the framebuffer through the [physical memory
+    /// mapping](Self::physical_memory) instead of creating a dedicated mapping for it.
+    ///
+    /// This avoids mapping the framebuffer memory twice for kernels that use the physical
+    /// memory mapping anyway. The start address of the framebuffer reported in
+    /// [`BootInfo::framebuffer`](crate::BootInfo::framebuffer) is then the physical address
+    /// of the framebuffer plus the physical memory offset. Note that the framebuffer then uses
+    /// the cache mode of the physical memory mapping, i.e.
+    /// [`physical_memory_mmio_cache_mode`](Self::physical_memory_mmio_cache_mode) if it is
+    /// set. If the physical memory mapping is disabled or doesn't cover the framebuffer, the
+    /// bootloader logs a warning and maps the framebuffer as configured in
+    /// [`framebuffer`](Self::framebuffer).
+    ///
+    /// Defaults to `false`.
+    pub framebuffer_in_physical_memory: bool,
+    /// The number of 4 KiB pages at the start of the ramdisk that are mapped before the
+    /// kernel starts.
+    ///
+    /// Mapping a large ramdisk requires many page table entries, which slows down the boot.
+    /// Kernels that only access parts of the ramdisk can use this option to map only the first
+    /// pages and map the remaining pages on demand. The virtual address range is still
+    /// reserved for the complete ramdisk, so the remaining pages can be mapped at
+    /// [`BootInfo::ramdisk_addr`](crate::BootInfo::ramdisk_addr) plus their offset. Their
+    /// physical addresses follow from
+    /// [`BootInfo::ramdisk_phys_addr`](crate::BootInfo::ramdisk_phys_addr). The mapped length
+    /// is reported in [`BootInfo::ramdisk_mapped_len`](crate::BootInfo::ramdisk_mapped_len).
+    ///
+    /// Defaults to `None`, i.e. the complete ramdisk is mapped.
+    pub ramdisk_eager_pages: Option<u64>,
+    /// Where the [kernel stack](Self::kernel_stack) is placed in the dynamic range if it uses
+    /// [`Mapping::Dynamic`].
+    ///
+    /// Higher-half kernels can set this to [`DynamicPlacement::Top`] to place the stack at a
+    /// well-known high address.
+    ///
+    /// Defaults to [`DynamicPlacement::Anywhere`].
+    pub kernel_stack_placement: DynamicPlacement,
+    /// Where the [boot info](Self::boot_info) is placed in the dynamic range if it uses
+    /// [`Mapping::Dynamic`].
+    ///
+    /// Defaults to [`DynamicPlacement::Anywhere`].
+    pub boot_info_placement: DynamicPlacement,
+    /// Where the [physical memory mapping](Self::physical_memory) is placed in the dynamic
+    /// range if it uses [`Mapping::Dynamic`].
+    ///
+    /// With [`DynamicPlacement::Low`], the physical memory offset is aligned to a 512 GiB
+    /// boundary and reproducible across boots on the same machine.
+    ///
+    /// Defaults to [`DynamicPlacement::Anywhere`].
+    pub physical_memory_placement: DynamicPlacement,
 }
 
 impl Mappings {
@@ -421,6 +1211,17 @@ impl Mappings {
             dynamic_range_start: None,
             dynamic_range_end: None,
             ramdisk_memory: Mapping::new_default(),
+            framebuffer_scratch: Option::None,
+            physical_memory_cache_mode: CacheMode::new_default(),
+            physical_memory_max_len: Option::None,
+            identity_mapped_range: Option::None,
+            boot_info_read_only: false,
+            physical_memory_mmio_cache_mode: Option::None,
+            framebuffer_in_physical_memory: false,
+            ramdisk_eager_pages: Option::None,
+            kernel_stack_placement: DynamicPlacement::new_default(),
+            boot_info_placement: DynamicPlacement::new_default(),
+            physical_memory_placement: DynamicPlacement::new_default(),
         }
     }
 
@@ -454,6 +1255,37 @@ impl Mappings {
                 Option::None
             },
             ramdisk_memory: Mapping::random(),
+            framebuffer_scratch: if rand::random() {
+                Option::Some(Mapping::random())
+            } else {
+                Option::None
+            },
+            physical_memory_cache_mode: CacheMode::random(),
+            physical_memory_max_len: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            identity_mapped_range: if rand::random() {
+                Option::Some((rand::random(), rand::random()))
+            } else {
+                Option::None
+            },
+            boot_info_read_only: rand::random(),
+            physical_memory_mmio_cache_mode: if rand::random() {
+                Option::Some(CacheMode::random())
+            } else {
+                Option::None
+            },
+            framebuffer_in_physical_memory: rand::random(),
+            ramdisk_eager_pages: if rand::random() {
+                Option::Some(rand::random())
+            } else {
+                Option::None
+            },
+            kernel_stack_placement: DynamicPlacement::random(),
+            boot_info_placement: DynamicPlacement::random(),
+            physical_memory_placement: DynamicPlacement::random(),
         }
     }
 }
@@ -462,6 +1294,9 @@ impl Mappings {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Mapping {
     /// Look for an unused virtual memory region at runtime.
+    ///
+    /// Some mappings allow to choose where the region is placed, e.g. through
+    /// [`Mappings::kernel_stack_placement`].
     Dynamic,
     /// Try to map the region at the given virtual address.
     ///
@@ -519,6 +1354,136 @@ impl Default for Mapping {
     }
 }
 
+/// Specifies where the bootloader places the region of a [`Mapping::Dynamic`] mapping, e.g.
+/// through [`Mappings::kernel_stack_placement`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum DynamicPlacement {
+    /// Use the first unused virtual memory region, or a random one if [`Mappings::aslr`] is
+    /// enabled.
+    Anywhere,
+    /// Use the highest unused virtual memory region.
+    ///
+    /// The region is placed at the high end of the dynamic range (see
+    /// [`Mappings::dynamic_range_end`]). This is useful for higher-half kernels that want to
+    /// place e.g. the kernel stack at a well-known high address. In contrast to
+    /// [`DynamicPlacement::Anywhere`], the resulting address is not randomized when
+    /// [`Mappings::aslr`] is enabled.
+    Top,
+    /// Use the lowest unused virtual memory region.
+    ///
+    /// The region starts at the beginning of the lowest unused level 4 entry, so the
+    /// resulting address is always aligned to a 512 GiB boundary. In contrast to
+    /// [`DynamicPlacement::Anywhere`], the address is not randomized when
+    /// [`Mappings::aslr`] is enabled. This makes the address reproducible across boots on
+    /// the same machine as long as the other mappings don't change, without having to
+    /// hardcode a [`Mapping::FixedAddress`].
+    Low,
+}
+
+impl DynamicPlacement {
+    /// Creates a new [`DynamicPlacement::Anywhere`].
+    ///
+    /// This function has identical results as [`Default::default`], the only difference is
+    /// that this is a `const` function.
+    pub const fn new_default() -> Self {
+        Self::Anywhere
+    }
+
+    #[cfg(test)]
+    fn random() -> DynamicPlacement {
+        match rand::random::<u8>() % 3 {
+            0 => Self::Anywhere,
+            1 => Self::Top,
+            _ => Self::Low,
+        }
+    }
+
+    const fn serialize(&self) -> u8 {
+        match self {
+            DynamicPlacement::Anywhere => 0,
+            DynamicPlacement::Top => 1,
+            DynamicPlacement::Low => 2,
+        }
+    }
+
+    fn deserialize(serialized: u8) -> Result<Self, &'static str> {
+        match serialized {
+            0 => Ok(DynamicPlacement::Anywhere),
+            1 => Ok(DynamicPlacement::Top),
+            2 => Ok(DynamicPlacement::Low),
+            _ => Err("invalid dynamic placement value"),
+        }
+    }
+}
+
+impl Default for DynamicPlacement {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
+/// Specifies the caching attributes that the bootloader applies to a memory mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CacheMode {
+    /// Don't set any caching flags and leave the `IA32_PAT` register untouched.
+    ///
+    /// The resulting memory type depends on the PAT configuration of the firmware, which
+    /// is typically write-back.
+    Default,
+    /// Map the memory as write-back.
+    WriteBack,
+    /// Map the memory as write-through.
+    WriteThrough,
+    /// Map the memory as uncached.
+    Uncached,
+}
+
+impl CacheMode {
+    /// Creates a new [`CacheMode::Default`].
+    ///
+    /// This function has identical results as [`Default::default`], the only difference is
+    /// that this is a `const` function.
+    pub const fn new_default() -> Self {
+        Self::Default
+    }
+
+    #[cfg(test)]
+    fn random() -> CacheMode {
+        match rand::random::<u8>() % 4 {
+            0 => Self::Default,
+            1 => Self::WriteBack,
+            2 => Self::WriteThrough,
+            _ => Self::Uncached,
+        }
+    }
+
+    const fn serialize(&self) -> u8 {
+        match self {
+            CacheMode::Default => 0,
+            CacheMode::WriteBack => 1,
+            CacheMode::WriteThrough => 2,
+            CacheMode::Uncached => 3,
+        }
+    }
+
+    fn deserialize(serialized: u8) -> Result<Self, &'static str> {
+        match serialized {
+            0 => Ok(CacheMode::Default),
+            1 => Ok(CacheMode::WriteBack),
+            2 => Ok(CacheMode::WriteThrough),
+            3 => Ok(CacheMode::Uncached),
+            _ => Err("invalid cache mode value"),
+        }
+    }
+}
+
+impl Default for CacheMode {
+    fn default() -> Self {
+        Self::new_default()
+    }
+}
+
 /// Configuration for the frame buffer used for graphical output.
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
 #[non_exhaustive]
@@ -569,6 +1534,12 @@ fn split_array_ref<const N: usize, T>(slice: &[T]) -> (&[T; N], &[T]) {
     unsafe { (&*(a.as_ptr() as *const [T; N]), b) }
 }
 
+/// Checks whether the given address is canonical with 4-level paging, i.e. whether bits 48
+/// to 63 are copies of bit 47.
+const fn is_canonical(addr: u64) -> bool {
+    ((addr << 16) as i64 >> 16) as u64 == addr
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -581,6 +1552,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn recommended_boot_info_addr() {
+        let config = BootloaderConfig::new_with_fixed_boot_info();
+        assert_eq!(
+            config.mappings.boot_info,
+            Mapping::FixedAddress(RECOMMENDED_BOOT_INFO_ADDR)
+        );
+        // canonical address at the start of level 4 entry 510
+        assert_eq!(RECOMMENDED_BOOT_INFO_ADDR >> 47, 0x1_ffff);
+        assert_eq!((RECOMMENDED_BOOT_INFO_ADDR >> 39) & 0x1ff, 510);
+        assert_eq!(RECOMMENDED_BOOT_INFO_ADDR & ((1 << 39) - 1), 0);
+    }
+
     #[test]
     fn config_serde() {
         for _ in 0..10000 {
@@ -591,4 +1575,79 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn extra_page_flags_are_validated() {
+        let mut config = BootloaderConfig::new_default();
+        config.extra_page_flags = BootloaderConfig::AVAILABLE_PAGE_FLAGS;
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Ok(config)
+        );
+        // the writable bit is defined by the architecture
+        config.extra_page_flags = 1 << 1;
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Err("invalid extra_page_flags value")
+        );
+    }
+
+    #[test]
+    fn api_version_of_other_versions() {
+        let mut config = BootloaderConfig::new_default();
+        config.version.version_major = 0x1234;
+        config.version.pre_release = true;
+        let mut serialized = config.serialize().to_vec();
+        assert_eq!(
+            BootloaderConfig::deserialize_api_version(&serialized),
+            Some(config.version)
+        );
+        // the rest of the config may have a different layout in other versions
+        serialized.truncate(30);
+        assert_eq!(
+            BootloaderConfig::deserialize_api_version(&serialized),
+            Some(config.version)
+        );
+        serialized[0] ^= 1;
+        assert_eq!(BootloaderConfig::deserialize_api_version(&serialized), None);
+        assert_eq!(BootloaderConfig::deserialize_api_version(&[]), None);
+    }
+
+    #[test]
+    fn cr4_flags_are_validated() {
+        let mut config = BootloaderConfig::new_default();
+        config.cr4_flags = BootloaderConfig::SUPPORTED_CR4_FLAGS;
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Ok(config)
+        );
+        // the PAE bit must not be changed by the kernel config
+        config.cr4_flags = 1 << 5;
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Err("invalid cr4_flags value")
+        );
+    }
+
+    #[test]
+    fn fs_gs_base_must_be_canonical() {
+        let mut config = BootloaderConfig::new_default();
+        config.fs_base = Some(0x7fff_ffff_f000);
+        config.gs_base = Some(0xffff_8000_0000_0000);
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Ok(config)
+        );
+        config.gs_base = Some(0x8000_0000_0000);
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Err("invalid gs_base value")
+        );
+        config.gs_base = None;
+        config.fs_base = Some(0xff00_0000_0000_0000);
+        assert_eq!(
+            BootloaderConfig::deserialize(&config.serialize()),
+            Err("invalid fs_base value")
+        );
+    }
 }
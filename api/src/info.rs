@@ -45,6 +45,10 @@ pub struct BootInfo {
     /// The virtual address of the recursively mapped level 4 page table.
     ///
     /// Only available if the `map-page-table-recursively` config option is enabled.
+    ///
+    /// If [`five_level_paging`](crate::BootloaderConfig::five_level_paging) is enabled, the
+    /// index still refers to the level 4 table, which is reachable through entries 0 and 511
+    /// of the level 5 table. The level 5 table itself is not mapped recursively.
     pub recursive_index: Optional<u16>,
     /// The address of the `RSDP` data structure, which can be use to find the ACPI tables.
     ///
@@ -56,12 +60,178 @@ pub struct BootInfo {
     pub ramdisk_addr: Optional<u64>,
     /// Ramdisk image size, set to 0 if addr is None
     pub ramdisk_len: u64,
+    /// Physical start address of the ramdisk, if loaded.
+    ///
+    /// The ramdisk is physically contiguous, so the physical address of each byte is its offset
+    /// plus this address. This allows kernels to map the parts of the ramdisk that are not
+    /// mapped yet, see [`ramdisk_mapped_len`](Self::ramdisk_mapped_len).
+    pub ramdisk_phys_addr: Optional<u64>,
+    /// The number of bytes at the start of the ramdisk that are mapped at
+    /// [`ramdisk_addr`](Self::ramdisk_addr).
+    ///
+    /// This is smaller than [`ramdisk_len`](Self::ramdisk_len) if
+    /// [`Mappings::ramdisk_eager_pages`](crate::config::Mappings::ramdisk_eager_pages) limits
+    /// the number of mapped pages. It is a multiple of the page size in that case.
+    pub ramdisk_mapped_len: u64,
     /// Physical address of the kernel ELF in memory.
     pub kernel_addr: u64,
     /// Size of the kernel ELF in memory.
     pub kernel_len: u64,
     /// Virtual address of the loaded kernel image.
     pub kernel_image_offset: u64,
+    /// Virtual start address of the off-screen framebuffer scratch buffer, if requested.
+    ///
+    /// The buffer has the same byte length as the framebuffer. Only available if the
+    /// [`framebuffer_scratch`](crate::config::Mappings::framebuffer_scratch) mapping is
+    /// enabled and a framebuffer exists.
+    pub framebuffer_scratch: Optional<u64>,
+    /// The refresh rate of the display that shows the [framebuffer](Self::framebuffer) in
+    /// Hz, if known.
+    ///
+    /// This is a best-effort value. It is currently only reported on BIOS systems, if the
+    /// display's preferred mode as reported through EDID has the resolution of the framebuffer.
+    pub framebuffer_refresh_rate: Optional<u32>,
+    /// Virtual address of the captured boot log, if enabled.
+    ///
+    /// The boot log contains the UTF-8 encoded log output of the bootloader in chronological
+    /// order. Only the most recent output is kept if the log output didn't fit into the
+    /// capture buffer, in which case the log starts at the first complete line. Only available
+    /// if the `boot_log` option of the boot config is enabled.
+    pub boot_log_addr: Optional<u64>,
+    /// Length of the captured boot log in bytes, set to 0 if addr is None.
+    pub boot_log_len: u64,
+    /// The date and time at which the kernel was loaded.
+    ///
+    /// Only available if the [`boot_time`](crate::BootloaderConfig::boot_time) config option
+    /// is enabled and the firmware reported a valid time.
+    pub boot_time: Optional<BootTime>,
+    /// A random seed that can be used to seed a random number generator in the kernel.
+    ///
+    /// The seed is taken from the `RDSEED` instruction if the CPU supports it, otherwise from
+    /// the `RDRAND` instruction. Entropy from the time stamp counter and the programmable
+    /// interval timer is mixed in. On CPUs that support neither instruction, the seed is only
+    /// based on these timing sources, so it should not be used as the only entropy source for
+    /// cryptographic purposes.
+    pub random_seed: [u8; 32],
+    /// The CPU vendor string reported by CPUID leaf 0, e.g. `GenuineIntel` or `AuthenticAMD`.
+    ///
+    /// Set to all zeros if the CPU does not report a vendor string.
+    pub cpu_vendor: [u8; 12],
+    /// The processor brand string reported by CPUID leaves `0x80000002` to `0x80000004`.
+    ///
+    /// The string is ASCII encoded and padded with null bytes. Set to all zeros if the CPU
+    /// does not support the brand string leaves.
+    pub cpu_brand: [u8; 48],
+    /// The I/O port base of the debug serial port, if configured in the boot config.
+    ///
+    /// The bootloader initializes this 16550 UART but doesn't print log messages to it.
+    pub debug_serial_port: Optional<u16>,
+    /// Whether the CPU supports 5-level paging (LA57), as reported by CPUID.
+    pub la57_supported: bool,
+    /// Whether 5-level paging is enabled, i.e. whether the `LA57` bit is set in `CR4`.
+    ///
+    /// This is `true` if the [`five_level_paging`](crate::BootloaderConfig::five_level_paging)
+    /// config option is enabled.
+    pub la57_enabled: bool,
+    /// The number of bytes of physical memory that are mapped at
+    /// [`physical_memory_offset`](Self::physical_memory_offset), starting at physical
+    /// address `0`.
+    ///
+    /// This is smaller than the physical memory size if the
+    /// [`physical_memory_max_len`](crate::config::Mappings::physical_memory_max_len) config
+    /// option is set. Set to 0 if `physical_memory_offset` is `None`.
+    pub physical_memory_len: u64,
+    /// Start address of the identity-mapped physical memory range, if configured.
+    ///
+    /// Only available if the
+    /// [`identity_mapped_range`](crate::config::Mappings::identity_mapped_range) config
+    /// option is set. The address is page-aligned and identical in the physical and virtual
+    /// address spaces.
+    pub identity_map_addr: Optional<u64>,
+    /// Length of the identity-mapped range in bytes, set to 0 if addr is None.
+    pub identity_map_len: u64,
+    /// Physical address of a zeroed 4 KiB frame below 1 MiB for the startup code of
+    /// additional CPUs.
+    ///
+    /// Only available if the
+    /// [`ap_trampoline_frame`](crate::BootloaderConfig::ap_trampoline_frame) config option is
+    /// enabled and a usable frame was found.
+    pub ap_trampoline_frame: Optional<u64>,
+    /// The hypervisor vendor string reported by CPUID leaf `0x40000000`, e.g. `TCGTCGTCGTCG`
+    /// or `KVMKVMKVM` for QEMU.
+    ///
+    /// Only available if the CPU reports that it runs under a hypervisor (bit 31 of `ECX` in
+    /// CPUID leaf 1). The string is padded with null bytes. Kernels can use this to enable
+    /// hypervisor-specific behavior, e.g. to use QEMU's `isa-debug-exit` device.
+    pub hypervisor_vendor: Optional<[u8; 12]>,
+    /// The physical address of the kernel's level 4 page table.
+    ///
+    /// This is the address that `CR3` points to when the kernel is entered. Kernels can use it
+    /// to walk their page tables through the
+    /// [`physical_memory_offset`](Self::physical_memory_offset) mapping without reading `CR3`.
+    ///
+    /// If [`five_level_paging`](crate::BootloaderConfig::five_level_paging) is enabled, `CR3`
+    /// points to a level 5 table instead, whose entries 0 and 511 reference this level 4 table.
+    pub kernel_page_table_phys: u64,
+    /// The virtual start address of the canary page below the guard page of the kernel stack.
+    ///
+    /// Only available if the
+    /// [`kernel_stack_canary`](crate::BootloaderConfig::kernel_stack_canary) config option is
+    /// enabled. The page is filled with copies of
+    /// [`kernel_stack_canary_pattern`](Self::kernel_stack_canary_pattern).
+    pub kernel_stack_canary_addr: Optional<u64>,
+    /// The 64-bit pattern that the [kernel stack canary page](Self::kernel_stack_canary_addr)
+    /// is filled with, or `0` if no canary page is mapped.
+    ///
+    /// Any other value in the canary page indicates a stack overflow.
+    pub kernel_stack_canary_pattern: u64,
+    /// The physical start address of the memory that contains the ACPI tables.
+    ///
+    /// This is the smallest range that contains all ACPI reclaimable and ACPI NVS regions of
+    /// the firmware memory map (`EfiACPIReclaimMemory` and `EfiACPIMemoryNVS` on UEFI, E820
+    /// types 3 and 4 on BIOS). Kernels that don't map all physical memory can map just this
+    /// range to parse the ACPI tables. The range might include other memory between the
+    /// regions, so the exact regions should be looked up in
+    /// [`memory_regions`](Self::memory_regions) before reclaiming any memory.
+    ///
+    /// Not available if the firmware memory map contains no ACPI regions. Note that some
+    /// firmware places ACPI tables in reserved memory instead.
+    pub acpi_tables_addr: Optional<u64>,
+    /// Length of the [ACPI tables range](Self::acpi_tables_addr) in bytes, set to 0 if addr
+    /// is None.
+    pub acpi_tables_len: u64,
+    /// The number of frames that the bootloader allocated for the zero-initialized parts of
+    /// the kernel's load segments, e.g. the `.bss` section.
+    ///
+    /// This is `0` if [`preallocate_bss`](crate::BootloaderConfig::preallocate_bss) is
+    /// disabled or the kernel is a flat binary. Frames that contain both initialized and
+    /// zero-initialized data are not included.
+    pub kernel_bss_frames: u64,
+    /// Information about the [`BootloaderConfig`](crate::BootloaderConfig) that the kernel
+    /// was loaded with.
+    pub kernel_config: KernelConfigInfo,
+    /// The effective runtime configuration of the bootloader.
+    ///
+    /// The runtime configuration is read from the `boot.json` file or embedded into the
+    /// bootloader, and merged with the [`BootloaderConfig`](crate::BootloaderConfig) of the
+    /// kernel. Kernels can use this e.g. to match their log verbosity to the bootloader's.
+    pub boot_config: BootConfigInfo,
+    /// The `CR4` flags that the bootloader set right before jumping to the kernel.
+    ///
+    /// These are the flags of [`BootloaderConfig::cr4_flags`](crate::BootloaderConfig::cr4_flags)
+    /// that the CPU supports. For example, bit 20 is set if SMEP was enabled and bit 21 is set
+    /// if SMAP was enabled.
+    pub enabled_cr4_flags: u64,
+    /// The value that the bootloader wrote to the `FS` base register before jumping to the
+    /// kernel.
+    ///
+    /// Only set if [`BootloaderConfig::fs_base`](crate::BootloaderConfig::fs_base) is set.
+    pub fs_base: Optional<u64>,
+    /// The value that the bootloader wrote to the `GS` base register before jumping to the
+    /// kernel.
+    ///
+    /// Only set if [`BootloaderConfig::gs_base`](crate::BootloaderConfig::gs_base) is set.
+    pub gs_base: Optional<u64>,
 
     #[doc(hidden)]
     pub _test_sentinel: u64,
@@ -82,14 +252,140 @@ impl BootInfo {
             tls_template: Optional::None,
             ramdisk_addr: Optional::None,
             ramdisk_len: 0,
+            ramdisk_phys_addr: Optional::None,
+            ramdisk_mapped_len: 0,
             kernel_addr: 0,
             kernel_len: 0,
             kernel_image_offset: 0,
+            framebuffer_scratch: Optional::None,
+            framebuffer_refresh_rate: Optional::None,
+            boot_log_addr: Optional::None,
+            boot_log_len: 0,
+            boot_time: Optional::None,
+            random_seed: [0; 32],
+            cpu_vendor: [0; 12],
+            cpu_brand: [0; 48],
+            debug_serial_port: Optional::None,
+            la57_supported: false,
+            la57_enabled: false,
+            physical_memory_len: 0,
+            identity_map_addr: Optional::None,
+            identity_map_len: 0,
+            ap_trampoline_frame: Optional::None,
+            hypervisor_vendor: Optional::None,
+            kernel_page_table_phys: 0,
+            kernel_stack_canary_addr: Optional::None,
+            kernel_stack_canary_pattern: 0,
+            acpi_tables_addr: Optional::None,
+            acpi_tables_len: 0,
+            kernel_bss_frames: 0,
+            kernel_config: KernelConfigInfo {
+                serialized_addr: Optional::None,
+                serialized_len: 0,
+                api_version: ApiVersion::new_default(),
+                kernel_stack_size: 0,
+                aslr: false,
+                safe_mode: false,
+            },
+            boot_config: BootConfigInfo {
+                log_level: LogLevelFilter::Off,
+                frame_buffer_logging: false,
+                serial_logging: false,
+                boot_log: false,
+                minimum_framebuffer_height: Optional::None,
+                minimum_framebuffer_width: Optional::None,
+                maximum_framebuffer_height: Optional::None,
+                maximum_framebuffer_width: Optional::None,
+            },
+            enabled_cr4_flags: 0,
+            fs_base: Optional::None,
+            gs_base: Optional::None,
             _test_sentinel: 0,
         }
     }
 }
 
+/// Information about the [`BootloaderConfig`](crate::BootloaderConfig) that the kernel was
+/// loaded with.
+///
+/// Helps to diagnose configuration issues, e.g. a kernel that was compiled against a different
+/// `bootloader_api` version or a config that was overridden in safe mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct KernelConfigInfo {
+    /// The virtual address of the serialized config in the kernel image, i.e. of the
+    /// `.bootloader-config` section that is created by the [`entry_point`](crate::entry_point)
+    /// macro.
+    ///
+    /// Not available for flat binary kernels and if the section is not part of a load
+    /// segment.
+    pub serialized_addr: Optional<u64>,
+    /// The length of the serialized config in bytes, set to 0 if addr is None.
+    pub serialized_len: u64,
+    /// The version of the `bootloader_api` crate that the kernel was compiled against.
+    ///
+    /// Compare with [`BootInfo::api_version`], which is the version that the bootloader was
+    /// compiled against.
+    pub api_version: ApiVersion,
+    /// The effective size of the kernel stack in bytes.
+    ///
+    /// Equal to [`kernel_stack_size`](crate::BootloaderConfig::kernel_stack_size), except in
+    /// safe mode, which always uses a fixed stack size of 64 KiB.
+    pub kernel_stack_size: u64,
+    /// Whether the kernel and the other mappings were placed at randomized addresses.
+    ///
+    /// Always `false` in safe mode.
+    pub aslr: bool,
+    /// Whether the bootloader was booted in safe mode, which overrides parts of the config.
+    pub safe_mode: bool,
+}
+
+/// The effective runtime configuration of the bootloader, see [`BootInfo::boot_config`].
+///
+/// The values reflect all overrides, e.g. by safe mode or by the kernel's
+/// [`BootloaderConfig`](crate::BootloaderConfig).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootConfigInfo {
+    /// The maximum level of the log messages that the bootloader printed.
+    pub log_level: LogLevelFilter,
+    /// Whether the bootloader logged to the framebuffer.
+    ///
+    /// This is `false` if logging to the framebuffer was enabled, but no framebuffer is
+    /// available.
+    pub frame_buffer_logging: bool,
+    /// Whether the bootloader logged to the serial port.
+    pub serial_logging: bool,
+    /// Whether the bootloader recorded its log messages, see [`BootInfo::boot_log_addr`].
+    pub boot_log: bool,
+    /// The minimum framebuffer height in pixels that was requested.
+    pub minimum_framebuffer_height: Optional<u64>,
+    /// The minimum framebuffer width in pixels that was requested.
+    pub minimum_framebuffer_width: Optional<u64>,
+    /// The maximum framebuffer height in pixels that was requested.
+    pub maximum_framebuffer_height: Optional<u64>,
+    /// The maximum framebuffer width in pixels that was requested.
+    pub maximum_framebuffer_width: Optional<u64>,
+}
+
+/// A maximum log level, see [`BootConfigInfo::log_level`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub enum LogLevelFilter {
+    /// No messages are logged.
+    Off,
+    /// Corresponds to the `Error` log level.
+    Error,
+    /// Corresponds to the `Warn` log level.
+    Warn,
+    /// Corresponds to the `Info` log level.
+    Info,
+    /// Corresponds to the `Debug` log level.
+    Debug,
+    /// Corresponds to the `Trace` log level.
+    Trace,
+}
+
 /// FFI-safe slice of [`MemoryRegion`] structs, semantically equivalent to
 /// `&'static mut [MemoryRegion]`.
 ///
@@ -103,6 +399,51 @@ pub struct MemoryRegions {
     pub(crate) len: usize,
 }
 
+impl MemoryRegions {
+    /// Returns an iterator over the memory regions that merges adjacent regions of the same
+    /// kind.
+    ///
+    /// Regions are only merged if the end address of a region is equal to the start address
+    /// of the next region in the memory map. The regions are not sorted.
+    pub fn coalesced(&self) -> Coalesced<'_> {
+        Coalesced {
+            regions: self.iter().peekable(),
+        }
+    }
+
+    /// Returns an iterator over the physical address ranges of all usable memory, with
+    /// adjacent usable regions merged.
+    ///
+    /// This walks the memory map only once, which is useful in early kernel code.
+    pub fn usable_ranges(&self) -> impl Iterator<Item = ops::Range<u64>> + '_ {
+        self.coalesced()
+            .filter(|region| region.kind == MemoryRegionKind::Usable)
+            .map(|region| region.start..region.end)
+    }
+}
+
+/// An iterator that merges adjacent memory regions of the same kind, see
+/// [`MemoryRegions::coalesced`].
+#[derive(Debug, Clone)]
+pub struct Coalesced<'a> {
+    regions: core::iter::Peekable<slice::Iter<'a, MemoryRegion>>,
+}
+
+impl Iterator for Coalesced<'_> {
+    type Item = MemoryRegion;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut region = *self.regions.next()?;
+        while let Some(next) = self
+            .regions
+            .next_if(|next| next.kind == region.kind && next.start == region.end)
+        {
+            region.end = next.end;
+        }
+        Some(region)
+    }
+}
+
 impl ops::Deref for MemoryRegions {
     type Target = [MemoryRegion];
 
@@ -176,6 +517,16 @@ pub enum MemoryRegionKind {
     ///
     /// Contains the E820 memory type.
     UnknownBios(u32),
+    /// Defective memory reported by the firmware, i.e. E820 type 5 on BIOS systems or
+    /// `EfiUnusableMemory` on UEFI systems.
+    ///
+    /// The kernel must never access these frames.
+    BadMemory,
+    /// Memory that was reserved through the `reserved_memory` option of the boot config,
+    /// e.g. because it is known to be defective or used by the firmware.
+    ///
+    /// The kernel should not use these frames.
+    Reserved,
 }
 
 /// A pixel-based framebuffer that controls the screen output.
@@ -227,6 +578,57 @@ impl FrameBuffer {
     pub fn info(&self) -> FrameBufferInfo {
         self.info
     }
+
+    /// Sets the pixel at the given position to the given color.
+    ///
+    /// The color is converted to the [`PixelFormat`] of the framebuffer using
+    /// [`Color::to_pixel_bytes`] and the pixel offset is calculated using the
+    /// [`stride`][FrameBufferInfo::stride] and
+    /// [`bytes_per_pixel`][FrameBufferInfo::bytes_per_pixel] values.
+    ///
+    /// Returns an error if the position lies outside the visible framebuffer area.
+    pub fn set_pixel(
+        &mut self,
+        x: usize,
+        y: usize,
+        color: impl Into<Color>,
+    ) -> Result<(), PixelOutOfBounds> {
+        let info = self.info;
+        if x >= info.width || y >= info.height {
+            return Err(PixelOutOfBounds { x, y });
+        }
+        let offset = (y * info.stride + x) * info.bytes_per_pixel;
+        let pixel = self
+            .buffer_mut()
+            .get_mut(offset..offset + info.bytes_per_pixel)
+            .ok_or(PixelOutOfBounds { x, y })?;
+
+        // pad pixels that are larger than four bytes with zeros
+        let bytes = color.into().to_pixel_bytes(info.pixel_format);
+        for (i, byte) in pixel.iter_mut().enumerate() {
+            *byte = bytes.get(i).copied().unwrap_or(0);
+        }
+        Ok(())
+    }
+}
+
+/// The error returned by [`FrameBuffer::set_pixel`] for positions outside of the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelOutOfBounds {
+    /// The requested horizontal position.
+    pub x: usize,
+    /// The requested vertical position.
+    pub y: usize,
+}
+
+impl core::fmt::Display for PixelOutOfBounds {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "pixel ({}, {}) is outside of the framebuffer",
+            self.x, self.y
+        )
+    }
 }
 
 /// Describes the layout and pixel format of a framebuffer.
@@ -282,6 +684,100 @@ pub enum PixelFormat {
     },
 }
 
+/// A calendar date and wall-clock time, see [`BootInfo::boot_time`].
+///
+/// The time zone is unknown. Depending on the firmware configuration, the time is either in
+/// UTC or in local time. The fields are ordered from most to least significant, so boot times
+/// can be compared directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub struct BootTime {
+    /// The full year, e.g. `2024`.
+    pub year: u16,
+    /// The month, in the range `1..=12`.
+    pub month: u8,
+    /// The day of the month, in the range `1..=31`.
+    pub day: u8,
+    /// The hour, in the range `0..=23`.
+    pub hour: u8,
+    /// The minute, in the range `0..=59`.
+    pub minute: u8,
+    /// The second, in the range `0..=59`.
+    pub second: u8,
+}
+
+impl BootTime {
+    /// Returns whether all fields are within their documented ranges.
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+/// A 24-bit RGB color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    /// The red component.
+    pub r: u8,
+    /// The green component.
+    pub g: u8,
+    /// The blue component.
+    pub b: u8,
+}
+
+impl Color {
+    /// Creates a new color from its red, green, and blue components.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Converts the color to the in-memory representation of a pixel with the given format.
+    ///
+    /// The returned bytes are in memory order. Only the first
+    /// [`bytes_per_pixel`][FrameBufferInfo::bytes_per_pixel] bytes should be written to the
+    /// framebuffer; pixels that are larger than four bytes should be padded with zeros.
+    ///
+    /// For the [`PixelFormat::U8`] format, the color is converted to its grayscale value. For
+    /// [`PixelFormat::Unknown`], each component is placed at its bit offset in a
+    /// little-endian value; components at offsets beyond 32 bits are dropped.
+    pub fn to_pixel_bytes(self, pixel_format: PixelFormat) -> [u8; 4] {
+        let Self { r, g, b } = self;
+        match pixel_format {
+            PixelFormat::Rgb => [r, g, b, 0],
+            PixelFormat::Bgr => [b, g, r, 0],
+            PixelFormat::U8 => [self.luma(), 0, 0, 0],
+            PixelFormat::Unknown {
+                red_position,
+                green_position,
+                blue_position,
+            } => {
+                let component = |value: u8, position: u8| {
+                    u32::from(value).checked_shl(position.into()).unwrap_or(0)
+                };
+                (component(r, red_position)
+                    | component(g, green_position)
+                    | component(b, blue_position))
+                .to_le_bytes()
+            }
+        }
+    }
+
+    /// Returns the grayscale value of the color, using the ITU-R BT.601 weights.
+    pub fn luma(self) -> u8 {
+        let luma = 299 * u32::from(self.r) + 587 * u32::from(self.g) + 114 * u32::from(self.b);
+        (luma / 1000) as u8
+    }
+}
+
+impl From<[u8; 3]> for Color {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        Self::new(r, g, b)
+    }
+}
+
 /// Information about the thread local storage (TLS) template.
 ///
 /// This template can be used to set up thread local storage for threads. For
@@ -289,6 +785,45 @@ pub enum PixelFormat {
 /// Then the first `file_size` bytes of this template needs to be copied to the
 /// location. The additional `mem_size - file_size` bytes must be initialized with
 /// zero.
+///
+/// On x86_64, the TLS block of a thread is placed directly below the address that the `FS`
+/// segment base points to (the _thread pointer_). The first word at the thread pointer must
+/// contain the thread pointer itself, because compilers read it to compute the addresses of
+/// thread local variables.
+///
+/// ## Example
+///
+/// ```no_run
+/// # use bootloader_api::info::TlsTemplate;
+/// # fn alloc(size: usize, align: usize) -> *mut u8 {
+/// #     let layout = std::alloc::Layout::from_size_align(size, align).unwrap();
+/// #     unsafe { std::alloc::alloc(layout) }
+/// # }
+/// # fn set_fs_base(_addr: u64) {}
+/// # let template = TlsTemplate {
+/// #     start_addr: 0x20_0000,
+/// #     file_size: 0x10,
+/// #     mem_size: 0x28,
+/// #     alignment: 0x10,
+/// # };
+/// // allocate the TLS block, followed by the word that the thread pointer points to
+/// let size = template.total_size() as usize;
+/// let align = (template.align() as usize).max(8);
+/// let block = alloc(size + 8, align);
+/// let thread_pointer = unsafe { block.add(size) };
+///
+/// // the TLS data starts at the beginning of the block, `total_size` bytes below the thread
+/// // pointer, so that both are aligned; copy the `.tdata` section and zero the `.tbss` part
+/// let tls_start = unsafe { thread_pointer.sub(template.total_size() as usize) };
+/// unsafe {
+///     core::ptr::copy_nonoverlapping(template.data().as_ptr(), tls_start, template.data().len());
+///     tls_start
+///         .add(template.data().len())
+///         .write_bytes(0, template.tbss_size() as usize);
+///     (thread_pointer as *mut u64).write(thread_pointer as u64);
+/// }
+/// set_fs_base(thread_pointer as u64);
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct TlsTemplate {
@@ -302,6 +837,49 @@ pub struct TlsTemplate {
     ///
     /// Corresponds to the combined length of the `.tdata` and `.tbss` sections.
     pub mem_size: u64,
+    /// The required alignment of the TLS segment in bytes, as given by the `p_align` field of
+    /// the `PT_TLS` program header.
+    ///
+    /// A value of `0` or `1` means that no alignment is required. Use [`Self::align`] to get
+    /// a normalized value.
+    pub alignment: u64,
+}
+
+impl TlsTemplate {
+    /// Returns the required alignment of the TLS block in bytes.
+    ///
+    /// This is at least `1`.
+    pub fn align(&self) -> u64 {
+        self.alignment.max(1)
+    }
+
+    /// Returns the size of the TLS block that must be reserved below the thread pointer.
+    ///
+    /// This is [`mem_size`](Self::mem_size) rounded up to the [alignment](Self::align), so
+    /// that both the start of the TLS data and the thread pointer are properly aligned if the
+    /// thread pointer is aligned.
+    pub fn total_size(&self) -> u64 {
+        self.mem_size.next_multiple_of(self.align())
+    }
+
+    /// Returns the number of bytes that must be zero-initialized after the template data.
+    ///
+    /// Corresponds to the length of the `.tbss` section.
+    pub fn tbss_size(&self) -> u64 {
+        self.mem_size - self.file_size
+    }
+
+    /// Returns the initialization data of the TLS template (the `.tdata` section).
+    ///
+    /// ## Safety
+    ///
+    /// The template must be mapped at [`start_addr`](Self::start_addr), which is the case for
+    /// the template passed by the bootloader as long as the kernel didn't unmap it.
+    pub unsafe fn data(&self) -> &'static [u8] {
+        unsafe {
+            core::slice::from_raw_parts(self.start_addr as *const u8, self.file_size as usize)
+        }
+    }
 }
 
 /// FFI-safe variant of [`Option`].
@@ -370,3 +948,134 @@ impl<T> From<Optional<T>> for Option<T> {
 
 /// Check that bootinfo is FFI-safe
 extern "C" fn _assert_ffi(_boot_info: BootInfo) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(start: u64, end: u64, kind: MemoryRegionKind) -> MemoryRegion {
+        MemoryRegion { start, end, kind }
+    }
+
+    #[test]
+    fn tls_template_size() {
+        let template = |mem_size, alignment| TlsTemplate {
+            start_addr: 0x1000,
+            file_size: 0,
+            mem_size,
+            alignment,
+        };
+        assert_eq!(template(0x11, 0).align(), 1);
+        assert_eq!(template(0x11, 0).total_size(), 0x11);
+        assert_eq!(template(0x11, 0x10).total_size(), 0x20);
+        assert_eq!(template(0x20, 0x10).total_size(), 0x20);
+        assert_eq!(template(0x21, 0x40).total_size(), 0x40);
+    }
+
+    #[test]
+    fn coalesce_memory_regions() {
+        let regions: &'static mut [MemoryRegion] = Box::leak(Box::new([
+            region(0x0, 0x1000, MemoryRegionKind::Bootloader),
+            region(0x1000, 0x5000, MemoryRegionKind::Usable),
+            region(0x5000, 0x8000, MemoryRegionKind::Usable),
+            region(0x9000, 0xa000, MemoryRegionKind::Usable),
+            region(0xa000, 0xb000, MemoryRegionKind::UnknownBios(2)),
+            region(0xb000, 0xc000, MemoryRegionKind::UnknownBios(3)),
+            region(0xc000, 0xd000, MemoryRegionKind::Usable),
+        ]));
+        let regions = MemoryRegions::from(regions);
+
+        let coalesced: Vec<_> = regions.coalesced().collect();
+        assert_eq!(
+            coalesced,
+            [
+                region(0x0, 0x1000, MemoryRegionKind::Bootloader),
+                region(0x1000, 0x8000, MemoryRegionKind::Usable),
+                region(0x9000, 0xa000, MemoryRegionKind::Usable),
+                region(0xa000, 0xb000, MemoryRegionKind::UnknownBios(2)),
+                region(0xb000, 0xc000, MemoryRegionKind::UnknownBios(3)),
+                region(0xc000, 0xd000, MemoryRegionKind::Usable),
+            ]
+        );
+
+        let usable: Vec<_> = regions.usable_ranges().collect();
+        assert_eq!(usable, [0x1000..0x8000, 0x9000..0xa000, 0xc000..0xd000]);
+    }
+
+    fn framebuffer(buffer: &mut [u8], pixel_format: PixelFormat) -> FrameBuffer {
+        let info = FrameBufferInfo {
+            byte_len: buffer.len(),
+            width: 3,
+            height: 2,
+            pixel_format,
+            bytes_per_pixel: 4,
+            stride: 4,
+        };
+        unsafe { FrameBuffer::new(buffer.as_mut_ptr() as u64, info) }
+    }
+
+    #[test]
+    fn set_pixel_respects_stride_and_format() {
+        let mut buffer = [0; 32];
+        let mut fb = framebuffer(&mut buffer, PixelFormat::Bgr);
+        fb.set_pixel(2, 1, Color::new(1, 2, 3)).unwrap();
+        assert_eq!(buffer[24..28], [3, 2, 1, 0]);
+        assert!(buffer[..24].iter().chain(&buffer[28..]).all(|&b| b == 0));
+
+        let mut fb = framebuffer(&mut buffer, PixelFormat::Rgb);
+        fb.set_pixel(0, 0, [1, 2, 3]).unwrap();
+        assert_eq!(buffer[..4], [1, 2, 3, 0]);
+
+        let mut fb = framebuffer(
+            &mut buffer,
+            PixelFormat::Unknown {
+                red_position: 24,
+                green_position: 16,
+                blue_position: 8,
+            },
+        );
+        fb.set_pixel(1, 0, [1, 2, 3]).unwrap();
+        assert_eq!(buffer[4..8], [0, 3, 2, 1]);
+
+        let mut fb = framebuffer(&mut buffer, PixelFormat::U8);
+        fb.set_pixel(1, 1, [255, 255, 255]).unwrap();
+        assert_eq!(buffer[20..24], [255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn color_to_pixel_bytes() {
+        let color = Color::new(0x11, 0x22, 0x33);
+        assert_eq!(
+            color.to_pixel_bytes(PixelFormat::Rgb),
+            [0x11, 0x22, 0x33, 0]
+        );
+        assert_eq!(
+            color.to_pixel_bytes(PixelFormat::Bgr),
+            [0x33, 0x22, 0x11, 0]
+        );
+        let unknown = PixelFormat::Unknown {
+            red_position: 0,
+            green_position: 8,
+            blue_position: 40,
+        };
+        assert_eq!(color.to_pixel_bytes(unknown), [0x11, 0x22, 0, 0]);
+        assert_eq!(Color::new(255, 255, 255).luma(), 255);
+        assert_eq!(Color::new(0, 0, 0).to_pixel_bytes(PixelFormat::U8), [0; 4]);
+    }
+
+    #[test]
+    fn set_pixel_out_of_bounds() {
+        let mut buffer = [0; 32];
+        let mut fb = framebuffer(&mut buffer, PixelFormat::Rgb);
+        // the padding at the end of each line is not part of the visible area
+        assert_eq!(
+            fb.set_pixel(3, 0, [1, 1, 1]),
+            Err(PixelOutOfBounds { x: 3, y: 0 })
+        );
+        assert_eq!(
+            fb.set_pixel(0, 2, [1, 1, 1]),
+            Err(PixelOutOfBounds { x: 0, y: 2 })
+        );
+        assert!(buffer.iter().all(|&b| b == 0));
+    }
+}
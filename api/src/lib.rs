@@ -28,6 +28,12 @@ mod version_info {
 /// point. The advantage of using this macro instead of providing an own `_start` function is
 /// that the macro ensures that the function and argument types are correct.
 ///
+/// ## Interrupts
+///
+/// The bootloader disables interrupts before jumping to the entry point. The kernel should
+/// keep them disabled until it has loaded its own interrupt descriptor table (IDT), because
+/// the IDT of the firmware is not mapped in the kernel address space.
+///
 /// ## Configuration
 ///
 /// This macro supports an optional second parameter to configure how the bootloader should
@@ -35,6 +41,13 @@ mod version_info {
 /// [`&BootloaderConfig`](crate::BootloaderConfig). If not given, the configuration defaults to
 /// [`BootloaderConfig::new_default`](crate::BootloaderConfig::new_default).
 ///
+/// ## Read-only Boot Info
+///
+/// When `read_only_boot_info` is given as last parameter, the bootloader maps the boot info
+/// read-only into the kernel address space (see
+/// [`Mappings::boot_info_read_only`](crate::config::Mappings::boot_info_read_only)). The
+/// function must then have the signature `fn(&'static BootInfo) -> !` instead.
+///
 /// ## Examples
 ///
 /// - With default configuration:
@@ -109,15 +122,18 @@ mod version_info {
 #[cfg(target_arch = "x86_64")]
 #[macro_export]
 macro_rules! entry_point {
-    ($path:path) => {
-        $crate::entry_point!($path, config = &$crate::BootloaderConfig::new_default());
-    };
-    ($path:path, config = $config:expr) => {
+    (@start $path:path, config = $config:expr, read_only = $read_only:literal, $boot_info:ty) => {
         const _: () = {
             #[link_section = ".bootloader-config"]
             pub static __BOOTLOADER_CONFIG: [u8; $crate::BootloaderConfig::SERIALIZED_LEN] = {
                 // validate the type
                 let config: &$crate::BootloaderConfig = $config;
+                let mut config = *config;
+                if $read_only {
+                    config.mappings.boot_info_read_only = true;
+                } else if config.mappings.boot_info_read_only {
+                    panic!("`boot_info_read_only` requires the `read_only_boot_info` entry point");
+                }
                 config.serialize()
             };
 
@@ -126,9 +142,9 @@ macro_rules! entry_point {
                 &__BOOTLOADER_CONFIG;
 
             #[export_name = "_start"]
-            pub extern "C" fn __impl_start(boot_info: &'static mut $crate::BootInfo) -> ! {
+            pub extern "C" fn __impl_start(boot_info: $boot_info) -> ! {
                 // validate the signature of the program entry point
-                let f: fn(&'static mut $crate::BootInfo) -> ! = $path;
+                let f: fn($boot_info) -> ! = $path;
 
                 // ensure that the config is used so that the linker keeps it
                 $crate::__force_use(&__BOOTLOADER_CONFIG_REF);
@@ -137,6 +153,32 @@ macro_rules! entry_point {
             }
         };
     };
+    ($path:path) => {
+        $crate::entry_point!($path, config = &$crate::BootloaderConfig::new_default());
+    };
+    ($path:path, read_only_boot_info) => {
+        $crate::entry_point!(
+            $path,
+            config = &$crate::BootloaderConfig::new_default(),
+            read_only_boot_info
+        );
+    };
+    ($path:path, config = $config:expr) => {
+        $crate::entry_point!(
+            @start $path,
+            config = $config,
+            read_only = false,
+            &'static mut $crate::BootInfo
+        );
+    };
+    ($path:path, config = $config:expr, read_only_boot_info) => {
+        $crate::entry_point!(
+            @start $path,
+            config = $config,
+            read_only = true,
+            &'static $crate::BootInfo
+        );
+    };
 }
 
 #[doc(hidden)]
@@ -1,3 +1,4 @@
+use bootloader_boot_config::{DEFAULT_DISK_READ_RETRIES, DISK_READ_RETRY_DELAY_MICROS};
 use core::arch::asm;
 
 #[repr(C, packed)]
@@ -34,6 +35,12 @@ impl DiskAddressPacket {
         }
     }
 
+    /// Loads the sectors described by this packet, retrying up to
+    /// [`DEFAULT_DISK_READ_RETRIES`] times.
+    ///
+    /// Before each retry, the disk system is reset, which recalibrates the drive, and we wait
+    /// for [`DISK_READ_RETRY_DELAY_MICROS`]. Calls `fail` with error code `z` if all attempts
+    /// fail.
     pub unsafe fn perform_load(&self, disk_number: u16) {
         let self_addr = self as *const Self as u16;
         unsafe {
@@ -41,15 +48,31 @@ impl DiskAddressPacket {
                 "push 'z'", // error code `z`, passed to `fail` on error
                 "mov {1:x}, si", // backup the `si` register, whose contents are required by LLVM
                 "mov si, {0:x}",
+                "3:",
+                "mov ax, 0x4200",
                 "int 0x13",
                 "jnc 2f", // carry is set on fail
+                "xor ax, ax", // reset the disk system before retrying
+                "int 0x13",
+                "push cx",
+                "push dx",
+                "mov ah, 0x86", // wait for `cx:dx` microseconds
+                "xor cx, cx",
+                "mov dx, {3}",
+                "int 0x15",
+                "pop dx",
+                "pop cx",
+                "dec {2:x}",
+                "jns 3b",
                 "call fail",
                 "2:",
                 "pop si", // remove error code again
                 "mov si, {1:x}", // restore the `si` register to its prior state
                 in(reg) self_addr,
                 out(reg) _,
-                in("ax") 0x4200u16,
+                inout(reg) DEFAULT_DISK_READ_RETRIES => _,
+                const DISK_READ_RETRY_DELAY_MICROS,
+                out("ax") _,
                 in("dx") disk_number,
             );
         }
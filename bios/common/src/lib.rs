@@ -25,6 +25,8 @@ pub struct BiosFramebufferInfo {
     pub bytes_per_pixel: u8,
     pub stride: u16,
     pub pixel_format: PixelFormat,
+    /// The refresh rate in Hz, or 0 if unknown.
+    pub refresh_rate: u32,
 }
 
 #[cfg_attr(feature = "debug", derive(Debug))]
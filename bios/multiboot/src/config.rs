@@ -0,0 +1,19 @@
+//! Minimal lookup of the boot config values that this stage needs.
+//!
+//! This stage runs before stage 4 parses the boot config, so it only scans the JSON text for
+//! the few boolean fields it is interested in instead of deserializing it.
+
+use bootloader_boot_config::{embedded, scan};
+
+/// Returns whether the serial port may be used, according to the boot config.
+///
+/// Like stage 4, a config that is embedded into the stage 4 binary takes precedence over the
+/// `boot.json` file. Serial logging is enabled by default and always enabled in safe mode.
+pub fn serial_logging(stage_4: &[u8], config_file: Option<&[u8]>) -> bool {
+    let embedded_config = embedded::find(stage_4).map(|range| &stage_4[range]);
+    let Some(json) = embedded_config.or(config_file) else {
+        return true;
+    };
+    scan::find_bool(json, "safe_mode") == Some(true)
+        || scan::find_bool(json, "serial_logging") != Some(false)
+}
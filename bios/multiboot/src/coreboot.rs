@@ -0,0 +1,186 @@
+//! Support for being loaded as a coreboot payload.
+//!
+//! Coreboot passes a pointer to its tables as first argument on the stack. The tables contain
+//! the memory map, which is converted to the E820 format. The stages, the kernel, and the
+//! other files are stored in a segment of the payload and described by the module table that
+//! the disk image builder fills in.
+
+use crate::Module;
+use bootloader_boot_config::coreboot_payload;
+use core::ptr;
+
+/// The signature of the coreboot table header.
+const HEADER_SIGNATURE: [u8; 4] = *b"LBIO";
+/// A record that contains the memory map.
+const TAG_MEMORY: u32 = 0x01;
+/// A record that points to the actual table, used by the copy in low memory.
+const TAG_FORWARD: u32 = 0x11;
+
+/// The memory ranges that are searched for the coreboot table header if no valid pointer
+/// was passed.
+const SEARCH_RANGES: [(u32, u32); 2] = [(0, 0x1000), (0xf_0000, 0x10_0000)];
+
+/// Reserved space for the module table, filled in by the disk image builder.
+///
+/// It must only be read through volatile reads, see `EMBEDDED_BOOT_CONFIG` in the common
+/// crate.
+#[used]
+#[link_section = ".payload_modules"]
+static mut MODULES: [u8; coreboot_payload::SIZE] = {
+    let mut buffer = [0; coreboot_payload::SIZE];
+    let mut i = 0;
+    while i < coreboot_payload::MAGIC.len() {
+        buffer[i] = coreboot_payload::MAGIC[i];
+        i += 1;
+    }
+    buffer
+};
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct Header {
+    signature: [u8; 4],
+    header_bytes: u32,
+    _header_checksum: u32,
+    table_bytes: u32,
+    _table_checksum: u32,
+    table_entries: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct Record {
+    tag: u32,
+    size: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct MemoryRange {
+    start: u64,
+    size: u64,
+    range_type: u32,
+}
+
+/// An entry of the coreboot memory map.
+#[derive(Clone, Copy)]
+pub struct MemoryMapEntry {
+    pub start: u64,
+    pub len: u64,
+    /// The memory type in E820 format.
+    pub region_type: u32,
+}
+
+/// Returns the modules that the disk image builder stored in the payload.
+pub fn modules() -> impl Iterator<Item = Module> {
+    let base = ptr::addr_of!(MODULES).cast::<u8>();
+    let read_u32 = move |offset: usize| {
+        u32::from_le_bytes(unsafe { ptr::read_volatile(base.add(offset).cast::<[u8; 4]>()) })
+    };
+    let count =
+        (read_u32(coreboot_payload::COUNT_OFFSET) as usize).min(coreboot_payload::MAX_MODULES);
+    (0..count).map(move |i| {
+        let offset = coreboot_payload::ENTRIES_OFFSET + i * coreboot_payload::ENTRY_SIZE;
+        let name = unsafe { &*base.add(offset).cast::<[u8; coreboot_payload::NAME_LEN]>() };
+        Module {
+            name: coreboot_payload::parse_name(name).unwrap_or(""),
+            start: read_u32(offset + coreboot_payload::NAME_LEN),
+            len: read_u32(offset + coreboot_payload::NAME_LEN + 4),
+        }
+    })
+}
+
+/// A parsed coreboot table.
+pub struct Table {
+    header: Header,
+    addr: u32,
+}
+
+impl Table {
+    /// Looks up the coreboot table at the given address, falling back to searching the
+    /// memory ranges that coreboot places it in.
+    ///
+    /// Forward records are followed.
+    ///
+    /// # Safety
+    ///
+    /// The given address must be zero or readable.
+    pub unsafe fn find(addr: u32) -> Option<Self> {
+        let table = unsafe { Self::read(addr) }.or_else(|| {
+            SEARCH_RANGES.iter().find_map(|&(start, end)| {
+                (start..end)
+                    .step_by(16)
+                    .find_map(|addr| unsafe { Self::read(addr) })
+            })
+        })?;
+        match table
+            .records()
+            .find(|&(record, _)| record.tag == TAG_FORWARD)
+        {
+            Some((_, addr)) => {
+                let forward = unsafe { ptr::read_unaligned((addr + 8) as *const u64) };
+                unsafe { Self::read(u32::try_from(forward).ok()?) }
+            }
+            None => Some(table),
+        }
+    }
+
+    unsafe fn read(addr: u32) -> Option<Self> {
+        if addr == 0 {
+            return None;
+        }
+        let header = unsafe { ptr::read_unaligned(addr as *const Header) };
+        let valid = header.signature == HEADER_SIGNATURE
+            && header.header_bytes as usize == core::mem::size_of::<Header>();
+        valid.then_some(Self { header, addr })
+    }
+
+    /// Returns the records of the table, together with their addresses.
+    fn records(&self) -> impl Iterator<Item = (Record, u32)> {
+        let start = self.addr + self.header.header_bytes;
+        let end = start + self.header.table_bytes;
+        let mut addr = start;
+        let mut remaining = self.header.table_entries;
+        core::iter::from_fn(move || {
+            if remaining == 0 || addr + 8 > end {
+                return None;
+            }
+            let record = unsafe { ptr::read_unaligned(addr as *const Record) };
+            if record.size < 8 {
+                return None;
+            }
+            let item = (record, addr);
+            addr += record.size;
+            remaining -= 1;
+            Some(item)
+        })
+    }
+
+    /// Returns the entries of the memory map.
+    pub fn memory_map(&self) -> impl Iterator<Item = MemoryMapEntry> + '_ {
+        self.records()
+            .filter(|(record, _)| record.tag == TAG_MEMORY)
+            .flat_map(|(record, addr)| {
+                let count = (record.size as usize - 8) / core::mem::size_of::<MemoryRange>();
+                let ranges = (addr + 8) as *const MemoryRange;
+                (0..count).map(move |i| {
+                    let range = unsafe { ptr::read_unaligned(ranges.add(i)) };
+                    MemoryMapEntry {
+                        start: range.start,
+                        len: range.size,
+                        region_type: e820_type(range.range_type),
+                    }
+                })
+            })
+    }
+}
+
+/// Converts a coreboot memory type to the corresponding E820 type.
+fn e820_type(coreboot_type: u32) -> u32 {
+    match coreboot_type {
+        // RAM, reserved, ACPI reclaimable, ACPI NVS, and unusable use the E820 values
+        1..=5 => coreboot_type,
+        // the memory that contains the coreboot tables must be kept intact
+        _ => 2,
+    }
+}
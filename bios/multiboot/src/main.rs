@@ -0,0 +1,302 @@
+//! An alternative to the first two BIOS stages that can be loaded by Multiboot and Multiboot2
+//! boot loaders, e.g. using QEMU's `-kernel` argument or GRUB, or by coreboot as a payload.
+//!
+//! The third and fourth stage, the kernel, and the optional ramdisk and config file are
+//! passed as multiboot modules or stored in the coreboot payload. This stage copies them to
+//! the locations where the second stage would load them and then jumps to the third stage.
+
+#![no_std]
+#![no_main]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use crate::serial::Writer;
+use bootloader_boot_config::default_kernel;
+use bootloader_x86_64_bios_common::{
+    hlt, BiosFramebufferInfo, BiosInfo, E820MemoryRegion, PixelFormat, Region,
+};
+use core::{arch::global_asm, fmt::Write as _, mem, ptr, slice};
+
+mod config;
+mod coreboot;
+mod multiboot;
+mod multiboot2;
+mod serial;
+
+// must match the start address in bios/stage-3/stage-3-link.ld
+const STAGE_3_DST: u64 = 0x0010_0000;
+// must match the start address in bios/stage-4/stage-4-link.ld
+const STAGE_4_DST: u64 = 0x0013_0000;
+// must match the start address in bios/multiboot/multiboot-link.ld
+const MULTIBOOT_START: u64 = 0x0200_0000;
+
+const PAGE_SIZE: u64 = 4096;
+/// The E820 memory type of usable RAM.
+const USABLE: u32 = 1;
+/// The maximum number of memory map entries that are passed to stage 4.
+const MAX_MEMORY_MAP_ENTRIES: usize = 64;
+
+#[used]
+#[link_section = ".multiboot_header"]
+static MULTIBOOT_HEADER: multiboot::Header = multiboot::Header::new();
+
+#[used]
+#[link_section = ".multiboot_header"]
+static MULTIBOOT2_HEADER: multiboot2::Header = multiboot2::Header::new();
+
+// The boot loader doesn't set up a stack, so we use the lower memory below 0x7c00 like the
+// BIOS stages. This memory is never used by the frame allocator of stage 4. Coreboot passes
+// the address of its tables as the first argument on its own stack instead of setting `eax`.
+global_asm!(
+    r#"
+    .section .start, "ax"
+    .global _start
+    _start:
+        xor ecx, ecx
+        cmp eax, {magic}
+        je 1f
+        cmp eax, {magic2}
+        je 1f
+        mov ecx, [esp + 4]
+    1:
+        mov esp, 0x7c00
+        push ecx
+        push ebx
+        push eax
+        call {main}
+    2:
+        hlt
+        jmp 2b
+    "#,
+    magic = const multiboot::BOOTLOADER_MAGIC,
+    magic2 = const multiboot2::BOOTLOADER_MAGIC,
+    main = sym multiboot_main,
+);
+
+/// A file that was passed as multiboot(2) module or stored in the coreboot payload.
+#[derive(Clone, Copy)]
+pub struct Module {
+    pub name: &'static str,
+    pub start: u32,
+    pub len: u32,
+}
+
+impl Module {
+    /// # Safety
+    ///
+    /// The module memory must not be modified while the returned slice is in use.
+    pub unsafe fn data(&self) -> &'static [u8] {
+        unsafe { slice::from_raw_parts(self.start as *const u8, self.len as usize) }
+    }
+}
+
+extern "C" fn multiboot_main(magic: u32, info_addr: u32, coreboot_table: u32) -> ! {
+    let info =
+        (magic == multiboot::BOOTLOADER_MAGIC).then(|| unsafe { multiboot::Info::read(info_addr) });
+    let info2 = (magic == multiboot2::BOOTLOADER_MAGIC)
+        .then(|| unsafe { multiboot2::Info::new(info_addr) });
+
+    let mut memory_map = [E820MemoryRegion {
+        start_addr: 0,
+        len: 0,
+        region_type: 0,
+        acpi_extended_attributes: 0,
+    }; MAX_MEMORY_MAP_ENTRIES];
+    let mut memory_map_len = 0;
+    let mut add_region = |start_addr, len, region_type| {
+        let region = memory_map
+            .get_mut(memory_map_len)
+            .expect("too many memory map entries");
+        *region = E820MemoryRegion {
+            start_addr,
+            len,
+            region_type,
+            acpi_extended_attributes: 0,
+        };
+        memory_map_len += 1;
+    };
+    match (&info, &info2) {
+        (Some(info), _) => {
+            for entry in info.memory_map() {
+                add_region(entry.base_addr, entry.length, entry.region_type);
+            }
+        }
+        (None, Some(info2)) => {
+            for (start, len, region_type) in info2.memory_map() {
+                add_region(start, len, region_type);
+            }
+        }
+        (None, None) => {
+            let table = unsafe { coreboot::Table::find(coreboot_table) }
+                .expect("not loaded by a multiboot(2) boot loader or by coreboot");
+            for entry in table.memory_map() {
+                add_region(entry.start, entry.len, entry.region_type);
+            }
+        }
+    }
+    let memory_map = &memory_map[..memory_map_len];
+    assert!(!memory_map.is_empty(), "no memory map provided");
+
+    let modules = || {
+        let multiboot_modules = info.iter().flat_map(|info| info.modules()).map(|m| Module {
+            name: m.name(),
+            start: m.start,
+            len: m.len(),
+        });
+        let multiboot2_modules = info2.iter().flat_map(|info2| info2.modules());
+        let coreboot_modules = (info.is_none() && info2.is_none())
+            .then(coreboot::modules)
+            .into_iter()
+            .flatten();
+        multiboot_modules
+            .chain(multiboot2_modules)
+            .chain(coreboot_modules)
+    };
+    let module = |name| modules().find(|m| m.name == name);
+    let config_file = module("boot.json");
+    let stage_4 = module("boot-stage-4").expect("boot-stage-4 module not found");
+    // don't touch the serial port before we know whether serial logging is enabled
+    serial::set_enabled(config::serial_logging(
+        unsafe { stage_4.data() },
+        config_file.map(|m| unsafe { m.data() }),
+    ));
+    writeln!(Writer, "Multiboot stage").unwrap();
+
+    let stage_3 = module("boot-stage-3").expect("boot-stage-3 module not found");
+    let kernel_name = match module(default_kernel::FILE_NAME) {
+        Some(m) => default_kernel::parse(unsafe { m.data() })
+            .unwrap_or_else(|| panic!("invalid {} module", default_kernel::FILE_NAME)),
+        None => "kernel-x86_64",
+    };
+    let kernel = module(kernel_name).unwrap_or_else(|| panic!("{kernel_name} module not found"));
+    let ramdisk = module("ramdisk");
+
+    let len = |module: Option<Module>| module.map_or(0, |m| u64::from(m.len));
+    assert!(
+        STAGE_3_DST + len(Some(stage_3)) <= STAGE_4_DST,
+        "boot-stage-3 is too large"
+    );
+
+    // place the kernel, the ramdisk, and the config file behind stage 4, like the second
+    // stage does, followed by the boot info and the memory map
+    let stage_4_end = align_up(STAGE_4_DST + len(Some(stage_4)), PAGE_SIZE);
+    let kernel_dst = stage_4_end;
+    let ramdisk_dst = kernel_dst + align_up(len(Some(kernel)), PAGE_SIZE);
+    let config_file_dst = ramdisk_dst + len(ramdisk);
+    let info_dst = align_up(config_file_dst + len(config_file), 8);
+    let memory_map_dst = info_dst + mem::size_of::<BiosInfo>() as u64;
+    let end = memory_map_dst + mem::size_of_val(memory_map) as u64;
+
+    assert!(
+        end <= MULTIBOOT_START,
+        "the stages, the kernel, the ramdisk, and the config file don't fit below \
+        {MULTIBOOT_START:#x} ({end:#x} bytes required)"
+    );
+    assert!(
+        usable_region_end(memory_map, STAGE_3_DST) >= end,
+        "not enough usable memory at {STAGE_3_DST:#x} ({end:#x} bytes required)"
+    );
+    // the modules are loaded or stored behind this executable, so copying them can't
+    // overwrite them
+    for module in modules() {
+        assert!(
+            u64::from(module.start) >= MULTIBOOT_START,
+            "module {} was loaded at {:#x}, below this executable",
+            module.name,
+            module.start
+        );
+    }
+
+    let copy = |module: Option<Module>, dst: u64| {
+        if let Some(module) = module {
+            let data = unsafe { module.data() };
+            unsafe { ptr::copy(data.as_ptr(), dst as *mut u8, data.len()) };
+            writeln!(Writer, "{} loaded at {dst:#x}", module.name).unwrap();
+        }
+    };
+    copy(Some(stage_3), STAGE_3_DST);
+    copy(Some(stage_4), STAGE_4_DST);
+    copy(Some(kernel), kernel_dst);
+    copy(ramdisk, ramdisk_dst);
+    copy(config_file, config_file_dst);
+
+    let memory_map_copy = memory_map_dst as *mut E820MemoryRegion;
+    unsafe {
+        ptr::copy_nonoverlapping(memory_map.as_ptr(), memory_map_copy, memory_map.len());
+    }
+
+    // only multiboot2 boot loaders report the graphics mode that they set up
+    let framebuffer = info2
+        .and_then(|info2| info2.framebuffer())
+        .unwrap_or(BiosFramebufferInfo {
+            region: Region { start: 0, len: 0 },
+            width: 0,
+            height: 0,
+            bytes_per_pixel: 0,
+            stride: 0,
+            pixel_format: PixelFormat::Rgb,
+            refresh_rate: 0,
+        });
+
+    let bios_info = info_dst as *mut BiosInfo;
+    unsafe {
+        bios_info.write(BiosInfo {
+            stage_4: Region {
+                start: STAGE_4_DST,
+                len: len(Some(stage_4)),
+            },
+            kernel: Region {
+                start: kernel_dst,
+                len: len(Some(kernel)),
+            },
+            ramdisk: Region {
+                start: ramdisk_dst,
+                len: len(ramdisk),
+            },
+            config_file: Region {
+                start: config_file_dst,
+                len: len(config_file),
+            },
+            last_used_addr: end - 1,
+            framebuffer,
+            memory_map_addr: memory_map_dst as u32,
+            memory_map_len: memory_map.len() as u16,
+        })
+    };
+
+    writeln!(Writer, "jumping to stage 3").unwrap();
+    let stage_3_entry: extern "C" fn(&mut BiosInfo) =
+        unsafe { mem::transmute(STAGE_3_DST as usize) };
+    stage_3_entry(unsafe { &mut *bios_info });
+
+    loop {
+        hlt();
+    }
+}
+
+/// Returns the end address of the usable memory that starts at `addr`.
+///
+/// Adjacent usable regions of the memory map are merged.
+fn usable_region_end(memory_map: &[E820MemoryRegion], addr: u64) -> u64 {
+    let mut end = addr;
+    while let Some(region) = memory_map.iter().find(|region| {
+        region.region_type == USABLE
+            && region.start_addr <= end
+            && end < region.start_addr + region.len
+    }) {
+        end = region.start_addr + region.len;
+    }
+    end
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+#[panic_handler]
+#[cfg(not(test))]
+pub fn panic(info: &core::panic::PanicInfo) -> ! {
+    let _ = writeln!(Writer, "PANIC: {info}");
+    loop {
+        hlt();
+    }
+}
@@ -0,0 +1,162 @@
+//! Data structures of the Multiboot (version 1) specification.
+
+use core::{ptr, slice, str};
+
+/// The magic value that identifies the multiboot header.
+const HEADER_MAGIC: u32 = 0x1BAD_B002;
+/// The magic value that the boot loader passes in `eax`.
+pub const BOOTLOADER_MAGIC: u32 = 0x2BAD_B002;
+
+/// Requests that all modules are loaded at page-aligned addresses.
+const HEADER_FLAG_PAGE_ALIGN: u32 = 1 << 0;
+/// Requests the memory map in the multiboot information structure.
+const HEADER_FLAG_MEMORY_INFO: u32 = 1 << 1;
+
+/// Signals that the `mods_*` fields of [`Info`] are valid.
+const INFO_FLAG_MODULES: u32 = 1 << 3;
+/// Signals that the `mmap_*` fields of [`Info`] are valid.
+const INFO_FLAG_MEMORY_MAP: u32 = 1 << 6;
+
+/// The multiboot header, which must be placed in the first 8 KiB of the executable.
+#[repr(C, align(4))]
+pub struct Header {
+    magic: u32,
+    flags: u32,
+    checksum: u32,
+}
+
+impl Header {
+    pub const fn new() -> Self {
+        let flags = HEADER_FLAG_PAGE_ALIGN | HEADER_FLAG_MEMORY_INFO;
+        Self {
+            magic: HEADER_MAGIC,
+            flags,
+            checksum: 0u32.wrapping_sub(HEADER_MAGIC).wrapping_sub(flags),
+        }
+    }
+}
+
+/// The multiboot information structure that is passed in `ebx`.
+///
+/// Only the fields up to the memory map are declared.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct Info {
+    flags: u32,
+    _mem_lower: u32,
+    _mem_upper: u32,
+    _boot_device: u32,
+    _cmdline: u32,
+    mods_count: u32,
+    mods_addr: u32,
+    _syms: [u32; 4],
+    mmap_length: u32,
+    mmap_addr: u32,
+}
+
+impl Info {
+    /// # Safety
+    ///
+    /// The given address must point to a valid multiboot information structure.
+    pub unsafe fn read(addr: u32) -> Self {
+        unsafe { ptr::read_unaligned(addr as *const Self) }
+    }
+
+    /// Returns the modules that were loaded by the boot loader.
+    pub fn modules(&self) -> impl Iterator<Item = Module> {
+        let count = if self.flags & INFO_FLAG_MODULES != 0 {
+            self.mods_count
+        } else {
+            0
+        };
+        let addr = self.mods_addr as *const RawModule;
+        (0..count as usize).map(move |i| {
+            let raw = unsafe { ptr::read_unaligned(addr.add(i)) };
+            Module {
+                start: raw.mod_start,
+                end: raw.mod_end,
+                string: raw.string,
+            }
+        })
+    }
+
+    /// Returns the entries of the BIOS memory map.
+    pub fn memory_map(&self) -> impl Iterator<Item = MemoryMapEntry> {
+        let (mut addr, end) = if self.flags & INFO_FLAG_MEMORY_MAP != 0 {
+            (self.mmap_addr, self.mmap_addr + self.mmap_length)
+        } else {
+            (0, 0)
+        };
+        core::iter::from_fn(move || {
+            if addr >= end {
+                return None;
+            }
+            // the `size` field precedes the entry and doesn't include itself
+            let size = unsafe { ptr::read_unaligned(addr as *const u32) };
+            let entry = unsafe { ptr::read_unaligned((addr + 4) as *const MemoryMapEntry) };
+            addr += size + 4;
+            Some(entry)
+        })
+    }
+}
+
+#[repr(C, packed)]
+struct RawModule {
+    mod_start: u32,
+    mod_end: u32,
+    string: u32,
+    _reserved: u32,
+}
+
+/// A module that was loaded by the boot loader.
+#[derive(Clone, Copy)]
+pub struct Module {
+    pub start: u32,
+    /// The exclusive end address.
+    pub end: u32,
+    string: u32,
+}
+
+impl Module {
+    pub fn len(&self) -> u32 {
+        self.end - self.start
+    }
+
+    /// Returns the file name of the module, see [`module_name`].
+    pub fn name(&self) -> &'static str {
+        if self.string == 0 {
+            return "";
+        }
+        let string = unsafe {
+            let start = self.string as *const u8;
+            let mut len = 0;
+            while *start.add(len) != 0 {
+                len += 1;
+            }
+            slice::from_raw_parts(start, len)
+        };
+        module_name(string)
+    }
+}
+
+/// Returns the file name of a module, given its module string.
+///
+/// The module string typically contains the module path followed by optional arguments,
+/// so this returns the last path component of the first word.
+pub fn module_name(string: &'static [u8]) -> &'static str {
+    let path = str::from_utf8(string)
+        .unwrap_or("")
+        .split_whitespace()
+        .next()
+        .unwrap_or("");
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// An entry of the memory map in the E820 format.
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct MemoryMapEntry {
+    pub base_addr: u64,
+    pub length: u64,
+    pub region_type: u32,
+}
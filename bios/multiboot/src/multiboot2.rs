@@ -0,0 +1,246 @@
+//! Data structures of the Multiboot2 specification.
+//!
+//! The multiboot2 header is placed next to the multiboot header, so that the stage can be
+//! loaded through both versions, e.g. through the `multiboot` and `multiboot2` commands of
+//! GRUB.
+
+use crate::Module;
+use bootloader_x86_64_bios_common::{BiosFramebufferInfo, PixelFormat, Region};
+use core::{ptr, slice};
+
+/// The magic value that identifies the multiboot2 header.
+const HEADER_MAGIC: u32 = 0xE852_50D6;
+/// The magic value that the boot loader passes in `eax`.
+pub const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+/// The architecture field of the header for 32-bit protected mode on x86.
+const ARCHITECTURE_I386: u32 = 0;
+
+/// Marks a header tag as optional, i.e. the boot loader may ignore it.
+const HEADER_TAG_OPTIONAL: u16 = 1;
+const HEADER_TAG_END: u16 = 0;
+const HEADER_TAG_FRAMEBUFFER: u16 = 5;
+const HEADER_TAG_MODULE_ALIGN: u16 = 6;
+
+const INFO_TAG_END: u32 = 0;
+const INFO_TAG_MODULE: u32 = 3;
+const INFO_TAG_MEMORY_MAP: u32 = 6;
+const INFO_TAG_FRAMEBUFFER: u32 = 8;
+
+/// The framebuffer type for direct RGB color.
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+/// The multiboot2 header, which must be placed in the first 32 KiB of the executable.
+///
+/// It requests page-aligned modules and, optionally, a linear framebuffer in the boot loader's
+/// preferred resolution.
+#[repr(C, align(8))]
+pub struct Header {
+    magic: u32,
+    architecture: u32,
+    header_length: u32,
+    checksum: u32,
+    framebuffer: FramebufferTag,
+    _framebuffer_padding: u32,
+    module_align: Tag,
+    end: Tag,
+}
+
+#[repr(C)]
+struct Tag {
+    tag_type: u16,
+    flags: u16,
+    size: u32,
+}
+
+#[repr(C)]
+struct FramebufferTag {
+    tag: Tag,
+    width: u32,
+    height: u32,
+    depth: u32,
+}
+
+impl Header {
+    pub const fn new() -> Self {
+        let header_length = core::mem::size_of::<Self>() as u32;
+        Self {
+            magic: HEADER_MAGIC,
+            architecture: ARCHITECTURE_I386,
+            header_length,
+            checksum: 0u32
+                .wrapping_sub(HEADER_MAGIC)
+                .wrapping_sub(ARCHITECTURE_I386)
+                .wrapping_sub(header_length),
+            framebuffer: FramebufferTag {
+                tag: Tag {
+                    tag_type: HEADER_TAG_FRAMEBUFFER,
+                    flags: HEADER_TAG_OPTIONAL,
+                    size: core::mem::size_of::<FramebufferTag>() as u32,
+                },
+                // no preference
+                width: 0,
+                height: 0,
+                depth: 0,
+            },
+            // tags are 8-byte aligned
+            _framebuffer_padding: 0,
+            module_align: Tag {
+                tag_type: HEADER_TAG_MODULE_ALIGN,
+                flags: 0,
+                size: core::mem::size_of::<Tag>() as u32,
+            },
+            end: Tag {
+                tag_type: HEADER_TAG_END,
+                flags: 0,
+                size: core::mem::size_of::<Tag>() as u32,
+            },
+        }
+    }
+}
+
+/// The multiboot2 boot information that is passed in `ebx`.
+#[derive(Clone, Copy)]
+pub struct Info {
+    addr: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct InfoTag {
+    tag_type: u32,
+    size: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct MemoryMapEntry {
+    base_addr: u64,
+    length: u64,
+    region_type: u32,
+    _reserved: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+struct FramebufferInfo {
+    addr: u64,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    bpp: u8,
+    framebuffer_type: u8,
+    _reserved: u16,
+    red_position: u8,
+    _red_mask_size: u8,
+    green_position: u8,
+    _green_mask_size: u8,
+    blue_position: u8,
+    _blue_mask_size: u8,
+}
+
+impl Info {
+    /// # Safety
+    ///
+    /// The given address must point to a valid multiboot2 boot information structure.
+    pub unsafe fn new(addr: u32) -> Self {
+        Self { addr }
+    }
+
+    /// Returns the tags of the boot information, together with their addresses.
+    fn tags(&self) -> impl Iterator<Item = (InfoTag, u32)> {
+        let total_size = unsafe { ptr::read_unaligned(self.addr as *const u32) };
+        let end = self.addr + total_size;
+        // the tags start behind the `total_size` and `reserved` fields
+        let mut addr = self.addr + 8;
+        core::iter::from_fn(move || {
+            if addr + 8 > end {
+                return None;
+            }
+            let tag = unsafe { ptr::read_unaligned(addr as *const InfoTag) };
+            if tag.tag_type == INFO_TAG_END || tag.size < 8 {
+                return None;
+            }
+            let item = (tag, addr);
+            // tags are padded to 8 bytes
+            addr += (tag.size + 7) & !7;
+            Some(item)
+        })
+    }
+
+    /// Returns the modules that were loaded by the boot loader.
+    pub fn modules(&self) -> impl Iterator<Item = Module> {
+        self.tags()
+            .filter(|(tag, _)| tag.tag_type == INFO_TAG_MODULE)
+            .map(|(tag, addr)| {
+                let start = unsafe { ptr::read_unaligned((addr + 8) as *const u32) };
+                let end = unsafe { ptr::read_unaligned((addr + 12) as *const u32) };
+                // the null-terminated string is stored inline
+                let string = unsafe {
+                    slice::from_raw_parts((addr + 16) as *const u8, (tag.size - 16) as usize)
+                };
+                let len = string.iter().position(|&b| b == 0).unwrap_or(string.len());
+                Module {
+                    name: crate::multiboot::module_name(&string[..len]),
+                    start,
+                    len: end - start,
+                }
+            })
+    }
+
+    /// Returns the entries of the memory map as `(start, length, E820 type)` tuples.
+    pub fn memory_map(&self) -> impl Iterator<Item = (u64, u64, u32)> {
+        self.tags()
+            .filter(|(tag, _)| tag.tag_type == INFO_TAG_MEMORY_MAP)
+            .flat_map(|(tag, addr)| {
+                let entry_size = unsafe { ptr::read_unaligned((addr + 8) as *const u32) };
+                let count = match entry_size {
+                    0 => 0,
+                    size => (tag.size - 16) / size,
+                };
+                (0..count).map(move |i| {
+                    let entry = unsafe {
+                        ptr::read_unaligned((addr + 16 + i * entry_size) as *const MemoryMapEntry)
+                    };
+                    // the memory types match the E820 types
+                    (entry.base_addr, entry.length, entry.region_type)
+                })
+            })
+    }
+
+    /// Returns the linear framebuffer that was set up by the boot loader, if any.
+    ///
+    /// Text mode and indexed color framebuffers are ignored.
+    pub fn framebuffer(&self) -> Option<BiosFramebufferInfo> {
+        let (tag, addr) = self
+            .tags()
+            .find(|(tag, _)| tag.tag_type == INFO_TAG_FRAMEBUFFER)?;
+        if (tag.size as usize) < 8 + core::mem::size_of::<FramebufferInfo>() {
+            return None;
+        }
+        let info = unsafe { ptr::read_unaligned((addr + 8) as *const FramebufferInfo) };
+        let bytes_per_pixel = info.bpp / 8;
+        if info.framebuffer_type != FRAMEBUFFER_TYPE_RGB || bytes_per_pixel == 0 {
+            return None;
+        }
+        Some(BiosFramebufferInfo {
+            region: Region {
+                start: info.addr,
+                len: u64::from(info.pitch) * u64::from(info.height),
+            },
+            width: info.width.try_into().ok()?,
+            height: info.height.try_into().ok()?,
+            bytes_per_pixel,
+            stride: (info.pitch / u32::from(bytes_per_pixel)).try_into().ok()?,
+            pixel_format: match (info.red_position, info.green_position, info.blue_position) {
+                (0, 8, 16) => PixelFormat::Rgb,
+                (16, 8, 0) => PixelFormat::Bgr,
+                (red_position, green_position, blue_position) => PixelFormat::Unknown {
+                    red_position,
+                    green_position,
+                    blue_position,
+                },
+            },
+            refresh_rate: 0,
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use core::{
+    arch::asm,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The I/O port base of the first serial port (`COM1`).
+const PORT: u16 = 0x3F8;
+
+/// Whether the serial port may be accessed.
+///
+/// Disabled until the boot config was checked, so that the port is never touched if
+/// serial logging is disabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Allows or forbids accesses to the serial port.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Writes text to the first serial port, which was already initialized by the firmware.
+///
+/// All output is discarded while the serial port is disabled through [`set_enabled`].
+pub struct Writer;
+
+impl Writer {
+    fn write_byte(&mut self, byte: u8) {
+        // wait until the transmit buffer is empty
+        while unsafe { inb(PORT + 5) } & 0x20 == 0 {}
+        unsafe { outb(PORT, byte) };
+    }
+}
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    unsafe { asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack)) };
+    value
+}
+
+unsafe fn outb(port: u16, value: u8) {
+    unsafe { asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack)) };
+}
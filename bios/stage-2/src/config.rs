@@ -0,0 +1,149 @@
+use crate::protected_mode::read_from_protected_mode;
+use bootloader_api::BootloaderConfig;
+use bootloader_boot_config::{
+    embedded,
+    scan::{self, Json},
+    DEFAULT_DISK_READ_RETRIES,
+};
+use core::ops::Range;
+
+/// A file that was loaded into protected mode memory.
+///
+/// The second stage runs in real mode, so it can only access these files byte by byte.
+#[derive(Clone, Copy)]
+pub struct LoadedFile {
+    start: *mut u8,
+    len: usize,
+}
+
+impl LoadedFile {
+    pub fn new(start: *mut u8, len: u64) -> Self {
+        Self {
+            start,
+            len: usize::try_from(len).unwrap(),
+        }
+    }
+
+    /// Returns the given part of the file, clamped to the file length.
+    fn slice(&self, range: Range<usize>) -> Self {
+        let start = range.start.min(self.len);
+        Self {
+            start: self.start.wrapping_add(start),
+            len: range.end.clamp(start, self.len) - start,
+        }
+    }
+
+    fn read<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let mut bytes = [0; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.byte(offset.checked_add(i)?)?;
+        }
+        Some(bytes)
+    }
+
+    fn read_usize<const N: usize>(&self, offset: usize) -> Option<usize> {
+        let mut bytes = [0; 8];
+        bytes[..N].copy_from_slice(&self.read::<N>(offset)?);
+        usize::try_from(u64::from_le_bytes(bytes)).ok()
+    }
+}
+
+impl scan::Json for LoadedFile {
+    fn byte(&self, offset: usize) -> Option<u8> {
+        (offset < self.len)
+            .then(|| unsafe { read_from_protected_mode(self.start.wrapping_add(offset)) })
+    }
+}
+
+/// The size limits for the VESA mode selection.
+pub struct FrameBufferLimits {
+    pub min_width: u16,
+    pub min_height: u16,
+    pub max_width: u16,
+    pub max_height: u16,
+}
+
+impl FrameBufferLimits {
+    /// Looks up the limits in the boot config.
+    ///
+    /// Like stage 4, a config that is embedded into the stage 4 binary takes precedence over
+    /// the `boot.json` file, and the minimum sizes fall back to the values that the kernel
+    /// specified at compile time.
+    pub fn new(stage_4: &LoadedFile, kernel: &LoadedFile, config_file: &LoadedFile) -> Self {
+        let json = &boot_config(stage_4, config_file);
+        #[allow(deprecated)]
+        let kernel_minimum = kernel_config(kernel).map(|config| {
+            let frame_buffer = config.frame_buffer;
+            let width = frame_buffer.minimum_framebuffer_width;
+            (width, frame_buffer.minimum_framebuffer_height)
+        });
+        let (kernel_width, kernel_height) = kernel_minimum.unwrap_or_default();
+
+        let value = |key, default: Option<u64>, fallback| {
+            scan::find_number(json, key)
+                .or(default)
+                .map_or(fallback, |v| u16::try_from(v).unwrap_or(u16::MAX))
+        };
+        Self {
+            min_width: value("minimum_framebuffer_width", kernel_width, 0),
+            min_height: value("minimum_framebuffer_height", kernel_height, 0),
+            max_width: value("maximum_framebuffer_width", None, 1280),
+            max_height: value("maximum_framebuffer_height", None, 720),
+        }
+    }
+}
+
+/// Looks up the `failure_signals` option in the boot config.
+pub fn failure_signals(stage_4: &LoadedFile, config_file: &LoadedFile) -> bool {
+    scan::find_bool(&boot_config(stage_4, config_file), "failure_signals").unwrap_or(false)
+}
+
+/// Looks up the `disk_read_retries` option in the boot config.
+pub fn disk_read_retries(stage_4: &LoadedFile, config_file: &LoadedFile) -> u16 {
+    scan::find_number(&boot_config(stage_4, config_file), "disk_read_retries")
+        .map_or(DEFAULT_DISK_READ_RETRIES, |v| {
+            u16::try_from(v).unwrap_or(u16::MAX)
+        })
+}
+
+/// Returns the boot config that applies.
+///
+/// Like in stage 4, a config that is embedded into the stage 4 binary takes precedence over
+/// the `boot.json` file.
+fn boot_config(stage_4: &LoadedFile, config_file: &LoadedFile) -> LoadedFile {
+    embedded::find(stage_4).map_or(*config_file, |range| stage_4.slice(range))
+}
+
+/// Reads the config from the `.bootloader-config` section of the given ELF kernel.
+///
+/// Returns `None` if the kernel is no valid ELF file, e.g. a flat binary, or if it doesn't
+/// contain the section.
+fn kernel_config(kernel: &LoadedFile) -> Option<BootloaderConfig> {
+    const SECTION_NAME: [u8; 19] = *b".bootloader-config\0";
+
+    if kernel.read::<5>(0)? != *b"\x7fELF\x02" {
+        return None;
+    }
+    let section_headers = kernel.read_usize::<8>(0x28)?;
+    let header_size = kernel.read_usize::<2>(0x3a)?;
+    let header_count = kernel.read_usize::<2>(0x3c)?;
+    let header = |index: usize| section_headers.checked_add(index.checked_mul(header_size)?);
+    let names_header = header(kernel.read_usize::<2>(0x3e)?)?;
+    let names = kernel.read_usize::<8>(names_header.checked_add(0x18)?)?;
+
+    for index in 0..header_count {
+        let header = header(index)?;
+        let name = names.checked_add(kernel.read_usize::<4>(header)?)?;
+        if kernel.read(name) != Some(SECTION_NAME) {
+            continue;
+        }
+        let offset = kernel.read_usize::<8>(header.checked_add(0x18)?)?;
+        let len = kernel.read_usize::<8>(header.checked_add(0x20)?)?;
+        if len != BootloaderConfig::SERIALIZED_LEN {
+            return None;
+        }
+        let serialized = kernel.read::<{ BootloaderConfig::SERIALIZED_LEN }>(offset)?;
+        return BootloaderConfig::deserialize(&serialized).ok();
+    }
+    None
+}
@@ -35,22 +35,38 @@ impl DiskAddressPacket {
         }
     }
 
-    pub unsafe fn perform_load(&self, disk_number: u16) {
+    /// Loads the sectors described by this packet using the extended read function of
+    /// BIOS interrupt `0x13`.
+    ///
+    /// On failure, returns the status code that the BIOS reported in `AH`.
+    pub unsafe fn perform_load(&self, disk_number: u16) -> Result<(), u8> {
         let self_addr = self as *const Self as u16;
+        let status: u16;
+        let failed: u8;
         asm!(
-            "push 'z'", // error code `z`, passed to `fail` on error
             "mov {1:x}, si",
             "mov si, {0:x}",
             "int 0x13",
-            "jnc 2f", // carry is set on fail
-            "call fail",
-            "2:",
-            "pop si", // remove error code again
+            "setc {2}", // carry is set on fail
             "mov si, {1:x}",
             in(reg) self_addr,
             out(reg) _,
-            in("ax") 0x4200u16,
+            out(reg_byte) failed,
+            inout("ax") 0x4200u16 => status,
             in("dx") disk_number,
         );
+        match failed {
+            0 => Ok(()),
+            _ => Err((status >> 8) as u8),
+        }
     }
 }
+
+/// Resets the disk system of the given disk, which recalibrates the drive after a failed read.
+pub unsafe fn reset_disk(disk_number: u16) {
+    asm!(
+        "int 0x13",
+        inout("ax") 0u16 => _,
+        in("dx") disk_number,
+    );
+}
@@ -1,10 +1,52 @@
 use crate::dap;
+use bootloader_boot_config::DISK_READ_RETRY_DELAY_MICROS;
+use core::arch::asm;
+
+/// The maximum number of sectors that are read in a single BIOS call.
+///
+/// This is the limit of the EDD specification, which many BIOS implementations enforce.
+const MAX_SECTORS_PER_READ: u64 = 127;
 
 #[derive(Clone)]
 pub struct DiskAccess {
     pub disk_number: u16,
     pub base_offset: u64,
     pub current_offset: u64,
+    /// The number of times a failed read is retried, see `BootConfig::disk_read_retries`.
+    pub read_retries: u16,
+}
+
+impl DiskAccess {
+    /// Performs the given disk read, retrying it after a short delay if it fails.
+    fn load(&self, dap: &dap::DiskAddressPacket) {
+        let mut attempt = 0;
+        loop {
+            match unsafe { dap.perform_load(self.disk_number) } {
+                Ok(()) => break,
+                Err(_) if attempt < self.read_retries => {
+                    attempt += 1;
+                    unsafe {
+                        dap::reset_disk(self.disk_number);
+                        wait(DISK_READ_RETRY_DELAY_MICROS.into());
+                    }
+                }
+                Err(status) => panic!(
+                    "disk read failed after {} attempts (status {status:#x})",
+                    attempt + 1
+                ),
+            }
+        }
+    }
+}
+
+/// Waits for the given number of microseconds using BIOS interrupt `0x15`.
+unsafe fn wait(micros: u32) {
+    asm!(
+        "int 0x15",
+        inout("ax") 0x8600u16 => _,
+        in("cx") (micros >> 16) as u16,
+        in("dx") micros as u16,
+    );
 }
 
 impl Read for DiskAccess {
@@ -34,16 +76,14 @@ impl Read for DiskAccess {
         let mut target_addr = buf.as_ptr_range().start as u32;
 
         loop {
-            let sectors = u64::min(number_of_sectors, 32) as u16;
+            let sectors = u64::min(number_of_sectors, MAX_SECTORS_PER_READ) as u16;
             let dap = dap::DiskAddressPacket::from_lba(
                 start_lba,
                 sectors,
                 (target_addr & 0b1111) as u16,
                 (target_addr >> 4).try_into().unwrap(),
             );
-            unsafe {
-                dap.perform_load(self.disk_number);
-            }
+            self.load(&dap);
 
             start_lba += u64::from(sectors);
             number_of_sectors -= u64::from(sectors);
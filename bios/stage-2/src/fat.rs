@@ -1,7 +1,7 @@
 // based on https://crates.io/crates/mini_fat by https://github.com/gridbugs
 
 use crate::disk::{AlignedBuffer, Read, Seek, SeekFrom};
-use core::char::DecodeUtf16Error;
+use core::fmt;
 
 const DIRECTORY_ENTRY_BYTES: usize = 32;
 const UNUSED_ENTRY_PREFIX: u8 = 0xE5;
@@ -28,14 +28,18 @@ struct Bpb {
     fat_size_16: u16,
     total_sectors_32: u32,
     fat_size_32: u32,
-    _root_cluster: u32,
+    root_cluster: u32,
 }
 
 impl Bpb {
-    fn parse<D: Read + Seek>(disk: &mut D) -> Self {
+    fn parse<D: Read + Seek>(disk: &mut D) -> Result<Self, BpbError> {
         disk.seek(SeekFrom::Start(0));
         let raw = unsafe { disk.read_exact(512) };
 
+        if raw[510..512] != BOOT_SIGNATURE {
+            return Err(BpbError::MissingBootSignature);
+        }
+
         let bytes_per_sector = u16::from_le_bytes(raw[11..13].try_into().unwrap());
         let sectors_per_cluster = raw[13];
         let reserved_sector_count = u16::from_le_bytes(raw[14..16].try_into().unwrap());
@@ -58,10 +62,10 @@ impl Bpb {
             fat_size_32 = 0;
             root_cluster = 0;
         } else {
-            panic!("ExactlyOneTotalSectorsFieldMustBeZero");
+            return Err(BpbError::ExactlyOneTotalSectorsFieldMustBeZero);
         }
 
-        Self {
+        let bpb = Self {
             bytes_per_sector,
             sectors_per_cluster,
             reserved_sector_count,
@@ -71,7 +75,69 @@ impl Bpb {
             fat_size_16,
             total_sectors_32,
             fat_size_32,
-            _root_cluster: root_cluster,
+            root_cluster,
+        };
+        bpb.validate()?;
+        Ok(bpb)
+    }
+
+    /// Checks that the fields are consistent, so that the other methods don't compute
+    /// garbage offsets.
+    fn validate(&self) -> Result<(), BpbError> {
+        // the disk access code assumes 512-byte sectors
+        if self.bytes_per_sector != 512 {
+            return Err(BpbError::UnsupportedBytesPerSector(self.bytes_per_sector));
+        }
+        if !self.sectors_per_cluster.is_power_of_two() {
+            return Err(BpbError::InvalidSectorsPerCluster(self.sectors_per_cluster));
+        }
+        if self.reserved_sector_count == 0 {
+            return Err(BpbError::NoReservedSectors);
+        }
+        if self.num_fats == 0 {
+            return Err(BpbError::NoFats);
+        }
+        if (self.fat_size_16 == 0) == (self.fat_size_32 == 0) {
+            return Err(BpbError::ExactlyOneFatSizeFieldMustBeZero);
+        }
+        if (self.root_entry_count as usize * DIRECTORY_ENTRY_BYTES) % 512 != 0 {
+            return Err(BpbError::InvalidRootEntryCount(self.root_entry_count));
+        }
+
+        let root_dir_sectors = self.root_entry_count as u64 * DIRECTORY_ENTRY_BYTES as u64 / 512;
+        let metadata_sectors = self.reserved_sector_count as u64
+            + self.num_fats as u64 * self.fat_size_in_sectors() as u64
+            + root_dir_sectors;
+        if metadata_sectors >= self.total_sectors() as u64 {
+            return Err(BpbError::NoDataSectors);
+        }
+
+        // the FAT type is determined by the cluster count, so the type-specific fields must
+        // match it
+        match self.fat_type() {
+            FatType::Fat32 => {
+                if self.root_entry_count != 0 || self.fat_size_16 != 0 {
+                    return Err(BpbError::FatTypeMismatch(FatType::Fat32));
+                }
+                if !(2..=self.maximum_valid_cluster()).contains(&self.root_cluster) {
+                    return Err(BpbError::InvalidRootCluster(self.root_cluster));
+                }
+            }
+            fat_type @ (FatType::Fat12 | FatType::Fat16) => {
+                if self.root_entry_count == 0 || self.fat_size_16 == 0 {
+                    return Err(BpbError::FatTypeMismatch(fat_type));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn total_sectors(&self) -> u32 {
+        if self.total_sectors_16 != 0 {
+            self.total_sectors_16 as u32
+        } else {
+            self.total_sectors_32
         }
     }
 
@@ -88,12 +154,7 @@ impl Bpb {
         let root_dir_sectors = ((self.root_entry_count as u32 * 32)
             + (self.bytes_per_sector as u32 - 1))
             / self.bytes_per_sector as u32;
-        let total_sectors = if self.total_sectors_16 != 0 {
-            self.total_sectors_16 as u32
-        } else {
-            self.total_sectors_32
-        };
-        let data_sectors = total_sectors
+        let data_sectors = self.total_sectors()
             - (self.reserved_sector_count as u32
                 + (self.num_fats as u32 * self.fat_size_in_sectors())
                 + root_dir_sectors);
@@ -143,6 +204,60 @@ impl Bpb {
     }
 }
 
+/// The signature at the end of a valid boot sector.
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// A malformed FAT boot sector, reported by [`FileSystem::parse`].
+#[derive(Debug, Clone, Copy)]
+enum BpbError {
+    MissingBootSignature,
+    ExactlyOneTotalSectorsFieldMustBeZero,
+    ExactlyOneFatSizeFieldMustBeZero,
+    UnsupportedBytesPerSector(u16),
+    InvalidSectorsPerCluster(u8),
+    NoReservedSectors,
+    NoFats,
+    InvalidRootEntryCount(u16),
+    NoDataSectors,
+    FatTypeMismatch(FatType),
+    InvalidRootCluster(u32),
+}
+
+impl fmt::Display for BpbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingBootSignature => write!(f, "missing boot signature"),
+            Self::ExactlyOneTotalSectorsFieldMustBeZero => {
+                write!(f, "exactly one total sectors field must be zero")
+            }
+            Self::ExactlyOneFatSizeFieldMustBeZero => {
+                write!(f, "exactly one FAT size field must be zero")
+            }
+            Self::UnsupportedBytesPerSector(value) => {
+                write!(
+                    f,
+                    "unsupported bytes per sector value {value}, expected 512"
+                )
+            }
+            Self::InvalidSectorsPerCluster(value) => {
+                write!(f, "sectors per cluster value {value} is not a power of two")
+            }
+            Self::NoReservedSectors => write!(f, "reserved sector count is zero"),
+            Self::NoFats => write!(f, "number of FATs is zero"),
+            Self::InvalidRootEntryCount(value) => write!(
+                f,
+                "root entry count {value} doesn't fill a whole number of sectors"
+            ),
+            Self::NoDataSectors => write!(f, "file system has no data sectors"),
+            Self::FatTypeMismatch(fat_type) => write!(
+                f,
+                "root directory and FAT size fields don't match the {fat_type:?} cluster count"
+            ),
+            Self::InvalidRootCluster(value) => write!(f, "invalid root cluster {value}"),
+        }
+    }
+}
+
 pub struct FileSystem<D> {
     disk: D,
     bpb: Bpb,
@@ -150,50 +265,33 @@ pub struct FileSystem<D> {
 
 impl<D: Read + Seek> FileSystem<D> {
     pub fn parse(mut disk: D) -> Self {
-        Self {
-            bpb: Bpb::parse(&mut disk),
-            disk,
-        }
+        let bpb =
+            Bpb::parse(&mut disk).unwrap_or_else(|err| panic!("invalid FAT boot sector: {err}"));
+        Self { bpb, disk }
     }
 
-    pub fn find_file_in_root_dir(
-        &mut self,
-        name: &str,
-        buffer: &mut dyn AlignedBuffer,
-    ) -> Option<File> {
-        let mut root_entries = self.read_root_dir(buffer).filter_map(|e| e.ok());
-        let raw_entry = root_entries.find(|e| e.eq_name(name))?;
-
-        let entry = match raw_entry {
-            RawDirectoryEntry::Normal(entry) => DirectoryEntry {
-                short_name: entry.short_filename_main,
-                short_name_extension: entry.short_filename_extension,
-                long_name_1: &[],
-                long_name_2: &[],
-                long_name_3: &[],
-                file_size: entry.file_size,
-                first_cluster: entry.first_cluster,
-                attributes: entry.attributes,
-            },
-            RawDirectoryEntry::LongName(long_name) => match root_entries.next() {
-                Some(RawDirectoryEntry::LongName(_)) => unimplemented!(),
-                Some(RawDirectoryEntry::Normal(entry)) => DirectoryEntry {
-                    short_name: entry.short_filename_main,
-                    short_name_extension: entry.short_filename_extension,
-                    long_name_1: long_name.name_1,
-                    long_name_2: long_name.name_2,
-                    long_name_3: long_name.name_3,
-                    file_size: entry.file_size,
-                    first_cluster: entry.first_cluster,
-                    attributes: entry.attributes,
-                },
-                None => {
-                    panic!("next none");
-                }
-            },
-        };
+    /// Returns the disk that the file system reads from.
+    pub fn disk_mut(&mut self) -> &mut D {
+        &mut self.disk
+    }
 
-        if entry.is_directory() {
+    /// Looks up the file at the given path, e.g. `boot/kernel-x86_64`.
+    ///
+    /// The path components are separated by `/` and looked up starting at the root directory.
+    /// Each name is compared against the long file name of the directory entries, if they
+    /// have one, and against their 8.3 short name. Short names are compared
+    /// case-insensitively.
+    pub fn find_file(&mut self, path: &str, buffer: &mut dyn AlignedBuffer) -> Option<File> {
+        let mut components = path.split('/').filter(|name| !name.is_empty());
+        let mut entry = self.find_in_root_dir(components.next()?, buffer)?;
+        for name in components {
+            if !entry.is_directory {
+                return None;
+            }
+            entry = self.find_in_dir(entry.first_cluster, name, buffer)?;
+        }
+
+        if entry.is_directory {
             None
         } else {
             Some(File {
@@ -203,15 +301,13 @@ impl<D: Read + Seek> FileSystem<D> {
         }
     }
 
-    fn read_root_dir<'a>(
-        &'a mut self,
-        buffer: &'a mut (dyn AlignedBuffer + 'a),
-    ) -> impl Iterator<Item = Result<RawDirectoryEntry, ()>> + 'a {
+    fn find_in_root_dir(
+        &mut self,
+        name: &str,
+        buffer: &mut dyn AlignedBuffer,
+    ) -> Option<DirectoryEntry> {
         match self.bpb.fat_type() {
-            FatType::Fat32 => {
-                // self.bpb.root_cluster;
-                unimplemented!();
-            }
+            FatType::Fat32 => self.find_in_dir(self.bpb.root_cluster, name, buffer),
             FatType::Fat12 | FatType::Fat16 => {
                 let root_directory_size = self.bpb.root_directory_size();
 
@@ -219,16 +315,54 @@ impl<D: Read + Seek> FileSystem<D> {
                     .seek(SeekFrom::Start(self.bpb.root_directory_offset()));
                 self.disk.read_exact_into(root_directory_size, buffer);
 
-                buffer
-                    .slice()
-                    .chunks(DIRECTORY_ENTRY_BYTES)
-                    .take_while(|raw_entry| raw_entry[0] != END_OF_DIRECTORY_PREFIX)
-                    .filter(|raw_entry| raw_entry[0] != UNUSED_ENTRY_PREFIX)
-                    .map(RawDirectoryEntry::parse)
+                let entries = &buffer.slice()[..root_directory_size];
+                match find_in_entries(entries, name, &mut LongNameBuffer::new()) {
+                    Lookup::Found(entry) => Some(entry),
+                    Lookup::EndOfDirectory | Lookup::NotFound => None,
+                }
             }
         }
     }
 
+    /// Looks up the given name in the directory that starts at the given cluster.
+    ///
+    /// The clusters of the directory are read in parts of the buffer size.
+    fn find_in_dir(
+        &mut self,
+        first_cluster: u32,
+        name: &str,
+        buffer: &mut dyn AlignedBuffer,
+    ) -> Option<DirectoryEntry> {
+        let buffer_len = buffer.slice().len();
+        // long names can span multiple clusters
+        let mut long_name = LongNameBuffer::new();
+        let mut clusters = Traverser {
+            current_entry: first_cluster,
+            bpb: &self.bpb,
+            disk: &mut self.disk,
+        };
+        while let Some(cluster) = clusters.next() {
+            let cluster = cluster.ok()?;
+            let cluster_len = usize::try_from(cluster.len_bytes).unwrap();
+            let mut offset = 0;
+            while offset < cluster_len {
+                let len = usize::min(buffer_len, cluster_len - offset);
+                clusters.disk.seek(SeekFrom::Start(
+                    cluster.start_offset + u64::try_from(offset).unwrap(),
+                ));
+                clusters.disk.read_exact_into(len, buffer);
+
+                match find_in_entries(&buffer.slice()[..len], name, &mut long_name) {
+                    Lookup::Found(entry) => return Some(entry),
+                    Lookup::EndOfDirectory => return None,
+                    Lookup::NotFound => {}
+                }
+                offset += len;
+            }
+        }
+        None
+    }
+
     pub fn file_clusters<'a>(
         &'a mut self,
         file: &File,
@@ -241,6 +375,55 @@ impl<D: Read + Seek> FileSystem<D> {
     }
 }
 
+/// A directory entry found by [`FileSystem::find_file`].
+struct DirectoryEntry {
+    first_cluster: u32,
+    file_size: u32,
+    is_directory: bool,
+}
+
+/// The result of looking up a name in a part of a directory.
+enum Lookup {
+    Found(DirectoryEntry),
+    /// The end of the directory was reached.
+    EndOfDirectory,
+    /// The name was not found, but the directory might contain more entries.
+    NotFound,
+}
+
+/// Looks up the given name in the given raw directory entries.
+///
+/// The `long_name` buffer keeps the long name entries that precede the current entry, which
+/// might be located in a previous part of the directory.
+fn find_in_entries(entries: &[u8], name: &str, long_name: &mut LongNameBuffer) -> Lookup {
+    for raw_entry in entries.chunks(DIRECTORY_ENTRY_BYTES) {
+        match raw_entry[0] {
+            END_OF_DIRECTORY_PREFIX => return Lookup::EndOfDirectory,
+            UNUSED_ENTRY_PREFIX => continue,
+            _ => {}
+        }
+        let entry = match RawDirectoryEntry::parse(raw_entry) {
+            Ok(RawDirectoryEntry::LongName(entry)) => {
+                long_name.push(&entry);
+                continue;
+            }
+            Ok(RawDirectoryEntry::Normal(entry)) => entry,
+            Err(()) => continue,
+        };
+        let long_name_matches = long_name
+            .take(&entry)
+            .is_some_and(|long_name| eq_long_name(long_name, name));
+        if !entry.is_volume_label() && (long_name_matches || entry.eq_short_name(name)) {
+            return Lookup::Found(DirectoryEntry {
+                first_cluster: entry.first_cluster,
+                file_size: entry.file_size,
+                is_directory: entry.is_directory(),
+            });
+        }
+    }
+    Lookup::NotFound
+}
+
 #[derive(Debug)]
 pub struct Cluster {
     pub index: u32,
@@ -312,25 +495,6 @@ impl FatType {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Clone)]
-pub struct DirectoryEntry<'a> {
-    short_name: &'a str,
-    short_name_extension: &'a str,
-    long_name_1: &'a [u8],
-    long_name_2: &'a [u8],
-    long_name_3: &'a [u8],
-    file_size: u32,
-    first_cluster: u32,
-    attributes: u8,
-}
-
-impl<'a> DirectoryEntry<'a> {
-    pub fn is_directory(&self) -> bool {
-        self.attributes & directory_attributes::DIRECTORY != 0
-    }
-}
-
 #[derive(Debug)]
 struct RawDirectoryEntryNormal<'a> {
     short_filename_main: &'a str,
@@ -338,6 +502,25 @@ struct RawDirectoryEntryNormal<'a> {
     attributes: u8,
     first_cluster: u32,
     file_size: u32,
+    /// The checksum of the short name, which is stored in the associated long name entries.
+    checksum: u8,
+}
+
+impl RawDirectoryEntryNormal<'_> {
+    fn is_directory(&self) -> bool {
+        self.attributes & directory_attributes::DIRECTORY != 0
+    }
+
+    fn is_volume_label(&self) -> bool {
+        self.attributes & directory_attributes::VOLUME_ID != 0
+    }
+
+    /// Compares the 8.3 short name of the entry with the given name, ignoring ASCII case.
+    fn eq_short_name(&self, name: &str) -> bool {
+        let (main, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+        main.eq_ignore_ascii_case(self.short_filename_main)
+            && extension.eq_ignore_ascii_case(self.short_filename_extension)
+    }
 }
 
 #[allow(dead_code)]
@@ -352,18 +535,102 @@ struct RawDirectoryEntryLongName<'a> {
 }
 
 impl<'a> RawDirectoryEntryLongName<'a> {
-    pub fn name(&self) -> impl Iterator<Item = Result<char, DecodeUtf16Error>> + 'a {
-        let iter = self
-            .name_1
+    /// Returns the UTF-16 code units of the name part stored in this entry.
+    ///
+    /// The last entry of a name is terminated by a null character and padded with `0xFFFF`.
+    fn chars(&self) -> impl Iterator<Item = u16> + 'a {
+        self.name_1
             .chunks(2)
             .chain(self.name_2.chunks(2))
             .chain(self.name_3.chunks(2))
             .map(|c| u16::from_le_bytes(c.try_into().unwrap()))
-            .take_while(|&c| c != 0);
-        char::decode_utf16(iter)
     }
 }
 
+/// The number of UTF-16 code units of the name that are stored in a long name entry.
+const LONG_NAME_CHARS_PER_ENTRY: usize = 13;
+
+/// The maximum number of long name entries of a file, for names of up to 255 characters.
+const MAX_LONG_NAME_ENTRIES: u8 = 20;
+
+/// Marks the long name entry that contains the end of the name, which is stored first.
+const LAST_LONG_NAME_ENTRY: u8 = 0x40;
+
+/// Assembles a long file name from the long name entries that precede a normal entry.
+///
+/// The entries are stored in reverse order, each with its position in the name (starting at
+/// 1) and the checksum of the short name of the normal entry.
+struct LongNameBuffer {
+    chars: [u16; MAX_LONG_NAME_ENTRIES as usize * LONG_NAME_CHARS_PER_ENTRY],
+    /// The position of the next expected entry, or `None` if there is no valid sequence.
+    next_order: Option<u8>,
+    checksum: u8,
+}
+
+impl LongNameBuffer {
+    fn new() -> Self {
+        Self {
+            chars: [0; MAX_LONG_NAME_ENTRIES as usize * LONG_NAME_CHARS_PER_ENTRY],
+            next_order: None,
+            checksum: 0,
+        }
+    }
+
+    fn push(&mut self, entry: &RawDirectoryEntryLongName) {
+        let order = entry.order & !LAST_LONG_NAME_ENTRY;
+        let expected_order = if entry.order & LAST_LONG_NAME_ENTRY != 0 {
+            // start of a new sequence
+            self.chars.fill(0);
+            self.checksum = entry.checksum;
+            Some(order)
+        } else {
+            self.next_order.filter(|_| entry.checksum == self.checksum)
+        };
+
+        self.next_order = match expected_order {
+            Some(expected) if order == expected && (1..=MAX_LONG_NAME_ENTRIES).contains(&order) => {
+                let start = usize::from(order - 1) * LONG_NAME_CHARS_PER_ENTRY;
+                let dst = &mut self.chars[start..][..LONG_NAME_CHARS_PER_ENTRY];
+                for (dst, c) in dst.iter_mut().zip(entry.chars()) {
+                    *dst = c;
+                }
+                Some(order - 1)
+            }
+            // orphaned or out-of-order entry, e.g. left over by a system that doesn't support
+            // long names
+            _ => None,
+        };
+    }
+
+    /// Returns the long name of the given normal entry, if it is preceded by a complete
+    /// sequence of long name entries.
+    ///
+    /// Resets the buffer for the next entry.
+    fn take(&mut self, entry: &RawDirectoryEntryNormal) -> Option<&[u16]> {
+        let complete = self.next_order == Some(0) && self.checksum == entry.checksum;
+        self.next_order = None;
+        complete.then(|| {
+            let len = self
+                .chars
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(self.chars.len());
+            &self.chars[..len]
+        })
+    }
+}
+
+fn eq_long_name(long_name: &[u16], name: &str) -> bool {
+    char::decode_utf16(long_name.iter().copied()).eq(name.chars().map(Ok))
+}
+
+/// Calculates the checksum of an 8.3 short name, as stored in long name entries.
+fn short_name_checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
 #[derive(Debug)]
 enum RawDirectoryEntry<'a> {
     Normal(RawDirectoryEntryNormal<'a>),
@@ -413,20 +680,10 @@ impl<'a> RawDirectoryEntry<'a> {
                 attributes,
                 first_cluster,
                 file_size,
+                checksum: short_name_checksum(&raw[0..11]),
             }))
         }
     }
-
-    pub fn eq_name(&self, name: &str) -> bool {
-        match self {
-            RawDirectoryEntry::Normal(entry) => entry
-                .short_filename_main
-                .chars()
-                .chain(entry.short_filename_extension.chars())
-                .eq(name.chars()),
-            RawDirectoryEntry::LongName(entry) => entry.name().eq(name.chars().map(Ok)),
-        }
-    }
 }
 
 mod directory_attributes {
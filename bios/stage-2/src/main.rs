@@ -7,12 +7,18 @@ use crate::{
         copy_to_protected_mode, enter_protected_mode_and_jump_to_stage_3, enter_unreal_mode,
     },
 };
-use bootloader_x86_64_bios_common::{hlt, BiosFramebufferInfo, BiosInfo, Region};
+use bootloader_boot_config::{
+    default_kernel,
+    failure::{self, FailureKind},
+    DEFAULT_DISK_READ_RETRIES,
+};
+use bootloader_x86_64_bios_common::{hlt, BiosFramebufferInfo, BiosInfo, E820MemoryRegion, Region};
 use byteorder::{ByteOrder, LittleEndian};
-use core::{fmt::Write as _, slice};
+use core::{fmt::Write as _, ops::Range, slice};
 use disk::AlignedArrayBuffer;
 use mbr_nostd::{PartitionTableEntry, PartitionType};
 
+mod config;
 mod dap;
 mod disk;
 mod fat;
@@ -21,19 +27,35 @@ mod protected_mode;
 mod screen;
 mod vesa;
 
-/// We use this partition type to store the second bootloader stage;
-const BOOTLOADER_SECOND_STAGE_PARTITION_TYPE: u8 = 0x20;
+/// The index of the partition table entry of the second stage partition.
+///
+/// The boot sector always loads the second stage from the first partition, independent of
+/// its partition type, which can be configured when creating the disk image.
+const SECOND_STAGE_PARTITION_INDEX: usize = 0;
 
 // 1MiB (typically 14MiB accessible here)
 const STAGE_3_DST: *mut u8 = 0x0010_0000 as *mut u8;
 // must match the start address in bios/stage-4/stage-4-link.ld
 const STAGE_4_DST: *mut u8 = 0x0013_0000 as *mut u8;
-// 16MiB
-const KERNEL_DST: *mut u8 = 0x0100_0000 as *mut u8;
 
-static mut DISK_BUFFER: AlignedArrayBuffer<0x4000> = AlignedArrayBuffer {
-    buffer: [0; 0x4000],
-};
+const PAGE_SIZE: u64 = 4096;
+/// The E820 memory type of usable RAM.
+const USABLE: u32 = 1;
+
+/// The start address of the buffer that files are read into before they are copied to their
+/// destination.
+///
+/// The buffer lies in conventional memory directly behind the second stage, which ends at
+/// `0x80000` (see `stage-2-link.ld`). It is not part of the second stage binary because
+/// statics must be addressable through 16-bit relocations.
+const DISK_BUFFER_ADDR: usize = 0x8_0000;
+/// The size of the disk buffer.
+///
+/// Larger buffers need fewer BIOS disk reads. The buffer must fit into the usable
+/// conventional memory below the extended BIOS data area, which is checked at runtime.
+const DISK_BUFFER_LEN: usize = 0x1_0000;
+
+type DiskBuffer = AlignedArrayBuffer<DISK_BUFFER_LEN>;
 
 #[no_mangle]
 #[link_section = ".start"]
@@ -63,16 +85,8 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
         }
         entries
     };
-    // look for second stage partition
-    let second_stage_partition_idx = partitions
-        .iter()
-        .enumerate()
-        .find(|(_, e)| {
-            e.partition_type == PartitionType::Unknown(BOOTLOADER_SECOND_STAGE_PARTITION_TYPE)
-        })
-        .unwrap()
-        .0;
-    let fat_partition = partitions.get(second_stage_partition_idx + 1).unwrap();
+    // the FAT partition directly follows the second stage partition
+    let fat_partition = partitions.get(SECOND_STAGE_PARTITION_INDEX + 1).unwrap();
     assert!(matches!(
         fat_partition.partition_type,
         PartitionType::Fat12(_) | PartitionType::Fat16(_) | PartitionType::Fat32(_)
@@ -83,30 +97,102 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
         disk_number,
         base_offset: u64::from(fat_partition.logical_block_address) * 512,
         current_offset: 0,
+        read_retries: DEFAULT_DISK_READ_RETRIES,
     };
 
     let mut fs = fat::FileSystem::parse(disk.clone());
 
-    let disk_buffer = unsafe { &mut DISK_BUFFER };
+    let memory_map = unsafe { memory_map::query_memory_map() }.unwrap();
+    writeln!(screen::Writer, "{memory_map:x?}").unwrap();
 
-    let stage_3_len = load_file("boot-stage-3", STAGE_3_DST, &mut fs, &mut disk, disk_buffer);
+    let disk_buffer_end = (DISK_BUFFER_ADDR + DISK_BUFFER_LEN) as u64;
+    if usable_region_end(memory_map, DISK_BUFFER_ADDR as u64) < disk_buffer_end {
+        panic!(
+            "disk buffer at {DISK_BUFFER_ADDR:#x} (len {DISK_BUFFER_LEN:#x}) is not in usable \
+            memory"
+        );
+    }
+    // SAFETY: the buffer lies in usable memory that is not used otherwise
+    let disk_buffer = unsafe { &mut *(DISK_BUFFER_ADDR as *mut DiskBuffer) };
+
+    // stage 3 and stage 4 are linked to fixed addresses, so they must fit into the usable
+    // memory at these addresses
+    let stages_end = usable_region_end(memory_map, STAGE_3_DST as u64);
+    load_file(
+        "boot-stage-3",
+        STAGE_3_DST,
+        (STAGE_4_DST as u64)
+            .min(stages_end)
+            .saturating_sub(STAGE_3_DST as u64),
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+    );
     writeln!(screen::Writer, "stage 3 loaded at {STAGE_3_DST:#p}").unwrap();
-    let stage_4_dst = {
-        let stage_3_end = STAGE_3_DST.wrapping_add(usize::try_from(stage_3_len).unwrap());
-        assert!(STAGE_4_DST > stage_3_end);
-        STAGE_4_DST
-    };
-    let stage_4_len = load_file("boot-stage-4", stage_4_dst, &mut fs, &mut disk, disk_buffer);
+    let stage_4_dst = STAGE_4_DST;
+    let stage_4_len = load_file(
+        "boot-stage-4",
+        stage_4_dst,
+        stages_end.saturating_sub(STAGE_4_DST as u64),
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+    );
     writeln!(screen::Writer, "stage 4 loaded at {stage_4_dst:#p}").unwrap();
 
+    // Some options of the boot config are needed before the kernel is loaded, so we load the
+    // config file behind stage 4 first. It is overwritten by the kernel later and loaded again
+    // behind the ramdisk for stage 4.
+    let stage_4 = config::LoadedFile::new(stage_4_dst, stage_4_len);
+    let stage_4_end = align_up(stage_4_dst as u64 + stage_4_len, PAGE_SIZE);
+    let early_config_file =
+        load_config_file_at(stage_4_end, memory_map, &mut fs, &mut disk, disk_buffer);
+    let read_retries = config::disk_read_retries(&stage_4, &early_config_file);
+    disk.read_retries = read_retries;
+    fs.disk_mut().read_retries = read_retries;
+
+    // place the kernel, the ramdisk, and the config file behind each other in the lowest
+    // usable memory window that is large enough for all of them
+    let mut kernel_name_buffer = [0; default_kernel::MAX_FILE_LEN];
+    let kernel_name = kernel_file_name(&mut fs, &mut disk, disk_buffer, &mut kernel_name_buffer);
+    let kernel_len = file_len(kernel_name, &mut fs, disk_buffer);
+    if kernel_len == 0 {
+        kernel_not_found(kernel_name, &stage_4, &early_config_file);
+    }
+    let required_len = align_up(kernel_len, PAGE_SIZE)
+        + file_len("ramdisk", &mut fs, disk_buffer)
+        + file_len("boot.json", &mut fs, disk_buffer);
+    let kernel_dst =
+        find_usable_window(memory_map, stage_4_end, required_len).unwrap_or_else(|| {
+            panic!(
+                "no usable memory window for the kernel, ramdisk, and config file \
+                ({required_len} bytes)"
+            )
+        }) as *mut u8;
+    let window_end = usable_region_end(memory_map, kernel_dst as u64);
+    let max_len = |dst: *mut u8| window_end.saturating_sub(dst as u64);
+
     writeln!(screen::Writer, "loading kernel...").unwrap();
-    let kernel_len = load_file("kernel-x86_64", KERNEL_DST, &mut fs, &mut disk, disk_buffer);
-    writeln!(screen::Writer, "kernel loaded at {KERNEL_DST:#p}").unwrap();
-    let kernel_page_size = (((kernel_len - 1) / 4096) + 1) as usize;
-    let ramdisk_start = KERNEL_DST.wrapping_add(kernel_page_size * 4096);
+    let kernel_len = load_file(
+        kernel_name,
+        kernel_dst,
+        max_len(kernel_dst),
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+    );
+    writeln!(screen::Writer, "kernel loaded at {kernel_dst:#p}").unwrap();
+    let ramdisk_start = kernel_dst.wrapping_add(align_up(kernel_len, PAGE_SIZE) as usize);
     writeln!(screen::Writer, "Loading ramdisk...").unwrap();
-    let ramdisk_len =
-        try_load_file("ramdisk", ramdisk_start, &mut fs, &mut disk, disk_buffer).unwrap_or(0u64);
+    let ramdisk_len = try_load_file(
+        "ramdisk",
+        ramdisk_start,
+        max_len(ramdisk_start),
+        &mut fs,
+        &mut disk,
+        disk_buffer,
+    )
+    .unwrap_or(0u64);
 
     if ramdisk_len == 0 {
         writeln!(screen::Writer, "No ramdisk found, skipping.").unwrap();
@@ -117,22 +203,27 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
     let config_file_len = try_load_file(
         "boot.json",
         config_file_start,
+        max_len(config_file_start),
         &mut fs,
         &mut disk,
         disk_buffer,
     )
     .unwrap_or(0);
 
-    let memory_map = unsafe { memory_map::query_memory_map() }.unwrap();
-    writeln!(screen::Writer, "{memory_map:x?}").unwrap();
-
-    // TODO: load these from the kernel's config instead of hardcoding
-    let max_width = 1280;
-    let max_height = 720;
+    let limits = config::FrameBufferLimits::new(
+        &stage_4,
+        &config::LoadedFile::new(kernel_dst, kernel_len),
+        &config::LoadedFile::new(config_file_start, config_file_len),
+    );
 
     let mut vesa_info = vesa::VesaInfo::query(disk_buffer).unwrap();
     let vesa_mode = vesa_info
-        .get_best_mode(max_width, max_height)
+        .get_best_mode(
+            limits.min_width,
+            limits.min_height,
+            limits.max_width,
+            limits.max_height,
+        )
         .unwrap()
         .expect("no suitable VESA mode found");
     writeln!(
@@ -143,6 +234,8 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
     )
     .unwrap();
     vesa_mode.enable().unwrap();
+    let refresh_rate = vesa::Edid::query(disk_buffer)
+        .and_then(|edid| edid.refresh_rate(vesa_mode.width, vesa_mode.height));
 
     let mut info = BiosInfo {
         stage_4: Region {
@@ -150,7 +243,7 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
             len: stage_4_len,
         },
         kernel: Region {
-            start: KERNEL_DST as u64,
+            start: kernel_dst as u64,
             len: kernel_len,
         },
         ramdisk: Region {
@@ -174,6 +267,7 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
             bytes_per_pixel: vesa_mode.bytes_per_pixel,
             stride: vesa_mode.bytes_per_scanline / u16::from(vesa_mode.bytes_per_pixel),
             pixel_format: vesa_mode.pixel_format,
+            refresh_rate: refresh_rate.unwrap_or(0),
         },
     };
 
@@ -184,38 +278,148 @@ fn start(disk_number: u16, partition_table_start: *const u8) -> ! {
     }
 }
 
+/// Returns the end address of the usable memory that starts at `addr`.
+///
+/// Adjacent usable regions of the memory map are merged. The end address is limited to
+/// 4 GiB, which is the addressable range in unreal mode.
+fn usable_region_end(memory_map: &[E820MemoryRegion], addr: u64) -> u64 {
+    const ADDRESSABLE_END: u64 = 1 << 32;
+
+    let mut end = addr;
+    while let Some(region) = memory_map.iter().find(|region| {
+        region.region_type == USABLE
+            && region.start_addr <= end
+            && end < region.start_addr + region.len
+    }) {
+        end = region.start_addr + region.len;
+    }
+    end.min(ADDRESSABLE_END)
+}
+
+/// Returns the name of the kernel file.
+///
+/// This is the name stored in the [`default_kernel::FILE_NAME`] file if it exists, or
+/// `kernel-x86_64` otherwise.
+fn kernel_file_name<'a>(
+    fs: &mut fat::FileSystem<disk::DiskAccess>,
+    disk: &mut disk::DiskAccess,
+    disk_buffer: &mut DiskBuffer,
+    name_buffer: &'a mut [u8; default_kernel::MAX_FILE_LEN],
+) -> &'a str {
+    let Some(file) = fs.find_file(default_kernel::FILE_NAME, disk_buffer) else {
+        return "kernel-x86_64";
+    };
+    let len = usize::try_from(file.file_size()).unwrap();
+    let cluster = match fs.file_clusters(&file).next() {
+        Some(Ok(cluster)) if len <= name_buffer.len() => cluster,
+        _ => panic!("invalid {} file", default_kernel::FILE_NAME),
+    };
+    // the file is at most a sector long, so it is stored in the first sector of the cluster
+    disk.seek(SeekFrom::Start(cluster.start_offset));
+    disk.read_exact_into(512, disk_buffer);
+    name_buffer[..len].copy_from_slice(&disk_buffer.buffer[..len]);
+    let name = default_kernel::parse(&name_buffer[..len])
+        .unwrap_or_else(|| panic!("invalid {} file", default_kernel::FILE_NAME));
+    writeln!(screen::Writer, "booting kernel {name}").unwrap();
+    name
+}
+
+/// Loads the `boot.json` file to the lowest usable memory at or above `min_addr`.
+///
+/// Returns an empty file if there is no config file or no usable memory for it.
+fn load_config_file_at(
+    min_addr: u64,
+    memory_map: &[E820MemoryRegion],
+    fs: &mut fat::FileSystem<disk::DiskAccess>,
+    disk: &mut disk::DiskAccess,
+    disk_buffer: &mut DiskBuffer,
+) -> config::LoadedFile {
+    let len = file_len("boot.json", fs, disk_buffer);
+    find_usable_window(memory_map, min_addr, len)
+        .and_then(|start| {
+            let start = start as *mut u8;
+            let len = try_load_file("boot.json", start, len, fs, disk, disk_buffer)?;
+            Some(config::LoadedFile::new(start, len))
+        })
+        .unwrap_or_else(|| config::LoadedFile::new(core::ptr::null_mut(), 0))
+}
+
+/// Reports that the kernel file doesn't exist and halts.
+///
+/// The kernel is missing before stage 4 is loaded, so we check the `failure_signals` option
+/// of the boot config here.
+fn kernel_not_found(
+    kernel_name: &str,
+    stage_4: &config::LoadedFile,
+    config_file: &config::LoadedFile,
+) -> ! {
+    if config::failure_signals(stage_4, config_file) {
+        writeln!(screen::Writer, "kernel file `{kernel_name}` not found").unwrap();
+        failure::signal(FailureKind::KernelNotFound);
+    }
+    panic!("kernel file `{kernel_name}` not found");
+}
+
+/// Returns the size of the given file, or 0 if it doesn't exist.
+fn file_len(
+    file_name: &str,
+    fs: &mut fat::FileSystem<disk::DiskAccess>,
+    disk_buffer: &mut DiskBuffer,
+) -> u64 {
+    fs.find_file(file_name, disk_buffer)
+        .map_or(0, |file| file.file_size().into())
+}
+
+/// Returns the lowest page-aligned address at or above `min_addr` that is followed by at least
+/// `len` bytes of usable memory.
+fn find_usable_window(memory_map: &[E820MemoryRegion], min_addr: u64, len: u64) -> Option<u64> {
+    memory_map
+        .iter()
+        .filter(|region| region.region_type == USABLE)
+        .map(|region| align_up(region.start_addr.max(min_addr), PAGE_SIZE))
+        .filter(|&start| usable_region_end(memory_map, start).saturating_sub(start) >= len)
+        .min()
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+/// Loads the given file to `dst`.
+///
+/// Panics if the file is larger than `max_len`, so that it doesn't overwrite other data.
 fn try_load_file(
     file_name: &str,
     dst: *mut u8,
+    max_len: u64,
     fs: &mut fat::FileSystem<disk::DiskAccess>,
     disk: &mut disk::DiskAccess,
-    disk_buffer: &mut AlignedArrayBuffer<16384>,
+    disk_buffer: &mut DiskBuffer,
 ) -> Option<u64> {
     let disk_buffer_size = disk_buffer.buffer.len();
-    let file = fs.find_file_in_root_dir(file_name, disk_buffer)?;
+    let file = fs.find_file(file_name, disk_buffer)?;
 
     let file_size = file.file_size().into();
+    if file_size > max_len {
+        panic!("{file_name} is {file_size} bytes, max is {max_len} bytes at {dst:#p}");
+    }
 
     let mut total_offset = 0;
-    for cluster in fs.file_clusters(&file) {
-        let cluster = cluster.unwrap();
-        let cluster_start = cluster.start_offset;
-        let cluster_end = cluster_start + u64::from(cluster.len_bytes);
-
+    for range in contiguous_ranges(fs.file_clusters(&file).map(Result::unwrap)) {
         let mut offset = 0;
         loop {
-            let range_start = cluster_start + offset;
-            if range_start >= cluster_end {
+            let range_start = range.start + offset;
+            if range_start >= range.end {
                 break;
             }
             let range_end = u64::min(
                 range_start + u64::try_from(disk_buffer_size).unwrap(),
-                cluster_end,
+                range.end,
             );
             let len = range_end - range_start;
 
             disk.seek(SeekFrom::Start(range_start));
-            disk.read_exact_into(disk_buffer_size, disk_buffer);
+            disk.read_exact_into(usize::try_from(align_up(len, 512)).unwrap(), disk_buffer);
 
             let slice = &disk_buffer.buffer[..usize::try_from(len).unwrap()];
             unsafe { copy_to_protected_mode(dst.wrapping_add(total_offset), slice) };
@@ -230,14 +434,34 @@ fn try_load_file(
     Some(file_size)
 }
 
+/// Merges adjacent clusters into contiguous byte ranges on disk.
+///
+/// This way, files that aren't fragmented are read in chunks of the full disk buffer size
+/// instead of one cluster at a time.
+fn contiguous_ranges(
+    clusters: impl Iterator<Item = fat::Cluster>,
+) -> impl Iterator<Item = Range<u64>> {
+    let mut clusters = clusters.peekable();
+    core::iter::from_fn(move || {
+        let first = clusters.next()?;
+        let start = first.start_offset;
+        let mut end = start + u64::from(first.len_bytes);
+        while let Some(next) = clusters.next_if(|cluster| cluster.start_offset == end) {
+            end += u64::from(next.len_bytes);
+        }
+        Some(start..end)
+    })
+}
+
 fn load_file(
     file_name: &str,
     dst: *mut u8,
+    max_len: u64,
     fs: &mut fat::FileSystem<disk::DiskAccess>,
     disk: &mut disk::DiskAccess,
-    disk_buffer: &mut AlignedArrayBuffer<16384>,
+    disk_buffer: &mut DiskBuffer,
 ) -> u64 {
-    try_load_file(file_name, dst, fs, disk, disk_buffer).expect("file not found")
+    try_load_file(file_name, dst, max_len, fs, disk, disk_buffer).expect("file not found")
 }
 
 /// Taken from https://github.com/rust-lang/rust/blob/e100ec5bc7cd768ec17d75448b29c9ab4a39272b/library/core/src/slice/mod.rs#L1673-L1677
@@ -37,8 +37,16 @@ impl<'a> VesaInfo<'a> {
         slice.fill(0);
         let block_ptr = slice.as_mut_ptr();
         let ret;
+        let mut target_addr = block_ptr as u32;
+        let segment = target_addr >> 4;
+        target_addr -= segment << 4;
         unsafe {
-            asm!("push es", "mov es, {:x}", "int 0x10", "pop es", in(reg)0, inout("ax") 0x4f00u16 => ret, in("di") block_ptr)
+            asm!(
+                "push es", "mov es, {:x}", "int 0x10", "pop es",
+                in(reg) segment as u16,
+                inout("ax") 0x4f00u16 => ret,
+                in("di") target_addr as u16
+            )
         };
         match ret {
             0x4f => {
@@ -52,11 +60,19 @@ impl<'a> VesaInfo<'a> {
         }
     }
 
+    /// Returns the largest mode within the given bounds.
+    ///
+    /// Falls back to the largest mode below the maximum bounds if no mode is large enough.
     pub fn get_best_mode(
         &mut self,
+        min_width: u16,
+        min_height: u16,
         max_width: u16,
         max_height: u16,
     ) -> Result<Option<VesaModeInfo>, u16> {
+        let large_enough =
+            |mode: &VesaModeInfo| mode.width >= min_width && mode.height >= min_height;
+
         let mut best: Option<VesaModeInfo> = None;
         for i in 0.. {
             let mode = match self.get_mode(i) {
@@ -85,6 +101,9 @@ impl<'a> VesaInfo<'a> {
 
             let replace = match &best {
                 None => true,
+                Some(best) if large_enough(best) != large_enough(&mode_info) => {
+                    large_enough(&mode_info)
+                }
                 Some(best) => {
                     best.pixel_format.is_unknown()
                         || best.width < mode_info.width
@@ -238,3 +257,64 @@ impl VesaModeInfo {
         }
     }
 }
+
+/// The Extended Display Identification Data (EDID) of the connected display.
+pub struct Edid {
+    block: [u8; Self::LEN],
+}
+
+impl Edid {
+    const LEN: usize = 128;
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+    /// Offset of the first detailed timing descriptor, which describes the preferred mode.
+    const PREFERRED_TIMING: usize = 54;
+
+    /// Reads the first EDID block of the display through the VBE/DDC interface.
+    ///
+    /// Returns `None` if the function is not supported or the block is invalid.
+    pub fn query<const N: usize>(buffer: &mut AlignedArrayBuffer<N>) -> Option<Self> {
+        let slice = &mut buffer.slice_mut()[..Self::LEN];
+        slice.fill(0);
+
+        let mut ret: u16;
+        let mut target_addr = slice.as_mut_ptr() as u32;
+        let segment = target_addr >> 4;
+        target_addr -= segment << 4;
+        unsafe {
+            asm!(
+                "push es", "push bx", "mov es, {:x}", "mov bx, 1", "int 0x10", "pop bx", "pop es",
+                in(reg) segment as u16,
+                inout("ax") 0x4f15u16 => ret,
+                in("cx") 0u16,
+                in("dx") 0u16,
+                in("di") target_addr as u16
+            )
+        };
+        if ret != 0x4f {
+            return None;
+        }
+
+        let mut block = [0; Self::LEN];
+        block.copy_from_slice(slice);
+        let checksum = block.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        (block[..8] == Self::HEADER && checksum == 0).then_some(Self { block })
+    }
+
+    /// Returns the refresh rate of the preferred mode in Hz if it has the given resolution.
+    pub fn refresh_rate(&self, width: u16, height: u16) -> Option<u32> {
+        let timing = &self.block[Self::PREFERRED_TIMING..][..18];
+        // in units of 10 kHz, zero if the descriptor is not a timing descriptor
+        let pixel_clock = u32::from(u16::from_le_bytes([timing[0], timing[1]])) * 10_000;
+        let high_bits = |value: u8, high: u8| u32::from(value) | (u32::from(high) << 8);
+        let h_active = high_bits(timing[2], timing[4] >> 4);
+        let h_blanking = high_bits(timing[3], timing[4] & 0xf);
+        let v_active = high_bits(timing[5], timing[7] >> 4);
+        let v_blanking = high_bits(timing[6], timing[7] & 0xf);
+
+        if pixel_clock == 0 || h_active != u32::from(width) || v_active != u32::from(height) {
+            return None;
+        }
+        let total = (h_active + h_blanking) * (v_active + v_blanking);
+        Some((pixel_clock + total / 2) / total)
+    }
+}
@@ -13,7 +13,10 @@ mod screen;
 #[no_mangle]
 #[link_section = ".start"]
 pub extern "C" fn _start(info: &mut BiosInfo) {
-    screen::init(info.framebuffer);
+    // the multiboot stage doesn't set up a framebuffer
+    if info.framebuffer.region.len != 0 {
+        screen::init(info.framebuffer);
+    }
     // Writer.clear_screen();
     writeln!(Writer, "Third Stage ({info:x?})").unwrap();
 
@@ -7,8 +7,10 @@ pub struct Writer;
 
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        let writer = unsafe { WRITER.get_mut() }.as_mut().unwrap();
-        writer.write_str(s)
+        match unsafe { WRITER.get_mut() }.as_mut() {
+            Some(writer) => writer.write_str(s),
+            None => Ok(()),
+        }
     }
 }
 
@@ -2,21 +2,26 @@
 #![no_main]
 
 use crate::memory_descriptor::MemoryRegion;
-use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use bootloader_api::info::{FrameBufferInfo, MemoryRegionKind, PixelFormat};
 use bootloader_boot_config::{BootConfig, LevelFilter};
 use bootloader_x86_64_bios_common::{BiosFramebufferInfo, BiosInfo, E820MemoryRegion};
 use bootloader_x86_64_common::RawFrameBufferInfo;
 use bootloader_x86_64_common::{
-    legacy_memory_region::LegacyFrameAllocator, load_and_switch_to_kernel, Kernel, PageTables,
-    SystemInfo,
+    apply_safe_mode,
+    boot_config::{parse_boot_config, BootConfigError},
+    boot_stage::{self, BootStage},
+    embedded_boot_config, exclude_reserved_memory,
+    failure::{self, FailureKind},
+    legacy_memory_region::{LegacyFrameAllocator, LegacyMemoryRegion},
+    load_and_switch_to_kernel, reserve_kernel_load_range, Kernel, PageTables, SystemInfo,
 };
-use core::{cmp, slice};
+use core::{cmp, ptr, slice};
 use usize_conversions::usize_from;
-use x86_64::structures::paging::{FrameAllocator, OffsetPageTable};
 use x86_64::structures::paging::{
-    Mapper, PageTable, PageTableFlags, PhysFrame, Size2MiB, Size4KiB,
+    frame::PhysFrameRange, Mapper, PageTable, PageTableFlags, PhysFrame, Size2MiB, Size4KiB,
 };
-use x86_64::{PhysAddr, VirtAddr};
+use x86_64::structures::paging::{FrameAllocator, OffsetPageTable};
+use x86_64::{align_up, PhysAddr, VirtAddr};
 
 const GIGABYTE: u64 = 4096 * 512 * 512;
 
@@ -49,16 +54,75 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
         cmp::min(max, 4 * GIGABYTE)
     };
 
-    let kernel_start = {
-        assert!(info.kernel.start != 0, "kernel start address must be set");
-        PhysAddr::new(info.kernel.start)
+    // prefer a config that was embedded into the bootloader binary over the `boot.json` file
+    let mut config_file_slice: Option<&[u8]> = embedded_boot_config();
+    if config_file_slice.is_none() && info.config_file.len != 0 {
+        config_file_slice = {
+            let ptr = info.config_file.start as *mut u8;
+            unsafe {
+                Some(slice::from_raw_parts_mut(
+                    ptr,
+                    usize_from(info.config_file.len),
+                ))
+            }
+        };
+    }
+    let mut error_loading_config: Option<BootConfigError> = None;
+    let mut config: BootConfig = match config_file_slice.map(parse_boot_config).transpose() {
+        Ok(data) => data.unwrap_or_default(),
+        Err(err) => {
+            error_loading_config = Some(err);
+            Default::default()
+        }
     };
+    if config.failure_signals {
+        failure::enable();
+    }
+    if config.stage_markers {
+        boot_stage::enable();
+    }
+    boot_stage::mark(BootStage::ConfigLoaded);
+
+    // A missing kernel file is already reported by stage 2 on disk boots, but the multiboot
+    // loader might pass an empty kernel module.
+    if info.kernel.start == 0 || info.kernel.len == 0 {
+        failure::set_kind(FailureKind::KernelNotFound);
+        panic!("no kernel was loaded");
+    }
+
+    // Stage 2 loads the kernel, the ramdisk, and the config file directly behind stage 4, so
+    // they might lie in the memory that the kernel requests for its segments.
+    let kernel_load_range = {
+        let kernel_slice = unsafe {
+            slice::from_raw_parts(info.kernel.start as *const u8, usize_from(info.kernel.len))
+        };
+        Kernel::parse(kernel_slice, &config).physical_load_range()
+    };
+    let kernel_load_range_result = kernel_load_range.and_then(|range| match range {
+        Some(range) => move_loaded_files_behind(info, memory_map, range),
+        None => Ok(()),
+    });
+
+    let kernel_start = PhysAddr::new(info.kernel.start);
     let kernel_size = info.kernel.len;
+
     let next_free_frame = PhysFrame::containing_address(PhysAddr::new(info.last_used_addr)) + 1;
     let mut frame_allocator = LegacyFrameAllocator::new_starting_at(
         next_free_frame,
         memory_map.iter().copied().map(MemoryRegion),
     );
+    // the logger is not initialized yet, so the errors are logged below
+    let reserved_memory_result = exclude_reserved_memory(&mut frame_allocator, &config);
+
+    let kernel_slice = {
+        let ptr = kernel_start.as_u64() as *const u8;
+        unsafe { slice::from_raw_parts(ptr, usize_from(kernel_size)) }
+    };
+    let mut kernel = Kernel::parse(kernel_slice, &config);
+    boot_stage::mark(BootStage::KernelLoaded);
+    // reserve the memory before any frames are allocated
+    let kernel_load_range_result = kernel_load_range_result
+        .and_then(|()| reserve_kernel_load_range(&mut frame_allocator, &kernel));
 
     // We identity-mapped all memory, so the offset between physical and virtual addresses is 0
     let phys_offset = VirtAddr::new(0);
@@ -99,36 +163,7 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
     x86_64::instructions::tlb::flush_all();
 
     let page_tables = create_page_tables(&mut frame_allocator);
-
-    let kernel_slice = {
-        let ptr = kernel_start.as_u64() as *const u8;
-        unsafe { slice::from_raw_parts(ptr, usize_from(kernel_size)) }
-    };
-    let kernel = Kernel::parse(kernel_slice);
-
-    let mut config_file_slice: Option<&[u8]> = None;
-    if info.config_file.len != 0 {
-        config_file_slice = {
-            let ptr = info.config_file.start as *mut u8;
-            unsafe {
-                Some(slice::from_raw_parts_mut(
-                    ptr,
-                    usize_from(info.config_file.len),
-                ))
-            }
-        };
-    }
-    let mut error_loading_config: Option<serde_json_core::de::Error> = None;
-    let mut config: BootConfig = match config_file_slice
-        .map(serde_json_core::from_slice)
-        .transpose()
-    {
-        Ok(data) => data.unwrap_or_default().0,
-        Err(err) => {
-            error_loading_config = Some(err);
-            Default::default()
-        }
-    };
+    boot_stage::mark(BootStage::MemoryMapDone);
 
     #[allow(deprecated)]
     if config.frame_buffer.minimum_framebuffer_height.is_none() {
@@ -140,15 +175,33 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
         config.frame_buffer.minimum_framebuffer_width =
             kernel.config.frame_buffer.minimum_framebuffer_width;
     }
+    if config.safe_mode {
+        apply_safe_mode(&mut kernel, &mut config);
+    }
     let framebuffer_info = init_logger(
         info.framebuffer,
         config.log_level,
-        config.frame_buffer_logging,
+        config.frame_buffer_logging && info.framebuffer.region.len != 0,
         config.serial_logging,
+        config.boot_log,
+        config.frame_buffer.self_test,
     );
 
     if let Some(err) = error_loading_config {
-        log::warn!("Failed to deserialize the config file {:?}", err);
+        log::warn!("Failed to deserialize the config file, using the default config: {err}");
+    }
+    if let Err(err) = reserved_memory_result {
+        log::warn!("{err}");
+    }
+    if let Err(err) = kernel_load_range_result {
+        panic!("requested physical load address of the kernel is not available: {err}");
+    }
+    if config.memory_test {
+        bootloader_x86_64_common::test_memory(
+            &mut frame_allocator,
+            phys_offset,
+            PhysAddr::new(max_phys_addr),
+        );
     }
 
     log::info!("4th Stage");
@@ -156,16 +209,33 @@ pub extern "C" fn _start(info: &mut BiosInfo) -> ! {
     log::info!("BIOS boot");
 
     let system_info = SystemInfo {
-        framebuffer: Some(RawFrameBufferInfo {
-            addr: PhysAddr::new(info.framebuffer.region.start),
-            info: framebuffer_info,
-        }),
-        rsdp_addr: detect_rsdp(),
+        // the multiboot stage only reports a framebuffer if it was set up by a multiboot2 boot
+        // loader
+        framebuffer: match info.framebuffer.region.len {
+            0 => None,
+            _ => Some(RawFrameBufferInfo {
+                addr: PhysAddr::new(info.framebuffer.region.start),
+                info: framebuffer_info,
+                refresh_rate: match info.framebuffer.refresh_rate {
+                    0 => None,
+                    rate => Some(rate),
+                },
+            }),
+        },
+        rsdp_addr: config.rsdp_addr.map(PhysAddr::new).or_else(detect_rsdp),
         ramdisk_addr: match info.ramdisk.len {
             0 => None,
             _ => Some(info.ramdisk.start),
         },
         ramdisk_len: info.ramdisk.len,
+        boot_time: kernel
+            .config
+            .boot_time
+            .then(bootloader_x86_64_common::rtc::read_cmos_time)
+            .flatten(),
+        // E820 types 3 (ACPI reclaimable) and 4 (ACPI NVS)
+        acpi_tables: frame_allocator
+            .region_bounds(|kind| matches!(kind, MemoryRegionKind::UnknownBios(3 | 4))),
     };
 
     load_and_switch_to_kernel(kernel, config, frame_allocator, page_tables, system_info);
@@ -176,6 +246,8 @@ fn init_logger(
     log_level: LevelFilter,
     frame_buffer_logger_status: bool,
     serial_logger_status: bool,
+    boot_log_status: bool,
+    self_test: bool,
 ) -> FrameBufferInfo {
     let framebuffer_info = FrameBufferInfo {
         byte_len: info.region.len.try_into().unwrap(),
@@ -198,12 +270,15 @@ fn init_logger(
         stride: info.stride.into(),
     };
 
-    let framebuffer = unsafe {
-        core::slice::from_raw_parts_mut(
-            info.region.start as *mut u8,
-            info.region.len.try_into().unwrap(),
-        )
+    let framebuffer: &'static mut [u8] = match info.region.len {
+        0 => &mut [],
+        len => unsafe {
+            core::slice::from_raw_parts_mut(info.region.start as *mut u8, len.try_into().unwrap())
+        },
     };
+    if self_test && !framebuffer.is_empty() {
+        bootloader_x86_64_common::framebuffer::self_test(framebuffer, framebuffer_info);
+    }
 
     bootloader_x86_64_common::init_logger(
         framebuffer,
@@ -211,11 +286,58 @@ fn init_logger(
         log_level,
         frame_buffer_logger_status,
         serial_logger_status,
+        boot_log_status,
     );
 
     framebuffer_info
 }
 
+/// Moves the kernel, the ramdisk, and the config file behind the given range if they overlap
+/// with it.
+///
+/// Used for kernels that request to be loaded at fixed physical addresses through the
+/// `load_at_physical_addresses` option. The range must not overlap with stage 3 and stage 4,
+/// which are still in use.
+fn move_loaded_files_behind(
+    info: &mut BiosInfo,
+    memory_map: &[E820MemoryRegion],
+    range: PhysFrameRange,
+) -> Result<(), &'static str> {
+    let range_start = range.start.start_address().as_u64();
+    let range_end = range.end.start_address().as_u64();
+    // stage 3 is loaded directly in front of stage 4
+    if range_start < info.stage_4.start + info.stage_4.len {
+        return Err("range overlaps with the bootloader stages");
+    }
+    let files_start = info.kernel.start;
+    let files_end = info.last_used_addr + 1;
+    if files_end <= range_start || range_end <= files_start {
+        return Ok(());
+    }
+
+    // the first 4GiB are identity-mapped and don't contain anything else that is still in use
+    let len = files_end - files_start;
+    let dst = memory_map
+        .iter()
+        .map(|&r| MemoryRegion(r))
+        .filter(|r| r.kind() == MemoryRegionKind::Usable)
+        .find_map(|r| {
+            let start = align_up(cmp::max(r.start().as_u64(), range_end), 4096);
+            let end = cmp::min((r.start() + r.len()).as_u64(), 4 * GIGABYTE);
+            (start.checked_add(len)? <= end).then_some(start)
+        })
+        .ok_or("no usable memory to move the kernel file out of the way")?;
+    // the old and the new location might overlap, which `ptr::copy` supports
+    unsafe { ptr::copy(files_start as *const u8, dst as *mut u8, usize_from(len)) };
+
+    let offset = dst - files_start;
+    info.kernel.start += offset;
+    info.ramdisk.start += offset;
+    info.config_file.start += offset;
+    info.last_used_addr += offset;
+    Ok(())
+}
+
 /// Creates page table abstraction types for both the bootloader and kernel page tables.
 fn create_page_tables(frame_allocator: &mut impl FrameAllocator<Size4KiB>) -> PageTables {
     // We identity-mapped all memory, so the offset between physical and virtual addresses is 0
@@ -299,7 +421,5 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
             .map(|l| l.force_unlock())
     };
     log::error!("{info}");
-    loop {
-        unsafe { core::arch::asm!("cli; hlt") };
-    }
+    failure::halt()
 }
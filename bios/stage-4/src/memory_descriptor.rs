@@ -15,6 +15,7 @@ impl LegacyMemoryRegion for MemoryRegion {
     fn kind(&self) -> MemoryRegionKind {
         match self.0.region_type {
             1 => MemoryRegionKind::Usable,
+            5 => MemoryRegionKind::BadMemory,
             other => MemoryRegionKind::UnknownBios(other),
         }
     }
@@ -21,11 +21,18 @@ async fn bios_main() {
     // BIOS crates don't have enough dependencies to utilize all cores on modern
     // CPUs. So by running the build commands in parallel, we increase the number
     // of utilized cores.)
-    let (bios_boot_sector_path, bios_stage_2_path, bios_stage_3_path, bios_stage_4_path) = (
+    let (
+        bios_boot_sector_path,
+        bios_stage_2_path,
+        bios_stage_3_path,
+        bios_stage_4_path,
+        bios_multiboot_path,
+    ) = (
         build_bios_boot_sector(&out_dir),
         build_bios_stage_2(&out_dir),
         build_bios_stage_3(&out_dir),
         build_bios_stage_4(&out_dir),
+        build_bios_multiboot(&out_dir),
     )
         .join()
         .await;
@@ -45,6 +52,10 @@ async fn bios_main() {
         "cargo:rustc-env=BIOS_STAGE_4_PATH={}",
         bios_stage_4_path.display()
     );
+    println!(
+        "cargo:rustc-env=BIOS_MULTIBOOT_PATH={}",
+        bios_multiboot_path.display()
+    );
 }
 
 #[cfg(feature = "uefi")]
@@ -371,6 +382,68 @@ async fn build_bios_stage_4(out_dir: &Path) -> PathBuf {
     path
 }
 
+#[cfg(not(docsrs_dummy_build))]
+#[cfg(feature = "bios")]
+async fn build_bios_multiboot(out_dir: &Path) -> PathBuf {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut cmd = Command::new(cargo);
+    cmd.arg("install").arg("bootloader-x86_64-bios-multiboot");
+    let local_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("bios")
+        .join("multiboot");
+    if local_path.exists() {
+        // local build
+        cmd.arg("--path").arg(&local_path);
+        println!("cargo:rerun-if-changed={}", local_path.display());
+    } else {
+        cmd.arg("--version").arg(BOOTLOADER_VERSION);
+    }
+    cmd.arg("--locked");
+    cmd.arg("--target").arg("i686-stage-3.json");
+    cmd.arg("--profile").arg("multiboot");
+    cmd.arg("-Zbuild-std=core")
+        .arg("-Zbuild-std-features=compiler-builtins-mem");
+    cmd.arg("--root").arg(out_dir);
+    cmd.env_remove("RUSTFLAGS");
+    cmd.env_remove("CARGO_ENCODED_RUSTFLAGS");
+    cmd.env_remove("RUSTC_WORKSPACE_WRAPPER"); // used by clippy
+    let status = cmd
+        .status()
+        .await
+        .expect("failed to run cargo install for bios multiboot stage");
+    // multiboot boot loaders load ELF files directly, so we don't convert it to a flat binary
+    if status.success() {
+        let path = out_dir.join("bin").join("bootloader-x86_64-bios-multiboot");
+        assert!(
+            path.exists(),
+            "bios multiboot executable does not exist after building"
+        );
+        path
+    } else {
+        panic!("failed to build bios multiboot stage");
+    }
+}
+
+// dummy implementation because docsrs builds have no network access.
+// This will put an empty file in out_dir and return its path.
+#[cfg(docsrs_dummy_build)]
+#[cfg(feature = "bios")]
+async fn build_bios_multiboot(out_dir: &Path) -> PathBuf {
+    use std::fs::File;
+
+    let path = out_dir.join("bootloader-dummy-bios-multiboot");
+
+    if File::create(&path).is_err() {
+        panic!("Failed to create dummy bios multiboot stage");
+    }
+    assert!(
+        path.exists(),
+        "bios multiboot dummy file does not exist after file creation"
+    );
+
+    path
+}
+
 #[cfg(not(docsrs_dummy_build))]
 #[cfg(feature = "bios")]
 async fn convert_elf_to_bin(elf_path: PathBuf) -> PathBuf {
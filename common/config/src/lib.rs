@@ -1,6 +1,7 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-use serde::{Deserialize, Serialize};
+use core::fmt;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 /// Configures the boot behavior of the bootloader.
 #[derive(Serialize, Deserialize)]
@@ -22,13 +23,196 @@ pub struct BootConfig {
 
     /// Whether the bootloader should print log messages to the serial port during boot.
     ///
+    /// When disabled, the bootloader doesn't access the serial port hardware (the UART at I/O
+    /// port `0x3F8`) at all, i.e. it neither initializes nor writes to it. The only exceptions
+    /// are [`safe_mode`](Self::safe_mode), which always enables serial logging, and a
+    /// [`debug_serial_port`](Self::debug_serial_port) that is explicitly set to `0x3F8`.
+    ///
     /// Enabled by default.
     pub serial_logging: bool,
 
+    /// Whether the bootloader should capture its log messages in a ring buffer that is passed
+    /// to the kernel.
+    ///
+    /// The captured log is reported in `BootInfo::boot_log_addr` and `BootInfo::boot_log_len`.
+    /// Only the most recent output that fits into the buffer is kept.
+    ///
+    /// Disabled by default.
+    pub boot_log: bool,
+
+    /// Boots the kernel in a minimal "safe mode" environment, e.g. for debugging boot failures.
+    ///
+    /// When enabled, the bootloader overrides parts of the kernel's compile-time configuration
+    /// and of this boot config:
+    ///
+    /// - the physical memory is not mapped
+    /// - the framebuffer is not mapped and not passed to the kernel
+    /// - ASLR is disabled
+    /// - the kernel stack has a fixed size of 64 KiB
+    /// - log messages are only printed to the serial port, using the [`LevelFilter::Trace`]
+    ///   log level
+    ///
+    /// Disabled by default.
+    pub safe_mode: bool,
+
+    /// Overrides the physical address of the ACPI _Root System Description Pointer_ (RSDP).
+    ///
+    /// If set, the given address is passed to the kernel in `BootInfo::rsdp_addr` instead of
+    /// the auto-detected address. This is useful on platforms where the RSDP detection fails
+    /// or returns a wrong address.
+    ///
+    /// Defaults to `None`, i.e. the RSDP is detected automatically.
+    ///
+    /// Like the other numeric fields listed in [`size::FIELDS`], the address can also be given
+    /// as a hexadecimal string in the JSON config, e.g. `"0xe0000"`.
+    pub rsdp_addr: Option<u64>,
+
+    /// Loads the kernel from the raw GPT partition with the given partition type GUID instead
+    /// of the `kernel-x86_64` file.
+    ///
+    /// The partition contains the kernel ELF file directly, without a file system. This is
+    /// useful for kernels that are packaged as raw partition images. The GUID is given in its
+    /// canonical string format, e.g. `"0fc63daf-8483-4772-8e79-3d69d8477de4"`.
+    ///
+    /// Only supported on UEFI systems. Defaults to `None`.
+    pub kernel_partition_type: Option<Guid>,
+
+    /// Boots a flat binary kernel instead of an ELF executable.
+    ///
+    /// This option only applies to kernel files that don't start with the ELF magic bytes.
+    /// Such kernels are mapped at the given virtual load address and entered at the given
+    /// offset. Since flat binaries can't contain a `BootloaderConfig`, the default config is
+    /// used for them.
+    ///
+    /// Defaults to `None`, i.e. the kernel must be an ELF executable.
+    pub flat_kernel: Option<FlatKernel>,
+
+    /// Whether the ramdisk should be loaded into physical memory above 4 GiB.
+    ///
+    /// This avoids using up the scarce memory below 4 GiB for large ramdisks. If there is no
+    /// large enough free memory region above 4 GiB, the ramdisk is loaded anywhere.
+    ///
+    /// Only supported on UEFI systems. Disabled by default.
+    pub ramdisk_above_4gib: bool,
+
+    /// The I/O port base of a second serial port that is initialized for debugging purposes,
+    /// e.g. `0x2f8` for `COM2`.
+    ///
+    /// The bootloader initializes the port but doesn't print log messages to it. The port base
+    /// is reported in `BootInfo::debug_serial_port` so that the kernel can use it, e.g. for a
+    /// debugger stub. The port can also be given as a hexadecimal string in the JSON config,
+    /// e.g. `"0x2f8"`, but in contrast to the fields listed in [`size::FIELDS`] not with a size
+    /// suffix.
+    ///
+    /// Defaults to `None`.
+    pub debug_serial_port: Option<u16>,
+
+    /// Physical memory ranges that the kernel must not use, e.g. RAM that is known to be
+    /// defective or that is used by system management mode (SMM) firmware on quirky
+    /// platforms.
+    ///
+    /// The bootloader doesn't allocate memory from these ranges and reports them as
+    /// `MemoryRegionKind::Reserved` in the memory map that is passed to the kernel. The ranges
+    /// are extended to page boundaries. In the JSON config, the ranges are given as a list of
+    /// `{ "start": ..., "len": ... }` objects, which also accept size strings.
+    ///
+    /// Defaults to an empty list.
+    pub reserved_memory: ReservedMemory,
+
+    /// Whether failures should be signaled through PC speaker beeps and POST codes.
+    ///
+    /// This is useful for diagnosing headless machines without a serial port. On failure,
+    /// the bootloader writes a POST code to port `0x80` and repeats a beep pattern forever:
+    ///
+    /// | Failure            | POST code | Beeps |
+    /// |--------------------|-----------|-------|
+    /// | other              | `0xe0`    | 1     |
+    /// | kernel not found   | `0xe1`    | 2     |
+    /// | invalid kernel     | `0xe2`    | 3     |
+    /// | out of memory      | `0xe3`    | 4     |
+    ///
+    /// When booting from disk, a missing kernel file is already signaled by the second stage,
+    /// which looks up this option in the `boot.json` file or the config embedded into the
+    /// fourth stage. All other failures are only signaled after the config was loaded.
+    ///
+    /// Only supported on BIOS systems. Disabled by default.
+    pub failure_signals: bool,
+
+    /// Whether the bootloader should log all mappings of the kernel's page tables right
+    /// before jumping to the kernel.
+    ///
+    /// Adjacent mappings with the same flags are merged into a single line of the form
+    /// `virtual range -> physical range flags`. This helps to find out why a kernel faults
+    /// immediately after entry. The mappings are logged at the `info` level, so they are
+    /// also written to the serial port if [`serial_logging`](Self::serial_logging) is
+    /// enabled. Disabled by default.
+    pub dump_page_tables: bool,
+
+    /// Whether the bootloader should test the usable memory before building the memory map
+    /// for the kernel.
+    ///
+    /// Each usable frame that the bootloader hasn't allocated yet is written with several bit
+    /// patterns, which are then read back. Frames that fail the test are reported as
+    /// `MemoryRegionKind::BadMemory` in the memory map that is passed to the kernel. Note that
+    /// testing all memory can considerably slow down the boot on machines with a lot of RAM.
+    ///
+    /// Disabled by default.
+    pub memory_test: bool,
+
+    /// The NUMA node whose memory should be used for the kernel stack.
+    ///
+    /// On multi-socket machines, this allows the early kernel code to run with a stack in
+    /// memory that is local to the boot CPU. The node is given as the proximity domain of the
+    /// ACPI _System Resource Affinity Table_ (SRAT). If the firmware provides no SRAT or the
+    /// node has not enough free memory, the bootloader logs a warning and allocates the stack
+    /// as usual.
+    ///
+    /// Defaults to `None`.
+    pub kernel_stack_numa_node: Option<u32>,
+
+    /// An EFI application on the boot partition that the UEFI loader starts instead of the
+    /// kernel, or if no kernel is found.
+    ///
+    /// This is useful for recovery setups, e.g. to start a UEFI shell or another bootloader.
+    /// The application is started through the UEFI boot services, so control returns to the
+    /// firmware when it exits. Only supported on UEFI systems.
+    ///
+    /// Defaults to `None`.
+    pub chainload: Option<Chainload>,
+
+    /// Whether the bootloader should print a marker line to the serial port at each boundary
+    /// between its boot stages.
+    ///
+    /// The markers have the form `BOOTSTAGE:<name>`, e.g. `BOOTSTAGE:mappings_done`, and are
+    /// printed independently of [`log_level`](Self::log_level) and
+    /// [`serial_logging`](Self::serial_logging). This allows automated tests to check that
+    /// all stages ran in the expected order. Disabled by default.
+    pub stage_markers: bool,
+
+    /// The number of times the second BIOS stage retries a failed disk read before it aborts
+    /// the boot.
+    ///
+    /// The disk system is reset before each retry. The boot sector, stage 3, stage 4, and this
+    /// config file are loaded before the option is known, so they always use
+    /// [`DEFAULT_DISK_READ_RETRIES`]. Only supported on BIOS systems.
+    ///
+    /// Defaults to [`DEFAULT_DISK_READ_RETRIES`].
+    pub disk_read_retries: u16,
+
     #[doc(hidden)]
     pub _test_sentinel: u64,
 }
 
+/// The default number of times a failed BIOS disk read is retried, see
+/// [`BootConfig::disk_read_retries`].
+///
+/// Reads from real floppy disks and USB drives sometimes fail spuriously, e.g. while the
+/// drive motor spins up.
+pub const DEFAULT_DISK_READ_RETRIES: u16 = 3;
+
+/// The delay between two attempts to read from a BIOS disk, in microseconds.
+pub const DISK_READ_RETRY_DELAY_MICROS: u16 = 50_000;
+
 impl Default for BootConfig {
     fn default() -> Self {
         Self {
@@ -36,13 +220,31 @@ impl Default for BootConfig {
             log_level: Default::default(),
             frame_buffer_logging: true,
             serial_logging: true,
+            boot_log: false,
+            safe_mode: false,
+            rsdp_addr: None,
+            kernel_partition_type: None,
+            flat_kernel: None,
+            ramdisk_above_4gib: false,
+            debug_serial_port: None,
+            reserved_memory: ReservedMemory::new(),
+            failure_signals: false,
+            dump_page_tables: false,
+            memory_test: false,
+            kernel_stack_numa_node: None,
+            chainload: None,
+            stage_markers: false,
+            disk_read_retries: DEFAULT_DISK_READ_RETRIES,
             _test_sentinel: 0,
         }
     }
 }
 
 /// Configuration for the frame buffer used for graphical output.
+///
+/// The numeric fields also accept size strings in the JSON config, see [`size`].
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(default)]
 #[non_exhaustive]
 pub struct FrameBuffer {
     /// Instructs the bootloader to set up a framebuffer format that has at least the given height.
@@ -53,6 +255,232 @@ pub struct FrameBuffer {
     ///
     /// If this is not possible, the bootloader will fall back to a smaller format.
     pub minimum_framebuffer_width: Option<u64>,
+    /// Instructs the bootloader to set up a framebuffer format that has at most the given height.
+    ///
+    /// The bootloader picks the largest format within the minimum and maximum bounds. This is
+    /// useful to avoid very large formats that are slow to clear and scroll. On BIOS systems,
+    /// the maximum height defaults to 720.
+    pub maximum_framebuffer_height: Option<u64>,
+    /// Instructs the bootloader to set up a framebuffer format that has at most the given width.
+    ///
+    /// The bootloader picks the largest format within the minimum and maximum bounds. This is
+    /// useful to avoid very large formats that are slow to clear and scroll. On BIOS systems,
+    /// the maximum width defaults to 1280.
+    pub maximum_framebuffer_width: Option<u64>,
+    /// Instructs the bootloader to set the UEFI GOP mode with the given index.
+    ///
+    /// This bypasses the resolution bounds above. If the index is not a valid mode, the
+    /// bootloader logs a warning and falls back to the bounds. Only used on UEFI systems.
+    pub gop_mode: Option<u32>,
+    /// Instructs the bootloader to keep the display mode that was set up by the firmware.
+    ///
+    /// This avoids mode switches, which cause flickering or fail on some firmware. The
+    /// resolution bounds and the [`gop_mode`](Self::gop_mode) are ignored if this is set.
+    /// Only used on UEFI systems.
+    pub keep_current_mode: bool,
+    /// Instructs the bootloader to zero the framebuffer right before jumping to the kernel.
+    ///
+    /// By default, the kernel inherits the framebuffer contents, e.g. the firmware logo and
+    /// the log output of the bootloader. With this option, the kernel starts with a black
+    /// screen instead. This is independent of the clearing that the framebuffer logger does
+    /// on initialization.
+    pub clear_before_handoff: bool,
+    /// Instructs the bootloader to draw color bars to the framebuffer for a few seconds
+    /// before it starts logging.
+    ///
+    /// This helps to diagnose display issues: if the pixel format or the stride are set up
+    /// incorrectly, the bars are distorted or show the wrong colors. From left to right, the
+    /// bars are white, yellow, cyan, green, magenta, red, blue, and black.
+    pub self_test: bool,
+}
+
+/// Describes how a flat binary kernel is loaded, see [`BootConfig::flat_kernel`].
+///
+/// The fields also accept size strings in the JSON config, see [`size`].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FlatKernel {
+    /// The page-aligned virtual address at which the start of the kernel file is mapped.
+    ///
+    /// The kernel file is mapped as a whole, as writable and executable memory. There is no
+    /// zero-initialized memory after the end of the file, so the file must include any `.bss`
+    /// section of the kernel.
+    pub load_addr: u64,
+    /// The offset of the entry point relative to the start of the kernel file.
+    pub entry_offset: u64,
+}
+
+/// A range of physical memory, see [`BootConfig::reserved_memory`].
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct MemoryRange {
+    /// The physical start address of the range.
+    pub start: u64,
+    /// The length of the range in bytes.
+    pub len: u64,
+}
+
+impl MemoryRange {
+    /// Returns the physical end address (exclusive) of the range.
+    pub fn end(&self) -> u64 {
+        self.start.saturating_add(self.len)
+    }
+}
+
+/// A list of up to [`CAPACITY`](Self::CAPACITY) reserved memory ranges, see
+/// [`BootConfig::reserved_memory`].
+///
+/// Serialized as a JSON list of [`MemoryRange`] objects.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ReservedMemory {
+    ranges: [MemoryRange; Self::CAPACITY],
+    len: usize,
+}
+
+impl ReservedMemory {
+    /// The maximum number of ranges.
+    pub const CAPACITY: usize = 8;
+
+    /// Creates an empty list.
+    pub const fn new() -> Self {
+        Self {
+            ranges: [MemoryRange { start: 0, len: 0 }; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Adds the given range to the list.
+    ///
+    /// Returns the range back as error if the list is full.
+    pub fn push(&mut self, range: MemoryRange) -> Result<(), MemoryRange> {
+        let slot = self.ranges.get_mut(self.len).ok_or(range)?;
+        *slot = range;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns the ranges as a slice.
+    pub fn as_slice(&self) -> &[MemoryRange] {
+        &self.ranges[..self.len]
+    }
+
+    /// Returns the number of ranges.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Serialize for ReservedMemory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.as_slice())
+    }
+}
+
+impl<'de> Deserialize<'de> for ReservedMemory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReservedMemoryVisitor;
+
+        impl<'de> de::Visitor<'de> for ReservedMemoryVisitor {
+            type Value = ReservedMemory;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "a list of at most {} memory ranges",
+                    ReservedMemory::CAPACITY
+                )
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut list = ReservedMemory::new();
+                while let Some(range) = seq.next_element()? {
+                    list.push(range)
+                        .map_err(|_| de::Error::invalid_length(list.len() + 1, &self))?;
+                }
+                Ok(list)
+            }
+        }
+
+        deserializer.deserialize_seq(ReservedMemoryVisitor)
+    }
+}
+
+/// An EFI application that is chainloaded by the UEFI loader, see [`BootConfig::chainload`].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Chainload {
+    /// The path of the EFI application relative to the root of the boot partition, e.g.
+    /// `EFI/tools/shell.efi`.
+    pub path: FilePath,
+    /// Whether the application is only started if no kernel is found.
+    ///
+    /// By default, the application is started instead of the kernel.
+    #[serde(default)]
+    pub fallback: bool,
+}
+
+/// A file path of up to [`CAPACITY`](Self::CAPACITY) bytes, using `/` as separator.
+///
+/// Serialized as a JSON string.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FilePath {
+    bytes: [u8; Self::CAPACITY],
+    len: usize,
+}
+
+impl FilePath {
+    /// The maximum length of the path in bytes.
+    pub const CAPACITY: usize = 128;
+
+    /// Creates a path from the given string.
+    ///
+    /// Returns `None` if the string is empty, longer than [`CAPACITY`](Self::CAPACITY), or
+    /// contains null bytes.
+    pub fn new(path: &str) -> Option<Self> {
+        if path.is_empty() || path.len() > Self::CAPACITY || path.contains('\0') {
+            return None;
+        }
+        let mut bytes = [0; Self::CAPACITY];
+        bytes[..path.len()].copy_from_slice(path.as_bytes());
+        Some(Self {
+            bytes,
+            len: path.len(),
+        })
+    }
+
+    /// Returns the path as a string.
+    pub fn as_str(&self) -> &str {
+        // the bytes were copied from a valid string
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+impl Serialize for FilePath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for FilePath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FilePathVisitor;
+
+        impl de::Visitor<'_> for FilePathVisitor {
+            type Value = FilePath;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a path of at most {} bytes", FilePath::CAPACITY)
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<FilePath, E> {
+                FilePath::new(s).ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &self))
+            }
+        }
+
+        deserializer.deserialize_str(FilePathVisitor)
+    }
 }
 
 /// An enum representing the available verbosity level filters of the logger.
@@ -80,3 +508,913 @@ impl Default for LevelFilter {
         Self::Trace
     }
 }
+
+/// A GUID, e.g. a GPT partition type.
+///
+/// Serialized in the canonical string format, e.g. `"c12a7328-f81f-11d2-ba4b-00a0c93ec93b"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid {
+    bytes: [u8; 16],
+}
+
+impl Guid {
+    /// The length of the canonical string format.
+    const STRING_LEN: usize = 36;
+
+    /// Creates a GUID from its binary representation, as it is stored in GPT partition
+    /// entries.
+    ///
+    /// The first three fields of the GUID are stored in little-endian byte order.
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the binary representation of the GUID, as it is stored in GPT partition
+    /// entries.
+    pub const fn to_bytes(self) -> [u8; 16] {
+        self.bytes
+    }
+
+    /// Parses a GUID in the canonical string format, e.g.
+    /// `"c12a7328-f81f-11d2-ba4b-00a0c93ec93b"`.
+    ///
+    /// Both upper and lower case hexadecimal digits are accepted.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.as_bytes();
+        if s.len() != Self::STRING_LEN {
+            return None;
+        }
+        let mut canonical = [0u8; 16];
+        let mut digits = 0;
+        for (i, &c) in s.iter().enumerate() {
+            if matches!(i, 8 | 13 | 18 | 23) {
+                if c != b'-' {
+                    return None;
+                }
+                continue;
+            }
+            let value = (c as char).to_digit(16)? as u8;
+            canonical[digits / 2] |= value << if digits % 2 == 0 { 4 } else { 0 };
+            digits += 1;
+        }
+        Some(Self::from_bytes(Self::swap_fields(canonical)))
+    }
+
+    /// Converts between the canonical (big-endian) and the mixed-endian byte order.
+    fn swap_fields(mut bytes: [u8; 16]) -> [u8; 16] {
+        bytes[0..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        bytes
+    }
+
+    fn format(self, buffer: &mut [u8; Self::STRING_LEN]) -> &str {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let canonical = Self::swap_fields(self.bytes);
+        let mut pos = 0;
+        for (i, byte) in canonical.into_iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                buffer[pos] = b'-';
+                pos += 1;
+            }
+            buffer[pos] = HEX[usize::from(byte >> 4)];
+            buffer[pos + 1] = HEX[usize::from(byte & 0xf)];
+            pos += 2;
+        }
+        // only ASCII characters were written
+        core::str::from_utf8(buffer).unwrap()
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.format(&mut [0; Self::STRING_LEN]))
+    }
+}
+
+impl Serialize for Guid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.format(&mut [0; Self::STRING_LEN]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Guid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct GuidVisitor;
+
+        impl de::Visitor<'_> for GuidVisitor {
+            type Value = Guid;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a GUID string")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Guid, E> {
+                Guid::parse(s).ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &self))
+            }
+        }
+
+        deserializer.deserialize_str(GuidVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FilePath, Guid};
+
+    #[test]
+    fn guid_roundtrip() {
+        let s = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+        let guid = Guid::parse(s).unwrap();
+        assert_eq!(
+            guid.to_bytes(),
+            [
+                0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e,
+                0xc9, 0x3b
+            ]
+        );
+        assert_eq!(guid.to_string(), s);
+        assert_eq!(
+            Guid::parse("C12A7328-F81F-11D2-BA4B-00A0C93EC93B"),
+            Some(guid)
+        );
+    }
+
+    #[test]
+    fn invalid_guid() {
+        assert_eq!(Guid::parse(""), None);
+        assert_eq!(Guid::parse("c12a7328f81f11d2ba4b00a0c93ec93b"), None);
+        assert_eq!(Guid::parse("c12a7328-f81f-11d2-ba4b-00a0c93ec93"), None);
+        assert_eq!(Guid::parse("c12a7328-f81f-11d2-ba4b_00a0c93ec93b"), None);
+        assert_eq!(Guid::parse("g12a7328-f81f-11d2-ba4b-00a0c93ec93b"), None);
+    }
+
+    #[test]
+    fn file_paths() {
+        let path = FilePath::new("EFI/tools/shell.efi").unwrap();
+        assert_eq!(path.as_str(), "EFI/tools/shell.efi");
+        assert!(FilePath::new(&"a".repeat(FilePath::CAPACITY)).is_some());
+
+        assert_eq!(FilePath::new(""), None);
+        assert_eq!(FilePath::new("shell\0.efi"), None);
+        assert_eq!(FilePath::new(&"a".repeat(FilePath::CAPACITY + 1)), None);
+    }
+}
+
+/// Layout of the space that is reserved for an embedded boot config in the bootloader binaries.
+///
+/// The reserved space starts with [`MAGIC`](embedded::MAGIC), followed by the length of the
+/// embedded JSON config as a little-endian `u32` and the config itself. A length of zero
+/// means that no config is embedded.
+pub mod embedded {
+    /// Marks the start of the reserved space.
+    pub const MAGIC: [u8; 16] = *b"EMBEDDED-BOOTCFG";
+
+    /// The maximum length of an embedded JSON config in bytes.
+    pub const MAX_LEN: usize = 2048;
+
+    /// The offset of the little-endian `u32` length field.
+    pub const LEN_OFFSET: usize = MAGIC.len();
+
+    /// The offset of the JSON config.
+    pub const DATA_OFFSET: usize = LEN_OFFSET + 4;
+
+    /// The total size of the reserved space.
+    pub const SIZE: usize = DATA_OFFSET + MAX_LEN;
+
+    /// Returns the range of the JSON config that is embedded in the given binary.
+    ///
+    /// Returns `None` if the binary contains no reserved space, or if no config is embedded.
+    pub fn find(binary: &(impl crate::scan::Json + ?Sized)) -> Option<core::ops::Range<usize>> {
+        let mut start = 0;
+        while !(0..MAGIC.len()).all(|i| binary.byte(start + i) == Some(MAGIC[i])) {
+            binary.byte(start + MAGIC.len())?;
+            start += 1;
+        }
+        let mut len = [0; 4];
+        for (i, byte) in len.iter_mut().enumerate() {
+            *byte = binary.byte(start + LEN_OFFSET + i)?;
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        let data = start + DATA_OFFSET;
+        (len != 0 && len <= MAX_LEN).then_some(data..data + len)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn find_config() {
+            let mut binary = [0u8; SIZE + 8];
+            assert_eq!(find(&binary[..]), None);
+
+            binary[8..][..MAGIC.len()].copy_from_slice(&MAGIC);
+            assert_eq!(find(&binary[..]), None);
+
+            binary[8 + LEN_OFFSET] = 2;
+            assert_eq!(
+                find(&binary[..]),
+                Some(8 + DATA_OFFSET..8 + DATA_OFFSET + 2)
+            );
+
+            binary[8 + LEN_OFFSET..][..4].copy_from_slice(&(MAX_LEN as u32 + 1).to_le_bytes());
+            assert_eq!(find(&binary[..]), None);
+        }
+    }
+}
+
+/// Support for selecting one of multiple kernels that are stored in the same disk image.
+///
+/// If the boot partition contains a file named [`FILE_NAME`](default_kernel::FILE_NAME), the
+/// bootloader loads the kernel from the file whose name is stored in it, instead of the
+/// `kernel-x86_64` file. This allows switching between e.g. a debug and a release kernel by
+/// only rewriting this small file.
+pub mod default_kernel {
+    /// The name of the file that contains the name of the kernel file.
+    pub const FILE_NAME: &str = "default-kernel";
+
+    /// The maximum length of the kernel file name in bytes, after trimming whitespace.
+    ///
+    /// The name and a null terminator fit into the 256 character file name buffers of the
+    /// UEFI bootloader.
+    pub const MAX_NAME_LEN: usize = 255;
+
+    /// The maximum size of the [`FILE_NAME`] file in bytes, including whitespace.
+    ///
+    /// The BIOS bootloader only reads the first sector of the file.
+    pub const MAX_FILE_LEN: usize = 512;
+
+    /// Parses the contents of the [`FILE_NAME`] file.
+    ///
+    /// Leading and trailing whitespace, e.g. a trailing newline, is ignored. Returns `None`
+    /// if the contents are longer than [`MAX_FILE_LEN`], or if the name is empty, longer
+    /// than [`MAX_NAME_LEN`], not valid UTF-8, or contains null bytes.
+    pub fn parse(contents: &[u8]) -> Option<&str> {
+        if contents.len() > MAX_FILE_LEN {
+            return None;
+        }
+        let name = core::str::from_utf8(contents).ok()?.trim();
+        let valid = !name.is_empty() && name.len() <= MAX_NAME_LEN && !name.contains('\0');
+        valid.then_some(name)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_names() {
+            assert_eq!(parse(b"kernel-debug"), Some("kernel-debug"));
+            assert_eq!(parse(b"  kernels/release\n"), Some("kernels/release"));
+
+            assert_eq!(parse(b""), None);
+            assert_eq!(parse(b" \n"), None);
+            assert_eq!(parse(b"kernel\0"), None);
+            assert_eq!(parse(&[0xff, 0xfe]), None);
+            assert_eq!(parse(&[b'a'; MAX_NAME_LEN + 1]), None);
+
+            let mut long_name = [b' '; MAX_FILE_LEN];
+            long_name[..MAX_NAME_LEN].fill(b'a');
+            assert_eq!(parse(&long_name).map(str::len), Some(MAX_NAME_LEN));
+            assert_eq!(parse(&[b' '; MAX_FILE_LEN + 1]), None);
+        }
+    }
+}
+
+/// Layout of the module table in coreboot payloads.
+///
+/// When the BIOS bootloader is packaged as a coreboot payload, the stages, the kernel, and the
+/// other files are stored in an additional segment of the payload executable. The multiboot
+/// stage finds them through a table that is reserved in its executable and filled in by the
+/// disk image builder. The table starts with [`MAGIC`](coreboot_payload::MAGIC), followed by
+/// the number of modules as a little-endian `u32` and [`MAX_MODULES`](coreboot_payload::MAX_MODULES)
+/// entries. Each entry consists of the null-padded file name and the little-endian `u32`
+/// physical address and length of the file.
+pub mod coreboot_payload {
+    /// Marks the start of the module table.
+    pub const MAGIC: [u8; 16] = *b"COREBOOT-MODULES";
+
+    /// The maximum number of modules.
+    pub const MAX_MODULES: usize = 16;
+
+    /// The maximum length of a module name in bytes.
+    pub const NAME_LEN: usize = 32;
+
+    /// The offset of the little-endian `u32` module count.
+    pub const COUNT_OFFSET: usize = MAGIC.len();
+
+    /// The offset of the first entry.
+    pub const ENTRIES_OFFSET: usize = COUNT_OFFSET + 4;
+
+    /// The size of an entry: the name, followed by the address and the length.
+    pub const ENTRY_SIZE: usize = NAME_LEN + 4 + 4;
+
+    /// The total size of the module table.
+    pub const SIZE: usize = ENTRIES_OFFSET + MAX_MODULES * ENTRY_SIZE;
+
+    /// Returns the module name that is stored in the given null-padded name field.
+    ///
+    /// Returns `None` if the name is empty or not valid UTF-8.
+    pub fn parse_name(name: &[u8; NAME_LEN]) -> Option<&str> {
+        let len = name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+        let name = core::str::from_utf8(&name[..len]).ok()?;
+        (!name.is_empty()).then_some(name)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_names() {
+            let mut name = [0; NAME_LEN];
+            assert_eq!(parse_name(&name), None);
+            name[..6].copy_from_slice(b"kernel");
+            assert_eq!(parse_name(&name), Some("kernel"));
+            let name = [b'a'; NAME_LEN];
+            assert_eq!(parse_name(&name).map(str::len), Some(NAME_LEN));
+            let mut name = [0; NAME_LEN];
+            name[0] = 0xff;
+            assert_eq!(parse_name(&name), None);
+        }
+    }
+}
+
+/// PC speaker beeps and POST codes that signal boot failures, see
+/// [`BootConfig::failure_signals`](crate::BootConfig::failure_signals).
+///
+/// Used both by the second BIOS stage, which reports a missing kernel file, and by the later
+/// stages, so that all of them produce the same patterns.
+pub mod failure {
+    /// The I/O port used by BIOS firmware for POST codes.
+    const POST_CODE_PORT: u16 = 0x80;
+    /// The data port of channel 2 of the programmable interval timer, which drives the PC
+    /// speaker.
+    const PIT_CHANNEL_2: u16 = 0x42;
+    const PIT_COMMAND: u16 = 0x43;
+    /// Selects channel 2, low/high byte access, and square wave mode.
+    const PIT_CHANNEL_2_SQUARE_WAVE: u8 = 0b1011_0110;
+    /// The system control port B, whose lowest two bits gate the PIT channel 2 output to the
+    /// PC speaker.
+    const SPEAKER_CONTROL: u16 = 0x61;
+    const SPEAKER_ENABLE: u8 = 0b11;
+
+    const PIT_FREQUENCY: u32 = 1_193_182;
+    const BEEP_FREQUENCY: u32 = 880;
+    const BEEP_MS: u32 = 200;
+    const PAUSE_MS: u32 = 1000;
+
+    /// Known classes of boot failures that are signaled through distinct patterns.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum FailureKind {
+        /// Any failure that doesn't belong to one of the other classes.
+        Other = 0,
+        /// The kernel file could not be found.
+        KernelNotFound = 1,
+        /// The kernel file could not be parsed.
+        InvalidKernel = 2,
+        /// The bootloader ran out of physical memory.
+        OutOfMemory = 3,
+    }
+
+    impl FailureKind {
+        /// The POST code that is written to port `0x80` for this failure.
+        pub fn post_code(self) -> u8 {
+            0xe0 | self as u8
+        }
+
+        /// The number of short beeps in the repeated beep pattern for this failure.
+        pub fn beep_count(self) -> u32 {
+            self as u32 + 1
+        }
+
+        /// Converts a value that was created through `kind as u8` back to the failure kind.
+        ///
+        /// Unknown values are mapped to [`FailureKind::Other`].
+        pub fn from_u8(value: u8) -> Self {
+            match value {
+                1 => FailureKind::KernelNotFound,
+                2 => FailureKind::InvalidKernel,
+                3 => FailureKind::OutOfMemory,
+                _ => FailureKind::Other,
+            }
+        }
+    }
+
+    /// Writes the POST code of the given failure to port `0x80` and repeats its beep pattern
+    /// on the PC speaker forever.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn signal(kind: FailureKind) -> ! {
+        let post_code = kind.post_code();
+        unsafe { outb(POST_CODE_PORT, post_code) };
+        loop {
+            for _ in 0..kind.beep_count() {
+                unsafe { speaker_on(BEEP_FREQUENCY) };
+                delay_ms(BEEP_MS, post_code);
+                unsafe { speaker_off() };
+                delay_ms(BEEP_MS, post_code);
+            }
+            delay_ms(PAUSE_MS, post_code);
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe fn speaker_on(frequency: u32) {
+        let divisor = (PIT_FREQUENCY / frequency) as u16;
+        unsafe {
+            outb(PIT_COMMAND, PIT_CHANNEL_2_SQUARE_WAVE);
+            outb(PIT_CHANNEL_2, divisor as u8);
+            outb(PIT_CHANNEL_2, (divisor >> 8) as u8);
+            outb(SPEAKER_CONTROL, inb(SPEAKER_CONTROL) | SPEAKER_ENABLE);
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe fn speaker_off() {
+        unsafe { outb(SPEAKER_CONTROL, inb(SPEAKER_CONTROL) & !SPEAKER_ENABLE) };
+    }
+
+    /// Busy-waits for roughly the given number of milliseconds.
+    ///
+    /// Each write to the POST code port takes about a microsecond, so rewriting the current
+    /// POST code doubles as a timer that doesn't need any setup.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn delay_ms(ms: u32, post_code: u8) {
+        for _ in 0..ms * 1000 {
+            unsafe { outb(POST_CODE_PORT, post_code) };
+        }
+    }
+
+    // The port I/O is implemented through inline assembly because the second stage can't
+    // depend on the `x86_64` crate.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe fn outb(port: u16, value: u8) {
+        unsafe {
+            core::arch::asm!(
+                "out dx, al",
+                in("dx") port,
+                in("al") value,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    unsafe fn inb(port: u16) -> u8 {
+        let value: u8;
+        unsafe {
+            core::arch::asm!(
+                "in al, dx",
+                in("dx") port,
+                out("al") value,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+        value
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn distinct_patterns() {
+            let kinds = [
+                FailureKind::Other,
+                FailureKind::KernelNotFound,
+                FailureKind::InvalidKernel,
+                FailureKind::OutOfMemory,
+            ];
+            for (i, a) in kinds.iter().enumerate() {
+                assert_eq!(FailureKind::from_u8(*a as u8), *a);
+                for b in &kinds[i + 1..] {
+                    assert_ne!(a.post_code(), b.post_code());
+                    assert_ne!(a.beep_count(), b.beep_count());
+                }
+            }
+        }
+    }
+}
+
+/// Support for hexadecimal and size-suffixed values (e.g. `"0x4000"` or `"64K"`) in the
+/// numeric fields of the JSON config.
+///
+/// The JSON parser used by the bootloader can't deserialize values that are either numbers or
+/// strings, so size strings are replaced by plain numbers through [`normalize`] before the
+/// config is deserialized. Plain numbers are kept as they are.
+pub mod size {
+    /// The numeric fields of the config that accept size strings.
+    pub const FIELDS: &[&str] = &[
+        "minimum_framebuffer_height",
+        "minimum_framebuffer_width",
+        "maximum_framebuffer_height",
+        "maximum_framebuffer_width",
+        "rsdp_addr",
+        "load_addr",
+        "entry_offset",
+        "start",
+        "len",
+    ];
+
+    /// The numeric fields of the config that accept hexadecimal strings, but no size suffix.
+    pub const INTEGER_FIELDS: &[&str] = &["debug_serial_port"];
+
+    /// Parses a size string.
+    ///
+    /// The value is either a decimal number or a hexadecimal number with a `0x` prefix. It can
+    /// be followed by one of the binary suffixes `K`, `M`, `G`, or `T` (case-insensitive).
+    /// Returns `None` if the string is invalid or the value overflows.
+    pub fn parse(s: &str) -> Option<u64> {
+        let (digits, shift) = match s.as_bytes().last()? {
+            b'K' | b'k' => (&s[..s.len() - 1], 10),
+            b'M' | b'm' => (&s[..s.len() - 1], 20),
+            b'G' | b'g' => (&s[..s.len() - 1], 30),
+            b'T' | b't' => (&s[..s.len() - 1], 40),
+            _ => (s, 0),
+        };
+        parse_integer(digits)?.checked_mul(1 << shift)
+    }
+
+    /// Parses a decimal number or a hexadecimal number with a `0x` prefix.
+    ///
+    /// Returns `None` if the string is invalid or the value overflows.
+    pub fn parse_integer(s: &str) -> Option<u64> {
+        match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                u64::from_str_radix(hex, 16).ok()
+            }
+            Some(_) => None,
+            None if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => s.parse().ok(),
+            None => None,
+        }
+    }
+
+    /// The output buffer passed to [`normalize`] is too small.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct BufferTooSmall;
+
+    /// Copies the given JSON config to `out`, replacing valid size strings in the [`FIELDS`]
+    /// and valid integer strings in the [`INTEGER_FIELDS`] by plain numbers.
+    ///
+    /// Returns the length of the normalized config, or `None` if the config contains no size
+    /// strings. In the latter case, `out` is not modified and the config can be deserialized
+    /// directly. Invalid size strings are kept, so that they are reported by the deserializer.
+    pub fn normalize(json: &[u8], out: &mut [u8]) -> Result<Option<usize>, BufferTooSmall> {
+        if !replace_sizes(json, |_| Ok(()))? {
+            return Ok(None);
+        }
+
+        let mut len = 0;
+        replace_sizes(json, |part| {
+            let dst = out.get_mut(len..len + part.len()).ok_or(BufferTooSmall)?;
+            dst.copy_from_slice(part);
+            len += part.len();
+            Ok(())
+        })?;
+        Ok(Some(len))
+    }
+
+    /// Passes the given JSON config in parts to `emit`, with size strings replaced by plain
+    /// numbers.
+    ///
+    /// Returns whether any size string was replaced.
+    fn replace_sizes(
+        json: &[u8],
+        mut emit: impl FnMut(&[u8]) -> Result<(), BufferTooSmall>,
+    ) -> Result<bool, BufferTooSmall> {
+        let mut replaced = false;
+        // start of the input that was not passed to `emit` yet
+        let mut copied = 0;
+        let mut pos = 0;
+        // the last string, which is an object key if it is followed by a colon
+        let mut last_string = None;
+        // the key of the value that follows
+        let mut value_key = None;
+
+        while let Some(&byte) = json.get(pos) {
+            match byte {
+                b'"' => {
+                    let Ok(end) = crate::scan::skip_string(json, pos) else {
+                        // unterminated string, reported by the deserializer
+                        break;
+                    };
+                    let string = &json[pos + 1..end - 1];
+                    let is_field = |fields: &[&str], key: &[u8]| {
+                        fields.iter().any(|field| field.as_bytes() == key)
+                    };
+                    let size = value_key.take().and_then(|key| {
+                        let string = core::str::from_utf8(string).ok()?;
+                        if is_field(FIELDS, key) {
+                            parse(string)
+                        } else if is_field(INTEGER_FIELDS, key) {
+                            parse_integer(string)
+                        } else {
+                            None
+                        }
+                    });
+                    match size {
+                        Some(size) => {
+                            emit(&json[copied..pos])?;
+                            let mut buffer = [0; 20];
+                            emit(format_decimal(size, &mut buffer))?;
+                            copied = end;
+                            replaced = true;
+                            last_string = None;
+                        }
+                        None => last_string = Some(string),
+                    }
+                    pos = end;
+                }
+                b':' => {
+                    value_key = last_string.take();
+                    pos += 1;
+                }
+                b' ' | b'\n' | b'\r' | b'\t' => pos += 1,
+                _ => {
+                    last_string = None;
+                    value_key = None;
+                    pos += 1;
+                }
+            }
+        }
+
+        emit(&json[copied..])?;
+        Ok(replaced)
+    }
+
+    fn format_decimal(mut value: u64, buffer: &mut [u8; 20]) -> &[u8] {
+        let mut start = buffer.len();
+        loop {
+            start -= 1;
+            buffer[start] = b'0' + (value % 10) as u8;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        &buffer[start..]
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parse_sizes() {
+            assert_eq!(parse("4096"), Some(4096));
+            assert_eq!(parse("0x4000"), Some(0x4000));
+            assert_eq!(parse("0XfF"), Some(0xff));
+            assert_eq!(parse("64K"), Some(64 * 1024));
+            assert_eq!(parse("2m"), Some(2 * 1024 * 1024));
+            assert_eq!(parse("0x10G"), Some(0x10 << 30));
+            assert_eq!(parse("1T"), Some(1 << 40));
+
+            assert_eq!(parse(""), None);
+            assert_eq!(parse("K"), None);
+            assert_eq!(parse("0x"), None);
+            assert_eq!(parse("-1"), None);
+            assert_eq!(parse("+1"), None);
+            assert_eq!(parse("1.5M"), None);
+            assert_eq!(parse("0x1g0"), None);
+            assert_eq!(parse("Info"), None);
+            assert_eq!(parse("16777216T"), None);
+        }
+
+        #[test]
+        fn parse_integers() {
+            assert_eq!(parse_integer("1016"), Some(1016));
+            assert_eq!(parse_integer("0x2f8"), Some(0x2f8));
+
+            assert_eq!(parse_integer("1K"), None);
+            assert_eq!(parse_integer("0x1k"), None);
+            assert_eq!(parse_integer(""), None);
+        }
+
+        fn normalized(json: &str) -> Option<String> {
+            let mut out = [0; 256];
+            normalize(json.as_bytes(), &mut out)
+                .unwrap()
+                .map(|len| String::from_utf8(out[..len].to_vec()).unwrap())
+        }
+
+        #[test]
+        fn normalize_size_strings() {
+            assert_eq!(
+                normalized(r#"{"rsdp_addr": "0xe0000", "log_level": "Info"}"#).as_deref(),
+                Some(r#"{"rsdp_addr": 917504, "log_level": "Info"}"#)
+            );
+            assert_eq!(
+                normalized(r#"{"frame_buffer": {"minimum_framebuffer_width": "1K"}}"#).as_deref(),
+                Some(r#"{"frame_buffer": {"minimum_framebuffer_width": 1024}}"#)
+            );
+        }
+
+        #[test]
+        fn normalize_keeps_other_values() {
+            // plain numbers, strings of other fields, and invalid sizes are not modified
+            assert_eq!(normalized(r#"{"rsdp_addr": 917504}"#), None);
+            assert_eq!(normalized(r#"{"log_level": "64K"}"#), None);
+            assert_eq!(normalized(r#"{"rsdp_addr": "abc"}"#), None);
+            assert_eq!(normalized(r#"["rsdp_addr", "64K"]"#), None);
+            // integer fields don't accept size suffixes
+            assert_eq!(normalized(r#"{"debug_serial_port": "1K"}"#), None);
+        }
+
+        #[test]
+        fn normalize_buffer_too_small() {
+            let mut out = [0; 8];
+            assert_eq!(
+                normalize(br#"{"rsdp_addr": "0xe0000"}"#, &mut out),
+                Err(BufferTooSmall)
+            );
+        }
+    }
+}
+
+/// Lookup of single values in the JSON config without deserializing it.
+///
+/// Deserializing the full config is too expensive for the early BIOS stages, and the second
+/// stage can only access the config byte by byte. So these stages look up the few values
+/// they need through the functions of this module. The functions only check the structure of
+/// the JSON text as far as required to find the values.
+pub mod scan {
+    use core::ops::Range;
+
+    /// JSON text that is accessed byte by byte.
+    pub trait Json {
+        /// Returns the byte at the given offset, or `None` if the offset lies behind the end
+        /// of the text.
+        fn byte(&self, offset: usize) -> Option<u8>;
+    }
+
+    impl Json for [u8] {
+        fn byte(&self, offset: usize) -> Option<u8> {
+            self.get(offset).copied()
+        }
+    }
+
+    /// Returns the offset of the first non-whitespace byte at or after `pos`.
+    pub fn skip_whitespace(json: &(impl Json + ?Sized), mut pos: usize) -> usize {
+        while let Some(b' ' | b'\n' | b'\r' | b'\t') = json.byte(pos) {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Returns the offset after the closing quote of the string that starts at `pos`.
+    ///
+    /// Returns the end of the text as error offset if the string is not terminated.
+    pub fn skip_string(json: &(impl Json + ?Sized), pos: usize) -> Result<usize, usize> {
+        let mut escaped = false;
+        let mut i = pos + 1;
+        while let Some(byte) = json.byte(i) {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => return Ok(i + 1),
+                _ => {}
+            }
+            i += 1;
+        }
+        Err(i)
+    }
+
+    /// Returns the offset after the value that starts at `pos`.
+    ///
+    /// Only the nesting of objects and arrays is checked, the contents are not validated.
+    pub fn skip_value(json: &(impl Json + ?Sized), pos: usize) -> Result<usize, usize> {
+        match json.byte(pos) {
+            None | Some(b',' | b'}' | b']') => Err(pos),
+            Some(b'"') => skip_string(json, pos),
+            Some(b'{' | b'[') => {
+                let mut depth = 0usize;
+                let mut i = pos;
+                while let Some(byte) = json.byte(i) {
+                    match byte {
+                        b'"' => {
+                            i = skip_string(json, i)?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return Ok(i + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                Err(i)
+            }
+            Some(_) => {
+                let mut i = pos;
+                while let Some(byte) = json.byte(i) {
+                    if matches!(byte, b',' | b'}' | b']' | b' ' | b'\n' | b'\r' | b'\t') {
+                        break;
+                    }
+                    i += 1;
+                }
+                Ok(i)
+            }
+        }
+    }
+
+    /// Returns the range of the value of the first field with the given name.
+    ///
+    /// Fields of nested objects are found too, e.g. the fields of the `frame_buffer` object.
+    /// Strings that are not followed by a colon, e.g. string values, are skipped.
+    pub fn find_value(json: &(impl Json + ?Sized), key: &str) -> Option<Range<usize>> {
+        let mut pos = 0;
+        loop {
+            if json.byte(pos)? != b'"' {
+                pos += 1;
+                continue;
+            }
+            let end = skip_string(json, pos).ok()?;
+            let colon = skip_whitespace(json, end);
+            if equals(json, pos + 1..end - 1, key.as_bytes()) && json.byte(colon) == Some(b':') {
+                let start = skip_whitespace(json, colon + 1);
+                return Some(start..skip_value(json, start).ok()?);
+            }
+            pos = end;
+        }
+    }
+
+    /// Returns the value of the first boolean field with the given name.
+    ///
+    /// Returns `None` if the field doesn't exist or has a non-boolean value.
+    pub fn find_bool(json: &(impl Json + ?Sized), key: &str) -> Option<bool> {
+        let value = find_value(json, key)?;
+        if equals(json, value.clone(), b"true") {
+            Some(true)
+        } else if equals(json, value, b"false") {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the first numeric field with the given name.
+    ///
+    /// The value is parsed through [`size::parse`](crate::size::parse), so it can be a plain
+    /// number or a size string. Returns `None` if the field doesn't exist or has a different
+    /// value.
+    pub fn find_number(json: &(impl Json + ?Sized), key: &str) -> Option<u64> {
+        let mut value = find_value(json, key)?;
+        if json.byte(value.start) == Some(b'"') {
+            value = value.start + 1..value.end - 1;
+        }
+        let mut buffer = [0; 24];
+        let buffer = buffer.get_mut(..value.len())?;
+        for (dst, offset) in buffer.iter_mut().zip(value) {
+            *dst = json.byte(offset)?;
+        }
+        crate::size::parse(core::str::from_utf8(buffer).ok()?)
+    }
+
+    fn equals(json: &(impl Json + ?Sized), range: Range<usize>, expected: &[u8]) -> bool {
+        range.len() == expected.len()
+            && range
+                .zip(expected)
+                .all(|(offset, &b)| json.byte(offset) == Some(b))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const CONFIG: &[u8] = br#"{
+            "log_level": "serial_logging",
+            "frame_buffer": { "maximum_framebuffer_width": "1K", "minimum_framebuffer_width": 640 },
+            "serial_logging" : false,
+            "reserved_memory": [{ "start": "0x1000", "len": 4096 }]
+        }"#;
+
+        #[test]
+        fn find_values() {
+            assert_eq!(find_bool(CONFIG, "serial_logging"), Some(false));
+            assert_eq!(find_number(CONFIG, "maximum_framebuffer_width"), Some(1024));
+            assert_eq!(find_number(CONFIG, "minimum_framebuffer_width"), Some(640));
+            assert_eq!(find_number(CONFIG, "start"), Some(0x1000));
+            let value = find_value(CONFIG, "reserved_memory").unwrap();
+            assert_eq!(CONFIG[value.end - 1], b']');
+
+            assert_eq!(find_bool(CONFIG, "log_level"), None);
+            assert_eq!(find_number(CONFIG, "log_level"), None);
+            assert_eq!(find_value(CONFIG, "safe_mode"), None);
+            assert_eq!(find_value(CONFIG, "frame"), None);
+        }
+
+        #[test]
+        fn skip_invalid_values() {
+            assert_eq!(skip_string(&br#""abc\"d"#[..], 0), Err(7));
+            assert_eq!(skip_value(&b"[1, {]"[..], 0), Err(6));
+            assert_eq!(skip_value(&b", 1"[..], 0), Err(0));
+            assert_eq!(skip_value(&b"123}"[..], 0), Ok(3));
+        }
+    }
+}
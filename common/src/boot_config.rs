@@ -0,0 +1,383 @@
+use bootloader_boot_config::{
+    scan::{skip_string, skip_value, skip_whitespace},
+    size, BootConfig,
+};
+use core::{fmt, str};
+
+/// The maximum size of a single `"field": value` pair that is checked separately to find the
+/// field that failed to deserialize.
+const FIELD_BUFFER_SIZE: usize = 256;
+
+/// The maximum size of a config that contains size strings.
+///
+/// The normalized config is stored on the stack, which is small on BIOS systems. Larger
+/// configs are deserialized without replacing their size strings, so they fail to deserialize
+/// if they contain any.
+const NORMALIZED_CONFIG_SIZE: usize = 2048;
+
+/// Parses the given `boot.json` contents.
+///
+/// Size strings in numeric fields (e.g. `"64K"`) are supported, see [`size`]. On error, the
+/// returned [`BootConfigError`] contains the location of the error if it can be determined.
+pub fn parse_boot_config(json: &[u8]) -> Result<BootConfig, BootConfigError<'_>> {
+    match deserialize(json, &mut [0; NORMALIZED_CONFIG_SIZE]) {
+        Ok(config) => Ok(config),
+        Err(error) => Err(BootConfigError {
+            error,
+            location: locate_error(json),
+        }),
+    }
+}
+
+/// Deserializes the given config, using `buffer` to replace size strings.
+fn deserialize(json: &[u8], buffer: &mut [u8]) -> Result<BootConfig, serde_json_core::de::Error> {
+    let json = match size::normalize(json, buffer) {
+        Ok(Some(len)) => &buffer[..len],
+        Ok(None) | Err(size::BufferTooSmall) => json,
+    };
+    serde_json_core::from_slice(json).map(|(config, _)| config)
+}
+
+/// A boot config that could not be deserialized.
+#[derive(Debug)]
+pub struct BootConfigError<'a> {
+    /// The error reported by the JSON deserializer.
+    pub error: serde_json_core::de::Error,
+    /// The location of the error, if known.
+    pub location: Option<ErrorLocation<'a>>,
+}
+
+/// The location of a [`BootConfigError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorLocation<'a> {
+    /// The byte offset of the invalid value or the invalid syntax.
+    pub offset: usize,
+    /// The name of the affected top-level field, if any.
+    ///
+    /// For syntax errors between two fields, this is the last field before the error.
+    pub field: Option<&'a str>,
+}
+
+impl fmt::Display for BootConfigError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(location) = self.location {
+            write!(f, " at byte offset {}", location.offset)?;
+            if let Some(field) = location.field {
+                write!(f, " (field `{field}`)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scans the top-level fields of the given JSON object to find the location of a
+/// deserialization error.
+///
+/// Syntax errors are reported at the offset where the scanner failed. Each syntactically valid
+/// field is deserialized on its own to find fields with invalid values.
+fn locate_error(json: &[u8]) -> Option<ErrorLocation<'_>> {
+    let error_at = |offset, field| Some(ErrorLocation { offset, field });
+
+    let mut pos = skip_whitespace(json, 0);
+    if json.get(pos) != Some(&b'{') {
+        return error_at(pos, None);
+    }
+    pos = skip_whitespace(json, pos + 1);
+    if json.get(pos) == Some(&b'}') {
+        return trailing_characters(json, pos + 1, None);
+    }
+
+    let mut last_field = None;
+    loop {
+        if json.get(pos) != Some(&b'"') {
+            return error_at(pos, last_field);
+        }
+        let key_end = match skip_string(json, pos) {
+            Ok(end) => end,
+            Err(offset) => return error_at(offset, last_field),
+        };
+        let key = &json[pos..key_end];
+        let field = str::from_utf8(&key[1..key.len() - 1]).ok();
+
+        pos = skip_whitespace(json, key_end);
+        if json.get(pos) != Some(&b':') {
+            return error_at(pos, field);
+        }
+        let value_start = skip_whitespace(json, pos + 1);
+        pos = match skip_value(json, value_start) {
+            Ok(end) => end,
+            Err(offset) => return error_at(offset, field),
+        };
+        if !field_is_valid(key, &json[value_start..pos]) {
+            return error_at(value_start, field);
+        }
+        last_field = field;
+
+        pos = skip_whitespace(json, pos);
+        match json.get(pos) {
+            Some(b',') => pos = skip_whitespace(json, pos + 1),
+            Some(b'}') => return trailing_characters(json, pos + 1, last_field),
+            _ => return error_at(pos, last_field),
+        }
+    }
+}
+
+/// Reports non-whitespace characters after the end of the top-level object.
+fn trailing_characters<'a>(
+    json: &'a [u8],
+    pos: usize,
+    field: Option<&'a str>,
+) -> Option<ErrorLocation<'a>> {
+    let pos = skip_whitespace(json, pos);
+    (pos < json.len()).then_some(ErrorLocation { offset: pos, field })
+}
+
+/// Checks whether a config that only consists of the given field can be deserialized.
+fn field_is_valid(key: &[u8], value: &[u8]) -> bool {
+    let mut buffer = [0; FIELD_BUFFER_SIZE];
+    let parts: [&[u8]; 5] = [b"{", key, b":", value, b"}"];
+    let len = parts.iter().map(|part| part.len()).sum();
+    if len > buffer.len() {
+        // too large to check, assume that it's valid
+        return true;
+    }
+
+    let mut offset = 0;
+    for part in parts {
+        buffer[offset..][..part.len()].copy_from_slice(part);
+        offset += part.len();
+    }
+    deserialize(&buffer[..len], &mut [0; FIELD_BUFFER_SIZE]).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bootloader_boot_config::{MemoryRange, ReservedMemory};
+
+    fn location(json: &str) -> Option<(usize, Option<&str>)> {
+        let error = parse_boot_config(json.as_bytes()).err().unwrap();
+        error.location.map(|l| (l.offset, l.field))
+    }
+
+    #[test]
+    fn valid_config() {
+        let config = parse_boot_config(br#"{ "log_level": "Info", "serial_logging": false }"#);
+        assert!(!config.unwrap().serial_logging);
+    }
+
+    #[test]
+    fn size_strings() {
+        let json =
+            br#"{ "rsdp_addr": "0xe0000", "frame_buffer": { "minimum_framebuffer_width": "1K" } }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert_eq!(config.rsdp_addr, Some(0xe0000));
+        assert_eq!(config.frame_buffer.minimum_framebuffer_width, Some(1024));
+
+        let json =
+            br#"{ "flat_kernel": { "load_addr": "0xffff800000000000", "entry_offset": 16 } }"#;
+        let flat_kernel = parse_boot_config(json).unwrap().flat_kernel.unwrap();
+        assert_eq!(flat_kernel.load_addr, 0xffff_8000_0000_0000);
+        assert_eq!(flat_kernel.entry_offset, 16);
+
+        let json = r#"{ "log_level": "Info", "rsdp_addr": "0xe000z" }"#;
+        assert_eq!(location(json), Some((36, Some("rsdp_addr"))));
+    }
+
+    #[test]
+    fn kernel_partition_type() {
+        let json = br#"{ "kernel_partition_type": "0fc63daf-8483-4772-8e79-3d69d8477de4" }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert_eq!(
+            config.kernel_partition_type.unwrap().to_string(),
+            "0fc63daf-8483-4772-8e79-3d69d8477de4"
+        );
+
+        let json = r#"{ "log_level": "Info", "kernel_partition_type": "0fc63daf" }"#;
+        assert_eq!(location(json), Some((48, Some("kernel_partition_type"))));
+    }
+
+    #[test]
+    fn debug_serial_port() {
+        let json = br#"{ "debug_serial_port": "0x2f8" }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert_eq!(config.debug_serial_port, Some(0x2f8));
+
+        let json = br#"{ "debug_serial_port": 744 }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert_eq!(config.debug_serial_port, Some(0x2e8));
+
+        let json = br#"{ "debug_serial_port": "1K" }"#;
+        assert!(parse_boot_config(json).is_err());
+    }
+
+    #[test]
+    fn gop_mode() {
+        let json = br#"{ "frame_buffer": { "gop_mode": 3 } }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert_eq!(config.frame_buffer.gop_mode, Some(3));
+        assert_eq!(config.frame_buffer.minimum_framebuffer_width, None);
+        assert!(!config.frame_buffer.keep_current_mode);
+
+        let json = br#"{ "frame_buffer": { "keep_current_mode": true } }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert!(config.frame_buffer.keep_current_mode);
+    }
+
+    #[test]
+    fn clear_framebuffer_before_handoff() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(!config.frame_buffer.clear_before_handoff);
+
+        let json = br#"{ "frame_buffer": { "clear_before_handoff": true } }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert!(config.frame_buffer.clear_before_handoff);
+    }
+
+    #[test]
+    fn failure_signals() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(!config.failure_signals);
+
+        let config = parse_boot_config(br#"{ "failure_signals": true }"#).unwrap();
+        assert!(config.failure_signals);
+    }
+
+    #[test]
+    fn framebuffer_self_test() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(!config.frame_buffer.self_test);
+
+        let json = br#"{ "frame_buffer": { "self_test": true } }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert!(config.frame_buffer.self_test);
+    }
+
+    #[test]
+    fn dump_page_tables() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(!config.dump_page_tables);
+
+        let config = parse_boot_config(br#"{ "dump_page_tables": true }"#).unwrap();
+        assert!(config.dump_page_tables);
+    }
+
+    #[test]
+    fn memory_test() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(!config.memory_test);
+
+        let config = parse_boot_config(br#"{ "memory_test": true }"#).unwrap();
+        assert!(config.memory_test);
+    }
+
+    #[test]
+    fn kernel_stack_numa_node() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert_eq!(config.kernel_stack_numa_node, None);
+
+        let config = parse_boot_config(br#"{ "kernel_stack_numa_node": 1 }"#).unwrap();
+        assert_eq!(config.kernel_stack_numa_node, Some(1));
+    }
+
+    #[test]
+    fn chainload() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert_eq!(config.chainload, None);
+
+        let config =
+            parse_boot_config(br#"{ "chainload": { "path": "EFI/tools/shell.efi" } }"#).unwrap();
+        let chainload = config.chainload.unwrap();
+        assert_eq!(chainload.path.as_str(), "EFI/tools/shell.efi");
+        assert!(!chainload.fallback);
+
+        let json = br#"{ "chainload": { "path": "shell.efi", "fallback": true } }"#;
+        assert!(parse_boot_config(json).unwrap().chainload.unwrap().fallback);
+        assert!(parse_boot_config(br#"{ "chainload": { "path": "" } }"#).is_err());
+    }
+
+    #[test]
+    fn stage_markers() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(!config.stage_markers);
+
+        let config = parse_boot_config(br#"{ "stage_markers": true }"#).unwrap();
+        assert!(config.stage_markers);
+    }
+
+    #[test]
+    fn disk_read_retries() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert_eq!(config.disk_read_retries, 3);
+
+        let config = parse_boot_config(br#"{ "disk_read_retries": 10 }"#).unwrap();
+        assert_eq!(config.disk_read_retries, 10);
+    }
+
+    #[test]
+    fn reserved_memory() {
+        let config = parse_boot_config(b"{}").unwrap();
+        assert!(config.reserved_memory.is_empty());
+
+        let json = br#"{ "reserved_memory": [
+            { "start": "0x9000000", "len": "64K" },
+            { "start": 268435456, "len": 4096 }
+        ] }"#;
+        let config = parse_boot_config(json).unwrap();
+        assert_eq!(
+            config.reserved_memory.as_slice(),
+            [
+                MemoryRange {
+                    start: 0x900_0000,
+                    len: 0x1_0000
+                },
+                MemoryRange {
+                    start: 0x1000_0000,
+                    len: 0x1000
+                }
+            ]
+        );
+
+        let range = r#"{ "start": 0, "len": 1 }"#;
+        let ranges = [range; ReservedMemory::CAPACITY + 1].join(", ");
+        let json = format!(r#"{{ "reserved_memory": [{ranges}] }}"#);
+        assert_eq!(location(&json), Some((21, Some("reserved_memory"))));
+    }
+
+    #[test]
+    fn invalid_value() {
+        let json = r#"{ "serial_logging": false, "log_level": 5 }"#;
+        assert_eq!(location(json), Some((40, Some("log_level"))));
+
+        let json = r#"{"frame_buffer": {"minimum_framebuffer_width": "wide"}}"#;
+        assert_eq!(location(json), Some((17, Some("frame_buffer"))));
+    }
+
+    #[test]
+    fn syntax_error() {
+        // missing comma
+        let json = r#"{ "serial_logging": false "log_level": "Info" }"#;
+        assert_eq!(location(json), Some((26, Some("serial_logging"))));
+
+        // missing colon
+        let json = r#"{ "serial_logging" false }"#;
+        assert_eq!(location(json), Some((19, Some("serial_logging"))));
+
+        // unterminated object
+        let json = r#"{ "frame_buffer": { "minimum_framebuffer_width": 1 "#;
+        assert_eq!(location(json), Some((json.len(), Some("frame_buffer"))));
+
+        // trailing characters
+        let json = r#"{ "serial_logging": false } x"#;
+        assert_eq!(location(json), Some((28, Some("serial_logging"))));
+    }
+
+    #[test]
+    fn error_message() {
+        let error = parse_boot_config(br#"{"log_level": 5}"#).err().unwrap();
+        assert!(error
+            .to_string()
+            .ends_with(" at byte offset 14 (field `log_level`)"));
+    }
+}
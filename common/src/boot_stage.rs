@@ -0,0 +1,94 @@
+use crate::serial::LOG_PORT;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The prefix of every stage marker line, followed by the [name](BootStage::name) of the
+/// stage.
+pub const MARKER_PREFIX: &str = "BOOTSTAGE:";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The stage boundaries of the boot process, in the order in which they are reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStage {
+    /// The boot config was loaded, or the default config is used.
+    ConfigLoaded,
+    /// The kernel executable was loaded into memory and parsed.
+    KernelLoaded,
+    /// The memory map was read and the page tables for the kernel were created.
+    MemoryMapDone,
+    /// The kernel was mapped into its address space.
+    MappingsDone,
+    /// The boot info was created in the kernel address space.
+    BootInfoDone,
+    /// The bootloader is about to jump to the kernel entry point.
+    Handoff,
+}
+
+impl BootStage {
+    /// All stages, in the order in which they are reached.
+    pub const ALL: [BootStage; 6] = [
+        BootStage::ConfigLoaded,
+        BootStage::KernelLoaded,
+        BootStage::MemoryMapDone,
+        BootStage::MappingsDone,
+        BootStage::BootInfoDone,
+        BootStage::Handoff,
+    ];
+
+    /// The name of the stage that is printed in its marker.
+    pub fn name(self) -> &'static str {
+        match self {
+            BootStage::ConfigLoaded => "config_loaded",
+            BootStage::KernelLoaded => "kernel_loaded",
+            BootStage::MemoryMapDone => "memory_map_done",
+            BootStage::MappingsDone => "mappings_done",
+            BootStage::BootInfoDone => "boot_info_done",
+            BootStage::Handoff => "handoff",
+        }
+    }
+}
+
+/// Enables the stage markers that are printed by [`mark`].
+///
+/// The serial port is initialized here because the markers don't depend on the
+/// `serial_logging` option.
+pub fn enable() {
+    let mut port = unsafe { uart_16550::SerialPort::new(LOG_PORT) };
+    port.init();
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Prints the marker of the given stage on its own line to the serial port, if markers
+/// were [enabled](enable).
+///
+/// The marker is written directly to the port, so it is printed independently of the log
+/// level and of the logger state.
+pub fn mark(stage: BootStage) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut port = unsafe { uart_16550::SerialPort::new(LOG_PORT) };
+    let line = [
+        b"\r\n",
+        MARKER_PREFIX.as_bytes(),
+        stage.name().as_bytes(),
+        b"\r\n",
+    ];
+    for byte in line.into_iter().flatten() {
+        port.send(*byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_names() {
+        for (i, a) in BootStage::ALL.iter().enumerate() {
+            for b in &BootStage::ALL[i + 1..] {
+                assert_ne!(a.name(), b.name());
+            }
+        }
+    }
+}
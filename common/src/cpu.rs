@@ -0,0 +1,91 @@
+use raw_cpuid::CpuId;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+/// Reads the CPU vendor string (e.g. `GenuineIntel`) using CPUID leaf 0.
+///
+/// Returns all zeros if the vendor string is not available.
+pub fn vendor_string() -> [u8; 12] {
+    let mut vendor = [0; 12];
+    if let Some(info) = CpuId::new().get_vendor_info() {
+        copy_str(&mut vendor, info.as_str());
+    }
+    vendor
+}
+
+/// Reads the processor brand string using CPUID leaves `0x80000002` to `0x80000004`.
+///
+/// The string is padded with null bytes. Returns all zeros if the brand string is not
+/// supported by the CPU.
+pub fn brand_string() -> [u8; 48] {
+    let mut brand = [0; 48];
+    if let Some(info) = CpuId::new().get_processor_brand_string() {
+        copy_str(&mut brand, info.as_str());
+    }
+    brand
+}
+
+/// Reads the hypervisor vendor string (e.g. `TCGTCGTCGTCG` for QEMU) using CPUID leaf
+/// `0x40000000`.
+///
+/// Returns `None` if the hypervisor bit (bit 31 of `ECX` in CPUID leaf 1) is not set, i.e. if
+/// the CPU is not virtualized.
+pub fn hypervisor_vendor_string() -> Option<[u8; 12]> {
+    let has_hypervisor = CpuId::new()
+        .get_feature_info()
+        .is_some_and(|info| info.has_hypervisor());
+    if !has_hypervisor {
+        return None;
+    }
+    let result = raw_cpuid::cpuid!(0x4000_0000);
+    let mut vendor = [0; 12];
+    vendor[0..4].copy_from_slice(&result.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&result.ecx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&result.edx.to_le_bytes());
+    Some(vendor)
+}
+
+/// Checks whether the CPU supports 5-level paging (LA57) using CPUID leaf 7.
+pub fn la57_supported() -> bool {
+    CpuId::new()
+        .get_extended_feature_info()
+        .is_some_and(|info| info.has_la57())
+}
+
+/// Checks whether 5-level paging is enabled in the `CR4` register.
+pub fn la57_enabled() -> bool {
+    Cr4::read().contains(Cr4Flags::L5_PAGING)
+}
+
+/// Returns the subset of the given `CR4` flags whose features the CPU supports according to
+/// CPUID leaf 7.
+///
+/// Only the flags in [`BootloaderConfig::SUPPORTED_CR4_FLAGS`] are checked; all other flags
+/// are treated as unsupported.
+///
+/// [`BootloaderConfig::SUPPORTED_CR4_FLAGS`]: bootloader_api::BootloaderConfig::SUPPORTED_CR4_FLAGS
+pub fn supported_cr4_flags(flags: Cr4Flags) -> Cr4Flags {
+    let mut supported = Cr4Flags::empty();
+    if let Some(info) = CpuId::new().get_extended_feature_info() {
+        supported.set(Cr4Flags::USER_MODE_INSTRUCTION_PREVENTION, info.has_umip());
+        supported.set(Cr4Flags::FSGSBASE, info.has_fsgsbase());
+        supported.set(
+            Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION,
+            info.has_smep(),
+        );
+        supported.set(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION, info.has_smap());
+    }
+    flags & supported
+}
+
+/// Interprets the given null-padded bytes as a string for logging.
+pub fn trimmed_str(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len])
+        .unwrap_or("<invalid>")
+        .trim()
+}
+
+fn copy_str(dst: &mut [u8], s: &str) {
+    let len = s.len().min(dst.len());
+    dst[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
@@ -5,11 +5,31 @@ use x86_64::instructions::{port::Port, random::RdRand};
 
 /// Gather entropy from various sources to seed a RNG.
 pub fn build_rng() -> Hc128Rng {
-    const ENTROPY_SOURCES: [fn() -> [u8; 32]; 3] = [rd_rand_entropy, tsc_entropy, pit_entropy];
+    Hc128Rng::from_seed(gather_seed())
+}
 
-    // Collect entropy from different sources and xor them all together.
-    let mut seed = [0; 32];
-    for entropy_source in ENTROPY_SOURCES {
+/// Gather 32 bytes of entropy from various sources.
+///
+/// Hardware random number generators are preferred: the `RDSEED` instruction is used if the
+/// CPU supports it, otherwise the `RDRAND` instruction. If neither instruction is available,
+/// the entropy is only based on timing sources, which doesn't provide good entropy. The
+/// timing sources are mixed in in any case.
+pub fn gather_seed() -> [u8; 32] {
+    const TIMING_SOURCES: [fn() -> [u8; 32]; 2] = [tsc_entropy, pit_entropy];
+
+    let mut seed = if let Some(entropy) = rd_seed_entropy() {
+        log::debug!("Gathered entropy using RDSEED");
+        entropy
+    } else if let Some(entropy) = rd_rand_entropy() {
+        log::debug!("Gathered entropy using RDRAND");
+        entropy
+    } else {
+        log::warn!("No hardware random number generator available, using timing-based entropy");
+        [0; 32]
+    };
+
+    // Collect entropy from the timing sources and xor them all together.
+    for entropy_source in TIMING_SOURCES {
         let entropy = entropy_source();
 
         for (seed, entropy) in seed.iter_mut().zip(entropy) {
@@ -17,26 +37,61 @@ pub fn build_rng() -> Hc128Rng {
         }
     }
 
-    // Construct the RNG.
-    Hc128Rng::from_seed(seed)
+    seed
 }
 
-/// Gather entropy by requesting random numbers with `RDRAND` instruction if it's available.
+/// Gather entropy by requesting random seeds with the `RDSEED` instruction if it's available.
 ///
-/// This function provides excellent entropy (unless you don't trust the CPU vendors).
-fn rd_rand_entropy() -> [u8; 32] {
+/// In contrast to `RDRAND`, the returned values come directly from the entropy source of the
+/// CPU instead of a pseudo-random generator, so they are intended for seeding RNGs.
+fn rd_seed_entropy() -> Option<[u8; 32]> {
+    let has_rd_seed = CpuId::new()
+        .get_extended_feature_info()
+        .is_some_and(|info| info.has_rdseed());
+    if !has_rd_seed {
+        return None;
+    }
+
     let mut entropy = [0; 32];
+    for chunk in entropy.as_chunks_mut::<8>().0 {
+        let value = unsafe {
+            // SAFETY: We checked that the CPU supports `RDSEED`.
+            rd_seed_64()
+        }?;
+        *chunk = value.to_ne_bytes();
+    }
+    Some(entropy)
+}
 
-    // Check if the CPU supports `RDRAND`.
-    if let Some(rd_rand) = RdRand::new() {
-        for i in 0..4 {
-            if let Some(value) = get_random_64(rd_rand) {
-                entropy[i * 8..(i + 1) * 8].copy_from_slice(&value.to_ne_bytes());
-            }
+/// Try to fetch a 64 bit random seed with a retry count limit.
+///
+/// `RDSEED` fails more often than `RDRAND` when the entropy source is exhausted, so a higher
+/// retry count is used.
+#[target_feature(enable = "rdseed")]
+unsafe fn rd_seed_64() -> Option<u64> {
+    const RETRY_LIMIT: u32 = 100;
+    for _ in 0..RETRY_LIMIT {
+        let mut value = 0;
+        if core::arch::x86_64::_rdseed64_step(&mut value) == 1 {
+            return Some(value);
         }
+        core::hint::spin_loop();
     }
+    None
+}
 
-    entropy
+/// Gather entropy by requesting random numbers with `RDRAND` instruction if it's available.
+///
+/// This function provides excellent entropy (unless you don't trust the CPU vendors).
+fn rd_rand_entropy() -> Option<[u8; 32]> {
+    // Check if the CPU supports `RDRAND`.
+    let rd_rand = RdRand::new()?;
+
+    let mut entropy = [0; 32];
+    for chunk in entropy.as_chunks_mut::<8>().0 {
+        *chunk = get_random_64(rd_rand)?.to_ne_bytes();
+    }
+    Some(entropy)
 }
 
 /// Try to fetch a 64 bit random value with a retry count limit of 10.
@@ -61,7 +116,7 @@ fn tsc_entropy() -> [u8; 32] {
     // Check if the CPU supports `RDTSC`.
     let cpu_id = CpuId::new();
     if let Some(feature_info) = cpu_id.get_feature_info() {
-        if !feature_info.has_tsc() {
+        if feature_info.has_tsc() {
             for i in 0..4 {
                 let value = unsafe {
                     // SAFETY: We checked that the cpu supports `RDTSC` and we run in ring 0.
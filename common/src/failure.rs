@@ -0,0 +1,35 @@
+use bootloader_boot_config::failure;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+pub use bootloader_boot_config::failure::FailureKind;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static KIND: AtomicU8 = AtomicU8::new(FailureKind::Other as u8);
+
+/// Enables the beep and POST code signals in [`halt`].
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Records the class of a failure that is about to happen, e.g. before panicking.
+pub fn set_kind(kind: FailureKind) {
+    KIND.store(kind as u8, Ordering::Relaxed);
+}
+
+/// Returns the most recently recorded failure class.
+pub fn kind() -> FailureKind {
+    FailureKind::from_u8(KIND.load(Ordering::Relaxed))
+}
+
+/// Halts the CPU after a failure.
+///
+/// If signals were [enabled](enable), the POST code of the recorded failure class is written
+/// to port `0x80` and its beep pattern is repeated on the PC speaker forever.
+pub fn halt() -> ! {
+    if ENABLED.load(Ordering::Relaxed) {
+        failure::signal(kind());
+    }
+    loop {
+        unsafe { core::arch::asm!("cli; hlt") };
+    }
+}
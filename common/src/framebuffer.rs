@@ -1,10 +1,24 @@
-use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use bootloader_api::info::{Color, FrameBufferInfo, PixelFormat};
 use core::{fmt, ptr};
 use font_constants::BACKUP_CHAR;
 use noto_sans_mono_bitmap::{
     get_raster, get_raster_width, FontWeight, RasterHeight, RasterizedChar,
 };
 
+/// The colors of the bars drawn by [`draw_test_pattern`], from left to right.
+const TEST_PATTERN_COLORS: [Color; 8] = [
+    Color::new(0xff, 0xff, 0xff),
+    Color::new(0xff, 0xff, 0x00),
+    Color::new(0x00, 0xff, 0xff),
+    Color::new(0x00, 0xff, 0x00),
+    Color::new(0xff, 0x00, 0xff),
+    Color::new(0xff, 0x00, 0x00),
+    Color::new(0x00, 0x00, 0xff),
+    Color::new(0x00, 0x00, 0x00),
+];
+/// How long the test pattern is shown by [`self_test`].
+const SELF_TEST_SECONDS: u32 = 3;
+
 /// Additional vertical space between lines
 const LINE_SPACING: usize = 2;
 /// Additional horizontal space between characters.
@@ -13,6 +27,9 @@ const LETTER_SPACING: usize = 0;
 /// Padding from the border. Prevent that font is too close to border.
 const BORDER_PADDING: usize = 1;
 
+/// Default distance between two tab stops, in characters.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
 /// Constants for the usage of the [`noto_sans_mono_bitmap`] crate.
 mod font_constants {
     use super::*;
@@ -32,6 +49,9 @@ mod font_constants {
 }
 
 /// Returns the raster of the given char or the raster of [`font_constants::BACKUP_CHAR`].
+///
+/// Box-drawing characters are not part of the font, so they are rendered using their closest
+/// ASCII approximation.
 fn get_char_raster(c: char) -> RasterizedChar {
     fn get(c: char) -> Option<RasterizedChar> {
         get_raster(
@@ -40,7 +60,30 @@ fn get_char_raster(c: char) -> RasterizedChar {
             font_constants::CHAR_RASTER_HEIGHT,
         )
     }
-    get(c).unwrap_or_else(|| get(BACKUP_CHAR).expect("Should get raster of backup char."))
+    get(c)
+        .or_else(|| box_drawing_fallback(c).and_then(get))
+        .unwrap_or_else(|| get(BACKUP_CHAR).expect("Should get raster of backup char."))
+}
+
+/// Maps characters of the Unicode box-drawing block (`U+2500..=U+257F`) to an ASCII character
+/// that looks similar.
+fn box_drawing_fallback(c: char) -> Option<char> {
+    let fallback =
+        match c {
+            // horizontal lines, including dashed and double variants
+            '─' | '━' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '═' | '╴' | '╶' | '╸' | '╺' | '╼'
+            | '╾' => '-',
+            // vertical lines, including dashed and double variants
+            '│' | '┃' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '║' | '╵' | '╷' | '╹' | '╻' | '╽'
+            | '╿' => '|',
+            '╱' => '/',
+            '╲' => '\\',
+            '╳' => 'X',
+            // corners, T-pieces, and crossings
+            '\u{2500}'..='\u{257f}' => '+',
+            _ => return None,
+        };
+    Some(fallback)
 }
 
 /// Allows logging text to a pixel-based framebuffer.
@@ -49,6 +92,7 @@ pub struct FrameBufferWriter {
     info: FrameBufferInfo,
     x_pos: usize,
     y_pos: usize,
+    tab_width: usize,
 }
 
 impl FrameBufferWriter {
@@ -59,6 +103,7 @@ impl FrameBufferWriter {
             info,
             x_pos: 0,
             y_pos: 0,
+            tab_width: DEFAULT_TAB_WIDTH,
         };
         logger.clear();
         logger
@@ -73,6 +118,28 @@ impl FrameBufferWriter {
         self.x_pos = BORDER_PADDING;
     }
 
+    /// Advances `self.x_pos` to the next tab stop, or to the next line if the tab stop is
+    /// outside of the framebuffer.
+    fn tab(&mut self) {
+        let char_width = font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING;
+        let column = (self.x_pos - BORDER_PADDING) / char_width;
+        let next_stop = (column / self.tab_width + 1) * self.tab_width;
+        let new_xpos = BORDER_PADDING + next_stop * char_width;
+        if new_xpos + font_constants::CHAR_RASTER_WIDTH >= self.width() {
+            self.newline();
+        } else {
+            self.x_pos = new_xpos;
+        }
+    }
+
+    /// Sets the distance between two tab stops, in characters.
+    ///
+    /// Panics if `tab_width` is zero.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        assert!(tab_width > 0, "tab width must not be zero");
+        self.tab_width = tab_width;
+    }
+
     /// Erases all text on the screen. Resets `self.x_pos` and `self.y_pos`.
     pub fn clear(&mut self) {
         self.x_pos = BORDER_PADDING;
@@ -94,6 +161,7 @@ impl FrameBufferWriter {
         match c {
             '\n' => self.newline(),
             '\r' => self.carriage_return(),
+            '\t' => self.tab(),
             c => {
                 let new_xpos = self.x_pos + font_constants::CHAR_RASTER_WIDTH;
                 if new_xpos >= self.width() {
@@ -123,15 +191,8 @@ impl FrameBufferWriter {
     fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
         let pixel_offset = y * self.info.stride + x;
         let color = match self.info.pixel_format {
-            PixelFormat::Rgb => [intensity, intensity, intensity / 2, 0],
-            PixelFormat::Bgr => [intensity / 2, intensity, intensity, 0],
             PixelFormat::U8 => [if intensity > 200 { 0xf } else { 0 }, 0, 0, 0],
-            other => {
-                // set a supported (but invalid) pixel format before panicking to avoid a double
-                // panic; it might not be readable though
-                self.info.pixel_format = PixelFormat::Rgb;
-                panic!("pixel format {:?} not supported in logger", other)
-            }
+            other => Color::new(intensity, intensity, intensity / 2).to_pixel_bytes(other),
         };
         let bytes_per_pixel = self.info.bytes_per_pixel;
         let byte_offset = pixel_offset * bytes_per_pixel;
@@ -141,6 +202,38 @@ impl FrameBufferWriter {
     }
 }
 
+/// Draws vertical color bars over the whole framebuffer.
+///
+/// Each pixel is converted to the pixel format of the framebuffer and placed according to
+/// its stride, so a misconfigured framebuffer shows up as distorted or wrongly colored bars.
+pub fn draw_test_pattern(framebuffer: &mut [u8], info: FrameBufferInfo) {
+    let bars = TEST_PATTERN_COLORS.len();
+    for y in 0..info.height {
+        for x in 0..info.width {
+            let color = TEST_PATTERN_COLORS[x * bars / info.width];
+            let bytes = color.to_pixel_bytes(info.pixel_format);
+            let byte_offset = (y * info.stride + x) * info.bytes_per_pixel;
+            let pixel = &mut framebuffer[byte_offset..][..info.bytes_per_pixel];
+            // pixels that are larger than four bytes are padded with zeros
+            for (i, byte) in pixel.iter_mut().enumerate() {
+                *byte = bytes.get(i).copied().unwrap_or(0);
+            }
+        }
+    }
+}
+
+/// Shows the [test pattern](draw_test_pattern) for a few seconds and clears the framebuffer
+/// afterwards.
+///
+/// The duration is measured using the CMOS real-time clock, see [`crate::rtc::wait_seconds`].
+/// If no RTC is present, the pattern is cleared right away. If the RTC doesn't tick, it is
+/// cleared after a few seconds.
+pub fn self_test(framebuffer: &mut [u8], info: FrameBufferInfo) {
+    draw_test_pattern(framebuffer, info);
+    crate::rtc::wait_seconds(SELF_TEST_SECONDS);
+    framebuffer.fill(0);
+}
+
 unsafe impl Send for FrameBufferWriter {}
 unsafe impl Sync for FrameBufferWriter {}
 
@@ -152,3 +245,133 @@ impl fmt::Write for FrameBufferWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin_1_supplement_is_rendered() {
+        for c in ['ä', 'é', 'ß', '©', 'ÿ'] {
+            assert!(get(c).is_some(), "missing raster for {c:?}");
+        }
+    }
+
+    #[test]
+    fn box_drawing_uses_fallback() {
+        assert_eq!(box_drawing_fallback('─'), Some('-'));
+        assert_eq!(box_drawing_fallback('║'), Some('|'));
+        assert_eq!(box_drawing_fallback('┼'), Some('+'));
+        assert_eq!(box_drawing_fallback('╯'), Some('+'));
+        assert_eq!(box_drawing_fallback('a'), None);
+        for c in '\u{2500}'..='\u{257f}' {
+            let fallback = box_drawing_fallback(c).unwrap();
+            assert!(get(fallback).is_some());
+        }
+    }
+
+    #[test]
+    fn unsupported_chars_use_backup_char() {
+        assert!(get('€').is_none());
+        assert!(get(BACKUP_CHAR).is_some());
+        assert_eq!(
+            get_char_raster('€').raster(),
+            get(BACKUP_CHAR).unwrap().raster()
+        );
+    }
+
+    fn get(c: char) -> Option<RasterizedChar> {
+        get_raster(
+            c,
+            font_constants::FONT_WEIGHT,
+            font_constants::CHAR_RASTER_HEIGHT,
+        )
+    }
+
+    fn writer() -> FrameBufferWriter {
+        let info = FrameBufferInfo {
+            byte_len: 640 * 100 * 4,
+            width: 640,
+            height: 100,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel: 4,
+            stride: 640,
+        };
+        let framebuffer = Box::leak(vec![0; info.byte_len].into_boxed_slice());
+        FrameBufferWriter::new(framebuffer, info)
+    }
+
+    #[test]
+    fn test_pattern_respects_format_and_stride() {
+        let info = FrameBufferInfo {
+            byte_len: 10 * 16 * 3,
+            width: 8,
+            height: 10,
+            pixel_format: PixelFormat::Bgr,
+            bytes_per_pixel: 3,
+            stride: 16,
+        };
+        let mut framebuffer = vec![0x42; info.byte_len];
+        draw_test_pattern(&mut framebuffer, info);
+
+        let pixel = |x: usize, y: usize| {
+            let offset = (y * info.stride + x) * info.bytes_per_pixel;
+            &framebuffer[offset..][..info.bytes_per_pixel]
+        };
+        for y in 0..info.height {
+            assert_eq!(pixel(0, y), [0xff, 0xff, 0xff]);
+            // yellow in BGR order
+            assert_eq!(pixel(1, y), [0x00, 0xff, 0xff]);
+            // red in BGR order
+            assert_eq!(pixel(5, y), [0x00, 0x00, 0xff]);
+            assert_eq!(pixel(7, y), [0x00, 0x00, 0x00]);
+            // the padding behind each row is not touched
+            assert_eq!(pixel(8, y), [0x42, 0x42, 0x42]);
+        }
+    }
+
+    fn column(writer: &FrameBufferWriter) -> usize {
+        (writer.x_pos - BORDER_PADDING) / (font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING)
+    }
+
+    #[test]
+    fn tab_advances_to_next_tab_stop() {
+        let mut writer = writer();
+        fmt::Write::write_str(&mut writer, "\t").unwrap();
+        assert_eq!(column(&writer), DEFAULT_TAB_WIDTH);
+        fmt::Write::write_str(&mut writer, "ab\t").unwrap();
+        assert_eq!(column(&writer), 2 * DEFAULT_TAB_WIDTH);
+
+        writer.set_tab_width(8);
+        fmt::Write::write_str(&mut writer, "\t").unwrap();
+        assert_eq!(column(&writer), 16);
+    }
+
+    #[test]
+    fn tab_wraps_at_line_end() {
+        let mut writer = writer();
+        let y_pos = writer.y_pos;
+        let columns = 640 / font_constants::CHAR_RASTER_WIDTH;
+        // the last tab stop that fits into the line is at `columns - 1` at most
+        let tab_stops_per_line = (columns - 1) / DEFAULT_TAB_WIDTH;
+        for _ in 0..tab_stops_per_line {
+            fmt::Write::write_str(&mut writer, "\t").unwrap();
+        }
+        assert_eq!(writer.y_pos, y_pos);
+        fmt::Write::write_str(&mut writer, "\t").unwrap();
+        assert_eq!(column(&writer), 0);
+        assert!(writer.y_pos > y_pos);
+    }
+
+    #[test]
+    fn carriage_return_and_newline() {
+        let mut writer = writer();
+        let y_pos = writer.y_pos;
+        fmt::Write::write_str(&mut writer, "abc\r").unwrap();
+        assert_eq!(column(&writer), 0);
+        assert_eq!(writer.y_pos, y_pos);
+        fmt::Write::write_str(&mut writer, "abc\n").unwrap();
+        assert_eq!(column(&writer), 0);
+        assert!(writer.y_pos > y_pos);
+    }
+}
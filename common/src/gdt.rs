@@ -1,13 +1,20 @@
 use x86_64::{
     instructions::segmentation::{self, Segment},
     structures::{
-        gdt::{Descriptor, GlobalDescriptorTable},
+        gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
         paging::PhysFrame,
     },
     VirtAddr,
 };
 
-pub fn create_and_load(frame: PhysFrame) {
+/// A flat 32-bit code segment, used to switch to compatibility mode.
+const COMPAT_CODE_SEGMENT: u64 = 0x00cf_9a00_0000_ffff;
+
+/// Creates and loads a GDT in the given frame.
+///
+/// Returns the selector of a 32-bit compatibility mode code segment, which is required for
+/// enabling 5-level paging.
+pub fn create_and_load(frame: PhysFrame) -> SegmentSelector {
     let phys_addr = frame.start_address();
     log::info!("Creating GDT at {:?}", phys_addr);
     let virt_addr = VirtAddr::new(phys_addr.as_u64()); // utilize identity mapping
@@ -17,6 +24,7 @@ pub fn create_and_load(frame: PhysFrame) {
     let mut gdt = GlobalDescriptorTable::new();
     let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
     let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+    let compat_code_selector = gdt.add_entry(Descriptor::UserSegment(COMPAT_CODE_SEGMENT));
     let gdt = unsafe {
         ptr.write(gdt);
         &*ptr
@@ -29,4 +37,6 @@ pub fn create_and_load(frame: PhysFrame) {
         segmentation::ES::set_reg(data_selector);
         segmentation::SS::set_reg(data_selector);
     }
+
+    compat_code_selector
 }
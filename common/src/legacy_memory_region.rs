@@ -6,7 +6,7 @@ use core::{
 };
 use x86_64::{
     align_down, align_up,
-    structures::paging::{FrameAllocator, PhysFrame, Size4KiB},
+    structures::paging::{frame::PhysFrameRange, FrameAllocator, PhysFrame, Size4KiB},
     PhysAddr,
 };
 
@@ -18,6 +18,9 @@ pub struct UsedMemorySlice {
     pub start: u64,
     /// The physical end address (exclusive) of the region.
     pub end: u64,
+    /// The kind of the slice in the kernel-visible memory map, usually
+    /// [`MemoryRegionKind::Bootloader`].
+    pub kind: MemoryRegionKind,
 }
 
 impl UsedMemorySlice {
@@ -26,11 +29,43 @@ impl UsedMemorySlice {
         Self {
             start,
             end: start + len,
+            kind: MemoryRegionKind::Bootloader,
         }
     }
+
+    /// Changes the kind of the slice in the kernel-visible memory map.
+    pub fn with_kind(self, kind: MemoryRegionKind) -> Self {
+        Self { kind, ..self }
+    }
 }
 
 /// Abstraction trait for a memory region returned by the UEFI or BIOS firmware.
+///
+/// This trait is the extension point for custom memory map sources. The
+/// [`LegacyFrameAllocator`] and the functions of this crate that take it (e.g.
+/// [`load_and_switch_to_kernel`](crate::load_and_switch_to_kernel)) accept any cloneable
+/// iterator over a type that implements this trait, so firmware paths with unusual memory
+/// descriptors or tests can provide their own region type:
+///
+/// ```ignore
+/// #[derive(Clone, Copy, Debug)]
+/// struct Region { start: u64, len: u64, usable: bool }
+///
+/// impl LegacyMemoryRegion for Region {
+///     fn start(&self) -> PhysAddr { PhysAddr::new(self.start) }
+///     fn len(&self) -> u64 { self.len }
+///     fn kind(&self) -> MemoryRegionKind {
+///         if self.usable { MemoryRegionKind::Usable } else { MemoryRegionKind::UnknownBios(2) }
+///     }
+///     fn usable_after_bootloader_exit(&self) -> bool { self.usable }
+/// }
+///
+/// let allocator = LegacyFrameAllocator::new(regions.iter().copied());
+/// ```
+///
+/// The regions must not overlap and should be sorted by their start address. The allocator
+/// only allocates full frames from [`Usable`](MemoryRegionKind::Usable) regions and skips
+/// usable regions that start below an already used region.
 pub trait LegacyMemoryRegion: Copy + core::fmt::Debug {
     /// Returns the physical start address of the region.
     fn start(&self) -> PhysAddr;
@@ -54,6 +89,25 @@ pub struct LegacyFrameAllocator<I, D> {
     current_descriptor: Option<D>,
     next_frame: PhysFrame,
     min_frame: PhysFrame,
+    reserved: Option<PhysFrameRange>,
+    excluded: [Option<(PhysFrameRange, MemoryRegionKind)>; MAX_EXCLUDED_RANGES],
+    low_frame: Option<PhysFrame>,
+}
+
+/// The maximum number of ranges that can be excluded through
+/// [`LegacyFrameAllocator::exclude_range`], [`LegacyFrameAllocator::test_memory`], and
+/// [`LegacyFrameAllocator::allocate_range_within`].
+pub const MAX_EXCLUDED_RANGES: usize = 8;
+
+/// The result of [`LegacyFrameAllocator::test_memory`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryTestResult {
+    /// The number of frames that failed the test.
+    pub bad_frames: u64,
+    /// The number of usable frames that are reported as bad memory although they passed the
+    /// test, because there were not enough slots to exclude the bad frames around them
+    /// separately.
+    pub lost_frames: u64,
 }
 
 /// Start address of the first frame that is not part of the lower 1MB of frames
@@ -61,8 +115,8 @@ const LOWER_MEMORY_END_PAGE: u64 = 0x10_0000;
 
 impl<I, D> LegacyFrameAllocator<I, D>
 where
-    I: ExactSizeIterator<Item = D> + Clone,
-    I::Item: LegacyMemoryRegion,
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
 {
     /// Creates a new frame allocator based on the given legacy memory regions.
     ///
@@ -91,7 +145,312 @@ where
             current_descriptor: None,
             next_frame: frame,
             min_frame: frame,
+            reserved: None,
+            excluded: [None; MAX_EXCLUDED_RANGES],
+            low_frame: None,
+        }
+    }
+
+    /// Reserves the given range of frames, e.g. for kernel segments that must be loaded at a
+    /// fixed physical address.
+    ///
+    /// The allocator skips the reserved frames and [`Self::construct_memory_map`] reports them
+    /// as used. Only a single range can be reserved. The range must lie in usable memory above
+    /// 1MiB and must not contain frames that were already allocated.
+    ///
+    /// The allocator doesn't know how the frames before its start frame (see
+    /// [`Self::new_starting_at`]) are used, so the caller must ensure that a range below the
+    /// start frame is not in use.
+    pub fn reserve_range(&mut self, range: PhysFrameRange) -> Result<(), &'static str> {
+        if self.reserved.is_some() {
+            return Err("a physical memory range is already reserved");
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        if range.start.start_address() < PhysAddr::new(LOWER_MEMORY_END_PAGE) {
+            return Err("reserved range overlaps with the lower 1MiB of memory");
+        }
+        let allocated = PhysFrame::range(self.min_frame, self.next_frame);
+        let low_frame_used = self
+            .low_frame
+            .is_some_and(|frame| range.start <= frame && frame < range.end);
+        if overlaps(range, allocated) || low_frame_used {
+            return Err("reserved range overlaps with memory that is already in use");
+        }
+        if self
+            .excluded_ranges()
+            .any(|excluded| overlaps(excluded, range))
+        {
+            return Err("reserved range overlaps with an excluded range");
+        }
+
+        // check that the range is fully covered by usable regions
+        let end = range.end.start_address();
+        let mut addr = range.start.start_address();
+        while addr < end {
+            let region = self
+                .original
+                .clone()
+                .filter(|r| r.kind() == MemoryRegionKind::Usable)
+                .find(|r| r.start() <= addr && addr < r.start() + r.len())
+                .ok_or("reserved range is not in usable memory")?;
+            addr = region.start() + region.len();
+        }
+
+        self.reserved = Some(range);
+        Ok(())
+    }
+
+    /// Returns the range that was reserved through [`Self::reserve_range`].
+    pub fn reserved_range(&self) -> Option<PhysFrameRange> {
+        self.reserved
+    }
+
+    /// Excludes the given range of frames from use, e.g. memory that is known to be defective
+    /// or used by the firmware.
+    ///
+    /// The allocator never returns the excluded frames and [`Self::construct_memory_map`]
+    /// reports them as [`MemoryRegionKind::Reserved`]. Up to [`MAX_EXCLUDED_RANGES`] ranges can
+    /// be excluded. The range must not contain frames that were already allocated.
+    pub fn exclude_range(&mut self, range: PhysFrameRange) -> Result<(), &'static str> {
+        self.exclude_range_as(range, MemoryRegionKind::Reserved)
+    }
+
+    fn exclude_range_as(
+        &mut self,
+        range: PhysFrameRange,
+        kind: MemoryRegionKind,
+    ) -> Result<(), &'static str> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let allocated = PhysFrame::range(self.min_frame, self.next_frame);
+        let low_frame_used = self
+            .low_frame
+            .is_some_and(|frame| range.start <= frame && frame < range.end);
+        if overlaps(range, allocated) || low_frame_used {
+            return Err("excluded range overlaps with memory that is already in use");
+        }
+        if self
+            .reserved
+            .is_some_and(|reserved| overlaps(range, reserved))
+        {
+            return Err("excluded range overlaps with the reserved range");
+        }
+        let slot = self
+            .excluded
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or("too many excluded memory ranges")?;
+        *slot = Some((range, kind));
+        Ok(())
+    }
+
+    fn excluded_ranges(&self) -> impl Iterator<Item = PhysFrameRange> + Clone + '_ {
+        self.excluded.iter().flatten().map(|&(range, _)| range)
+    }
+
+    /// Allocates `count` contiguous frames that lie within the given range, e.g. memory that
+    /// belongs to a specific NUMA node.
+    ///
+    /// The frames are taken from the usable memory that was not allocated yet. Like the
+    /// excluded ranges, the allocated range uses one of the [`MAX_EXCLUDED_RANGES`] slots and
+    /// [`Self::construct_memory_map`] reports it as [`MemoryRegionKind::Bootloader`]. Returns
+    /// `None` if no suitable range is available.
+    pub fn allocate_range_within(
+        &mut self,
+        count: u64,
+        within: PhysFrameRange,
+    ) -> Option<PhysFrameRange> {
+        if count == 0 || self.excluded.iter().all(Option::is_some) {
+            return None;
         }
+        let range = self
+            .original
+            .clone()
+            .filter(|r| r.kind() == MemoryRegionKind::Usable)
+            .find_map(|region| {
+                let start = PhysFrame::containing_address(region.start().align_up(0x1000u64));
+                let end = PhysFrame::containing_address(
+                    (region.start() + region.len()).align_down(0x1000u64),
+                );
+                let end = cmp::min(end, within.end);
+                let mut start = cmp::max(cmp::max(start, within.start), self.next_frame);
+                while start + count <= end {
+                    // the low frame lies below `next_frame`, so it can't overlap
+                    let candidate = PhysFrame::range(start, start + count);
+                    let blocking = self
+                        .reserved
+                        .into_iter()
+                        .chain(self.excluded_ranges())
+                        .filter(|r| overlaps(*r, candidate))
+                        .map(|r| r.end)
+                        .max();
+                    match blocking {
+                        Some(blocking_end) => start = blocking_end,
+                        None => return Some(candidate),
+                    }
+                }
+                None
+            })?;
+        self.exclude_range_as(range, MemoryRegionKind::Bootloader)
+            .ok()?;
+        Some(range)
+    }
+
+    /// Tests the usable frames below `end` that were not allocated yet with the given function
+    /// and excludes the frames for which it returns `false`.
+    ///
+    /// Adjacent failing frames are combined into a single range, which
+    /// [`Self::construct_memory_map`] reports as [`MemoryRegionKind::BadMemory`]. The ranges
+    /// share the [`MAX_EXCLUDED_RANGES`] slots with [`Self::exclude_range`]. When all slots are
+    /// used, the previous bad range is extended instead if no other range lies in between. The
+    /// usable frames in between are then lost, even if they passed the test, so their number
+    /// is reported in [`MemoryTestResult::lost_frames`].
+    ///
+    /// Returns an error if a failing frame could not be excluded.
+    pub fn test_memory(
+        &mut self,
+        end: PhysAddr,
+        mut test_frame: impl FnMut(PhysFrame) -> bool,
+    ) -> Result<MemoryTestResult, &'static str> {
+        let end_frame = PhysFrame::containing_address(end.align_down(0x1000u64));
+        let mut result = MemoryTestResult::default();
+        let mut bad_range: Option<PhysFrameRange> = None;
+        for region in self
+            .original
+            .clone()
+            .filter(|r| r.kind() == MemoryRegionKind::Usable)
+        {
+            let start = PhysFrame::containing_address(region.start().align_up(0x1000u64));
+            let region_end = PhysFrame::containing_address(
+                (region.start() + region.len()).align_down(0x1000u64),
+            );
+            // frames below `next_frame` are already allocated or skipped
+            let mut frame = cmp::max(start, self.next_frame);
+            while frame < cmp::min(region_end, end_frame) {
+                if let Some(skip_end) = self.skipped_range_end(frame) {
+                    frame = skip_end;
+                    continue;
+                }
+                if !test_frame(frame) {
+                    result.bad_frames += 1;
+                    match &mut bad_range {
+                        Some(range) if range.end == frame => range.end += 1,
+                        _ => {
+                            if let Some(range) =
+                                bad_range.replace(PhysFrame::range(frame, frame + 1))
+                            {
+                                result.lost_frames += self.exclude_bad_range(range)?;
+                            }
+                        }
+                    }
+                }
+                frame += 1;
+            }
+        }
+        if let Some(range) = bad_range {
+            result.lost_frames += self.exclude_bad_range(range)?;
+        }
+        Ok(result)
+    }
+
+    /// Excludes the given range as bad memory.
+    ///
+    /// Returns the number of usable frames that were excluded in addition because the
+    /// previous bad range had to be extended.
+    fn exclude_bad_range(&mut self, range: PhysFrameRange) -> Result<u64, &'static str> {
+        if self.excluded.iter().any(Option::is_none) {
+            return self
+                .exclude_range_as(range, MemoryRegionKind::BadMemory)
+                .map(|()| 0);
+        }
+
+        // all slots are used, so try to extend the closest bad range before this one
+        let (index, previous) = self
+            .excluded
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match *slot {
+                Some((r, MemoryRegionKind::BadMemory)) if r.end <= range.start => Some((index, r)),
+                _ => None,
+            })
+            .max_by_key(|(_, r)| r.start)
+            .ok_or("too many excluded memory ranges")?;
+        let in_between = PhysFrame::range(previous.end, range.start);
+        let low_frame_in_between = self
+            .low_frame
+            .is_some_and(|frame| in_between.start <= frame && frame < in_between.end);
+        if low_frame_in_between
+            || self
+                .reserved
+                .into_iter()
+                .chain(self.excluded_ranges())
+                .any(|r| overlaps(r, in_between))
+        {
+            return Err("too many excluded memory ranges");
+        }
+        self.excluded[index] = Some((
+            PhysFrame::range(previous.start, range.end),
+            MemoryRegionKind::BadMemory,
+        ));
+        Ok(self.usable_frame_count(in_between))
+    }
+
+    /// Returns the number of frames of the given range that lie in usable memory regions.
+    fn usable_frame_count(&self, range: PhysFrameRange) -> u64 {
+        self.original
+            .clone()
+            .filter(|r| r.kind() == MemoryRegionKind::Usable)
+            .map(|r| {
+                let start = PhysFrame::containing_address(r.start().align_up(0x1000u64));
+                let end =
+                    PhysFrame::containing_address((r.start() + r.len()).align_down(0x1000u64));
+                let start = cmp::max(start, range.start);
+                let end = cmp::min(end, range.end);
+                if start < end {
+                    end - start
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+
+    /// Returns the end of the reserved or excluded range that contains the given frame.
+    fn skipped_range_end(&self, frame: PhysFrame) -> Option<PhysFrame> {
+        self.reserved
+            .into_iter()
+            .chain(self.excluded_ranges())
+            .find(|range| range.start <= frame && frame < range.end)
+            .map(|range| range.end)
+    }
+
+    /// Allocates a frame below 1 MiB, e.g. for the startup code of additional CPUs.
+    ///
+    /// The regular allocation never returns frames below 1 MiB, so the frame is taken from the
+    /// highest usable frame of the lower memory. This avoids the low frames that the BIOS
+    /// stages use for their stack and data. [`Self::construct_memory_map`] reports the frame
+    /// as used. Only a single low frame can be allocated.
+    pub fn allocate_low_frame(&mut self) -> Option<PhysFrame> {
+        if self.low_frame.is_some() {
+            return None;
+        }
+        let lower_mem_end = PhysAddr::new(LOWER_MEMORY_END_PAGE);
+        let frame = self
+            .original
+            .clone()
+            .filter(|r| r.kind() == MemoryRegionKind::Usable)
+            .filter_map(|r| {
+                let end = cmp::min(r.start() + r.len(), lower_mem_end).align_down(0x1000u64);
+                // skip frame 0, see `new`
+                let start = cmp::max(r.start().align_up(0x1000u64), PhysAddr::new(0x1000));
+                (start < end).then(|| PhysFrame::containing_address(end - 1u64))
+            })
+            .max()?;
+        self.low_frame = Some(frame);
+        Some(frame)
     }
 
     fn allocate_frame_from_descriptor(&mut self, descriptor: D) -> Option<PhysFrame> {
@@ -105,6 +464,11 @@ where
             self.next_frame = start_frame;
         }
 
+        // skip the reserved and excluded frames
+        while let Some(end) = self.skipped_range_end(self.next_frame) {
+            self.next_frame = end;
+        }
+
         if self.next_frame <= end_frame {
             let ret = self.next_frame;
             self.next_frame += 1;
@@ -120,7 +484,7 @@ where
     /// The function always returns the same value, i.e. the length doesn't
     /// change after calls to `allocate_frame`.
     pub fn len(&self) -> usize {
-        self.original.len()
+        self.original.clone().count()
     }
 
     /// Returns whether this memory map is empty.
@@ -145,20 +509,61 @@ where
         cmp::max(max, PhysAddr::new(0x1_0000_0000))
     }
 
+    /// Returns whether the given physical address range overlaps a region of the memory map
+    /// that contains RAM.
+    ///
+    /// Gaps in the memory map and regions of reserved memory or memory-mapped I/O are not
+    /// considered RAM.
+    pub fn contains_ram(&self, start: PhysAddr, len: u64) -> bool {
+        let end = start + len;
+        self.original
+            .clone()
+            .filter(|r| is_ram(r.kind()))
+            .any(|r| r.start() < end && start < r.start() + r.len())
+    }
+
+    /// Returns the smallest physical address range that contains all memory regions that
+    /// match the given predicate, as a `(start, len)` tuple.
+    ///
+    /// Returns `None` if no region matches.
+    pub fn region_bounds(
+        &self,
+        mut predicate: impl FnMut(MemoryRegionKind) -> bool,
+    ) -> Option<(PhysAddr, u64)> {
+        let (start, end) = self
+            .original
+            .clone()
+            .filter(|r| r.len() > 0 && predicate(r.kind()))
+            .map(|r| (r.start(), r.start() + r.len()))
+            .reduce(|(start, end), (r_start, r_end)| (start.min(r_start), end.max(r_end)))?;
+        Some((start, end - start))
+    }
+
     /// Calculate the maximum number of regions produced by [Self::construct_memory_map]
-    pub fn memory_map_max_region_count(&self) -> usize {
-        // every used region can split an original region into 3 new regions,
-        // this means we need to reserve 2 extra spaces for each region.
-        // There are 3 used regions: kernel, ramdisk and the bootloader heap
-        self.len() + 6
+    ///
+    /// The `ramdisk` argument specifies whether a ramdisk slice will be passed to
+    /// [Self::construct_memory_map].
+    pub fn memory_map_max_region_count(&self, ramdisk: bool) -> usize {
+        // the memory allocated by the bootloader and the kernel are always used, the ramdisk,
+        // the reserved range, the low frame, and the excluded ranges are optional
+        let used_slices = 2
+            + usize::from(ramdisk)
+            + usize::from(self.reserved.is_some())
+            + usize::from(self.low_frame.is_some())
+            + self.excluded_ranges().count();
+        max_region_count(self.len(), used_slices)
     }
 
     /// Converts this type to a boot info memory map.
     ///
     /// The memory map is placed in the given `regions` slice. The length of the given slice
-    /// must be at least the value returned by [`len`] plus 1.
+    /// must be at least the value returned by [`Self::memory_map_max_region_count`].
     ///
-    /// The return slice is a subslice of `regions`, shortened to the actual number of regions.
+    /// The memory that was allocated through this allocator, the given kernel and ramdisk
+    /// slices, the reserved range, and the low frame are reported as used. The excluded ranges
+    /// are reported as [`MemoryRegionKind::Reserved`], or as [`MemoryRegionKind::BadMemory`]
+    /// if they failed [`Self::test_memory`]. See the free [`construct_memory_map`]
+    /// function for details on how the regions are split.
     pub fn construct_memory_map(
         self,
         regions: &mut [MaybeUninit<MemoryRegion>],
@@ -167,140 +572,225 @@ where
         ramdisk_slice_start: Option<PhysAddr>,
         ramdisk_slice_len: u64,
     ) -> &mut [MemoryRegion] {
-        let used_slices = [
-            UsedMemorySlice {
-                start: self.min_frame.start_address().as_u64(),
-                end: self.next_frame.start_address().as_u64(),
-            },
-            UsedMemorySlice::new_from_len(kernel_slice_start.as_u64(), kernel_slice_len),
-        ]
-        .into_iter()
-        .chain(
-            ramdisk_slice_start
-                .map(|start| UsedMemorySlice::new_from_len(start.as_u64(), ramdisk_slice_len)),
-        )
-        .map(|slice| UsedMemorySlice {
-            start: align_down(slice.start, 0x1000),
-            end: align_up(slice.end, 0x1000),
-        });
+        let frame_range_slice = |range: PhysFrameRange| UsedMemorySlice {
+            start: range.start.start_address().as_u64(),
+            end: range.end.start_address().as_u64(),
+            kind: MemoryRegionKind::Bootloader,
+        };
+        // the excluded ranges come first because they take precedence over the memory
+        // allocated by the bootloader, which spans them
+        let used_slices = self
+            .excluded
+            .into_iter()
+            .flatten()
+            .map(move |(range, kind)| frame_range_slice(range).with_kind(kind))
+            .chain([
+                frame_range_slice(PhysFrame::range(self.min_frame, self.next_frame)),
+                UsedMemorySlice::new_from_len(kernel_slice_start.as_u64(), kernel_slice_len),
+            ])
+            .chain(
+                ramdisk_slice_start
+                    .map(|start| UsedMemorySlice::new_from_len(start.as_u64(), ramdisk_slice_len)),
+            )
+            .chain(self.reserved.map(frame_range_slice))
+            .chain(self.low_frame.map(|frame| {
+                UsedMemorySlice::new_from_len(frame.start_address().as_u64(), frame.size())
+            }));
 
-        let mut next_index = 0;
-        for descriptor in self.original {
-            let kind = match descriptor.kind() {
-                _ if descriptor.usable_after_bootloader_exit() => {
-                    // Region was not usable before, but it will be as soon as
-                    // the bootloader passes control to the kernel. We don't
-                    // need to check against `next_free` because the
-                    // LegacyFrameAllocator only allocates memory from usable
-                    // descriptors.
-                    MemoryRegionKind::Usable
-                }
-                other => other,
-            };
+        construct_memory_map(self.original, used_slices, regions)
+    }
+}
 
-            let end = descriptor.start() + descriptor.len();
-            let region = MemoryRegion {
-                start: descriptor.start().as_u64(),
-                end: end.as_u64(),
-                kind,
-            };
-            if region.kind == MemoryRegionKind::Usable {
-                Self::split_and_add_region(region, regions, &mut next_index, used_slices.clone());
-            } else {
-                Self::add_region(region, regions, &mut next_index);
+/// Returns the maximum number of regions that [`construct_memory_map`] can produce for a
+/// memory map with `memory_map_len` regions and `used_slices` used memory slices.
+///
+/// Every boundary of a used slice splits at most one region into two, so each used slice adds
+/// at most two regions.
+pub fn max_region_count(memory_map_len: usize, used_slices: usize) -> usize {
+    memory_map_len + 2 * used_slices
+}
+
+/// Builds the kernel-visible memory map from the given firmware memory map and the memory
+/// slices that are in use by the bootloader.
+///
+/// The regions are written to the given `regions` slice in the order of the firmware memory
+/// map. The returned slice is a subslice of `regions`, shortened to the actual number of
+/// regions. The conversion works as follows:
+///
+/// - Regions that are [`usable_after_bootloader_exit`](LegacyMemoryRegion::usable_after_bootloader_exit)
+///   are reported as [`Usable`](MemoryRegionKind::Usable). All other regions keep their kind
+///   and are passed through unchanged, even if they overlap with a used slice.
+/// - The used slices are extended to page boundaries. Each usable region is split at the
+///   boundaries of the used slices that overlap with it, so a single used slice can split a
+///   region into up to 3 regions: a usable region before the slice, a region of the slice's
+///   [`kind`](UsedMemorySlice::kind) for the overlap, and a usable region after it. Each
+///   additional used slice inside the same region adds up to 2 more regions.
+/// - If used slices overlap, the slices that come first in `used_slices` take precedence.
+/// - Empty regions are skipped.
+///
+/// ## Panics
+///
+/// Panics if `regions` is too small for the resulting memory map.
+pub fn construct_memory_map<D, U>(
+    memory_map: impl Iterator<Item = D>,
+    used_slices: U,
+    regions: &mut [MaybeUninit<MemoryRegion>],
+) -> &mut [MemoryRegion]
+where
+    D: LegacyMemoryRegion,
+    U: IntoIterator<Item = UsedMemorySlice>,
+    U::IntoIter: Clone,
+{
+    let used_slices = used_slices.into_iter().map(|slice| UsedMemorySlice {
+        start: align_down(slice.start, 0x1000),
+        end: align_up(slice.end, 0x1000),
+        kind: slice.kind,
+    });
+
+    let mut next_index = 0;
+    for descriptor in memory_map {
+        let kind = match descriptor.kind() {
+            _ if descriptor.usable_after_bootloader_exit() => {
+                // Region was not usable before, but it will be as soon as
+                // the bootloader passes control to the kernel. We don't
+                // need to check against `next_free` because the
+                // LegacyFrameAllocator only allocates memory from usable
+                // descriptors.
+                MemoryRegionKind::Usable
             }
-        }
+            // Regions of defective memory (`MemoryRegionKind::BadMemory`) are passed
+            // through unchanged. They are never usable, so the frame allocator doesn't
+            // allocate from them and they are never split by the used slices.
+            other => other,
+        };
 
-        let initialized = &mut regions[..next_index];
-        unsafe {
-            // inlined variant of: `MaybeUninit::slice_assume_init_mut(initialized)`
-            // TODO: undo inlining when `slice_assume_init_mut` becomes stable
-            &mut *(initialized as *mut [_] as *mut [_])
+        let end = descriptor.start() + descriptor.len();
+        let region = MemoryRegion {
+            start: descriptor.start().as_u64(),
+            end: end.as_u64(),
+            kind,
+        };
+        if region.kind == MemoryRegionKind::Usable {
+            split_and_add_region(region, regions, &mut next_index, used_slices.clone());
+        } else {
+            add_region(region, regions, &mut next_index);
         }
     }
 
-    fn split_and_add_region<'a, U>(
-        mut region: MemoryRegion,
-        regions: &mut [MaybeUninit<MemoryRegion>],
-        next_index: &mut usize,
-        used_slices: U,
-    ) where
-        U: Iterator<Item = UsedMemorySlice> + Clone,
-    {
-        assert!(region.kind == MemoryRegionKind::Usable);
-        // Each loop iteration takes a chunk of `region` and adds it to
-        // `regions`. Do this until `region` is empty.
-        while region.start != region.end {
-            // Check if there is overlap between `region` and `used_slices`.
-            if let Some((overlap_start, overlap_end)) = used_slices
-                .clone()
-                .map(|slice| {
-                    // Calculate the start and end points of the overlap
-                    // between `slice` and `region`. If `slice` and `region`
-                    // don't overlap, the range will be ill-formed
-                    // (overlap_start > overlap_end).
-                    let overlap_start = cmp::max(region.start, slice.start);
-                    let overlap_end = cmp::min(region.end, slice.end);
-                    (overlap_start, overlap_end)
-                })
-                .filter(|(overlap_start, overlap_end)| {
-                    // Only consider non-empty overlap.
-                    overlap_start < overlap_end
-                })
-                .min_by_key(|&(overlap_start, _)| {
-                    // Find the earliest overlap.
-                    overlap_start
-                })
-            {
-                // There's no overlapping used slice before `overlap_start`, so
-                // we know that memory between `region.start` and
-                // `overlap_start` is usable.
-                let usable = MemoryRegion {
-                    start: region.start,
-                    end: overlap_start,
-                    kind: MemoryRegionKind::Usable,
-                };
-                let bootloader = MemoryRegion {
-                    start: overlap_start,
-                    end: overlap_end,
-                    kind: MemoryRegionKind::Bootloader,
-                };
-                Self::add_region(usable, regions, next_index);
-                Self::add_region(bootloader, regions, next_index);
-                // Continue after the overlapped region.
-                region.start = overlap_end;
-            } else {
-                // There's no overlap. We can add the whole region.
-                Self::add_region(region, regions, next_index);
-                break;
-            }
+    let initialized = &mut regions[..next_index];
+    unsafe {
+        // inlined variant of: `MaybeUninit::slice_assume_init_mut(initialized)`
+        // TODO: undo inlining when `slice_assume_init_mut` becomes stable
+        &mut *(initialized as *mut [_] as *mut [_])
+    }
+}
+
+fn split_and_add_region<'a, U>(
+    mut region: MemoryRegion,
+    regions: &mut [MaybeUninit<MemoryRegion>],
+    next_index: &mut usize,
+    used_slices: U,
+) where
+    U: Iterator<Item = UsedMemorySlice> + Clone,
+{
+    assert!(region.kind == MemoryRegionKind::Usable);
+    // Each loop iteration takes a chunk of `region` and adds it to
+    // `regions`. Do this until `region` is empty.
+    while region.start != region.end {
+        let overlaps = used_slices.clone().map(|slice| {
+            // Calculate the start and end points of the overlap
+            // between `slice` and `region`. If `slice` and `region`
+            // don't overlap, the range will be ill-formed
+            // (overlap_start > overlap_end).
+            let overlap_start = cmp::max(region.start, slice.start);
+            let overlap_end = cmp::min(region.end, slice.end);
+            (overlap_start, overlap_end, slice.kind)
+        });
+        // Check if there is overlap between `region` and `used_slices`.
+        if let Some((index, (overlap_start, overlap_end, kind))) = overlaps
+            .clone()
+            .enumerate()
+            .filter(|(_, (overlap_start, overlap_end, _))| {
+                // Only consider non-empty overlap.
+                overlap_start < overlap_end
+            })
+            .min_by_key(|&(_, (overlap_start, _, _))| {
+                // Find the earliest overlap. On ties, the first slice wins.
+                overlap_start
+            })
+        {
+            // Slices that come earlier take precedence, so end the overlap where the
+            // first of them starts.
+            let overlap_end = overlaps
+                .take(index)
+                .filter(|(start, end, _)| start < end)
+                .map(|(start, _, _)| start)
+                .filter(|&start| overlap_start < start && start < overlap_end)
+                .min()
+                .unwrap_or(overlap_end);
+
+            // There's no overlapping used slice before `overlap_start`, so
+            // we know that memory between `region.start` and
+            // `overlap_start` is usable.
+            let usable = MemoryRegion {
+                start: region.start,
+                end: overlap_start,
+                kind: MemoryRegionKind::Usable,
+            };
+            let used = MemoryRegion {
+                start: overlap_start,
+                end: overlap_end,
+                kind,
+            };
+            add_region(usable, regions, next_index);
+            add_region(used, regions, next_index);
+            // Continue after the overlapped region.
+            region.start = overlap_end;
+        } else {
+            // There's no overlap. We can add the whole region.
+            add_region(region, regions, next_index);
+            break;
         }
     }
+}
 
-    fn add_region(
-        region: MemoryRegion,
-        regions: &mut [MaybeUninit<MemoryRegion>],
-        next_index: &mut usize,
-    ) {
-        if region.start == region.end {
-            // skip zero sized regions
-            return;
-        }
-        unsafe {
-            regions
-                .get_mut(*next_index)
-                .expect("cannot add region: no more free entries in memory map")
-                .as_mut_ptr()
-                .write(region)
-        };
-        *next_index += 1;
+/// Returns whether memory regions of the given kind contain RAM, as opposed to reserved memory
+/// or memory-mapped I/O.
+fn is_ram(kind: MemoryRegionKind) -> bool {
+    // E820 type 2 and the UEFI reserved, MMIO, and MMIO port space types
+    !matches!(
+        kind,
+        MemoryRegionKind::UnknownBios(2) | MemoryRegionKind::UnknownUefi(0 | 11 | 12)
+    )
+}
+
+fn overlaps(a: PhysFrameRange, b: PhysFrameRange) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+fn add_region(
+    region: MemoryRegion,
+    regions: &mut [MaybeUninit<MemoryRegion>],
+    next_index: &mut usize,
+) {
+    if region.start == region.end {
+        // skip zero sized regions
+        return;
     }
+    unsafe {
+        regions
+            .get_mut(*next_index)
+            .expect("cannot add region: no more free entries in memory map")
+            .as_mut_ptr()
+            .write(region)
+    };
+    *next_index += 1;
 }
 
 unsafe impl<I, D> FrameAllocator<Size4KiB> for LegacyFrameAllocator<I, D>
 where
-    I: ExactSizeIterator<Item = D> + Clone,
-    I::Item: LegacyMemoryRegion,
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
 {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
         if let Some(current_descriptor) = self.current_descriptor {
@@ -323,6 +813,7 @@ where
             }
         }
 
+        crate::failure::set_kind(crate::failure::FailureKind::OutOfMemory);
         None
     }
 }
@@ -597,4 +1088,650 @@ mod tests {
         );
         assert_eq!(kernel_regions.next(), None);
     }
+
+    fn usable(start: u64, end: u64) -> MemoryRegion {
+        MemoryRegion {
+            start,
+            end,
+            kind: MemoryRegionKind::Usable,
+        }
+    }
+
+    fn bootloader(start: u64, end: u64) -> MemoryRegion {
+        MemoryRegion {
+            start,
+            end,
+            kind: MemoryRegionKind::Bootloader,
+        }
+    }
+
+    /// Runs the free `construct_memory_map` function on a single usable region from
+    /// `0x1_0000` to `0x2_0000`.
+    fn split_single_region(used_slices: &[UsedMemorySlice]) -> Vec<MemoryRegion> {
+        let memory_map = [TestMemoryRegion {
+            start: PhysAddr::new(0x1_0000),
+            len: 0x1_0000,
+            kind: MemoryRegionKind::Usable,
+        }];
+        let mut regions = [MaybeUninit::uninit(); 10];
+        construct_memory_map(
+            memory_map.into_iter(),
+            used_slices.iter().copied(),
+            &mut regions,
+        )
+        .to_vec()
+    }
+
+    #[test]
+    fn test_split_no_overlap() {
+        let slice = UsedMemorySlice::new_from_len(0x3_0000, 0x1000);
+        assert_eq!(split_single_region(&[slice]), [usable(0x1_0000, 0x2_0000)]);
+    }
+
+    #[test]
+    fn test_split_at_start() {
+        let slice = UsedMemorySlice::new_from_len(0x1_0000, 0x2000);
+        assert_eq!(
+            split_single_region(&[slice]),
+            [bootloader(0x1_0000, 0x1_2000), usable(0x1_2000, 0x2_0000)]
+        );
+    }
+
+    #[test]
+    fn test_split_at_end() {
+        let slice = UsedMemorySlice::new_from_len(0x1_e000, 0x2000);
+        assert_eq!(
+            split_single_region(&[slice]),
+            [usable(0x1_0000, 0x1_e000), bootloader(0x1_e000, 0x2_0000)]
+        );
+    }
+
+    #[test]
+    fn test_split_in_middle() {
+        let slice = UsedMemorySlice::new_from_len(0x1_4000, 0x1000);
+        assert_eq!(
+            split_single_region(&[slice]),
+            [
+                usable(0x1_0000, 0x1_4000),
+                bootloader(0x1_4000, 0x1_5000),
+                usable(0x1_5000, 0x2_0000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_whole_region() {
+        // the slice extends beyond both ends of the region
+        let slice = UsedMemorySlice::new_from_len(0x8000, 0x2_0000);
+        assert_eq!(
+            split_single_region(&[slice]),
+            [bootloader(0x1_0000, 0x2_0000)]
+        );
+    }
+
+    #[test]
+    fn test_split_multiple_slices() {
+        // the slices are given out of order and the second one is not page-aligned
+        let slices = [
+            UsedMemorySlice::new_from_len(0x1_8000, 0x1000),
+            UsedMemorySlice::new_from_len(0x1_2800, 0x1000),
+        ];
+        assert_eq!(
+            split_single_region(&slices),
+            [
+                usable(0x1_0000, 0x1_2000),
+                bootloader(0x1_2000, 0x1_4000),
+                usable(0x1_4000, 0x1_8000),
+                bootloader(0x1_8000, 0x1_9000),
+                usable(0x1_9000, 0x2_0000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_ignores_unusable_regions() {
+        let memory_map = [
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x1_0000,
+                kind: MemoryRegionKind::UnknownBios(2),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x1_0000),
+                len: 0x1_0000,
+                kind: MemoryRegionKind::Usable,
+            },
+        ];
+        // the slice spans both regions, but only the usable one is split
+        let slices = [UsedMemorySlice::new_from_len(0x8000, 0x1_0000)];
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let result = construct_memory_map(memory_map.into_iter(), slices, &mut regions);
+        assert_eq!(
+            result,
+            [
+                MemoryRegion {
+                    start: 0,
+                    end: 0x1_0000,
+                    kind: MemoryRegionKind::UnknownBios(2)
+                },
+                bootloader(0x1_0000, 0x1_8000),
+                usable(0x1_8000, 0x2_0000)
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no more free entries in memory map")]
+    fn test_split_too_many_regions() {
+        let memory_map = create_single_test_region();
+        let slices = [UsedMemorySlice::new_from_len(0x1000, 0x1000)];
+        let mut regions = [MaybeUninit::uninit(); 2];
+        construct_memory_map(memory_map.into_iter(), slices, &mut regions);
+    }
+
+    #[test]
+    fn test_max_region_count_worst_case() {
+        // every used slice lies in the middle of a separate usable region
+        let regions: Vec<_> = (0..8u64)
+            .map(|i| TestMemoryRegion {
+                start: PhysAddr::new(i * 0x10_0000),
+                len: 0x10_0000,
+                kind: MemoryRegionKind::Usable,
+            })
+            .collect();
+        let mut allocator = LegacyFrameAllocator::new_starting_at(
+            PhysFrame::containing_address(PhysAddr::new(0x18_0000)),
+            regions.into_iter(),
+        );
+        let low_frame = allocator.allocate_low_frame().unwrap();
+        assert_eq!(low_frame.start_address(), PhysAddr::new(0xf_f000));
+        allocator
+            .reserve_range(PhysFrame::range(
+                PhysFrame::containing_address(PhysAddr::new(0x58_0000)),
+                PhysFrame::containing_address(PhysAddr::new(0x58_1000)),
+            ))
+            .unwrap();
+        allocator.allocate_frame().unwrap();
+
+        let count = allocator.memory_map_max_region_count(true);
+        assert_eq!(count, 8 + 2 * 5);
+        let mut regions = vec![MaybeUninit::uninit(); count];
+        let kernel_regions = allocator.construct_memory_map(
+            &mut regions,
+            PhysAddr::new(0x38_0000),
+            0x1000,
+            Some(PhysAddr::new(0x48_0000)),
+            0x1000,
+        );
+        // the low frame lies at the end of its region, so it only adds a single region
+        assert_eq!(kernel_regions.len(), count - 1);
+    }
+
+    #[test]
+    fn test_max_region_count_spanning_slice() {
+        // a slice that spans multiple regions splits only the first and the last one
+        let memory_map: Vec<_> = (0..3u64)
+            .map(|i| TestMemoryRegion {
+                start: PhysAddr::new(i * 0x1_0000),
+                len: 0x1_0000,
+                kind: MemoryRegionKind::Usable,
+            })
+            .collect();
+        let slices = [UsedMemorySlice::new_from_len(0x8000, 0x2_0000)];
+        let count = max_region_count(memory_map.len(), slices.len());
+        let mut regions = vec![MaybeUninit::uninit(); count];
+        let result = construct_memory_map(memory_map.into_iter(), slices, &mut regions);
+        assert_eq!(result.len(), count);
+    }
+
+    #[test]
+    fn test_region_bounds() {
+        let region = |start, len, kind| TestMemoryRegion {
+            start: PhysAddr::new(start),
+            len,
+            kind,
+        };
+        let regions = vec![
+            region(0, 0x8_0000, MemoryRegionKind::Usable),
+            region(0x8_0000, 0x1000, MemoryRegionKind::UnknownUefi(9)),
+            region(0x8_1000, 0x2000, MemoryRegionKind::Usable),
+            region(0x8_3000, 0x3000, MemoryRegionKind::UnknownUefi(10)),
+            region(0x10_0000, 0x1000, MemoryRegionKind::UnknownUefi(10)),
+        ];
+        let allocator = LegacyFrameAllocator::new(regions.into_iter());
+
+        let acpi = |kind| matches!(kind, MemoryRegionKind::UnknownUefi(9 | 10));
+        assert_eq!(
+            allocator.region_bounds(acpi),
+            Some((PhysAddr::new(0x8_0000), 0x8_1000))
+        );
+        assert_eq!(
+            allocator.region_bounds(|kind| kind == MemoryRegionKind::BadMemory),
+            None
+        );
+    }
+
+    #[test]
+    fn test_excluded_ranges() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        let excluded = PhysFrame::range(
+            PhysFrame::containing_address(PhysAddr::new(0x10_1000)),
+            PhysFrame::containing_address(PhysAddr::new(0x10_3000)),
+        );
+        allocator.exclude_range(excluded).unwrap();
+        assert!(allocator.reserve_range(excluded).is_err());
+
+        // the allocator skips the excluded frames
+        let frames: Vec<_> = (0..3)
+            .map(|_| allocator.allocate_frame().unwrap().start_address().as_u64())
+            .collect();
+        assert_eq!(frames, [0x10_0000, 0x10_3000, 0x10_4000]);
+        // already allocated frames can't be excluded
+        let allocated = PhysFrame::range(
+            PhysFrame::containing_address(PhysAddr::new(0x10_3000)),
+            PhysFrame::containing_address(PhysAddr::new(0x10_4000)),
+        );
+        assert!(allocator.exclude_range(allocated).is_err());
+
+        // the excluded range is reported as reserved, although the memory allocated by the
+        // bootloader spans it
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert_eq!(
+            kernel_regions[2..6],
+            [
+                usable(0x5_1000, 0x10_0000),
+                bootloader(0x10_0000, 0x10_1000),
+                MemoryRegion {
+                    start: 0x10_1000,
+                    end: 0x10_3000,
+                    kind: MemoryRegionKind::Reserved
+                },
+                bootloader(0x10_3000, 0x10_5000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memory_test_marks_bad_frames() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        allocator.allocate_frame().unwrap();
+        let bad = [0x10_2000, 0x10_3000, 0x10_6000];
+        let mut tested = Vec::new();
+        let result = allocator
+            .test_memory(PhysAddr::new(0x10_8000), |frame| {
+                let addr = frame.start_address().as_u64();
+                tested.push(addr);
+                !bad.contains(&addr)
+            })
+            .unwrap();
+        assert_eq!(
+            result,
+            MemoryTestResult {
+                bad_frames: 3,
+                lost_frames: 0
+            }
+        );
+        // already allocated frames are not tested
+        assert_eq!(
+            tested,
+            (0x10_1000..0x10_8000).step_by(0x1000).collect::<Vec<_>>()
+        );
+
+        // the allocator skips the bad frames
+        let frames: Vec<_> = (0..3)
+            .map(|_| allocator.allocate_frame().unwrap().start_address().as_u64())
+            .collect();
+        assert_eq!(frames, [0x10_1000, 0x10_4000, 0x10_5000]);
+
+        let bad_memory = |start, end| MemoryRegion {
+            start,
+            end,
+            kind: MemoryRegionKind::BadMemory,
+        };
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert_eq!(
+            kernel_regions[3..8],
+            [
+                bootloader(0x10_0000, 0x10_2000),
+                bad_memory(0x10_2000, 0x10_4000),
+                bootloader(0x10_4000, 0x10_6000),
+                bad_memory(0x10_6000, 0x10_7000),
+                usable(0x10_7000, MAX_PHYS_ADDR),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memory_test_extends_bad_range_when_full() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        // every other frame is bad, which needs more ranges than there are slots
+        let result = allocator
+            .test_memory(PhysAddr::new(0x20_0000), |frame| {
+                frame.start_address().as_u64() % 0x2000 != 0
+            })
+            .unwrap();
+        // the first 8 bad frames get their own slot, each following one extends the last range
+        // over the good frame before it
+        assert_eq!(
+            result,
+            MemoryTestResult {
+                bad_frames: 128,
+                lost_frames: 120
+            }
+        );
+
+        let mut regions = [MaybeUninit::uninit(); 20];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        // no bad frame is reported as usable
+        for region in kernel_regions
+            .iter()
+            .filter(|r| r.kind == MemoryRegionKind::Usable)
+        {
+            let mut bad = (0x10_0000..0x20_0000).step_by(0x2000);
+            assert!(bad.all(|addr| addr < region.start || addr >= region.end));
+        }
+        // only the good frames between the first 8 bad frames and the one behind the last bad
+        // frame remain usable
+        let usable_tested_frames: u64 = kernel_regions
+            .iter()
+            .filter(|r| r.kind == MemoryRegionKind::Usable)
+            .map(|r| (r.end.min(0x20_0000)).saturating_sub(r.start.max(0x10_0000)) / 0x1000)
+            .sum();
+        assert_eq!(usable_tested_frames, 128 - 120);
+
+        // a reserved range between the bad frames prevents extending the bad range
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        for i in 0..MAX_EXCLUDED_RANGES as u64 - 1 {
+            let frame = PhysFrame::containing_address(PhysAddr::new(0x20_0000 + i * 0x2000));
+            allocator
+                .exclude_range(PhysFrame::range(frame, frame + 1))
+                .unwrap();
+        }
+        let result = allocator.test_memory(PhysAddr::new(0x40_0000), |frame| {
+            let addr = frame.start_address().as_u64();
+            addr != 0x10_0000 && addr != 0x30_0000
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_allocate_range_within() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        let frame = |addr| PhysFrame::containing_address(PhysAddr::new(addr));
+        allocator
+            .exclude_range(PhysFrame::range(frame(0x20_1000), frame(0x20_2000)))
+            .unwrap();
+
+        // the range skips the excluded frame
+        let range = allocator
+            .allocate_range_within(2, PhysFrame::range(frame(0x20_0000), frame(0x30_0000)))
+            .unwrap();
+        assert_eq!(range, PhysFrame::range(frame(0x20_2000), frame(0x20_4000)));
+        // ranges that are too small or outside of the memory map fail
+        let small = PhysFrame::range(frame(0x20_0000), frame(0x20_1000));
+        assert_eq!(allocator.allocate_range_within(2, small), None);
+        let outside = PhysFrame::range(frame(MAX_PHYS_ADDR), frame(MAX_PHYS_ADDR + 0x1_0000));
+        assert_eq!(allocator.allocate_range_within(1, outside), None);
+
+        // the regular allocation skips the allocated range
+        for _ in 0..0x104 {
+            let frame = allocator.allocate_frame().unwrap();
+            assert!(!range.into_iter().any(|f| f == frame));
+        }
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert_eq!(
+            kernel_regions[3..8],
+            [
+                bootloader(0x10_0000, 0x20_1000),
+                MemoryRegion {
+                    start: 0x20_1000,
+                    end: 0x20_2000,
+                    kind: MemoryRegionKind::Reserved
+                },
+                bootloader(0x20_2000, 0x20_4000),
+                bootloader(0x20_4000, 0x20_7000),
+                usable(0x20_7000, MAX_PHYS_ADDR),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_contains_ram() {
+        let regions = [
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x8000_0000,
+                kind: MemoryRegionKind::Usable,
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0xfec0_0000),
+                len: 0x1000,
+                kind: MemoryRegionKind::UnknownBios(2),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0xfee0_0000),
+                len: 0x1000,
+                kind: MemoryRegionKind::UnknownBios(4),
+            },
+        ];
+        let allocator = LegacyFrameAllocator::new(regions.into_iter());
+        assert!(allocator.contains_ram(PhysAddr::new(0x7fe0_0000), 0x20_0000));
+        // gaps in the memory map
+        assert!(!allocator.contains_ram(PhysAddr::new(0x8000_0000), 0x20_0000));
+        // reserved regions
+        assert!(!allocator.contains_ram(PhysAddr::new(0xfec0_0000), 0x20_0000));
+        // ACPI NVS memory
+        assert!(allocator.contains_ram(PhysAddr::new(0xfee0_0000), 0x20_0000));
+    }
+
+    #[test]
+    fn test_custom_region_iterator() {
+        // the allocator accepts iterators that don't know their exact length
+        let regions = create_single_test_region();
+        let iter = regions.iter().copied().filter(|r| r.len != 0);
+        let mut allocator = LegacyFrameAllocator::new(iter);
+        assert_eq!(allocator.len(), 1);
+        let frame = allocator.allocate_frame().unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0x10_0000));
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert_eq!(kernel_regions.len(), 5);
+        assert_eq!(kernel_regions.last().unwrap().end, MAX_PHYS_ADDR);
+    }
+
+    #[test]
+    fn test_low_frame() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x9_f000,
+                kind: MemoryRegionKind::Usable,
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x9_f000),
+                len: 0x1000,
+                kind: MemoryRegionKind::UnknownBios(2),
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x10_0000),
+                len: MAX_PHYS_ADDR - 0x10_0000,
+                kind: MemoryRegionKind::Usable,
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        let frame = allocator.allocate_low_frame().unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0x9_e000));
+        assert_eq!(allocator.allocate_low_frame(), None);
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert!(kernel_regions.contains(&MemoryRegion {
+            start: 0x9_e000,
+            end: 0x9_f000,
+            kind: MemoryRegionKind::Bootloader
+        }));
+    }
+
+    #[test]
+    fn test_bad_memory_is_skipped_and_reported() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x10_0000,
+                kind: MemoryRegionKind::Usable,
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x10_0000),
+                len: 0x1000,
+                kind: MemoryRegionKind::BadMemory,
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x10_1000),
+                len: MAX_PHYS_ADDR - 0x10_1000,
+                kind: MemoryRegionKind::Usable,
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        let frame = allocator.allocate_frame().unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0x10_1000));
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert!(kernel_regions.contains(&MemoryRegion {
+            start: 0x10_0000,
+            end: 0x10_1000,
+            kind: MemoryRegionKind::BadMemory
+        }));
+    }
+
+    fn frame_range(start: u64, end: u64) -> PhysFrameRange {
+        PhysFrame::range(
+            PhysFrame::containing_address(PhysAddr::new(start)),
+            PhysFrame::containing_address(PhysAddr::new(end)),
+        )
+    }
+
+    #[test]
+    fn test_reserved_range_is_skipped() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        allocator
+            .reserve_range(frame_range(0x10_0000, 0x10_2000))
+            .unwrap();
+
+        let frame = allocator.allocate_frame().unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0x10_2000));
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert!(kernel_regions.contains(&MemoryRegion {
+            start: 0x10_0000,
+            end: 0x10_3000,
+            kind: MemoryRegionKind::Bootloader
+        }));
+    }
+
+    #[test]
+    fn test_reserved_range_is_reported_as_used() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        allocator.allocate_frame();
+        allocator
+            .reserve_range(frame_range(0x20_0000, 0x20_4000))
+            .unwrap();
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert!(kernel_regions.contains(&MemoryRegion {
+            start: 0x20_0000,
+            end: 0x20_4000,
+            kind: MemoryRegionKind::Bootloader
+        }));
+    }
+
+    #[test]
+    fn test_reserve_invalid_range() {
+        let regions = vec![
+            TestMemoryRegion {
+                start: PhysAddr::new(0),
+                len: 0x20_0000,
+                kind: MemoryRegionKind::Usable,
+            },
+            TestMemoryRegion {
+                start: PhysAddr::new(0x20_0000),
+                len: 0x1000,
+                kind: MemoryRegionKind::UnknownBios(0),
+            },
+        ];
+        let mut allocator = LegacyFrameAllocator::new(regions.into_iter());
+        allocator.allocate_frame();
+
+        // already allocated
+        assert!(allocator
+            .reserve_range(frame_range(0x10_0000, 0x10_2000))
+            .is_err());
+        // in the lower 1MiB
+        assert!(allocator
+            .reserve_range(frame_range(0xf_f000, 0x10_0000))
+            .is_err());
+        // not usable
+        assert!(allocator
+            .reserve_range(frame_range(0x1f_f000, 0x20_1000))
+            .is_err());
+        // outside of the memory map
+        assert!(allocator
+            .reserve_range(frame_range(0x30_0000, 0x30_1000))
+            .is_err());
+
+        allocator
+            .reserve_range(frame_range(0x18_0000, 0x18_1000))
+            .unwrap();
+        // only a single range can be reserved
+        assert!(allocator
+            .reserve_range(frame_range(0x19_0000, 0x19_1000))
+            .is_err());
+    }
+
+    #[test]
+    fn test_reserve_range_below_start_frame() {
+        let regions = create_single_test_region();
+        let mut allocator = LegacyFrameAllocator::new_starting_at(
+            PhysFrame::containing_address(PhysAddr::new(0x40_0000)),
+            regions.into_iter(),
+        );
+        allocator
+            .reserve_range(frame_range(0x20_0000, 0x20_4000))
+            .unwrap();
+
+        let frame = allocator.allocate_frame().unwrap();
+        assert_eq!(frame.start_address(), PhysAddr::new(0x40_0000));
+
+        let mut regions = [MaybeUninit::uninit(); 10];
+        let kernel_regions =
+            allocator.construct_memory_map(&mut regions, PhysAddr::new(0x50000), 0x1000, None, 0);
+        assert!(kernel_regions.contains(&MemoryRegion {
+            start: 0x20_0000,
+            end: 0x20_4000,
+            kind: MemoryRegionKind::Bootloader
+        }));
+    }
 }
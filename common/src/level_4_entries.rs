@@ -1,5 +1,9 @@
 use crate::{entropy, load_kernel::VirtualAddressOffset, BootInfo, RawFrameBufferInfo};
-use bootloader_api::{config, info::MemoryRegion, BootloaderConfig};
+use bootloader_api::{
+    config::{self, DynamicPlacement},
+    info::MemoryRegion,
+    BootloaderConfig,
+};
 use core::{alloc::Layout, iter::Step};
 use rand::{
     distributions::{Distribution, Uniform},
@@ -13,6 +17,16 @@ use x86_64::{
 };
 use xmas_elf::program::ProgramHeader;
 
+const LEVEL_4_SIZE: u64 = 4096 * 512 * 512 * 512;
+/// The highest canonical address of the lower half of the virtual address space.
+const LOWER_HALF_END: u64 = 0x0000_7fff_ffff_ffff;
+/// The lowest canonical address of the higher half of the virtual address space.
+const HIGHER_HALF_START: u64 = 0xffff_8000_0000_0000;
+
+const PHYSICAL_MEMORY_MAPPING_HINT: &str = "consider lowering `dynamic_range_start`, raising \
+    `dynamic_range_end`, or disabling the `physical_memory` mapping and mapping only the \
+    required physical memory regions through the recursive page table mapping instead";
+
 /// Keeps track of used entries in a level 4 page table.
 ///
 /// Useful for determining a free virtual memory block, e.g. for mapping additional data.
@@ -22,6 +36,10 @@ pub struct UsedLevel4Entries {
     /// A random number generator that should be used to generate random addresses or
     /// `None` if aslr is disabled.
     rng: Option<Hc128Rng>,
+    /// The lowest usable virtual address for dynamic mappings.
+    dynamic_range_start: u64,
+    /// The highest usable virtual address for dynamic mappings (inclusive).
+    dynamic_range_end: u64,
 }
 
 impl UsedLevel4Entries {
@@ -37,6 +55,8 @@ impl UsedLevel4Entries {
         let mut used = UsedLevel4Entries {
             entry_state: [false; 512],
             rng: config.mappings.aslr.then(entropy::build_rng),
+            dynamic_range_start: 0,
+            dynamic_range_end: u64::MAX,
         };
 
         // The bootloader maps of the kernel's memory into its own page tables.
@@ -56,7 +76,33 @@ impl UsedLevel4Entries {
         if let Some(config::Mapping::FixedAddress(physical_memory_offset)) =
             config.mappings.physical_memory
         {
-            used.mark_range_as_used(physical_memory_offset, max_phys_addr.as_u64().into_usize());
+            let size = crate::physical_memory_mapping_len(max_phys_addr, config);
+            let end_inclusive = physical_memory_offset.checked_add(size.saturating_sub(1));
+            let fits = match end_inclusive {
+                // the mapping must not cross the non-canonical hole
+                Some(end) if physical_memory_offset <= LOWER_HALF_END => end <= LOWER_HALF_END,
+                Some(_) => physical_memory_offset >= HIGHER_HALF_START,
+                None => false,
+            };
+            if !fits {
+                panic!(
+                    "the physical memory mapping at the fixed offset {physical_memory_offset:#x} \
+                    doesn't fit into the virtual address space (physical memory size is {size:#x} \
+                    bytes); {PHYSICAL_MEMORY_MAPPING_HINT}"
+                );
+            }
+            used.mark_range_as_used(physical_memory_offset, size.into_usize());
+        }
+
+        if let Some((start, len)) = config.mappings.identity_mapped_range {
+            // invalid ranges are reported when the identity mapping is created
+            if len > 0
+                && start
+                    .checked_add(len)
+                    .is_some_and(|end| end <= LOWER_HALF_END + 1)
+            {
+                used.mark_range_as_used(start, len);
+            }
         }
 
         if let Some(config::Mapping::FixedAddress(recursive_address)) =
@@ -67,7 +113,12 @@ impl UsedLevel4Entries {
         }
 
         if let config::Mapping::FixedAddress(kernel_stack_address) = config.mappings.kernel_stack {
-            used.mark_range_as_used(kernel_stack_address, config.kernel_stack_size);
+            // the guard page and the optional canary page are placed below the stack
+            let extra_pages = 1 + u64::from(config.kernel_stack_canary);
+            used.mark_range_as_used(
+                kernel_stack_address,
+                extra_pages * 4096 + config.kernel_stack_size,
+            );
         }
 
         if let config::Mapping::FixedAddress(boot_info_address) = config.mappings.boot_info {
@@ -85,10 +136,19 @@ impl UsedLevel4Entries {
             }
         }
 
+        if let Some(config::Mapping::FixedAddress(scratch_address)) =
+            config.mappings.framebuffer_scratch
+        {
+            if let Some(framebuffer) = framebuffer {
+                used.mark_range_as_used(scratch_address, framebuffer.info.byte_len);
+            }
+        }
+
         // Mark everything before the dynamic range as unusable.
         if let Some(dynamic_range_start) = config.mappings.dynamic_range_start {
             let dynamic_range_start = VirtAddr::new(dynamic_range_start);
             let start_page: Page = Page::containing_address(dynamic_range_start);
+            used.dynamic_range_start = start_page.start_address().as_u64();
             if let Some(unusable_page) = Step::backward_checked(start_page, 1) {
                 for i in 0..=u16::from(unusable_page.p4_index()) {
                     used.mark_p4_index_as_used(PageTableIndex::new(i));
@@ -100,6 +160,7 @@ impl UsedLevel4Entries {
         if let Some(dynamic_range_end) = config.mappings.dynamic_range_end {
             let dynamic_range_end = VirtAddr::new(dynamic_range_end);
             let end_page: Page = Page::containing_address(dynamic_range_end);
+            used.dynamic_range_end = end_page.start_address().as_u64() + (end_page.size() - 1);
             if let Some(unusable_page) = Step::forward_checked(end_page, 1) {
                 for i in u16::from(unusable_page.p4_index())..512 {
                     used.mark_p4_index_as_used(PageTableIndex::new(i));
@@ -113,7 +174,7 @@ impl UsedLevel4Entries {
     /// Marks all p4 entries in the range `[address..address+size)` as used.
     ///
     /// `size` can be a `u64` or `usize`.
-    fn mark_range_as_used<S>(&mut self, address: u64, size: S)
+    pub fn mark_range_as_used<S>(&mut self, address: u64, size: S)
     where
         VirtAddr: core::ops::Add<S, Output = VirtAddr>,
     {
@@ -169,7 +230,68 @@ impl UsedLevel4Entries {
             free_entries.next()
         };
         let Some(idx) = idx_opt else {
-            panic!("no usable level 4 entries found ({num} entries requested)");
+            self.no_free_entries(num);
+        };
+
+        // Mark the entries as used.
+        for i in 0..num.into_usize() {
+            self.entry_state[idx + i] = true;
+        }
+
+        PageTableIndex::new(idx.try_into().unwrap())
+    }
+
+    /// Returns a virtual address for a dynamic mapping and marks the level 4 entries that it
+    /// uses as used.
+    ///
+    /// The address is chosen according to the given [`DynamicPlacement`].
+    pub fn get_dynamic_address(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        placement: DynamicPlacement,
+    ) -> VirtAddr {
+        match placement {
+            DynamicPlacement::Top => self.get_free_address_top(size, alignment),
+            DynamicPlacement::Low => self.get_free_address_low(size, alignment),
+            _ => self.get_free_address(size, alignment),
+        }
+    }
+
+    /// Returns the first index of the highest `num` contiguous unused level 4 entries and marks
+    /// them as used.
+    ///
+    /// In contrast to [`get_free_entries`], the returned entries are not randomized if
+    /// `CONFIG.aslr` is enabled.
+    pub fn get_free_entries_top(&mut self, num: u64) -> PageTableIndex {
+        let Some(idx) = self
+            .entry_state
+            .windows(num.into_usize())
+            .rposition(|entries| entries.iter().all(|used| !used))
+        else {
+            self.no_free_entries(num);
+        };
+
+        // Mark the entries as used.
+        for i in 0..num.into_usize() {
+            self.entry_state[idx + i] = true;
+        }
+
+        PageTableIndex::new(idx.try_into().unwrap())
+    }
+
+    /// Returns the first index of the lowest `num` contiguous unused level 4 entries and marks
+    /// them as used.
+    ///
+    /// In contrast to [`get_free_entries`], the returned entries are not randomized if
+    /// `CONFIG.aslr` is enabled.
+    pub fn get_free_entries_low(&mut self, num: u64) -> PageTableIndex {
+        let Some(idx) = self
+            .entry_state
+            .windows(num.into_usize())
+            .position(|entries| entries.iter().all(|used| !used))
+        else {
+            self.no_free_entries(num);
         };
 
         // Mark the entries as used.
@@ -187,8 +309,6 @@ impl UsedLevel4Entries {
     pub fn get_free_address(&mut self, size: u64, alignment: u64) -> VirtAddr {
         assert!(alignment.is_power_of_two());
 
-        const LEVEL_4_SIZE: u64 = 4096 * 512 * 512 * 512;
-
         let level_4_entries = (size + (LEVEL_4_SIZE - 1)) / LEVEL_4_SIZE;
         let base = Page::from_page_table_indices_1gib(
             self.get_free_entries(level_4_entries),
@@ -197,9 +317,9 @@ impl UsedLevel4Entries {
         .start_address();
 
         let offset = if let Some(rng) = self.rng.as_mut() {
-            // Choose a random offset.
-            let max_offset = LEVEL_4_SIZE - (size % LEVEL_4_SIZE);
-            let uniform_range = Uniform::from(0..max_offset / alignment);
+            // Choose a random offset that keeps the range inside the chosen entries.
+            let max_offset = level_4_entries * LEVEL_4_SIZE - size;
+            let uniform_range = Uniform::from(0..=max_offset / alignment);
             uniform_range.sample(rng) * alignment
         } else {
             0
@@ -207,4 +327,195 @@ impl UsedLevel4Entries {
 
         base + offset
     }
+
+    /// Returns the start address of the lowest unused level 4 entries that fit `size` bytes
+    /// and marks them as used.
+    ///
+    /// The returned address is aligned to a level 4 entry boundary (512 GiB), so any `alignment`
+    /// up to that is satisfied. This function calls [`get_free_entries_low`] internally, so all
+    /// of its docs applies here too.
+    pub fn get_free_address_low(&mut self, size: u64, alignment: u64) -> VirtAddr {
+        assert!(alignment.is_power_of_two());
+
+        let level_4_entries = size.div_ceil(LEVEL_4_SIZE);
+        Page::from_page_table_indices_1gib(
+            self.get_free_entries_low(level_4_entries),
+            PageTableIndex::new(0),
+        )
+        .start_address()
+    }
+
+    /// Panics with a descriptive message if a dynamic mapping of the physical memory with the
+    /// given size doesn't fit into the unused level 4 entries of the dynamic range.
+    pub fn assert_physical_memory_mapping_fits(&self, size: u64) {
+        let required = size.div_ceil(LEVEL_4_SIZE);
+        let available = self.max_free_entries();
+        if required > available {
+            panic!(
+                "the physical memory mapping ({size:#x} bytes, {required} level 4 entries) \
+                doesn't fit into the dynamic range {:#x}..={:#x}, which has at most {available} \
+                contiguous unused level 4 entries; {PHYSICAL_MEMORY_MAPPING_HINT}",
+                self.dynamic_range_start, self.dynamic_range_end
+            );
+        }
+    }
+
+    /// Returns the length of the longest run of contiguous unused level 4 entries.
+    fn max_free_entries(&self) -> u64 {
+        self.entry_state
+            .split(|&used| used)
+            .map(|free| free.len() as u64)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn no_free_entries(&self, num: u64) -> ! {
+        panic!(
+            "no usable level 4 entries found in dynamic range {:#x}..={:#x} ({num} entries \
+            requested); note that only level 4 entries that lie completely inside the dynamic \
+            range are used",
+            self.dynamic_range_start, self.dynamic_range_end
+        );
+    }
+
+    /// Returns the highest suitable virtual address in the highest unused level 4 entries and
+    /// marks them as used.
+    ///
+    /// The returned address range ends at the end of the chosen level 4 entries. This function
+    /// calls [`get_free_entries_top`] internally, so all of its docs applies here too.
+    pub fn get_free_address_top(&mut self, size: u64, alignment: u64) -> VirtAddr {
+        assert!(alignment.is_power_of_two());
+
+        let level_4_entries = size.div_ceil(LEVEL_4_SIZE);
+        let base = Page::from_page_table_indices_1gib(
+            self.get_free_entries_top(level_4_entries),
+            PageTableIndex::new(0),
+        )
+        .start_address()
+        .as_u64();
+
+        let end_inclusive = base + (level_4_entries * LEVEL_4_SIZE - 1);
+        match end_inclusive
+            .checked_sub(size.saturating_sub(1))
+            .map(|start| start & !(alignment - 1))
+        {
+            Some(start) if start >= base => VirtAddr::new(start),
+            _ => {
+                panic!("not enough space at the end of the dynamic range ({size} bytes requested)")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn used_entries(dynamic_range_start: u64, dynamic_range_end: u64) -> UsedLevel4Entries {
+        let mut config = BootloaderConfig::new_default();
+        config.mappings.dynamic_range_start = Some(dynamic_range_start);
+        config.mappings.dynamic_range_end = Some(dynamic_range_end);
+        UsedLevel4Entries::new(PhysAddr::new(0x4000_0000), 10, None, &config)
+    }
+
+    #[test]
+    fn dynamic_addresses_stay_in_range() {
+        // covers exactly the level 4 entries 256 and 257
+        let start = 0xffff_8000_0000_0000;
+        let end = start + 2 * LEVEL_4_SIZE - 4096;
+        let mut used = used_entries(start, end);
+
+        for _ in 0..2 {
+            let addr = used.get_free_address(0x1000, 0x1000).as_u64();
+            assert!(addr >= start);
+            assert!(addr + 0x1000 - 1 <= end + 4095);
+        }
+    }
+
+    #[test]
+    fn partially_covered_entries_are_not_used() {
+        // covers the entries 256 and 258 only partially and the entry 257 completely
+        let start = 0xffff_8000_0000_0000 + 0x1000;
+        let end = start + 2 * LEVEL_4_SIZE;
+        let mut used = used_entries(start, end);
+
+        let addr = used.get_free_address(0x1000, 0x1000);
+        assert_eq!(u16::from(addr.p4_index()), 257);
+
+        let mut used = used_entries(start, end);
+        let addr = used.get_free_address_top(0x1000, 0x1000);
+        assert_eq!(u16::from(addr.p4_index()), 257);
+    }
+
+    #[test]
+    fn dynamic_top_address_ends_at_range_end() {
+        let start = 0xffff_8000_0000_0000;
+        let end = start + 2 * LEVEL_4_SIZE - 0x1000;
+        let mut used = used_entries(start, end);
+
+        let addr = used.get_free_address_top(0x3000, 0x1000).as_u64();
+        assert_eq!(addr + 0x3000, end + 0x1000);
+    }
+
+    #[test]
+    fn dynamic_placement_top() {
+        let start = 0xffff_8000_0000_0000;
+        let end = start + 2 * LEVEL_4_SIZE - 0x1000;
+        let mut used = used_entries(start, end);
+
+        let addr = used
+            .get_dynamic_address(0x3000, 0x1000, DynamicPlacement::Top)
+            .as_u64();
+        assert_eq!(addr + 0x3000, end + 0x1000);
+        let addr = used.get_dynamic_address(0x1000, 0x1000, DynamicPlacement::Anywhere);
+        assert_eq!(addr.as_u64(), start);
+    }
+
+    #[test]
+    fn dynamic_placement_low() {
+        let start = 0xffff_8000_0000_0000;
+        let end = start + 4 * LEVEL_4_SIZE - 0x1000;
+        let mut used = used_entries(start, end);
+        used.mark_p4_index_as_used(PageTableIndex::new(256));
+
+        let addr = used.get_dynamic_address(0x1000, 0x1000, DynamicPlacement::Low);
+        assert_eq!(addr.as_u64(), start + LEVEL_4_SIZE);
+        let addr = used.get_dynamic_address(0x1000, 0x1000, DynamicPlacement::Low);
+        assert_eq!(addr.as_u64(), start + 2 * LEVEL_4_SIZE);
+    }
+
+    #[test]
+    fn dynamic_low_address_is_lowest_free_entry() {
+        let start = 0xffff_8000_0000_0000;
+        let end = start + 4 * LEVEL_4_SIZE - 0x1000;
+        let mut used = used_entries(start, end);
+        used.mark_p4_index_as_used(PageTableIndex::new(256));
+
+        let addr = used.get_free_address_low(LEVEL_4_SIZE + 1, 0x1000);
+        assert_eq!(addr.as_u64(), start + LEVEL_4_SIZE);
+        let addr = used.get_free_address_low(0x1000, 0x1000);
+        assert_eq!(addr.as_u64(), start + 3 * LEVEL_4_SIZE);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit into the dynamic range")]
+    fn physical_memory_mapping_too_large() {
+        let start = 0xffff_8000_0000_0000;
+        let end = start + 2 * LEVEL_4_SIZE - 4096;
+        let used = used_entries(start, end);
+
+        used.assert_physical_memory_mapping_fits(2 * LEVEL_4_SIZE);
+        used.assert_physical_memory_mapping_fits(2 * LEVEL_4_SIZE + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "no usable level 4 entries found in dynamic range")]
+    fn dynamic_range_overflow() {
+        let start = 0xffff_8000_0000_0000;
+        let end = start + LEVEL_4_SIZE - 4096;
+        let mut used = used_entries(start, end);
+
+        used.get_free_address(0x1000, 0x1000);
+        used.get_free_address(0x1000, 0x1000);
+    }
 }
@@ -2,27 +2,58 @@
 #![feature(step_trait)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
-use crate::legacy_memory_region::{LegacyFrameAllocator, LegacyMemoryRegion};
+use crate::{
+    boot_stage::BootStage,
+    legacy_memory_region::{LegacyFrameAllocator, LegacyMemoryRegion, MemoryTestResult},
+};
 use bootloader_api::{
-    config::Mapping,
-    info::{FrameBuffer, FrameBufferInfo, MemoryRegion, TlsTemplate},
+    config::{CacheMode, DynamicPlacement, Mapping},
+    info::{
+        BootConfigInfo, BootTime, FrameBuffer, FrameBufferInfo, KernelConfigInfo, LogLevelFilter,
+        MemoryRegion, MemoryRegionKind, TlsTemplate,
+    },
     BootInfo, BootloaderConfig,
 };
-use bootloader_boot_config::{BootConfig, LevelFilter};
-use core::{alloc::Layout, arch::asm, mem::MaybeUninit, slice};
+use bootloader_boot_config::{
+    embedded as embedded_config, BootConfig, FlatKernel, LevelFilter, MemoryRange,
+};
+use core::{
+    alloc::Layout,
+    arch::asm,
+    fmt,
+    mem::{self, MaybeUninit},
+    ptr, slice,
+};
 use level_4_entries::UsedLevel4Entries;
 use usize_conversions::FromUsize;
 use x86_64::{
+    instructions::port::Port,
+    registers::{
+        control::{Cr0, Cr0Flags, Cr4, Cr4Flags},
+        model_specific::{Efer, EferFlags, FsBase, GsBase},
+    },
+    structures::gdt::SegmentSelector,
     structures::paging::{
-        page_table::PageTableLevel, FrameAllocator, Mapper, OffsetPageTable, Page, PageSize,
-        PageTableFlags, PageTableIndex, PhysFrame, Size2MiB, Size4KiB,
+        frame::PhysFrameRange,
+        mapper::{MapToError, TranslateResult},
+        page_table::PageTableLevel,
+        FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags,
+        PageTableIndex, PhysFrame, Size2MiB, Size4KiB, Translate,
     },
     PhysAddr, VirtAddr,
 };
-use xmas_elf::ElfFile;
+use xmas_elf::{header, ElfFile};
 
+/// Parses the `boot.json` config file.
+pub mod boot_config;
+/// Prints machine-readable markers to the serial port at the boundaries of the boot stages.
+pub mod boot_stage;
+/// Reads the CPU vendor and brand strings and paging capabilities.
+mod cpu;
 /// Provides a function to gather entropy and build a RNG.
 mod entropy;
+/// Signals boot failures through PC speaker beeps and POST codes.
+pub mod failure;
 /// Provides a type that logs output as text to pixel-based framebuffers.
 pub mod framebuffer;
 mod gdt;
@@ -34,11 +65,55 @@ pub mod level_4_entries;
 pub mod load_kernel;
 /// Provides a logger that logs output as text in various formats.
 pub mod logger;
+/// Reads the NUMA memory ranges from the ACPI System Resource Affinity Table.
+pub mod numa;
+/// Reads the current time from the CMOS real-time clock.
+pub mod rtc;
 /// Provides a type that logs output as text to a Serial Being port.
 pub mod serial;
 
 const PAGE_SIZE: u64 = 4096;
 
+/// Reserved space for a boot config that is embedded into the bootloader binary.
+///
+/// The space is filled in by the disk image builder after compilation, so it must only be
+/// read through volatile reads. It is `static mut` so that the compiler doesn't assume that it
+/// still contains its initial value.
+#[used]
+#[link_section = ".bootcfg"]
+static mut EMBEDDED_BOOT_CONFIG: [u8; embedded_config::SIZE] = {
+    let mut buffer = [0; embedded_config::SIZE];
+    let mut i = 0;
+    while i < embedded_config::MAGIC.len() {
+        buffer[i] = embedded_config::MAGIC[i];
+        i += 1;
+    }
+    buffer
+};
+
+/// Returns the JSON boot config that was embedded into the bootloader binary, if any.
+pub fn embedded_boot_config() -> Option<&'static [u8]> {
+    let base = ptr::addr_of!(EMBEDDED_BOOT_CONFIG).cast::<u8>();
+    let len_bytes =
+        unsafe { ptr::read_volatile(base.add(embedded_config::LEN_OFFSET).cast::<[u8; 4]>()) };
+    let len = usize::try_from(u32::from_le_bytes(len_bytes)).unwrap();
+    if len == 0 {
+        return None;
+    }
+    assert!(
+        len <= embedded_config::MAX_LEN,
+        "invalid embedded boot config length {len}"
+    );
+    Some(unsafe { slice::from_raw_parts(base.add(embedded_config::DATA_OFFSET), len) })
+}
+
+/// The pattern that the kernel stack canary page is filled with.
+const KERNEL_STACK_CANARY_PATTERN: u64 = 0x57ac_ca4a_57ac_ca4a;
+
+/// The kernel stack size that is used in safe mode, smaller than the default to keep the
+/// memory footprint minimal.
+const SAFE_MODE_KERNEL_STACK_SIZE: u64 = 64 * 1024;
+
 /// Initialize a text-based logger using the given pixel-based framebuffer as output.
 pub fn init_logger(
     framebuffer: &'static mut [u8],
@@ -46,6 +121,7 @@ pub fn init_logger(
     log_level: LevelFilter,
     frame_buffer_logger_status: bool,
     serial_logger_status: bool,
+    boot_log_status: bool,
 ) {
     let logger = logger::LOGGER.get_or_init(move || {
         logger::LockedLogger::new(
@@ -53,6 +129,7 @@ pub fn init_logger(
             info,
             frame_buffer_logger_status,
             serial_logger_status,
+            boot_log_status,
         )
     });
     log::set_logger(logger).expect("logger already set");
@@ -80,6 +157,11 @@ pub struct SystemInfo {
     pub rsdp_addr: Option<PhysAddr>,
     pub ramdisk_addr: Option<u64>,
     pub ramdisk_len: u64,
+    /// The current date and time, if requested by the kernel config.
+    pub boot_time: Option<BootTime>,
+    /// Physical start address and length of the memory that contains the ACPI tables, see
+    /// [`BootInfo::acpi_tables_addr`].
+    pub acpi_tables: Option<(PhysAddr, u64)>,
 }
 
 /// The physical address of the framebuffer and information about the framebuffer.
@@ -89,33 +171,270 @@ pub struct RawFrameBufferInfo {
     pub addr: PhysAddr,
     /// Information about the framebuffer, including layout and pixel format.
     pub info: FrameBufferInfo,
+    /// The refresh rate of the display in Hz, if known, see
+    /// [`BootInfo::framebuffer_refresh_rate`].
+    pub refresh_rate: Option<u32>,
 }
 
 pub struct Kernel<'a> {
-    pub elf: ElfFile<'a>,
+    pub image: KernelImage<'a>,
     pub config: BootloaderConfig,
+    /// The link-time virtual address and the length of the `.bootloader-config` section, if
+    /// it is part of the loaded kernel image.
+    pub config_section: Option<(u64, u64)>,
     pub start_address: *const u8,
     pub len: usize,
 }
 
+/// The executable format of a [`Kernel`].
+pub enum KernelImage<'a> {
+    /// A 64-bit ELF executable.
+    Elf(ElfFile<'a>),
+    /// A flat binary that is mapped at a fixed virtual address, see
+    /// [`BootConfig::flat_kernel`].
+    Flat {
+        /// The raw bytes of the kernel.
+        bytes: &'a [u8],
+        /// Describes where the kernel is mapped and entered.
+        layout: FlatKernel,
+    },
+}
+
 impl<'a> Kernel<'a> {
-    pub fn parse(kernel_slice: &'a [u8]) -> Self {
-        let kernel_elf = ElfFile::new(kernel_slice).unwrap();
-        let config = {
+    /// Parses the given kernel file.
+    ///
+    /// Kernel files that don't start with the ELF magic bytes are treated as flat binaries
+    /// if the [`BootConfig::flat_kernel`] option is set.
+    pub fn parse(kernel_slice: &'a [u8], boot_config: &BootConfig) -> Self {
+        failure::set_kind(failure::FailureKind::InvalidKernel);
+        let kernel = match boot_config.flat_kernel {
+            Some(layout) if !kernel_slice.starts_with(&header::MAGIC) => {
+                Self::parse_flat(kernel_slice, layout)
+            }
+            _ => Self::parse_elf(kernel_slice),
+        };
+        failure::set_kind(failure::FailureKind::Other);
+        kernel
+    }
+
+    /// Creates a flat binary kernel, which uses the default [`BootloaderConfig`].
+    pub fn parse_flat(kernel_slice: &'a [u8], layout: FlatKernel) -> Self {
+        Kernel {
+            image: KernelImage::Flat {
+                bytes: kernel_slice,
+                layout,
+            },
+            config: BootloaderConfig::new_default(),
+            config_section: None,
+            start_address: kernel_slice.as_ptr(),
+            len: kernel_slice.len(),
+        }
+    }
+
+    /// Parses the given kernel ELF file.
+    pub fn parse_elf(kernel_slice: &'a [u8]) -> Self {
+        let kernel_elf = ElfFile::new(kernel_slice).expect("failed to parse kernel ELF file");
+        match kernel_elf.header.pt1.class() {
+            header::Class::SixtyFour => {}
+            other => panic!(
+                "kernel must be a 64-bit ELF file (expected ELF class `SixtyFour`, found `{other:?}`)"
+            ),
+        }
+        match kernel_elf.header.pt2.machine().as_machine() {
+            header::Machine::X86_64 => {}
+            other => panic!(
+                "kernel must be compiled for the x86_64 architecture (expected ELF machine \
+                `X86_64`, found `{other:?}`)"
+            ),
+        }
+        let (config, config_section) = {
             let section = kernel_elf
                 .find_section_by_name(".bootloader-config")
                 .expect("bootloader config section not found; kernel must be compiled against bootloader_api");
             let raw = section.raw_data(&kernel_elf);
-            BootloaderConfig::deserialize(raw)
-                .expect("kernel was compiled with incompatible bootloader_api version")
+            let config = BootloaderConfig::deserialize(raw)
+                .expect("kernel was compiled with incompatible bootloader_api version");
+            let loaded = section.flags() & xmas_elf::sections::SHF_ALLOC != 0;
+            (config, loaded.then(|| (section.address(), section.size())))
         };
         Kernel {
-            elf: kernel_elf,
+            image: KernelImage::Elf(kernel_elf),
             config,
+            config_section,
             start_address: kernel_slice.as_ptr(),
             len: kernel_slice.len(),
         }
     }
+
+    /// Returns the physical frames that the load segments of the kernel request if the kernel
+    /// enables the [`load_at_physical_addresses`](BootloaderConfig::load_at_physical_addresses)
+    /// option.
+    pub fn physical_load_range(&self) -> Result<Option<PhysFrameRange>, &'static str> {
+        match &self.image {
+            KernelImage::Elf(elf_file) if self.config.load_at_physical_addresses => {
+                load_kernel::physical_load_range(elf_file).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Overrides the given kernel and boot configuration for booting in safe mode.
+///
+/// Should be called by the loaders before initializing the logger if
+/// [`BootConfig::safe_mode`] is enabled. The framebuffer is additionally not mapped and not
+/// passed to the kernel in safe mode, which is handled by [`load_and_switch_to_kernel`].
+pub fn apply_safe_mode(kernel: &mut Kernel, boot_config: &mut BootConfig) {
+    let mappings = &mut kernel.config.mappings;
+    mappings.physical_memory = None;
+    mappings.framebuffer_scratch = None;
+    mappings.aslr = false;
+    kernel.config.kernel_stack_size = SAFE_MODE_KERNEL_STACK_SIZE;
+
+    boot_config.frame_buffer_logging = false;
+    boot_config.serial_logging = true;
+    boot_config.log_level = LevelFilter::Trace;
+}
+
+/// Excludes the [`BootConfig::reserved_memory`] ranges from the given frame allocator.
+///
+/// Should be called by the loaders right after creating the frame allocator, so that no frames
+/// of the ranges are allocated. All ranges are processed, even if some of them fail. The
+/// first failure is returned.
+pub fn exclude_reserved_memory<I, D>(
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    boot_config: &BootConfig,
+) -> Result<(), ReservedMemoryError>
+where
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    let mut result = Ok(());
+    for &range in boot_config.reserved_memory.as_slice() {
+        let frames = PhysFrame::range(
+            PhysFrame::containing_address(PhysAddr::new(range.start)),
+            PhysFrame::containing_address(PhysAddr::new(range.end()).align_up(4096u64)),
+        );
+        if let Err(reason) = frame_allocator.exclude_range(frames) {
+            result = result.and(Err(ReservedMemoryError { range, reason }));
+        }
+    }
+    result
+}
+
+/// Reserves the physical memory that the load segments of the given kernel request through
+/// the [`load_at_physical_addresses`](BootloaderConfig::load_at_physical_addresses) option.
+///
+/// Must be called by the loaders right after creating the frame allocator, before any frame is
+/// allocated. If the requested memory lies before the start frame of the frame allocator, the
+/// loader must ensure that it is not in use. Does nothing if the kernel doesn't enable the
+/// option.
+pub fn reserve_kernel_load_range<I, D>(
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    kernel: &Kernel,
+) -> Result<(), &'static str>
+where
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    match kernel.physical_load_range()? {
+        Some(range) => frame_allocator.reserve_range(range),
+        None => Ok(()),
+    }
+}
+
+/// A [`BootConfig::reserved_memory`] range that could not be excluded from the frame
+/// allocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReservedMemoryError {
+    /// The affected range.
+    pub range: MemoryRange,
+    /// The reason why the range could not be excluded.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ReservedMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to reserve memory range {:#x}..{:#x}: {}",
+            self.range.start,
+            self.range.end(),
+            self.reason
+        )
+    }
+}
+
+/// Runs the [`BootConfig::memory_test`] pass over the usable frames of the given frame
+/// allocator that were not allocated yet.
+///
+/// All frames below `end` are accessed through the given physical memory offset, so they
+/// must be mapped there. Frames that fail the test are reported as
+/// [`MemoryRegionKind::BadMemory`] in the memory map that is passed to the kernel. Should be
+/// called by the loaders before the kernel and boot info are loaded, so that as much memory as
+/// possible is tested.
+pub fn test_memory<I, D>(
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    phys_offset: VirtAddr,
+    end: PhysAddr,
+) where
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    log::info!("Testing usable memory below {:#x}", end.as_u64());
+    let result = frame_allocator.test_memory(end, |frame| {
+        let ptr: *mut u64 = (phys_offset + frame.start_address().as_u64()).as_mut_ptr();
+        // SAFETY: the frame is unused and mapped at the given offset
+        unsafe { test_frame(ptr) }
+    });
+    match result {
+        Ok(MemoryTestResult { bad_frames: 0, .. }) => log::info!("Memory test passed"),
+        Ok(MemoryTestResult {
+            bad_frames,
+            lost_frames,
+        }) => {
+            log::warn!(
+                "Memory test found {bad_frames} defective frames, marking them as bad memory"
+            );
+            if lost_frames > 0 {
+                log::warn!(
+                    "Not enough slots to exclude the defective frames separately, \
+                    {lost_frames} good frames are marked as bad memory too"
+                );
+            }
+        }
+        Err(err) => log::warn!("Memory test failed to exclude defective frames: {err}"),
+    }
+}
+
+/// Writes test patterns to the given frame, reads them back, and zeroes the frame afterwards.
+///
+/// The alternating bit patterns find stuck and coupled bits, the address pattern finds faulty
+/// address lines. The cache lines are flushed before reading, so that the values are read
+/// from RAM instead of the cache.
+///
+/// ## Safety
+///
+/// The pointer must point to an unused, writable 4KiB frame.
+unsafe fn test_frame(frame: *mut u64) -> bool {
+    const WORDS: usize = 4096 / mem::size_of::<u64>();
+
+    let check = |pattern: &dyn Fn(usize) -> u64| {
+        for i in 0..WORDS {
+            unsafe { ptr::write_volatile(frame.add(i), pattern(i)) };
+        }
+        for line in (0..WORDS).step_by(64 / mem::size_of::<u64>()) {
+            unsafe { asm!("clflush [{}]", in(reg) frame.add(line), options(nostack)) };
+        }
+        unsafe { asm!("mfence", options(nostack)) };
+        (0..WORDS).all(|i| unsafe { ptr::read_volatile(frame.add(i)) } == pattern(i))
+    };
+    let passed = check(&|_| 0x5555_5555_5555_5555)
+        && check(&|_| 0xaaaa_aaaa_aaaa_aaaa)
+        && check(&|i| frame as u64 + (i * mem::size_of::<u64>()) as u64);
+
+    unsafe { ptr::write_bytes(frame, 0, WORDS) };
+    passed
 }
 
 /// Loads the kernel ELF executable into memory and switches to it.
@@ -128,12 +447,17 @@ pub fn load_and_switch_to_kernel<I, D>(
     boot_config: BootConfig,
     mut frame_allocator: LegacyFrameAllocator<I, D>,
     mut page_tables: PageTables,
-    system_info: SystemInfo,
+    mut system_info: SystemInfo,
 ) -> !
 where
-    I: ExactSizeIterator<Item = D> + Clone,
+    I: Iterator<Item = D> + Clone,
     D: LegacyMemoryRegion,
 {
+    if boot_config.safe_mode {
+        log::info!("Booting in safe mode");
+        system_info.framebuffer = None;
+    }
+
     let config = kernel.config;
     let mut mappings = set_up_mappings(
         kernel,
@@ -141,8 +465,12 @@ where
         &mut page_tables,
         system_info.framebuffer.as_ref(),
         &config,
+        &boot_config,
         &system_info,
-    );
+    )
+    .unwrap_or_else(|err| panic!("{err}"));
+    boot_stage::mark(BootStage::MappingsDone);
+    let framebuffer = system_info.framebuffer;
     let boot_info = create_boot_info(
         &config,
         &boot_config,
@@ -150,10 +478,122 @@ where
         &mut page_tables,
         &mut mappings,
         system_info,
-    );
+    )
+    .unwrap_or_else(|err| panic!("{err}"));
+    boot_stage::mark(BootStage::BootInfoDone);
+    if boot_config.frame_buffer.clear_before_handoff {
+        mappings.framebuffer_to_clear = framebuffer;
+    }
+    if config.mask_pic {
+        mask_pic();
+    }
+    if boot_config.dump_page_tables {
+        dump_page_table(
+            &mut page_tables.kernel,
+            mappings.five_level_paging.is_some(),
+        );
+    }
     switch_to_kernel(page_tables, mappings, boot_info);
 }
 
+/// Logs all present mappings of the given level 4 page table.
+///
+/// Mappings that are contiguous in both virtual and physical memory and have the same flags
+/// are merged, ignoring the accessed, dirty, and huge page bits.
+///
+/// With 5-level paging, the level 5 table references the level 4 table through its first
+/// and last entry (see [`set_up_five_level_paging`]), so the logged 48-bit addresses stay
+/// valid. The aliases of these mappings in the rest of the 57-bit address space are not
+/// logged.
+fn dump_page_table(page_table: &mut OffsetPageTable, five_level_paging: bool) {
+    let ignored_flags =
+        PageTableFlags::ACCESSED | PageTableFlags::DIRTY | PageTableFlags::HUGE_PAGE;
+
+    log::info!("Kernel page table mappings:");
+    if five_level_paging {
+        log::info!("  (5-level paging, only the sign-extended 48-bit addresses are listed)");
+    }
+    let mut current: Option<(u64, u64, u64, PageTableFlags)> = None;
+    let log_range = |(virt, phys, len, flags): (u64, u64, u64, PageTableFlags)| {
+        log::info!(
+            "  {virt:#018x}..{:#018x} -> {phys:#x}..{:#x} {flags:?}",
+            virt.wrapping_add(len),
+            phys + len
+        );
+    };
+    let phys_offset = page_table.phys_offset();
+    walk_page_table(
+        page_table.level_4_table(),
+        4,
+        0,
+        phys_offset,
+        &mut |virt, phys, len, flags| {
+            let flags = flags - ignored_flags;
+            match &mut current {
+                Some((start_virt, start_phys, current_len, current_flags))
+                    if start_virt.wrapping_add(*current_len) == virt
+                        && *start_phys + *current_len == phys
+                        && *current_flags == flags =>
+                {
+                    *current_len += len;
+                }
+                _ => {
+                    if let Some(range) = current.replace((virt, phys, len, flags)) {
+                        log_range(range);
+                    }
+                }
+            }
+        },
+    );
+    if let Some(range) = current {
+        log_range(range);
+    }
+}
+
+/// Calls `f` with the virtual address, physical address, size, and flags of each present
+/// mapping below the given page table, in ascending order of the virtual addresses.
+///
+/// Assumes 4-level paging, i.e. a `level` of 4 is the root table whose entries are
+/// sign-extended from bit 47.
+fn walk_page_table(
+    table: &PageTable,
+    level: u8,
+    virt_base: u64,
+    phys_offset: VirtAddr,
+    f: &mut impl FnMut(u64, u64, u64, PageTableFlags),
+) {
+    let entry_size = 1u64 << (12 + 9 * u32::from(level - 1));
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let mut virt = virt_base + index as u64 * entry_size;
+        if level == 4 && virt & (1 << 47) != 0 {
+            // sign-extend addresses in the higher half
+            virt |= 0xffff_0000_0000_0000;
+        }
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            f(virt, entry.addr().as_u64(), entry_size, flags);
+        } else {
+            let next: *const PageTable = (phys_offset + entry.addr().as_u64()).as_ptr();
+            walk_page_table(unsafe { &*next }, level - 1, virt, phys_offset, f);
+        }
+    }
+}
+
+/// Masks all interrupt lines of the legacy 8259 PIC.
+fn mask_pic() {
+    const PIC_1_DATA: u16 = 0x21;
+    const PIC_2_DATA: u16 = 0xa1;
+
+    log::info!("Masking the legacy PIC");
+    unsafe {
+        Port::<u8>::new(PIC_1_DATA).write(0xff);
+        Port::<u8>::new(PIC_2_DATA).write(0xff);
+    }
+}
+
 /// Sets up mappings for a kernel stack and the framebuffer.
 ///
 /// The `kernel_bytes` slice should contain the raw bytes of the kernel ELF executable. The
@@ -166,28 +606,26 @@ where
 /// maps this framebuffer in the kernel-level page table, unless the `map_framebuffer` config
 /// option is disabled.
 ///
-/// This function reacts to unexpected situations (e.g. invalid kernel ELF file) with a panic, so
-/// errors are not recoverable.
+/// Returns an error if the frame allocator runs out of memory. Other unexpected situations
+/// (e.g. an invalid kernel ELF file) lead to a panic, so these errors are not recoverable.
 pub fn set_up_mappings<I, D>(
     kernel: Kernel,
     frame_allocator: &mut LegacyFrameAllocator<I, D>,
     page_tables: &mut PageTables,
     framebuffer: Option<&RawFrameBufferInfo>,
     config: &BootloaderConfig,
+    boot_config: &BootConfig,
     system_info: &SystemInfo,
-) -> Mappings
+) -> Result<Mappings, BootError>
 where
-    I: ExactSizeIterator<Item = D> + Clone,
+    I: Iterator<Item = D> + Clone,
     D: LegacyMemoryRegion,
 {
     let kernel_page_table = &mut page_tables.kernel;
 
-    let mut used_entries = UsedLevel4Entries::new(
-        frame_allocator.max_phys_addr(),
-        frame_allocator.len(),
-        framebuffer,
-        config,
-    );
+    let phys_mem_end = physical_memory_end(frame_allocator.max_phys_addr(), framebuffer);
+    let mut used_entries =
+        UsedLevel4Entries::new(phys_mem_end, frame_allocator.len(), framebuffer, config);
 
     // Enable support for the no-execute bit in page tables.
     enable_nxe_bit();
@@ -198,36 +636,85 @@ where
     let kernel_slice_start = PhysAddr::new(kernel.start_address as _);
     let kernel_slice_len = u64::try_from(kernel.len).unwrap();
 
-    let (kernel_image_offset, entry_point, tls_template) = load_kernel::load_kernel(
-        kernel,
-        kernel_page_table,
-        frame_allocator,
-        &mut used_entries,
-    )
-    .expect("no entry point");
+    if let Some(range) = kernel
+        .physical_load_range()
+        .expect("failed to determine the kernel's physical load address")
+    {
+        // the range must be reserved before the loaders allocate any frames
+        assert_eq!(
+            frame_allocator.reserved_range(),
+            Some(range),
+            "physical load address of the kernel was not reserved"
+        );
+        log::info!(
+            "Loading the kernel segments to {:#x}..{:#x}",
+            range.start.start_address(),
+            range.end.start_address()
+        );
+    }
+
+    let kernel_config_section = kernel.config_section;
+    let (kernel_image_offset, entry_point, tls_template, kernel_bss_frames) =
+        load_kernel::load_kernel(
+            kernel,
+            kernel_page_table,
+            frame_allocator,
+            &mut used_entries,
+        )?;
+    let entry_point = match config.entry_point {
+        Some(addr) => entry_point_override(addr, kernel_image_offset, kernel_page_table),
+        None => entry_point,
+    };
     log::info!("Entry point at: {:#x}", entry_point.as_u64());
     // create a stack
-    let stack_start = {
+    let (stack_start, stack_canary) = {
+        // the optional canary page lies directly below the guard page
+        let canary_pages = u64::from(config.kernel_stack_canary);
         // we need page-alignment because we want a guard page directly below the stack
-        let guard_page = mapping_addr_page_aligned(
+        let first_page = mapping_addr_page_aligned_placed(
             config.mappings.kernel_stack,
+            config.mappings.kernel_stack_placement,
             // allocate an additional page as a guard page
-            Size4KiB::SIZE + config.kernel_stack_size,
+            (canary_pages + 1) * Size4KiB::SIZE + config.kernel_stack_size,
             &mut used_entries,
             "kernel stack start",
         );
-        guard_page + 1
+        let guard_page = first_page + canary_pages;
+        (
+            guard_page + 1,
+            config.kernel_stack_canary.then_some(first_page),
+        )
     };
+    if let Some(page) = stack_canary {
+        map_stack_canary(page, kernel_page_table, frame_allocator, &config)?;
+        log::info!("Kernel stack canary page at {:#x}", page.start_address());
+    }
     let stack_end_addr = stack_start.start_address() + config.kernel_stack_size;
 
     let stack_end = Page::containing_address(stack_end_addr - 1u64);
+    let mut numa_frames = boot_config.kernel_stack_numa_node.and_then(|node| {
+        numa_stack_frames(
+            node,
+            stack_end - stack_start + 1,
+            frame_allocator,
+            system_info.rsdp_addr,
+        )
+    });
     for page in Page::range_inclusive(stack_start, stack_end) {
-        let frame = frame_allocator
-            .allocate_frame()
-            .expect("frame allocation failed when mapping a kernel stack");
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let frame = numa_frames
+            .as_mut()
+            .and_then(Iterator::next)
+            .or_else(|| frame_allocator.allocate_frame())
+            .ok_or(BootError::FrameAllocationFailed("kernel stack"))?;
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::NO_EXECUTE
+            | load_kernel::extra_page_flags(&config);
         match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
             Ok(tlb) => tlb.flush(),
+            Err(MapToError::FrameAllocationFailed) => {
+                return Err(BootError::FrameAllocationFailed("kernel stack"))
+            }
             Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
         }
     }
@@ -255,6 +742,14 @@ where
             )
         } {
             Ok(tlb) => tlb.flush(),
+            Err(MapToError::PageAlreadyMapped(_)) => {
+                identity_mapping_collision(page, "context switch function")
+            }
+            Err(MapToError::FrameAllocationFailed) => {
+                return Err(BootError::FrameAllocationFailed(
+                    "context switch function mapping",
+                ))
+            }
             Err(err) => panic!("failed to identity map frame {:?}: {:?}", frame, err),
         }
     }
@@ -262,8 +757,8 @@ where
     // create, load, and identity-map GDT (required for working `iretq`)
     let gdt_frame = frame_allocator
         .allocate_frame()
-        .expect("failed to allocate GDT frame");
-    gdt::create_and_load(gdt_frame);
+        .ok_or(BootError::FrameAllocationFailed("GDT"))?;
+    let compat_code_selector = gdt::create_and_load(gdt_frame);
     let gdt_page = Page::containing_address(VirtAddr::new(gdt_frame.start_address().as_u64()));
     match unsafe {
         // The parent table flags need to be both readable and writable to
@@ -277,11 +772,75 @@ where
         )
     } {
         Ok(tlb) => tlb.flush(),
+        Err(MapToError::PageAlreadyMapped(_)) => identity_mapping_collision(gdt_page, "GDT"),
+        Err(MapToError::FrameAllocationFailed) => {
+            return Err(BootError::FrameAllocationFailed("GDT mapping"))
+        }
         Err(err) => panic!("failed to identity map frame {:?}: {:?}", gdt_frame, err),
     }
 
+    let five_level_paging = config
+        .five_level_paging
+        .then(|| {
+            set_up_five_level_paging(
+                page_tables.kernel_level_4_frame,
+                compat_code_selector,
+                kernel_page_table,
+                frame_allocator,
+            )
+        })
+        .transpose()?;
+
+    let identity_mapped_range = config.mappings.identity_mapped_range.map(|(start, len)| {
+        log::info!(
+            "Identity-map the physical range {start:#x}..{:#x}",
+            start + len
+        );
+        identity_map_range(start, len, kernel_page_table, frame_allocator)
+    });
+    let identity_mapped_range = identity_mapped_range.transpose()?;
+
+    let ap_trampoline_frame = if config.ap_trampoline_frame {
+        let frame = frame_allocator.allocate_low_frame();
+        match frame {
+            Some(frame) => {
+                log::info!(
+                    "Reserve AP trampoline frame at {:#x}",
+                    frame.start_address()
+                );
+                // zero frame, utilizing identity-mapping
+                let frame_ptr = frame.start_address().as_u64() as *mut u8;
+                unsafe { frame_ptr.write_bytes(0, Size4KiB::SIZE as usize) };
+            }
+            None => log::warn!("Found no usable frame below 1 MiB for the AP trampoline"),
+        }
+        frame
+    } else {
+        None
+    };
+
+    // the framebuffer can only be accessed through the physical memory mapping if the mapping
+    // covers it completely
+    let framebuffer_in_physical_memory = match framebuffer {
+        Some(framebuffer) if config.mappings.framebuffer_in_physical_memory => {
+            let end = framebuffer.addr + u64::from_usize(framebuffer.info.byte_len);
+            let covered = config.mappings.physical_memory.is_some()
+                && end.as_u64() <= physical_memory_mapping_len(phys_mem_end, &config);
+            if !covered {
+                log::warn!(
+                    "The physical memory mapping doesn't cover the framebuffer, mapping it \
+                    separately"
+                );
+            }
+            covered
+        }
+        _ => false,
+    };
+
     // map framebuffer
-    let framebuffer_virt_addr = if let Some(framebuffer) = framebuffer {
+    let framebuffer_virt_addr = if let Some(framebuffer) =
+        framebuffer.filter(|_| !framebuffer_in_physical_memory)
+    {
         log::info!("Map framebuffer");
 
         let framebuffer_start_frame: PhysFrame = PhysFrame::containing_address(framebuffer.addr);
@@ -297,10 +856,15 @@ where
             PhysFrame::range_inclusive(framebuffer_start_frame, framebuffer_end_frame).enumerate()
         {
             let page = start_page + u64::from_usize(i);
-            let flags =
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+            let flags = PageTableFlags::PRESENT
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::NO_EXECUTE
+                | load_kernel::extra_page_flags(&config);
             match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
                 Ok(tlb) => tlb.flush(),
+                Err(MapToError::FrameAllocationFailed) => {
+                    return Err(BootError::FrameAllocationFailed("framebuffer mapping"))
+                }
                 Err(err) => panic!(
                     "failed to map page {:?} to frame {:?}: {:?}",
                     page, frame, err
@@ -312,8 +876,45 @@ where
     } else {
         None
     };
+    // allocate and map the framebuffer scratch buffer
+    let framebuffer_scratch = match (framebuffer, config.mappings.framebuffer_scratch) {
+        (Some(framebuffer), Some(mapping)) => {
+            log::info!("Map framebuffer scratch buffer");
+
+            let size = u64::from_usize(framebuffer.info.byte_len);
+            let start_page =
+                mapping_addr_page_aligned(mapping, size, &mut used_entries, "framebuffer scratch");
+            let end_page = Page::containing_address(start_page.start_address() + size - 1u64);
+            for page in Page::range_inclusive(start_page, end_page) {
+                let frame =
+                    frame_allocator
+                        .allocate_frame()
+                        .ok_or(BootError::FrameAllocationFailed(
+                            "framebuffer scratch buffer",
+                        ))?;
+                // zero frame, utilizing identity-mapping
+                let frame_ptr = frame.start_address().as_u64() as *mut u8;
+                unsafe { frame_ptr.write_bytes(0, Size4KiB::SIZE as usize) };
+                let flags =
+                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+                match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+                    Ok(tlb) => tlb.flush(),
+                    Err(MapToError::FrameAllocationFailed) => {
+                        return Err(BootError::FrameAllocationFailed(
+                            "framebuffer scratch buffer",
+                        ))
+                    }
+                    Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+                }
+            }
+            Some(start_page.start_address())
+        }
+        _ => None,
+    };
+
     let ramdisk_slice_len = system_info.ramdisk_len;
     let ramdisk_slice_phys_start = system_info.ramdisk_addr.map(PhysAddr::new);
+    let mut ramdisk_slice_mapped_len = 0;
     let ramdisk_slice_start = if let Some(physical_address) = ramdisk_slice_phys_start {
         let start_page = mapping_addr_page_aligned(
             config.mappings.ramdisk_memory,
@@ -323,17 +924,30 @@ where
         );
         let ramdisk_physical_start_page: PhysFrame<Size4KiB> =
             PhysFrame::containing_address(physical_address);
-        let ramdisk_page_count = (system_info.ramdisk_len - 1) / Size4KiB::SIZE;
-        let ramdisk_physical_end_page = ramdisk_physical_start_page + ramdisk_page_count;
+        let ramdisk_page_count = system_info.ramdisk_len.div_ceil(Size4KiB::SIZE);
+        // the complete virtual range is reserved above, so the kernel can map the remaining
+        // pages on demand
+        let mapped_page_count = match config.mappings.ramdisk_eager_pages {
+            Some(pages) => pages.min(ramdisk_page_count),
+            None => ramdisk_page_count,
+        };
+        if mapped_page_count < ramdisk_page_count {
+            log::info!("Mapping {mapped_page_count} of {ramdisk_page_count} ramdisk pages");
+        }
+        ramdisk_slice_mapped_len =
+            (mapped_page_count * Size4KiB::SIZE).min(system_info.ramdisk_len);
+        let ramdisk_physical_end_page = ramdisk_physical_start_page + mapped_page_count;
 
         let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
         for (i, frame) in
-            PhysFrame::range_inclusive(ramdisk_physical_start_page, ramdisk_physical_end_page)
-                .enumerate()
+            PhysFrame::range(ramdisk_physical_start_page, ramdisk_physical_end_page).enumerate()
         {
             let page = start_page + i as u64;
             match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
                 Ok(tlb) => tlb.ignore(),
+                Err(MapToError::FrameAllocationFailed) => {
+                    return Err(BootError::FrameAllocationFailed("ramdisk mapping"))
+                }
                 Err(err) => panic!(
                     "Failed to map page {:?} to frame {:?}: {:?}",
                     page, frame, err
@@ -345,34 +959,71 @@ where
         None
     };
 
-    let physical_memory_offset = if let Some(mapping) = config.mappings.physical_memory {
-        log::info!("Map physical memory");
+    let (physical_memory_offset, physical_memory_len) =
+        if let Some(mapping) = config.mappings.physical_memory {
+            log::info!("Map physical memory");
 
-        let start_frame = PhysFrame::containing_address(PhysAddr::new(0));
-        let max_phys = frame_allocator.max_phys_addr();
-        let end_frame: PhysFrame<Size2MiB> = PhysFrame::containing_address(max_phys - 1u64);
+            let size = physical_memory_mapping_len(phys_mem_end, &config);
+            let start_frame = PhysFrame::containing_address(PhysAddr::new(0));
+            let end_frame: PhysFrame<Size2MiB> = PhysFrame::containing_address(PhysAddr::new(size));
 
-        let size = max_phys.as_u64();
-        let alignment = Size2MiB::SIZE;
-        let offset = mapping_addr(mapping, size, alignment, &mut used_entries)
+            let alignment = Size2MiB::SIZE;
+            if !matches!(mapping, Mapping::FixedAddress(_)) {
+                used_entries.assert_physical_memory_mapping_fits(size);
+            }
+            let offset = mapping_addr(
+                mapping,
+                config.mappings.physical_memory_placement,
+                size,
+                alignment,
+                &mut used_entries,
+            )
             .expect("start address for physical memory mapping must be 2MiB-page-aligned");
 
-        for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
-            let page = Page::containing_address(offset + frame.start_address().as_u64());
-            let flags =
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
-            match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
-                Ok(tlb) => tlb.ignore(),
-                Err(err) => panic!(
-                    "failed to map page {:?} to frame {:?}: {:?}",
-                    page, frame, err
-                ),
-            };
-        }
+            let cache_mode = config.mappings.physical_memory_cache_mode;
+            let mmio_cache_mode = config.mappings.physical_memory_mmio_cache_mode;
+            if cache_mode != CacheMode::Default
+                || mmio_cache_mode.is_some_and(|mode| mode != CacheMode::Default)
+            {
+                program_default_pat();
+            }
 
-        Some(offset)
-    } else {
-        None
+            for frame in PhysFrame::range(start_frame, end_frame) {
+                let page = Page::containing_address(offset + frame.start_address().as_u64());
+                let frame_cache_mode = match mmio_cache_mode {
+                    Some(mode)
+                        if !frame_allocator.contains_ram(frame.start_address(), frame.size()) =>
+                    {
+                        mode
+                    }
+                    _ => cache_mode,
+                };
+                let flags = PageTableFlags::PRESENT
+                    | PageTableFlags::WRITABLE
+                    | PageTableFlags::NO_EXECUTE
+                    | cache_mode_flags(frame_cache_mode);
+                match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+                    Ok(tlb) => tlb.ignore(),
+                    Err(MapToError::FrameAllocationFailed) => {
+                        return Err(BootError::FrameAllocationFailed("physical memory mapping"))
+                    }
+                    Err(err) => panic!(
+                        "failed to map page {:?} to frame {:?}: {:?}",
+                        page, frame, err
+                    ),
+                };
+            }
+
+            (Some(offset), size)
+        } else {
+            (None, 0)
+        };
+    let framebuffer_virt_addr = match (framebuffer, physical_memory_offset) {
+        (Some(framebuffer), Some(offset)) if framebuffer_in_physical_memory => {
+            log::info!("Using the physical memory mapping for the framebuffer");
+            Some(offset + framebuffer.addr.as_u64())
+        }
+        _ => framebuffer_virt_addr,
     };
 
     let recursive_index = if let Some(mapping) = config.mappings.page_table_recursive {
@@ -409,8 +1060,18 @@ where
         None
     };
 
-    Mappings {
+    let requested_cr4_flags = Cr4Flags::from_bits_truncate(config.cr4_flags);
+    let cr4_flags = cpu::supported_cr4_flags(requested_cr4_flags);
+    if cr4_flags != requested_cr4_flags {
+        log::warn!(
+            "Skipping CR4 flags that are not supported by the CPU: {:?}",
+            requested_cr4_flags - cr4_flags
+        );
+    }
+
+    Ok(Mappings {
         framebuffer: framebuffer_virt_addr,
+        framebuffer_scratch,
         entry_point,
         // Use the configured stack size, even if it's not page-aligned. However, we
         // need to align it down to the next 16-byte boundary because the System V
@@ -418,16 +1079,76 @@ where
         stack_top: stack_end_addr.align_down(16u8),
         used_entries,
         physical_memory_offset,
+        physical_memory_len,
         recursive_index,
         tls_template,
+        five_level_paging,
+        identity_mapped_range,
+        ap_trampoline_frame,
+        stack_canary: stack_canary.map(Page::start_address),
+        framebuffer_to_clear: None,
+        cr4_flags,
+        fs_base: config.fs_base,
+        gs_base: config.gs_base,
 
         kernel_slice_start,
         kernel_slice_len,
         kernel_image_offset,
+        kernel_bss_frames,
+        kernel_config_section: kernel_config_section
+            .map(|(addr, len)| (kernel_image_offset + addr, len)),
 
         ramdisk_slice_phys_start,
         ramdisk_slice_start,
         ramdisk_slice_len,
+        ramdisk_slice_mapped_len,
+    })
+}
+
+/// An error that prevents the bootloader from setting up the kernel's environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootError {
+    /// The frame allocator ran out of memory while allocating frames for the given purpose.
+    FrameAllocationFailed(&'static str),
+    /// There is less usable memory than [`BootloaderConfig::minimum_memory_bytes`].
+    InsufficientMemory {
+        /// The total size of the usable memory regions, in bytes.
+        available: u64,
+        /// The required amount of memory, in bytes.
+        required: u64,
+    },
+    /// The kernel executable could not be loaded, e.g. because it is malformed.
+    KernelLoadFailed(&'static str),
+    /// The kernel requested 5-level paging, but the CPU doesn't support it.
+    La57Unsupported,
+}
+
+impl From<&'static str> for BootError {
+    fn from(reason: &'static str) -> Self {
+        BootError::KernelLoadFailed(reason)
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootError::FrameAllocationFailed(context) => write!(
+                f,
+                "out of memory during boot: failed to allocate a frame for the {context}"
+            ),
+            BootError::InsufficientMemory {
+                available,
+                required,
+            } => write!(
+                f,
+                "insufficient memory (have {available} bytes, need {required} bytes)"
+            ),
+            BootError::KernelLoadFailed(reason) => write!(f, "failed to load the kernel: {reason}"),
+            BootError::La57Unsupported => write!(
+                f,
+                "the kernel requested 5-level paging, but the CPU doesn't support it"
+            ),
+        }
     }
 }
 
@@ -437,17 +1158,41 @@ pub struct Mappings {
     pub entry_point: VirtAddr,
     /// The (exclusive) end address of the kernel stack.
     pub stack_top: VirtAddr,
+    /// The start address of the kernel stack canary page, if enabled.
+    pub stack_canary: Option<VirtAddr>,
     /// Keeps track of used entries in the level 4 page table, useful for finding a free
     /// virtual memory when needed.
     pub used_entries: UsedLevel4Entries,
     /// The start address of the framebuffer, if any.
     pub framebuffer: Option<VirtAddr>,
+    /// The start address of the framebuffer scratch buffer, if enabled.
+    pub framebuffer_scratch: Option<VirtAddr>,
     /// The start address of the physical memory mapping, if enabled.
     pub physical_memory_offset: Option<VirtAddr>,
+    /// The number of bytes mapped by the physical memory mapping, or 0 if it is disabled.
+    pub physical_memory_len: u64,
     /// The level 4 page table index of the recursive mapping, if enabled.
     pub recursive_index: Option<PageTableIndex>,
     /// The thread local storage template of the kernel executable, if it contains one.
     pub tls_template: Option<TlsTemplate>,
+    /// The structures for switching to 5-level paging, if enabled.
+    pub five_level_paging: Option<FiveLevelPaging>,
+    /// The identity-mapped physical frames, if configured.
+    pub identity_mapped_range: Option<PhysFrameRange>,
+    /// The reserved frame below 1 MiB for starting additional CPUs, if enabled.
+    pub ap_trampoline_frame: Option<PhysFrame>,
+    /// The framebuffer that is zeroed by [`switch_to_kernel`] after the last log message.
+    ///
+    /// Not set by [`set_up_mappings`]. The framebuffer must be identity-mapped in the
+    /// bootloader page table.
+    pub framebuffer_to_clear: Option<RawFrameBufferInfo>,
+    /// The `CR4` flags that are set by [`switch_to_kernel`] after switching to the kernel's
+    /// page tables.
+    pub cr4_flags: Cr4Flags,
+    /// The value that [`switch_to_kernel`] writes to the `FS` base register, if configured.
+    pub fs_base: Option<u64>,
+    /// The value that [`switch_to_kernel`] writes to the `GS` base register, if configured.
+    pub gs_base: Option<u64>,
 
     /// Start address of the kernel slice allocation in memory.
     pub kernel_slice_start: PhysAddr,
@@ -455,9 +1200,106 @@ pub struct Mappings {
     pub kernel_slice_len: u64,
     /// Relocation offset of the kernel image in virtual memory.
     pub kernel_image_offset: VirtAddr,
+    /// The number of frames that were allocated for the `.bss` memory of the kernel.
+    pub kernel_bss_frames: u64,
+    /// The virtual address and length of the kernel's `.bootloader-config` section, if it is
+    /// part of the loaded kernel image.
+    pub kernel_config_section: Option<(VirtAddr, u64)>,
     pub ramdisk_slice_phys_start: Option<PhysAddr>,
     pub ramdisk_slice_start: Option<VirtAddr>,
     pub ramdisk_slice_len: u64,
+    /// The number of bytes at the start of the ramdisk that are mapped.
+    pub ramdisk_slice_mapped_len: u64,
+}
+
+/// The structures that are required to enable 5-level paging on the context switch.
+#[derive(Debug, Clone, Copy)]
+pub struct FiveLevelPaging {
+    /// The level 5 page table, which references the kernel's level 4 table in entries 0 and
+    /// 511.
+    pub level_5_frame: PhysFrame,
+    /// An identity-mapped frame that is used as stack while switching the paging mode.
+    pub trampoline_frame: PhysFrame,
+    /// The selector of the 32-bit code segment, see [`gdt::create_and_load`].
+    pub compat_code_selector: SegmentSelector,
+}
+
+/// Creates a level 5 page table for the kernel and identity-maps a trampoline stack.
+///
+/// 5-level paging can only be enabled while paging is disabled, so the context switch has
+/// to temporarily leave long mode. For this, the context switch function and all frames
+/// that are used in 32-bit mode must be located below 4 GiB.
+fn set_up_five_level_paging(
+    level_4_frame: PhysFrame,
+    compat_code_selector: SegmentSelector,
+    kernel_page_table: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<FiveLevelPaging, BootError> {
+    if !cpu::la57_supported() {
+        return Err(BootError::La57Unsupported);
+    }
+    log::info!("Setting up 5-level paging");
+
+    let below_4gib = |addr: u64| addr + Size4KiB::SIZE <= 0x1_0000_0000;
+    // the context switch function spans up to two pages
+    let context_switch_function = context_switch as *const () as u64;
+    assert!(
+        below_4gib(context_switch_function + Size4KiB::SIZE),
+        "5-level paging requires the bootloader to be loaded below 4 GiB"
+    );
+
+    let level_5_frame = frame_allocator
+        .allocate_frame()
+        .ok_or(BootError::FrameAllocationFailed("level 5 page table"))?;
+    let trampoline_frame =
+        frame_allocator
+            .allocate_frame()
+            .ok_or(BootError::FrameAllocationFailed(
+                "5-level paging trampoline stack",
+            ))?;
+    assert!(
+        below_4gib(level_5_frame.start_address().as_u64())
+            && below_4gib(trampoline_frame.start_address().as_u64()),
+        "no memory below 4 GiB available for switching to 5-level paging"
+    );
+
+    // utilize identity mapping
+    let level_5_table = unsafe { &mut *(level_5_frame.start_address().as_u64() as *mut PageTable) };
+    level_5_table.zero();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    level_5_table[0].set_frame(level_4_frame, flags);
+    level_5_table[511].set_frame(level_4_frame, flags);
+
+    let page = Page::containing_address(VirtAddr::new(trampoline_frame.start_address().as_u64()));
+    match unsafe {
+        // The parent table flags need to be both readable and writable to
+        // support recursive page tables.
+        kernel_page_table.map_to_with_table_flags(
+            page,
+            trampoline_frame,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+            frame_allocator,
+        )
+    } {
+        Ok(tlb) => tlb.flush(),
+        Err(MapToError::PageAlreadyMapped(_)) => identity_mapping_collision(page, "trampoline"),
+        Err(MapToError::FrameAllocationFailed) => {
+            return Err(BootError::FrameAllocationFailed(
+                "5-level paging trampoline mapping",
+            ))
+        }
+        Err(err) => panic!(
+            "failed to identity map frame {:?}: {:?}",
+            trampoline_frame, err
+        ),
+    }
+
+    Ok(FiveLevelPaging {
+        level_5_frame,
+        trampoline_frame,
+        compat_code_selector,
+    })
 }
 
 /// Allocates and initializes the boot info struct and the memory map.
@@ -465,7 +1307,9 @@ pub struct Mappings {
 /// The boot info and memory map are mapped to both the kernel and bootloader
 /// address space at the same address. This makes it possible to return a Rust
 /// reference that is valid in both address spaces. The necessary physical frames
-/// are taken from the given `frame_allocator`.
+/// are taken from the given `frame_allocator`. Returns an error if the frame allocator runs
+/// out of memory or if there is less usable memory than
+/// [`BootloaderConfig::minimum_memory_bytes`].
 pub fn create_boot_info<I, D>(
     config: &BootloaderConfig,
     boot_config: &BootConfig,
@@ -473,9 +1317,9 @@ pub fn create_boot_info<I, D>(
     page_tables: &mut PageTables,
     mappings: &mut Mappings,
     system_info: SystemInfo,
-) -> &'static mut BootInfo
+) -> Result<&'static mut BootInfo, BootError>
 where
-    I: ExactSizeIterator<Item = D> + Clone,
+    I: Iterator<Item = D> + Clone,
     D: LegacyMemoryRegion,
 {
     log::info!("Allocate bootinfo");
@@ -483,37 +1327,61 @@ where
     // allocate and map space for the boot info
     let (boot_info, memory_regions) = {
         let boot_info_layout = Layout::new::<BootInfo>();
-        let regions = frame_allocator.memory_map_max_region_count();
+        let regions = frame_allocator
+            .memory_map_max_region_count(mappings.ramdisk_slice_phys_start.is_some());
         let memory_regions_layout = Layout::array::<MemoryRegion>(regions).unwrap();
         let (combined, memory_regions_offset) =
             boot_info_layout.extend(memory_regions_layout).unwrap();
 
         let boot_info_addr = mapping_addr(
             config.mappings.boot_info,
+            config.mappings.boot_info_placement,
             u64::from_usize(combined.size()),
             u64::from_usize(combined.align()),
             &mut mappings.used_entries,
         )
         .expect("boot info addr is not properly aligned");
+        let map_error = |page: Page, err| -> ! {
+            match (config.mappings.boot_info, err) {
+                (
+                    Mapping::FixedAddress(addr),
+                    MapToError::PageAlreadyMapped(_) | MapToError::ParentEntryHugePage,
+                ) => panic!(
+                    "the fixed boot info address {addr:#x} collides with an existing mapping \
+                    at page {page:?}, e.g. a kernel segment or the identity-mapped physical \
+                    memory; choose a different `mappings.boot_info` address"
+                ),
+                (_, err) => panic!("failed to map page {:?}: {:?}", page, err),
+            }
+        };
 
         let memory_map_regions_addr = boot_info_addr + memory_regions_offset;
         let memory_map_regions_end = boot_info_addr + combined.size();
 
         let start_page = Page::containing_address(boot_info_addr);
         let end_page = Page::containing_address(memory_map_regions_end - 1u64);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        // the bootloader fills in the boot info through its own mapping, which is always
+        // writable
+        let kernel_flags = if config.mappings.boot_info_read_only {
+            flags - PageTableFlags::WRITABLE
+        } else {
+            flags
+        };
         for page in Page::range_inclusive(start_page, end_page) {
-            let flags =
-                PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
             let frame = frame_allocator
                 .allocate_frame()
-                .expect("frame allocation for boot info failed");
+                .ok_or(BootError::FrameAllocationFailed("boot info"))?;
             match unsafe {
                 page_tables
                     .kernel
-                    .map_to(page, frame, flags, &mut frame_allocator)
+                    .map_to(page, frame, kernel_flags, &mut frame_allocator)
             } {
                 Ok(tlb) => tlb.flush(),
-                Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+                Err(MapToError::FrameAllocationFailed) => {
+                    return Err(BootError::FrameAllocationFailed("boot info mapping"))
+                }
+                Err(err) => map_error(page, err),
             }
             // we need to be able to access it too
             match unsafe {
@@ -522,7 +1390,10 @@ where
                     .map_to(page, frame, flags, &mut frame_allocator)
             } {
                 Ok(tlb) => tlb.flush(),
-                Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+                Err(MapToError::FrameAllocationFailed) => {
+                    return Err(BootError::FrameAllocationFailed("boot info mapping"))
+                }
+                Err(err) => map_error(page, err),
             }
         }
 
@@ -533,6 +1404,16 @@ where
         (boot_info, memory_regions)
     };
 
+    let (boot_log_addr, boot_log_len) = if boot_config.boot_log {
+        map_boot_log(
+            &mut frame_allocator,
+            page_tables,
+            &mut mappings.used_entries,
+        )?
+    } else {
+        (None, 0)
+    };
+
     log::info!("Create Memory Map");
 
     // build memory map
@@ -544,6 +1425,53 @@ where
         mappings.ramdisk_slice_len,
     );
 
+    if let Some(required) = config.minimum_memory_bytes {
+        // memory used by the bootloader is usable RAM too, so it counts towards the total
+        let available = memory_regions
+            .iter()
+            .filter(|r| {
+                matches!(
+                    r.kind,
+                    MemoryRegionKind::Usable | MemoryRegionKind::Bootloader
+                )
+            })
+            .map(|r| r.end - r.start)
+            .sum();
+        log::info!("Usable memory: {available:#x} bytes, required: {required:#x} bytes");
+        if available < required {
+            failure::set_kind(failure::FailureKind::OutOfMemory);
+            return Err(BootError::InsufficientMemory {
+                available,
+                required,
+            });
+        }
+    }
+
+    let debug_serial_port = match boot_config.debug_serial_port {
+        Some(base) if base == serial::LOG_PORT && boot_config.serial_logging => {
+            log::warn!("Debug serial port {base:#x} is used for logging, ignoring it");
+            None
+        }
+        Some(base) => {
+            log::info!("Initializing debug serial port at {base:#x}");
+            unsafe { serial::SerialPort::init_at(base) };
+            Some(base)
+        }
+        None => None,
+    };
+
+    let cpu_vendor = cpu::vendor_string();
+    let cpu_brand = cpu::brand_string();
+    log::info!(
+        "CPU: {} ({})",
+        cpu::trimmed_str(&cpu_vendor),
+        cpu::trimmed_str(&cpu_brand)
+    );
+    let hypervisor_vendor = cpu::hypervisor_vendor_string();
+    if let Some(vendor) = &hypervisor_vendor {
+        log::info!("Running under hypervisor: {}", cpu::trimmed_str(vendor));
+    }
+
     log::info!("Create bootinfo");
 
     // create boot info
@@ -564,7 +1492,21 @@ where
                 )
             })
             .into();
+        info.framebuffer_refresh_rate = mappings
+            .framebuffer
+            .and(system_info.framebuffer)
+            .and_then(|framebuffer| framebuffer.refresh_rate)
+            .into();
         info.physical_memory_offset = mappings.physical_memory_offset.map(VirtAddr::as_u64).into();
+        info.physical_memory_len = mappings.physical_memory_len;
+        info.ap_trampoline_frame = mappings
+            .ap_trampoline_frame
+            .map(|frame| frame.start_address().as_u64())
+            .into();
+        if let Some(range) = mappings.identity_mapped_range {
+            info.identity_map_addr = Some(range.start.start_address().as_u64()).into();
+            info.identity_map_len = range.end.start_address() - range.start.start_address();
+        }
         info.recursive_index = mappings.recursive_index.map(Into::into).into();
         info.rsdp_addr = system_info.rsdp_addr.map(|addr| addr.as_u64()).into();
         info.tls_template = mappings.tls_template.into();
@@ -573,30 +1515,160 @@ where
             .map(|addr| addr.as_u64())
             .into();
         info.ramdisk_len = mappings.ramdisk_slice_len;
+        info.ramdisk_phys_addr = mappings
+            .ramdisk_slice_phys_start
+            .map(PhysAddr::as_u64)
+            .into();
+        info.ramdisk_mapped_len = mappings.ramdisk_slice_mapped_len;
         info.kernel_addr = mappings.kernel_slice_start.as_u64();
         info.kernel_len = mappings.kernel_slice_len as _;
         info.kernel_image_offset = mappings.kernel_image_offset.as_u64();
+        info.kernel_bss_frames = mappings.kernel_bss_frames;
+        info.kernel_config = KernelConfigInfo {
+            serialized_addr: mappings
+                .kernel_config_section
+                .map(|(addr, _)| addr.as_u64())
+                .into(),
+            serialized_len: mappings.kernel_config_section.map_or(0, |(_, len)| len),
+            api_version: config.api_version(),
+            kernel_stack_size: config.kernel_stack_size,
+            aslr: config.mappings.aslr,
+            safe_mode: boot_config.safe_mode,
+        };
+        let frame_buffer = &boot_config.frame_buffer;
+        info.boot_config = BootConfigInfo {
+            log_level: match boot_config.log_level {
+                LevelFilter::Off => LogLevelFilter::Off,
+                LevelFilter::Error => LogLevelFilter::Error,
+                LevelFilter::Warn => LogLevelFilter::Warn,
+                LevelFilter::Info => LogLevelFilter::Info,
+                LevelFilter::Debug => LogLevelFilter::Debug,
+                LevelFilter::Trace => LogLevelFilter::Trace,
+            },
+            frame_buffer_logging: boot_config.frame_buffer_logging
+                && system_info.framebuffer.is_some(),
+            serial_logging: boot_config.serial_logging,
+            boot_log: boot_config.boot_log,
+            minimum_framebuffer_height: frame_buffer.minimum_framebuffer_height.into(),
+            minimum_framebuffer_width: frame_buffer.minimum_framebuffer_width.into(),
+            maximum_framebuffer_height: frame_buffer.maximum_framebuffer_height.into(),
+            maximum_framebuffer_width: frame_buffer.maximum_framebuffer_width.into(),
+        };
+        info.enabled_cr4_flags = mappings.cr4_flags.bits();
+        info.fs_base = mappings.fs_base.into();
+        info.gs_base = mappings.gs_base.into();
+        info.framebuffer_scratch = mappings
+            .framebuffer_scratch
+            .map(|addr| addr.as_u64())
+            .into();
+        info.boot_log_addr = boot_log_addr.map(VirtAddr::as_u64).into();
+        info.boot_log_len = boot_log_len;
+        info.boot_time = system_info.boot_time.into();
+        // gather a new seed instead of passing the one used for ASLR
+        info.random_seed = entropy::gather_seed();
+        info.cpu_vendor = cpu_vendor;
+        info.cpu_brand = cpu_brand;
+        info.hypervisor_vendor = hypervisor_vendor.into();
+        info.kernel_page_table_phys = page_tables.kernel_level_4_frame.start_address().as_u64();
+        if let Some(addr) = mappings.stack_canary {
+            info.kernel_stack_canary_addr = Some(addr.as_u64()).into();
+            info.kernel_stack_canary_pattern = KERNEL_STACK_CANARY_PATTERN;
+        }
+        if let Some((addr, len)) = system_info.acpi_tables {
+            info.acpi_tables_addr = Some(addr.as_u64()).into();
+            info.acpi_tables_len = len;
+        }
+        info.debug_serial_port = debug_serial_port.into();
+        info.la57_supported = cpu::la57_supported();
+        info.la57_enabled = mappings.five_level_paging.is_some() || cpu::la57_enabled();
         info._test_sentinel = boot_config._test_sentinel;
         info
     });
 
-    boot_info
+    Ok(boot_info)
+}
+
+/// Copies the captured boot log into newly allocated frames and maps them into the kernel
+/// address space.
+///
+/// Log messages that are emitted after this function was called are not part of the boot log.
+fn map_boot_log<I, D>(
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    page_tables: &mut PageTables,
+    used_entries: &mut UsedLevel4Entries,
+) -> Result<(Option<VirtAddr>, u64), BootError>
+where
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    // Don't hold the lock while mapping the pages: a panic in between would deadlock when the
+    // panic handler tries to log. The log might grow until it is copied, so we map the whole
+    // capacity instead of the current length.
+    if logger::BOOT_LOG.lock().is_empty() {
+        return Ok((None, 0));
+    }
+    let size = u64::from_usize(logger::BOOT_LOG_CAPACITY);
+
+    let start_page = mapping_addr_page_aligned(Mapping::Dynamic, size, used_entries, "boot log");
+    let end_page = Page::containing_address(start_page.start_address() + size - 1u64);
+    for page in Page::range_inclusive(start_page, end_page) {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(BootError::FrameAllocationFailed("boot log"))?;
+        match unsafe {
+            page_tables
+                .kernel
+                .map_to(page, frame, flags, frame_allocator)
+        } {
+            Ok(tlb) => tlb.flush(),
+            Err(MapToError::FrameAllocationFailed) => {
+                return Err(BootError::FrameAllocationFailed("boot log mapping"))
+            }
+            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+        }
+        // we need to be able to access it too
+        match unsafe {
+            page_tables
+                .bootloader
+                .map_to(page, frame, flags, frame_allocator)
+        } {
+            Ok(tlb) => tlb.flush(),
+            Err(MapToError::FrameAllocationFailed) => {
+                return Err(BootError::FrameAllocationFailed("boot log mapping"))
+            }
+            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+        }
+    }
+
+    let dst = unsafe {
+        slice::from_raw_parts_mut(
+            start_page.start_address().as_mut_ptr(),
+            logger::BOOT_LOG_CAPACITY,
+        )
+    };
+    let len = logger::BOOT_LOG.lock().copy_to(dst);
+
+    Ok((Some(start_page.start_address()), u64::from_usize(len)))
 }
 
 /// Switches to the kernel address space and jumps to the kernel entry point.
 pub fn switch_to_kernel(
-    page_tables: PageTables,
+    mut page_tables: PageTables,
     mappings: Mappings,
     boot_info: &'static mut BootInfo,
 ) -> ! {
+    check_handoff_state(&mut page_tables, &mappings, boot_info);
     let PageTables {
         kernel_level_4_frame,
         ..
     } = page_tables;
     let addresses = Addresses {
         page_table: kernel_level_4_frame,
+        five_level_paging: mappings.five_level_paging,
         stack_top: mappings.stack_top,
         entry_point: mappings.entry_point,
+        cr4_flags: mappings.cr4_flags,
         boot_info,
     };
 
@@ -605,11 +1677,88 @@ pub fn switch_to_kernel(
         addresses.entry_point
     );
 
+    if let Some(framebuffer) = mappings.framebuffer_to_clear {
+        // no log messages are printed after this point, so the kernel starts with an empty
+        // screen
+        let ptr: *mut u8 = framebuffer.addr.as_u64() as *mut u8;
+        unsafe { ptr::write_bytes(ptr, 0, framebuffer.info.byte_len) };
+    }
+
+    // the bootloader doesn't use segment-relative addressing, so the registers can be set
+    // before switching to the kernel's page tables
+    if let Some(fs_base) = mappings.fs_base {
+        FsBase::write(VirtAddr::new(fs_base));
+    }
+    if let Some(gs_base) = mappings.gs_base {
+        GsBase::write(VirtAddr::new(gs_base));
+    }
+    boot_stage::mark(BootStage::Handoff);
+
     unsafe {
         context_switch(addresses);
     }
 }
 
+/// Verifies the CPU state and the kernel address space right before the context switch.
+///
+/// A violated invariant, e.g. caused by a bug in the transitions between the BIOS stages,
+/// would otherwise lead to a triple fault without any diagnostics, so this function panics
+/// with a description of the problem instead.
+fn check_handoff_state(page_tables: &mut PageTables, mappings: &Mappings, boot_info: &BootInfo) {
+    let efer = Efer::read();
+    assert!(
+        efer.contains(EferFlags::LONG_MODE_ACTIVE),
+        "the CPU is not in long mode (EFER: {efer:?})"
+    );
+    let cr0 = Cr0::read();
+    assert!(
+        cr0.contains(Cr0Flags::PROTECTED_MODE_ENABLE | Cr0Flags::PAGING),
+        "paging is not enabled (CR0: {cr0:?})"
+    );
+    let cr4 = Cr4::read();
+    assert!(
+        cr4.contains(Cr4Flags::PHYSICAL_ADDRESS_EXTENSION),
+        "physical address extension is not enabled (CR4: {cr4:?})"
+    );
+
+    let kernel = &mut page_tables.kernel;
+    let level_4_table = kernel.level_4_table() as *const PageTable as u64;
+    let level_4_addr = level_4_table.wrapping_sub(kernel.phys_offset().as_u64());
+    assert_eq!(
+        level_4_addr,
+        page_tables.kernel_level_4_frame.start_address().as_u64(),
+        "the kernel page table doesn't match the level 4 frame that is loaded into CR3"
+    );
+
+    let flags = |addr: VirtAddr| match kernel.translate(addr) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    };
+    let context_switch_addr = VirtAddr::new(context_switch as *const () as u64);
+    assert_eq!(
+        kernel.translate_addr(context_switch_addr),
+        Some(PhysAddr::new(context_switch_addr.as_u64())),
+        "the context switch function is not identity-mapped in the kernel page table"
+    );
+    assert!(
+        flags(mappings.entry_point).is_some_and(|f| !f.contains(PageTableFlags::NO_EXECUTE)),
+        "the kernel entry point {:#x} is not mapped as executable",
+        mappings.entry_point.as_u64()
+    );
+    assert!(
+        flags(mappings.stack_top - 1u64).is_some_and(|f| f.contains(PageTableFlags::WRITABLE)),
+        "the top of the kernel stack {:#x} is not mapped as writable",
+        mappings.stack_top.as_u64()
+    );
+    let boot_info_addr = VirtAddr::from_ptr(boot_info);
+    assert!(
+        flags(boot_info_addr).is_some(),
+        "the boot info at {:#x} is not mapped in the kernel page table",
+        boot_info_addr.as_u64()
+    );
+    log::debug!("Verified long mode, paging, and the kernel mappings");
+}
+
 /// Provides access to the page tables of the bootloader and kernel address space.
 pub struct PageTables {
     /// Provides access to the page tables of the bootloader address space.
@@ -625,20 +1774,141 @@ pub struct PageTables {
 }
 
 /// Performs the actual context switch.
+///
+/// Interrupts are disabled first because the firmware might have left them enabled. An
+/// interrupt after the page table switch would cause a triple fault since the firmware's
+/// interrupt handlers are not mapped in the kernel address space.
 unsafe fn context_switch(addresses: Addresses) -> ! {
+    if let Some(five_level_paging) = addresses.five_level_paging {
+        if cpu::la57_enabled() {
+            // 5-level paging is already active, so we can load the level 5 table directly
+            unsafe {
+                asm!(
+                    r#"
+                    cli
+                    xor rbp, rbp
+                    mov cr3, {}
+                    mov rax, cr4
+                    or rax, {}
+                    mov cr4, rax
+                    mov rsp, {}
+                    push 0
+                    jmp {}
+                    "#,
+                    in(reg) five_level_paging.level_5_frame.start_address().as_u64(),
+                    in(reg) addresses.cr4_flags.bits(),
+                    in(reg) addresses.stack_top.as_u64(),
+                    in(reg) addresses.entry_point.as_u64(),
+                    in("rdi") addresses.boot_info as *const _ as usize,
+                    out("rax") _,
+                );
+            }
+        } else {
+            // The `LA57` bit in `CR4` can only be changed while paging is disabled, which
+            // requires leaving long mode. So we switch to 32-bit compatibility mode first,
+            // disable paging, enable `LA57`, load the level 5 table, re-enable paging, and
+            // switch back to 64-bit mode. The upper halves of the registers are undefined
+            // after leaving 64-bit mode, so the 64-bit values are kept on the identity-mapped
+            // trampoline stack. The code must stay in this function because only its pages
+            // are identity-mapped in the kernel address space.
+            let trampoline_stack_top =
+                five_level_paging.trampoline_frame.start_address() + Size4KiB::SIZE;
+            unsafe {
+                asm!(
+                    r#"
+                    cli
+                    xor rbp, rbp
+                    mov rsp, rsi
+                    push r8
+                    push r9
+                    push rdi
+                    push r11
+
+                    // PCIDE must be cleared before disabling paging
+                    mov rax, cr4
+                    btr rax, 17
+                    mov cr4, rax
+
+                    // remember the 64-bit code segment and the return address for switching back
+                    mov cx, cs
+                    movzx ecx, cx
+                    lea rdi, [rip + 3f]
+
+                    // switch to compatibility mode
+                    push r10
+                    lea rax, [rip + 2f]
+                    push rax
+                    retfq
+
+                    .code32
+                    2:
+                    // disable paging, which also leaves long mode
+                    mov eax, cr0
+                    and eax, 0x7fffffff
+                    mov cr0, eax
+                    // enable LA57 and load the level 5 table
+                    mov eax, cr4
+                    or eax, 0x1000
+                    mov cr4, eax
+                    mov cr3, edx
+                    // re-enable paging, which re-activates long mode because EFER.LME is still set
+                    mov eax, cr0
+                    or eax, 0x80000000
+                    mov cr0, eax
+
+                    // switch back to 64-bit mode
+                    push ecx
+                    push edi
+                    retf
+
+                    .code64
+                    3:
+                    mov esp, esp
+                    // set the requested CR4 flags now that the kernel's page tables are active
+                    pop rcx
+                    mov rax, cr4
+                    or rax, rcx
+                    mov cr4, rax
+                    pop rdi
+                    pop rax
+                    pop rsp
+                    push 0
+                    jmp rax
+                    "#,
+                    in("rdx") five_level_paging.level_5_frame.start_address().as_u64(),
+                    in("rsi") trampoline_stack_top.as_u64(),
+                    in("r8") addresses.stack_top.as_u64(),
+                    in("r9") addresses.entry_point.as_u64(),
+                    in("rdi") addresses.boot_info as *const _ as usize,
+                    in("r10") u64::from(five_level_paging.compat_code_selector.0),
+                    in("r11") addresses.cr4_flags.bits(),
+                    out("rax") _,
+                    out("rcx") _,
+                );
+            }
+        }
+        unreachable!();
+    }
+
     unsafe {
         asm!(
             r#"
+            cli
             xor rbp, rbp
             mov cr3, {}
+            mov rax, cr4
+            or rax, {}
+            mov cr4, rax
             mov rsp, {}
             push 0
             jmp {}
             "#,
             in(reg) addresses.page_table.start_address().as_u64(),
+            in(reg) addresses.cr4_flags.bits(),
             in(reg) addresses.stack_top.as_u64(),
             in(reg) addresses.entry_point.as_u64(),
             in("rdi") addresses.boot_info as *const _ as usize,
+            out("rax") _,
         );
     }
     unreachable!();
@@ -647,18 +1917,229 @@ unsafe fn context_switch(addresses: Addresses) -> ! {
 /// Memory addresses required for the context switch.
 struct Addresses {
     page_table: PhysFrame,
+    five_level_paging: Option<FiveLevelPaging>,
     stack_top: VirtAddr,
     entry_point: VirtAddr,
+    cr4_flags: Cr4Flags,
     boot_info: &'static mut BootInfo,
 }
 
+/// Applies the kernel image offset to the configured entry point override and checks that
+/// the resulting address lies in an executable segment of the kernel.
+fn entry_point_override(
+    addr: u64,
+    kernel_image_offset: VirtAddr,
+    kernel_page_table: &OffsetPageTable,
+) -> VirtAddr {
+    let entry_point = addr
+        .checked_add(kernel_image_offset.as_u64())
+        .and_then(|addr| VirtAddr::try_new(addr).ok())
+        .unwrap_or_else(|| panic!("the entry point override {addr:#x} is not a valid address"));
+    match kernel_page_table.translate(entry_point) {
+        TranslateResult::Mapped { flags, .. } if !flags.contains(PageTableFlags::NO_EXECUTE) => {
+            entry_point
+        }
+        TranslateResult::Mapped { .. } => panic!(
+            "the entry point override {:#x} lies in a non-executable segment of the kernel",
+            entry_point.as_u64()
+        ),
+        _ => panic!(
+            "the entry point override {:#x} doesn't lie in a segment of the kernel",
+            entry_point.as_u64()
+        ),
+    }
+}
+
+/// Allocates `count` contiguous frames for the kernel stack from the memory of the given NUMA
+/// node, see [`BootConfig::kernel_stack_numa_node`].
+///
+/// Logs a warning and returns `None` if the node has no suitable free memory.
+fn numa_stack_frames<I, D>(
+    node: u32,
+    count: u64,
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+    rsdp_addr: Option<PhysAddr>,
+) -> Option<PhysFrameRange>
+where
+    I: Iterator<Item = D> + Clone,
+    D: LegacyMemoryRegion,
+{
+    let Some(rsdp_addr) = rsdp_addr else {
+        log::warn!("Cannot allocate the kernel stack on NUMA node {node}: no RSDP found");
+        return None;
+    };
+    let mut frames = None;
+    // SAFETY: the bootloader runs identity-mapped, which includes the ACPI tables
+    let result = unsafe {
+        numa::memory_affinities(rsdp_addr, |affinity| {
+            if frames.is_none() && affinity.proximity_domain == node {
+                let start = PhysAddr::new(affinity.base).align_up(Size4KiB::SIZE);
+                let end = PhysAddr::new(affinity.base + affinity.len).align_down(Size4KiB::SIZE);
+                if start < end {
+                    let range = PhysFrame::range(
+                        PhysFrame::containing_address(start),
+                        PhysFrame::containing_address(end),
+                    );
+                    frames = frame_allocator.allocate_range_within(count, range);
+                }
+            }
+        })
+    };
+    match (frames, result) {
+        (Some(range), _) => log::info!(
+            "Allocated the kernel stack on NUMA node {node} at {:#x}",
+            range.start.start_address()
+        ),
+        (None, Err(err)) => {
+            log::warn!("Cannot allocate the kernel stack on NUMA node {node}: {err}")
+        }
+        (None, Ok(())) => {
+            log::warn!(
+                "Cannot allocate the kernel stack on NUMA node {node}: not enough free memory"
+            )
+        }
+    }
+    frames
+}
+
+/// Maps the kernel stack canary page and fills it with [`KERNEL_STACK_CANARY_PATTERN`].
+fn map_stack_canary(
+    page: Page,
+    kernel_page_table: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    config: &BootloaderConfig,
+) -> Result<(), BootError> {
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or(BootError::FrameAllocationFailed("kernel stack canary"))?;
+    // the frame is identity-mapped in the bootloader address space
+    let frame_ptr = frame.start_address().as_u64() as *mut u64;
+    let words = Size4KiB::SIZE as usize / mem::size_of::<u64>();
+    unsafe { slice::from_raw_parts_mut(frame_ptr, words) }.fill(KERNEL_STACK_CANARY_PATTERN);
+
+    // the page is writable, so that an overflow modifies the pattern instead of faulting
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_EXECUTE
+        | load_kernel::extra_page_flags(config);
+    match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+        Ok(tlb) => tlb.flush(),
+        Err(MapToError::FrameAllocationFailed) => {
+            return Err(BootError::FrameAllocationFailed("kernel stack canary"))
+        }
+        Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+    }
+    Ok(())
+}
+
+/// Identity-maps the given physical range into the kernel address space.
+///
+/// Pages that are already identity-mapped, e.g. the GDT or the context switch function, are
+/// skipped. All other existing mappings in the range are reported as collision.
+fn identity_map_range(
+    start: u64,
+    len: u64,
+    kernel_page_table: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<PhysFrameRange, BootError> {
+    if len == 0 {
+        panic!("the identity-mapped range at {start:#x} must not be empty");
+    }
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= 0x0000_8000_0000_0000)
+        .unwrap_or_else(|| {
+            panic!(
+                "the identity-mapped range {start:#x} (+{len:#x}) is not a canonical address range"
+            )
+        });
+    let range = PhysFrame::range(
+        PhysFrame::containing_address(PhysAddr::new(start)),
+        PhysFrame::containing_address(PhysAddr::new(end - 1)) + 1,
+    );
+
+    for frame in range {
+        let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64()));
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.flush(),
+            Err(MapToError::PageAlreadyMapped(existing)) if existing == frame => {}
+            Err(MapToError::FrameAllocationFailed) => {
+                return Err(BootError::FrameAllocationFailed("identity-mapped range"))
+            }
+            Err(MapToError::PageAlreadyMapped(_) | MapToError::ParentEntryHugePage) => {
+                identity_mapping_collision(page, "configured identity-mapped range")
+            }
+        }
+    }
+    Ok(range)
+}
+
+/// Reports that an identity mapping required for switching to the kernel collides with an
+/// existing mapping, e.g. a kernel that is linked to a low fixed address.
+fn identity_mapping_collision(page: Page, kind: &str) -> ! {
+    panic!(
+        "failed to identity map the {kind} at {:#x}: the page is already mapped in the kernel \
+        address space, most likely because the kernel or a fixed-address mapping overlaps the \
+        memory used by the bootloader; please choose a different virtual address",
+        page.start_address()
+    )
+}
+
+/// Returns the end of the physical address range that the physical memory mapping covers.
+///
+/// The framebuffer is included because it lies above the end of the memory map on some
+/// machines, e.g. in a 64-bit PCI BAR above 4 GiB.
+fn physical_memory_end(
+    max_phys_addr: PhysAddr,
+    framebuffer: Option<&RawFrameBufferInfo>,
+) -> PhysAddr {
+    match framebuffer {
+        Some(framebuffer) => {
+            let end = framebuffer.addr + u64::from_usize(framebuffer.info.byte_len);
+            max_phys_addr.max(end)
+        }
+        None => max_phys_addr,
+    }
+}
+
+/// Returns the number of bytes that the physical memory mapping covers, starting at physical
+/// address `0`.
+///
+/// This is the physical memory size, limited by the `physical_memory_max_len` config option
+/// and rounded up to the next 2 MiB boundary.
+fn physical_memory_mapping_len(max_phys_addr: PhysAddr, config: &BootloaderConfig) -> u64 {
+    let len = match config.mappings.physical_memory_max_len {
+        Some(0) => panic!("the `physical_memory_max_len` config option must not be zero"),
+        Some(max_len) => max_len.min(max_phys_addr.as_u64()),
+        None => max_phys_addr.as_u64(),
+    };
+    len.next_multiple_of(Size2MiB::SIZE)
+}
+
 fn mapping_addr_page_aligned(
     mapping: Mapping,
     size: u64,
     used_entries: &mut UsedLevel4Entries,
     kind: &str,
 ) -> Page {
-    match mapping_addr(mapping, size, Size4KiB::SIZE, used_entries) {
+    mapping_addr_page_aligned_placed(
+        mapping,
+        DynamicPlacement::Anywhere,
+        size,
+        used_entries,
+        kind,
+    )
+}
+
+fn mapping_addr_page_aligned_placed(
+    mapping: Mapping,
+    placement: DynamicPlacement,
+    size: u64,
+    used_entries: &mut UsedLevel4Entries,
+    kind: &str,
+) -> Page {
+    match mapping_addr(mapping, placement, size, Size4KiB::SIZE, used_entries) {
         Ok(addr) => Page::from_start_address(addr).unwrap(),
         Err(addr) => panic!("{kind} address must be page-aligned (is `{addr:?})`"),
     }
@@ -666,13 +2147,14 @@ fn mapping_addr_page_aligned(
 
 fn mapping_addr(
     mapping: Mapping,
+    placement: DynamicPlacement,
     size: u64,
     alignment: u64,
     used_entries: &mut UsedLevel4Entries,
 ) -> Result<VirtAddr, VirtAddr> {
     let addr = match mapping {
         Mapping::FixedAddress(addr) => VirtAddr::new(addr),
-        Mapping::Dynamic => used_entries.get_free_address(size, alignment),
+        Mapping::Dynamic => used_entries.get_dynamic_address(size, alignment, placement),
     };
     if addr.is_aligned(alignment) {
         Ok(addr)
@@ -681,6 +2163,43 @@ fn mapping_addr(
     }
 }
 
+/// Returns the page table flags that select the given cache mode, assuming that the PAT
+/// is programmed with its power-on default value (see [`program_default_pat`]).
+fn cache_mode_flags(cache_mode: CacheMode) -> PageTableFlags {
+    match cache_mode {
+        // PAT entry 0
+        CacheMode::Default | CacheMode::WriteBack => PageTableFlags::empty(),
+        // PAT entry 1
+        CacheMode::WriteThrough => PageTableFlags::WRITE_THROUGH,
+        // PAT entry 3
+        CacheMode::Uncached => PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH,
+    }
+}
+
+/// Programs the `IA32_PAT` register with its power-on default value.
+///
+/// The firmware might have changed the PAT entries, so we reset them to make sure that the
+/// flags returned by [`cache_mode_flags`] select the expected memory types.
+fn program_default_pat() {
+    use x86_64::{instructions::tlb, registers::model_specific::Msr};
+
+    const IA32_PAT: u32 = 0x277;
+    // WB, WT, UC-, UC, WB, WT, UC-, UC
+    const DEFAULT_PAT: u64 = 0x0007_0406_0007_0406;
+
+    let mut pat = Msr::new(IA32_PAT);
+    if unsafe { pat.read() } == DEFAULT_PAT {
+        return;
+    }
+    // follow the procedure for changing memory types described in the Intel SDM
+    unsafe {
+        asm!("wbinvd", options(nostack, preserves_flags));
+        pat.write(DEFAULT_PAT);
+        asm!("wbinvd", options(nostack, preserves_flags));
+    }
+    tlb::flush_all();
+}
+
 fn enable_nxe_bit() {
     use x86_64::registers::control::{Efer, EferFlags};
     unsafe { Efer::update(|efer| *efer |= EferFlags::NO_EXECUTE_ENABLE) }
@@ -1,11 +1,13 @@
-use crate::{level_4_entries::UsedLevel4Entries, PAGE_SIZE};
-use bootloader_api::info::TlsTemplate;
+use crate::{level_4_entries::UsedLevel4Entries, BootError, PAGE_SIZE};
+use bootloader_api::{info::TlsTemplate, BootloaderConfig};
+use bootloader_boot_config::FlatKernel;
 use core::{cmp, iter::Step, mem::size_of, ops::Add};
 
 use x86_64::{
     align_up,
     structures::paging::{
-        mapper::{MappedFrame, MapperAllSizes, TranslateResult},
+        frame::PhysFrameRange,
+        mapper::{MapToError, MappedFrame, MapperAllSizes, TranslateResult},
         FrameAllocator, Page, PageSize, PageTableFlags as Flags, PhysFrame, Size4KiB, Translate,
     },
     PhysAddr, VirtAddr,
@@ -17,7 +19,7 @@ use xmas_elf::{
     ElfFile,
 };
 
-use super::Kernel;
+use super::{Kernel, KernelImage};
 
 /// Used by [`Inner::make_mut`] and [`Inner::clean_copied_flag`].
 const COPIED: Flags = Flags::BIT_9;
@@ -29,9 +31,15 @@ struct Loader<'a, M, F> {
 
 struct Inner<'a, M, F> {
     kernel_offset: PhysAddr,
+    load_at_physical_addresses: bool,
     virtual_address_offset: VirtualAddressOffset,
     page_table: &'a mut M,
     frame_allocator: &'a mut F,
+    preallocate_bss: bool,
+    /// Set in the mappings of all load segments after loading.
+    extra_flags: Flags,
+    /// The number of frames that were allocated for `.bss` memory.
+    bss_frames: u64,
 }
 
 impl<'a, M, F> Loader<'a, M, F>
@@ -40,18 +48,20 @@ where
     F: FrameAllocator<Size4KiB>,
 {
     fn new(
-        kernel: Kernel<'a>,
+        elf_file: ElfFile<'a>,
+        load_at_physical_addresses: bool,
+        preallocate_bss: bool,
+        extra_flags: Flags,
         page_table: &'a mut M,
         frame_allocator: &'a mut F,
         used_entries: &mut UsedLevel4Entries,
     ) -> Result<Self, &'static str> {
-        log::info!("Elf file loaded at {:#p}", kernel.elf.input);
-        let kernel_offset = PhysAddr::new(&kernel.elf.input[0] as *const u8 as u64);
+        log::info!("Elf file loaded at {:#p}", elf_file.input);
+        let kernel_offset = PhysAddr::new(&elf_file.input[0] as *const u8 as u64);
         if !kernel_offset.is_aligned(PAGE_SIZE) {
             return Err("Loaded kernel ELF file is not sufficiently aligned");
         }
 
-        let elf_file = kernel.elf;
         for program_header in elf_file.program_iter() {
             program::sanity_check(program_header, &elf_file)?;
         }
@@ -96,16 +106,20 @@ where
             elf_file,
             inner: Inner {
                 kernel_offset,
+                load_at_physical_addresses,
                 virtual_address_offset,
                 page_table,
                 frame_allocator,
+                preallocate_bss,
+                extra_flags,
+                bss_frames: 0,
             },
         };
 
         Ok(loader)
     }
 
-    fn load_segments(&mut self) -> Result<Option<TlsTemplate>, &'static str> {
+    fn load_segments(&mut self) -> Result<Option<TlsTemplate>, BootError> {
         // Load the segments into virtual memory.
         let mut tls_template = None;
         for program_header in self.elf_file.program_iter() {
@@ -115,7 +129,9 @@ where
                     if tls_template.is_none() {
                         tls_template = Some(self.inner.handle_tls_segment(program_header)?);
                     } else {
-                        return Err("multiple TLS segments not supported");
+                        return Err(BootError::KernelLoadFailed(
+                            "multiple TLS segments not supported",
+                        ));
                     }
                 }
                 Type::Null
@@ -161,7 +177,7 @@ where
     M: MapperAllSizes + Translate,
     F: FrameAllocator<Size4KiB>,
 {
-    fn handle_load_segment(&mut self, segment: ProgramHeader) -> Result<(), &'static str> {
+    fn handle_load_segment(&mut self, segment: ProgramHeader) -> Result<(), BootError> {
         log::info!("Handling Segment: {:x?}", segment);
 
         let phys_start_addr = self.kernel_offset + segment.offset();
@@ -180,6 +196,12 @@ where
             segment_flags |= Flags::WRITABLE;
         }
 
+        if self.load_at_physical_addresses {
+            return self
+                .handle_load_segment_at_physical_address(&segment, segment_flags)
+                .map_err(BootError::from);
+        }
+
         // map all frames of the segment at the desired virtual address
         for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
             let offset = frame - start_frame;
@@ -196,7 +218,12 @@ where
                         Flags::PRESENT | Flags::WRITABLE,
                         self.frame_allocator,
                     )
-                    .map_err(|_err| "map_to failed")?
+                    .map_err(|err| match err {
+                        MapToError::FrameAllocationFailed => {
+                            BootError::FrameAllocationFailed("kernel segment mapping")
+                        }
+                        _ => BootError::KernelLoadFailed("map_to failed"),
+                    })?
             };
             // we operate on an inactive page table, so there's no need to flush anything
             flusher.ignore();
@@ -211,16 +238,94 @@ where
         Ok(())
     }
 
-    fn handle_bss_section(
+    /// Copies the segment to the frames at its `p_paddr` and maps them at the segment's
+    /// virtual address.
+    ///
+    /// The frames must have been reserved beforehand, see [`physical_load_range`].
+    fn handle_load_segment_at_physical_address(
         &mut self,
         segment: &ProgramHeader,
         segment_flags: Flags,
     ) -> Result<(), &'static str> {
-        log::info!("Mapping bss section");
+        let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
+        let phys_start_addr = PhysAddr::new(segment.physical_addr());
+        if virt_start_addr.as_u64() % Size4KiB::SIZE != phys_start_addr.as_u64() % Size4KiB::SIZE {
+            return Err("segment virtual and physical addresses have different page offsets");
+        }
+        if segment.mem_size() == 0 {
+            return Ok(());
+        }
 
+        let start_page: Page = Page::containing_address(virt_start_addr);
+        let end_page: Page = Page::containing_address(virt_start_addr + segment.mem_size() - 1u64);
+        let start_frame: PhysFrame = PhysFrame::containing_address(phys_start_addr);
+
+        // The first and the last frame might be shared with other segments, so we only write
+        // the bytes that belong to this segment.
+        let file_start = self.kernel_offset + segment.offset();
+        let dst = phys_start_addr.as_u64() as *mut u8;
+        unsafe {
+            // utilizing that the frames are identity-mapped
+            core::ptr::copy(
+                file_start.as_u64() as *const u8,
+                dst,
+                segment.file_size() as usize,
+            );
+            core::ptr::write_bytes(
+                dst.add(segment.file_size() as usize),
+                0,
+                (segment.mem_size() - segment.file_size()) as usize,
+            );
+        }
+
+        // pages that only contain zero-initialized memory
+        let bss_start_page: Page = Page::containing_address(VirtAddr::new(align_up(
+            virt_start_addr.as_u64() + segment.file_size(),
+            Size4KiB::SIZE,
+        )));
+        let mut unmapped_bss_pages = 0;
+        for page in Page::range_inclusive(start_page, end_page) {
+            let is_bss = page >= bss_start_page;
+            if is_bss && !self.preallocate_bss {
+                unmapped_bss_pages += 1;
+                continue;
+            }
+            let frame = start_frame + (page - start_page);
+            let flusher = unsafe {
+                // The frames are owned by the kernel, so we mark them as copied to allow
+                // modifications when applying relocations.
+                self.page_table
+                    .map_to_with_table_flags(
+                        page,
+                        frame,
+                        segment_flags | COPIED,
+                        Flags::PRESENT | Flags::WRITABLE,
+                        self.frame_allocator,
+                    )
+                    .map_err(|_err| "map_to failed")?
+            };
+            // we operate on an inactive page table, so there's no need to flush anything
+            flusher.ignore();
+            if is_bss {
+                self.bss_frames += 1;
+            }
+        }
+        if unmapped_bss_pages > 0 {
+            log::info!("Leaving {unmapped_bss_pages} bss pages unmapped");
+        }
+
+        Ok(())
+    }
+
+    fn handle_bss_section(
+        &mut self,
+        segment: &ProgramHeader,
+        segment_flags: Flags,
+    ) -> Result<(), BootError> {
         let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
         let mem_size = segment.mem_size();
         let file_size = segment.file_size();
+        log::info!("Mapping bss section ({} bytes)", mem_size - file_size);
 
         // calculate virtual memory region that must be zeroed
         let zero_start = virt_start_addr + file_size;
@@ -264,7 +369,7 @@ where
             // segments now.
 
             let last_page = Page::containing_address(virt_start_addr + file_size - 1u64);
-            let new_frame = unsafe { self.make_mut(last_page) };
+            let new_frame = unsafe { self.make_mut(last_page)? };
             let new_bytes_ptr = new_frame.start_address().as_u64() as *mut u8;
             unsafe {
                 core::ptr::write_bytes(
@@ -279,9 +384,17 @@ where
         let start_page: Page =
             Page::containing_address(VirtAddr::new(align_up(zero_start.as_u64(), Size4KiB::SIZE)));
         let end_page = Page::containing_address(zero_end - 1u64);
-        for page in Page::range_inclusive(start_page, end_page) {
+        let pages = Page::range_inclusive(start_page, end_page);
+        if !self.preallocate_bss {
+            log::info!("Leaving {} bss pages unmapped", pages.count());
+            return Ok(());
+        }
+        for page in pages {
             // allocate a new unused frame
-            let frame = self.frame_allocator.allocate_frame().unwrap();
+            let frame = self
+                .frame_allocator
+                .allocate_frame()
+                .ok_or(BootError::FrameAllocationFailed("kernel bss"))?;
 
             // zero frame, utilizing identity-mapping
             let frame_ptr = frame.start_address().as_u64() as *mut PageArray;
@@ -300,10 +413,16 @@ where
                         Flags::PRESENT | Flags::WRITABLE,
                         self.frame_allocator,
                     )
-                    .map_err(|_err| "Failed to map new frame for bss memory")?
+                    .map_err(|err| match err {
+                        MapToError::FrameAllocationFailed => {
+                            BootError::FrameAllocationFailed("kernel bss mapping")
+                        }
+                        _ => BootError::KernelLoadFailed("Failed to map new frame for bss memory"),
+                    })?
             };
             // we operate on an inactive page table, so we don't need to flush our changes
             flusher.ignore();
+            self.bss_frames += 1;
         }
 
         Ok(())
@@ -381,7 +500,7 @@ where
     /// ## Panics
     ///
     /// Panics if a page is not mapped in `self.page_table`.
-    unsafe fn copy_to(&mut self, addr: VirtAddr, buf: &[u8]) {
+    unsafe fn copy_to(&mut self, addr: VirtAddr, buf: &[u8]) -> Result<(), BootError> {
         // We can't know for sure that contiguous virtual address are contiguous
         // in physical memory, so we iterate of the pages spanning the
         // addresses, translate them to frames and copy the data.
@@ -395,7 +514,7 @@ where
             // Translate the virtual page to the physical frame.
             let phys_addr = unsafe {
                 // SAFETY: The caller asserts that the pages are mapped by a Load segment.
-                self.make_mut(page)
+                self.make_mut(page)?
             };
 
             // Figure out which address range we want to copy from the frame.
@@ -438,6 +557,8 @@ where
             // Do the actual copy.
             dest.copy_from_slice(src);
         }
+
+        Ok(())
     }
 
     /// This method is intended for making the memory loaded by a Load segment mutable.
@@ -456,7 +577,7 @@ where
     ///  
     /// ## Panics
     /// Panics if the page is not mapped in `self.page_table`.
-    unsafe fn make_mut(&mut self, page: Page) -> PhysFrame {
+    unsafe fn make_mut(&mut self, page: Page) -> Result<PhysFrame, BootError> {
         let (frame, flags) = match self.page_table.translate(page.start_address()) {
             TranslateResult::Mapped {
                 frame,
@@ -475,11 +596,14 @@ where
 
         if flags.contains(COPIED) {
             // The frame was already copied, we are free to modify it.
-            return frame;
+            return Ok(frame);
         }
 
         // Allocate a new frame and copy the memory, utilizing that both frames are identity mapped.
-        let new_frame = self.frame_allocator.allocate_frame().unwrap();
+        let new_frame = self
+            .frame_allocator
+            .allocate_frame()
+            .ok_or(BootError::FrameAllocationFailed("kernel segment copy"))?;
         let frame_ptr = frame.start_address().as_u64() as *const u8;
         let new_frame_ptr = new_frame.start_address().as_u64() as *mut u8;
         unsafe {
@@ -496,7 +620,7 @@ where
                 .ignore();
         }
 
-        new_frame
+        Ok(new_frame)
     }
 
     /// Cleans up the custom flags set by [`Inner::make_mut`].
@@ -518,16 +642,21 @@ where
                             offset: _,
                             flags,
                         } => flags,
+                        // `.bss` pages are left unmapped if `preallocate_bss` is disabled
+                        TranslateResult::NotMapped if !self.preallocate_bss => continue,
                         TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => {
                             unreachable!("has the elf file not been mapped correctly?")
                         }
                     };
 
-                    if flags.contains(COPIED) {
-                        // Remove the flag.
+                    // Remove the flag and set the extra flags requested by the kernel. This
+                    // must happen after loading because the extra flags might include the
+                    // `COPIED` bit.
+                    let new_flags = (flags & !COPIED) | self.extra_flags;
+                    if new_flags != flags {
                         unsafe {
                             self.page_table
-                                .update_flags(page, flags & !COPIED)
+                                .update_flags(page, new_flags)
                                 .unwrap()
                                 .ignore();
                         }
@@ -543,6 +672,7 @@ where
             start_addr: self.virtual_address_offset + segment.virtual_addr(),
             mem_size: segment.mem_size(),
             file_size: segment.file_size(),
+            alignment: segment.align(),
         })
     }
 
@@ -550,7 +680,7 @@ where
         &mut self,
         segment: ProgramHeader,
         elf_file: &ElfFile,
-    ) -> Result<(), &'static str> {
+    ) -> Result<(), BootError> {
         let data = segment.get_data(elf_file)?;
         let data = if let SegmentData::Dynamic64(data) = data {
             data
@@ -569,21 +699,27 @@ where
                     let ptr = rel.get_ptr()?;
                     let prev = rela.replace(ptr);
                     if prev.is_some() {
-                        return Err("Dynamic section contains more than one Rela entry");
+                        return Err(BootError::KernelLoadFailed(
+                            "Dynamic section contains more than one Rela entry",
+                        ));
                     }
                 }
                 dynamic::Tag::RelaSize => {
                     let val = rel.get_val()?;
                     let prev = rela_size.replace(val);
                     if prev.is_some() {
-                        return Err("Dynamic section contains more than one RelaSize entry");
+                        return Err(BootError::KernelLoadFailed(
+                            "Dynamic section contains more than one RelaSize entry",
+                        ));
                     }
                 }
                 dynamic::Tag::RelaEnt => {
                     let val = rel.get_val()?;
                     let prev = rela_ent.replace(val);
                     if prev.is_some() {
-                        return Err("Dynamic section contains more than one RelaEnt entry");
+                        return Err(BootError::KernelLoadFailed(
+                            "Dynamic section contains more than one RelaEnt entry",
+                        ));
                     }
                 }
                 _ => {}
@@ -595,7 +731,9 @@ where
             // The section doesn't contain any relocations.
 
             if rela_size.is_some() || rela_ent.is_some() {
-                return Err("Rela entry is missing but RelaSize or RelaEnt have been provided");
+                return Err(BootError::KernelLoadFailed(
+                    "Rela entry is missing but RelaSize or RelaEnt have been provided",
+                ));
             }
 
             return Ok(());
@@ -639,11 +777,7 @@ where
         }
     }
 
-    fn apply_relocation(
-        &mut self,
-        rela: Rela<u64>,
-        elf_file: &ElfFile,
-    ) -> Result<(), &'static str> {
+    fn apply_relocation(&mut self, rela: Rela<u64>, elf_file: &ElfFile) -> Result<(), BootError> {
         let symbol_idx = rela.get_symbol_table_index();
         assert_eq!(
             symbol_idx, 0,
@@ -667,7 +801,7 @@ where
                 // Write the relocated value to memory.
                 unsafe {
                     // SAFETY: We just verified that the address is in a Load segment.
-                    self.copy_to(addr, &value.to_ne_bytes());
+                    self.copy_to(addr, &value.to_ne_bytes())?;
                 }
             }
             ty => unimplemented!("relocation type {:x} not supported", ty),
@@ -697,6 +831,8 @@ where
                     offset: _,
                     flags,
                 } => flags,
+                // `.bss` pages are left unmapped if `preallocate_bss` is disabled
+                TranslateResult::NotMapped if !self.preallocate_bss => continue,
                 TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => {
                     unreachable!("has the elf file not been mapped correctly?")
                 }
@@ -730,26 +866,140 @@ fn check_is_in_load(elf_file: &ElfFile, virt_offset: u64) -> Result<(), &'static
     Err("offset is not in load segment")
 }
 
-/// Loads the kernel ELF file given in `bytes` in the given `page_table`.
+/// Loads the given kernel in the given `page_table`.
 ///
-/// Returns the kernel entry point address, it's thread local storage template (if any),
-/// and a structure describing which level 4 page table entries are in use.  
+/// Returns the kernel image offset, the kernel entry point address, its thread local
+/// storage template (if any), and the number of frames allocated for `.bss` memory. The used
+/// level 4 entries are marked in `used_entries`.
 pub fn load_kernel(
     kernel: Kernel<'_>,
     page_table: &mut (impl MapperAllSizes + Translate),
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
     used_entries: &mut UsedLevel4Entries,
-) -> Result<(VirtAddr, VirtAddr, Option<TlsTemplate>), &'static str> {
-    let mut loader = Loader::new(kernel, page_table, frame_allocator, used_entries)?;
+) -> Result<(VirtAddr, VirtAddr, Option<TlsTemplate>, u64), BootError> {
+    let elf_file = match kernel.image {
+        KernelImage::Elf(elf_file) => elf_file,
+        KernelImage::Flat { bytes, layout } => {
+            let (offset, entry_point, tls_template) = load_flat_kernel(
+                bytes,
+                layout,
+                extra_page_flags(&kernel.config),
+                page_table,
+                frame_allocator,
+                used_entries,
+            )?;
+            return Ok((offset, entry_point, tls_template, 0));
+        }
+    };
+    let mut loader = Loader::new(
+        elf_file,
+        kernel.config.load_at_physical_addresses,
+        kernel.config.preallocate_bss,
+        extra_page_flags(&kernel.config),
+        page_table,
+        frame_allocator,
+        used_entries,
+    )?;
     let tls_template = loader.load_segments()?;
+    log::info!(
+        "Allocated {} frames for bss memory",
+        loader.inner.bss_frames
+    );
 
     Ok((
         VirtAddr::new(loader.inner.virtual_address_offset.virtual_address_offset() as u64),
         loader.entry_point(),
         tls_template,
+        loader.inner.bss_frames,
+    ))
+}
+
+/// Returns the frames that are requested by the `p_paddr` fields of the kernel's load
+/// segments.
+///
+/// Used to reserve these frames before loading the kernel with the
+/// [`load_at_physical_addresses`](bootloader_api::BootloaderConfig::load_at_physical_addresses)
+/// option.
+pub fn physical_load_range(elf_file: &ElfFile) -> Result<PhysFrameRange, &'static str> {
+    let mut start = u64::MAX;
+    let mut end = 0;
+    for program_header in elf_file.program_iter() {
+        if matches!(program_header.get_type()?, Type::Load) && program_header.mem_size() > 0 {
+            let segment_end = program_header
+                .physical_addr()
+                .checked_add(program_header.mem_size())
+                .ok_or("segment physical address overflows")?;
+            start = cmp::min(start, program_header.physical_addr());
+            end = cmp::max(end, segment_end);
+        }
+    }
+    if start >= end {
+        return Err("kernel has no load segments");
+    }
+    let start = PhysAddr::try_new(start).map_err(|_| "invalid segment physical address")?;
+    let end = PhysAddr::try_new(align_up(end, Size4KiB::SIZE))
+        .map_err(|_| "invalid segment physical address")?;
+    Ok(PhysFrame::range(
+        PhysFrame::containing_address(start),
+        PhysFrame::containing_address(end),
     ))
 }
 
+/// Returns the [`extra_page_flags`](BootloaderConfig::extra_page_flags) of the given config.
+pub fn extra_page_flags(config: &BootloaderConfig) -> Flags {
+    Flags::from_bits_truncate(config.extra_page_flags & BootloaderConfig::AVAILABLE_PAGE_FLAGS)
+}
+
+/// Maps the given flat binary kernel at its load address.
+///
+/// The kernel is mapped in place, i.e. without copying it to new frames.
+fn load_flat_kernel(
+    bytes: &[u8],
+    layout: FlatKernel,
+    extra_flags: Flags,
+    page_table: &mut (impl MapperAllSizes + Translate),
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    used_entries: &mut UsedLevel4Entries,
+) -> Result<(VirtAddr, VirtAddr, Option<TlsTemplate>), &'static str> {
+    log::info!("Flat kernel binary loaded at {:#p}", bytes.as_ptr());
+    let kernel_offset = PhysAddr::new(bytes.as_ptr() as u64);
+    if !kernel_offset.is_aligned(PAGE_SIZE) {
+        return Err("Loaded kernel binary is not sufficiently aligned");
+    }
+    let load_addr = VirtAddr::try_new(layout.load_addr)
+        .map_err(|_| "flat kernel load address is not canonical")?;
+    if !load_addr.is_aligned(PAGE_SIZE) {
+        return Err("flat kernel load address is not page-aligned");
+    }
+    let len = u64::try_from(bytes.len()).unwrap();
+    if layout.entry_offset >= len {
+        return Err("flat kernel entry offset is outside of the kernel binary");
+    }
+    let end_addr = layout
+        .load_addr
+        .checked_add(len)
+        .and_then(|end| VirtAddr::try_new(end).ok())
+        .ok_or("flat kernel does not fit at its load address")?;
+
+    used_entries.mark_range_as_used(load_addr.as_u64(), len);
+
+    let start_page = Page::<Size4KiB>::containing_address(load_addr);
+    let end_page = Page::containing_address(end_addr - 1u64);
+    for (page, frame) in
+        Page::range_inclusive(start_page, end_page).zip(PhysFrame::range_inclusive(
+            PhysFrame::containing_address(kernel_offset),
+            PhysFrame::containing_address(kernel_offset + (len - 1)),
+        ))
+    {
+        let flags = Flags::PRESENT | Flags::WRITABLE | extra_flags;
+        unsafe { page_table.map_to(page, frame, flags, frame_allocator) }
+            .map_err(|_| "failed to map flat kernel binary")?
+            .ignore();
+    }
+
+    Ok((load_addr, load_addr + layout.entry_offset, None))
+}
+
 /// A helper type used to offset virtual addresses for position independent
 /// executables.
 #[derive(Clone, Copy)]
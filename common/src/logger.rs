@@ -1,16 +1,42 @@
 use crate::{framebuffer::FrameBufferWriter, serial::SerialPort};
 use bootloader_api::info::FrameBufferInfo;
 use conquer_once::spin::OnceCell;
-use core::fmt::Write;
-use spinning_top::Spinlock;
+use core::fmt::{self, Write};
+use spinning_top::{const_spinlock, Spinlock};
+use x86_64::instructions::interrupts;
 
 /// The global logger instance used for the `log` crate.
 pub static LOGGER: OnceCell<LockedLogger> = OnceCell::uninit();
 
+/// The capacity of the [`BOOT_LOG`] ring buffer in bytes.
+pub const BOOT_LOG_CAPACITY: usize = 16 * 1024;
+
+/// Captures the log output of the bootloader if the boot log is enabled.
+pub static BOOT_LOG: Spinlock<LogBuffer> = const_spinlock(LogBuffer::new());
+
 /// A logger instance protected by a spinlock.
+///
+/// The locks are only held while interrupts are disabled, so an interrupt handler can't
+/// deadlock by trying to log while the interrupted code holds a lock on the same CPU.
+/// Code that must never block, e.g. NMI or exception handlers, can use
+/// [`try_log`](Self::try_log) instead.
+///
+/// The [`LOGGER`] instance of the bootloader is not mapped into the kernel address space.
+/// Kernels that want to continue logging in the same way after boot can depend on this crate
+/// and create their own instance using the framebuffer passed in `BootInfo::framebuffer`:
+///
+/// ```ignore
+/// let framebuffer = boot_info.framebuffer.as_mut().unwrap();
+/// let info = framebuffer.info();
+/// let logger = LockedLogger::new(framebuffer.buffer_mut(), info, true, true, false);
+/// ```
+///
+/// The instance then needs to be stored in a `static` (e.g. a `OnceCell` like [`LOGGER`])
+/// so that it can be registered through [`log::set_logger`].
 pub struct LockedLogger {
     framebuffer: Option<Spinlock<FrameBufferWriter>>,
     serial: Option<Spinlock<SerialPort>>,
+    boot_log: Option<&'static Spinlock<LogBuffer>>,
 }
 
 impl LockedLogger {
@@ -20,6 +46,7 @@ impl LockedLogger {
         info: FrameBufferInfo,
         frame_buffer_logger_status: bool,
         serial_logger_status: bool,
+        boot_log_status: bool,
     ) -> Self {
         let framebuffer = match frame_buffer_logger_status {
             true => Some(Spinlock::new(FrameBufferWriter::new(framebuffer, info))),
@@ -31,12 +58,53 @@ impl LockedLogger {
             false => None,
         };
 
+        let boot_log = match boot_log_status {
+            true => Some(&BOOT_LOG),
+            false => None,
+        };
+
         LockedLogger {
             framebuffer,
             serial,
+            boot_log,
         }
     }
 
+    /// Tries to log the given record without blocking.
+    ///
+    /// Outputs that are currently locked are skipped. Returns `true` if the record was
+    /// written to all enabled outputs.
+    pub fn try_log(&self, record: &log::Record) -> bool {
+        interrupts::without_interrupts(|| {
+            let mut complete = true;
+            if let Some(framebuffer) = &self.framebuffer {
+                match framebuffer.try_lock() {
+                    Some(mut framebuffer) => {
+                        writeln!(framebuffer, "{:5}: {}", record.level(), record.args()).unwrap()
+                    }
+                    None => complete = false,
+                }
+            }
+            if let Some(serial) = &self.serial {
+                match serial.try_lock() {
+                    Some(mut serial) => {
+                        writeln!(serial, "{:5}: {}", record.level(), record.args()).unwrap()
+                    }
+                    None => complete = false,
+                }
+            }
+            if let Some(boot_log) = self.boot_log {
+                match boot_log.try_lock() {
+                    Some(mut boot_log) => {
+                        writeln!(boot_log, "{:5}: {}", record.level(), record.args()).unwrap()
+                    }
+                    None => complete = false,
+                }
+            }
+            complete
+        })
+    }
+
     /// Force-unlocks the logger to prevent a deadlock.
     ///
     /// ## Safety
@@ -48,6 +116,9 @@ impl LockedLogger {
         if let Some(serial) = &self.serial {
             unsafe { serial.force_unlock() };
         }
+        if let Some(boot_log) = self.boot_log {
+            unsafe { boot_log.force_unlock() };
+        }
     }
 }
 
@@ -57,15 +128,153 @@ impl log::Log for LockedLogger {
     }
 
     fn log(&self, record: &log::Record) {
-        if let Some(framebuffer) = &self.framebuffer {
-            let mut framebuffer = framebuffer.lock();
-            writeln!(framebuffer, "{:5}: {}", record.level(), record.args()).unwrap();
+        interrupts::without_interrupts(|| {
+            if let Some(framebuffer) = &self.framebuffer {
+                let mut framebuffer = framebuffer.lock();
+                writeln!(framebuffer, "{:5}: {}", record.level(), record.args()).unwrap();
+            }
+            if let Some(serial) = &self.serial {
+                let mut serial = serial.lock();
+                writeln!(serial, "{:5}: {}", record.level(), record.args()).unwrap();
+            }
+            if let Some(boot_log) = self.boot_log {
+                let mut boot_log = boot_log.lock();
+                writeln!(boot_log, "{:5}: {}", record.level(), record.args()).unwrap();
+            }
+        })
+    }
+
+    fn flush(&self) {}
+}
+
+/// A fixed-size ring buffer that keeps the most recent log output.
+pub struct LogBuffer {
+    buffer: [u8; BOOT_LOG_CAPACITY],
+    /// The index at which the next byte is written.
+    head: usize,
+    /// Whether older output was overwritten.
+    wrapped: bool,
+}
+
+impl LogBuffer {
+    /// Creates a new empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; BOOT_LOG_CAPACITY],
+            head: 0,
+            wrapped: false,
         }
-        if let Some(serial) = &self.serial {
-            let mut serial = serial.lock();
-            writeln!(serial, "{:5}: {}", record.level(), record.args()).unwrap();
+    }
+
+    /// Returns the length of the output returned by [`Self::copy_to`].
+    pub fn len(&self) -> usize {
+        let (first, second) = self.as_slices();
+        first.len() + second.len()
+    }
+
+    /// Returns whether no output was captured.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the captured output in chronological order to the start of `dst`.
+    ///
+    /// If older output was overwritten, the output starts at the first complete line to avoid
+    /// truncated lines and split UTF-8 characters. Returns the number of copied bytes.
+    ///
+    /// Panics if `dst` is smaller than [`Self::len`].
+    pub fn copy_to(&self, dst: &mut [u8]) -> usize {
+        let (first, second) = self.as_slices();
+        dst[..first.len()].copy_from_slice(first);
+        dst[first.len()..][..second.len()].copy_from_slice(second);
+        first.len() + second.len()
+    }
+
+    /// Returns the captured output as two slices in chronological order.
+    fn as_slices(&self) -> (&[u8], &[u8]) {
+        if !self.wrapped {
+            return (&self.buffer[..self.head], &[]);
+        }
+
+        let (newer, older) = self.buffer.split_at(self.head);
+        match older.iter().position(|&b| b == b'\n') {
+            Some(newline) => (&older[newline + 1..], newer),
+            None => match newer.iter().position(|&b| b == b'\n') {
+                Some(newline) => (&newer[newline + 1..], &[]),
+                // a single line that fills the whole buffer
+                None => (&[], &[]),
+            },
         }
     }
+}
 
-    fn flush(&self) {}
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.buffer[self.head] = byte;
+            self.head += 1;
+            if self.head == self.buffer.len() {
+                self.head = 0;
+                self.wrapped = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy(buffer: &LogBuffer) -> Vec<u8> {
+        let mut dst = vec![0; buffer.len()];
+        assert_eq!(buffer.copy_to(&mut dst), dst.len());
+        dst
+    }
+
+    #[test]
+    fn log_buffer_keeps_output() {
+        let mut buffer = LogBuffer::new();
+        assert!(buffer.is_empty());
+        writeln!(buffer, "first").unwrap();
+        writeln!(buffer, "second").unwrap();
+        assert_eq!(copy(&buffer), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn log_buffer_wraps_at_line_boundary() {
+        let mut buffer = LogBuffer::new();
+        let line = "0123456789abcde\n";
+        assert_eq!(BOOT_LOG_CAPACITY % line.len(), 0);
+        for _ in 0..BOOT_LOG_CAPACITY / line.len() {
+            buffer.write_str(line).unwrap();
+        }
+        buffer.write_str("last\n").unwrap();
+
+        let output = copy(&buffer);
+        // the partially overwritten first line is skipped
+        assert_eq!(
+            output.len(),
+            BOOT_LOG_CAPACITY - line.len() + "last\n".len()
+        );
+        assert!(output.starts_with(line.as_bytes()));
+        assert!(output.ends_with(b"e\nlast\n"));
+    }
+
+    #[test]
+    fn log_buffer_single_long_line() {
+        let mut buffer = LogBuffer::new();
+        for _ in 0..2 * BOOT_LOG_CAPACITY {
+            buffer.write_str("x").unwrap();
+        }
+        assert!(buffer.is_empty());
+        buffer.write_str("\nnext\n").unwrap();
+        assert_eq!(copy(&buffer), b"next\n");
+    }
 }
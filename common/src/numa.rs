@@ -0,0 +1,187 @@
+use core::ptr;
+use x86_64::PhysAddr;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const SRAT_SIGNATURE: &[u8; 4] = b"SRAT";
+/// The length of the header that all system description tables start with.
+const SDT_HEADER_LEN: u64 = 36;
+/// The SRAT contains 12 reserved bytes between the table header and the affinity structures.
+const SRAT_STRUCTURES_OFFSET: u64 = SDT_HEADER_LEN + 12;
+const MEMORY_AFFINITY_TYPE: u8 = 1;
+const MEMORY_AFFINITY_LEN: u8 = 40;
+const MEMORY_AFFINITY_ENABLED: u32 = 1;
+
+/// A range of physical memory that belongs to a NUMA proximity domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAffinity {
+    /// The proximity domain, i.e. the NUMA node, of the memory.
+    pub proximity_domain: u32,
+    /// The physical start address of the memory range.
+    pub base: u64,
+    /// The length of the memory range in bytes.
+    pub len: u64,
+}
+
+/// Calls the given function for every enabled memory affinity structure of the ACPI
+/// _System Resource Affinity Table_ (SRAT).
+///
+/// Returns an error if the RSDP is invalid or if the firmware doesn't provide an SRAT.
+///
+/// ## Safety
+///
+/// The RSDP and all ACPI tables must be identity-mapped.
+pub unsafe fn memory_affinities(
+    rsdp_addr: PhysAddr,
+    mut f: impl FnMut(MemoryAffinity),
+) -> Result<(), &'static str> {
+    let srat = unsafe { find_srat(rsdp_addr.as_u64()) }?;
+    let srat_len = u64::from(unsafe { read::<u32>(srat + 4) });
+
+    let mut offset = SRAT_STRUCTURES_OFFSET;
+    while offset + 2 <= srat_len {
+        let kind = unsafe { read::<u8>(srat + offset) };
+        let len = unsafe { read::<u8>(srat + offset + 1) };
+        if len < 2 || offset + u64::from(len) > srat_len {
+            return Err("SRAT contains an invalid affinity structure");
+        }
+        if kind == MEMORY_AFFINITY_TYPE && len == MEMORY_AFFINITY_LEN {
+            let s = srat + offset;
+            let flags = unsafe { read::<u32>(s + 28) };
+            if flags & MEMORY_AFFINITY_ENABLED != 0 {
+                let low_high = |o| unsafe {
+                    u64::from(read::<u32>(s + o)) | u64::from(read::<u32>(s + o + 4)) << 32
+                };
+                f(MemoryAffinity {
+                    proximity_domain: unsafe { read::<u32>(s + 2) },
+                    base: low_high(8),
+                    len: low_high(16),
+                });
+            }
+        }
+        offset += u64::from(len);
+    }
+    Ok(())
+}
+
+/// Returns the physical address of the SRAT, using the XSDT if the RSDP provides one.
+unsafe fn find_srat(rsdp: u64) -> Result<u64, &'static str> {
+    if unsafe { read::<[u8; 8]>(rsdp) } != *RSDP_SIGNATURE {
+        return Err("invalid RSDP signature");
+    }
+    let revision = unsafe { read::<u8>(rsdp + 15) };
+    let xsdt = match revision {
+        0 => 0,
+        _ => unsafe { read::<u64>(rsdp + 24) },
+    };
+    let (sdt, entry_len) = match xsdt {
+        0 => (u64::from(unsafe { read::<u32>(rsdp + 16) }), 4),
+        xsdt => (xsdt, 8),
+    };
+
+    let sdt_len = u64::from(unsafe { read::<u32>(sdt + 4) });
+    let mut entry = sdt + SDT_HEADER_LEN;
+    while entry + entry_len <= sdt + sdt_len {
+        let table = match entry_len {
+            4 => u64::from(unsafe { read::<u32>(entry) }),
+            _ => unsafe { read::<u64>(entry) },
+        };
+        if table != 0 && unsafe { read::<[u8; 4]>(table) } == *SRAT_SIGNATURE {
+            return Ok(table);
+        }
+        entry += entry_len;
+    }
+    Err("no SRAT found")
+}
+
+/// Reads a value from the given identity-mapped physical address, which doesn't need to be
+/// aligned.
+unsafe fn read<T: Copy>(addr: u64) -> T {
+    unsafe { ptr::read_unaligned(addr as *const T) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_affinity(domain: u32, base: u64, len: u64, flags: u32) -> Vec<u8> {
+        let mut s = vec![MEMORY_AFFINITY_TYPE, MEMORY_AFFINITY_LEN];
+        s.extend_from_slice(&domain.to_le_bytes());
+        s.extend_from_slice(&[0; 2]);
+        s.extend_from_slice(&base.to_le_bytes());
+        s.extend_from_slice(&len.to_le_bytes());
+        s.extend_from_slice(&[0; 4]);
+        s.extend_from_slice(&flags.to_le_bytes());
+        s.extend_from_slice(&[0; 8]);
+        s
+    }
+
+    fn table(signature: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut t = signature.to_vec();
+        t.extend_from_slice(&(SDT_HEADER_LEN as u32 + body.len() as u32).to_le_bytes());
+        t.resize(SDT_HEADER_LEN as usize, 0);
+        t.extend_from_slice(body);
+        t
+    }
+
+    #[test]
+    fn parses_srat_through_xsdt() {
+        let mut body = vec![0; 12];
+        // a processor affinity structure, which is skipped
+        body.extend_from_slice(&[0, 16]);
+        body.extend_from_slice(&[0; 14]);
+        body.extend(memory_affinity(0, 0, 0x8000_0000, 1));
+        body.extend(memory_affinity(1, 0x1_0000_0000, 0x4000_0000, 1));
+        // disabled entries are skipped
+        body.extend(memory_affinity(2, 0x2_0000_0000, 0x1000, 0));
+        let srat = table(SRAT_SIGNATURE, &body);
+        let other = table(b"APIC", &[]);
+
+        let mut entries = Vec::new();
+        entries.extend_from_slice(&(other.as_ptr() as u64).to_le_bytes());
+        entries.extend_from_slice(&(srat.as_ptr() as u64).to_le_bytes());
+        let xsdt = table(b"XSDT", &entries);
+
+        let mut rsdp = RSDP_SIGNATURE.to_vec();
+        rsdp.resize(15, 0);
+        rsdp.push(2);
+        rsdp.resize(24, 0);
+        rsdp.extend_from_slice(&(xsdt.as_ptr() as u64).to_le_bytes());
+
+        let mut affinities = Vec::new();
+        let rsdp_addr = PhysAddr::new(rsdp.as_ptr() as u64);
+        unsafe { memory_affinities(rsdp_addr, |a| affinities.push(a)) }.unwrap();
+        assert_eq!(
+            affinities,
+            [
+                MemoryAffinity {
+                    proximity_domain: 0,
+                    base: 0,
+                    len: 0x8000_0000
+                },
+                MemoryAffinity {
+                    proximity_domain: 1,
+                    base: 0x1_0000_0000,
+                    len: 0x4000_0000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_srat_and_invalid_rsdp() {
+        let xsdt = table(b"XSDT", &[]);
+        let mut rsdp = RSDP_SIGNATURE.to_vec();
+        rsdp.resize(15, 0);
+        rsdp.push(2);
+        rsdp.resize(24, 0);
+        rsdp.extend_from_slice(&(xsdt.as_ptr() as u64).to_le_bytes());
+
+        let rsdp_addr = PhysAddr::new(rsdp.as_ptr() as u64);
+        let result = unsafe { memory_affinities(rsdp_addr, |_| {}) };
+        assert_eq!(result, Err("no SRAT found"));
+
+        rsdp[0] = b'X';
+        let result = unsafe { memory_affinities(rsdp_addr, |_| {}) };
+        assert_eq!(result, Err("invalid RSDP signature"));
+    }
+}
@@ -0,0 +1,130 @@
+use bootloader_api::info::BootTime;
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECOND: u8 = 0x00;
+const REG_MINUTE: u8 = 0x02;
+const REG_HOUR: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+
+/// Set in status register A while the RTC updates its time registers.
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Set in status register B if the hour is stored in 24-hour format.
+const HOUR_FORMAT_24: u8 = 1 << 1;
+/// Set in status register B if the values are stored in binary instead of BCD.
+const BINARY_MODE: u8 = 1 << 2;
+/// Set in the hour register for PM times in 12-hour format.
+const HOUR_PM: u8 = 1 << 7;
+
+/// Limits the busy waiting, in case no RTC is present.
+const MAX_ATTEMPTS: usize = 100_000;
+
+/// Reads the current date and time from the CMOS real-time clock.
+///
+/// The century register is not standardized, so the year is assumed to be in the 21st
+/// century. Returns `None` if the RTC reports an invalid time or doesn't respond.
+pub fn read_cmos_time() -> Option<BootTime> {
+    // read the registers until we get the same values twice in a row, to avoid reading
+    // inconsistent values during an update
+    let mut registers = read_registers()?;
+    let mut attempts = 0;
+    loop {
+        let next = read_registers()?;
+        if next == registers {
+            break;
+        }
+        registers = next;
+        attempts += 1;
+        if attempts == MAX_ATTEMPTS {
+            return None;
+        }
+    }
+    let [second, minute, hour, day, month, year] = registers;
+
+    let status_b = read_register(REG_STATUS_B);
+    let decode = |value: u8| {
+        if status_b & BINARY_MODE != 0 {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0xf)
+        }
+    };
+    let hour = if status_b & HOUR_FORMAT_24 != 0 {
+        decode(hour)
+    } else {
+        // 12-hour format: 12 AM is midnight and 12 PM is noon
+        let pm = hour & HOUR_PM != 0;
+        decode(hour & !HOUR_PM) % 12 + if pm { 12 } else { 0 }
+    };
+
+    let time = BootTime {
+        year: 2000 + u16::from(decode(year)),
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+    };
+    time.is_valid().then_some(time)
+}
+
+/// Busy-waits until the seconds register of the RTC changed the given number of times.
+///
+/// The first change can happen at any time, so the wait takes between `seconds - 1` and
+/// `seconds` seconds. Returns `false` right away if no RTC is present, or after a few
+/// seconds if the RTC doesn't seem to tick.
+pub fn wait_seconds(seconds: u32) -> bool {
+    /// Limits the busy waiting for each tick, in case the RTC doesn't tick.
+    ///
+    /// Each poll accesses the CMOS ports twice, which takes at least a microsecond, so this
+    /// waits for at least two seconds.
+    const MAX_POLLS_PER_TICK: usize = 2_000_000;
+
+    if !is_present() {
+        return false;
+    }
+    let mut last = read_register(REG_SECOND);
+    for _ in 0..seconds {
+        let tick = (0..MAX_POLLS_PER_TICK).find_map(|_| {
+            let second = read_register(REG_SECOND);
+            (second != last).then_some(second)
+        });
+        match tick {
+            Some(second) => last = second,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Checks whether an RTC responds on the CMOS ports.
+///
+/// Reads from unconnected ports return all ones, which is not a valid value of status
+/// register A since it would select a divider that stops the clock.
+fn is_present() -> bool {
+    read_register(REG_STATUS_A) != 0xff
+}
+
+/// Reads the time registers after waiting for a pending update to finish.
+fn read_registers() -> Option<[u8; 6]> {
+    (0..MAX_ATTEMPTS).find(|_| read_register(REG_STATUS_A) & UPDATE_IN_PROGRESS == 0)?;
+    Some(
+        [
+            REG_SECOND, REG_MINUTE, REG_HOUR, REG_DAY, REG_MONTH, REG_YEAR,
+        ]
+        .map(read_register),
+    )
+}
+
+fn read_register(register: u8) -> u8 {
+    unsafe {
+        Port::new(CMOS_ADDRESS).write(register);
+        Port::new(CMOS_DATA).read()
+    }
+}
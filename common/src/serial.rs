@@ -1,5 +1,8 @@
 use core::fmt;
 
+/// The I/O port base of the serial port that is used for logging (`COM1`).
+pub const LOG_PORT: u16 = 0x3F8;
+
 pub struct SerialPort {
     port: uart_16550::SerialPort,
 }
@@ -9,7 +12,17 @@ impl SerialPort {
     ///
     /// unsafe because this function must only be called once
     pub unsafe fn init() -> Self {
-        let mut port = unsafe { uart_16550::SerialPort::new(0x3F8) };
+        unsafe { Self::init_at(LOG_PORT) }
+    }
+
+    /// Initializes the serial port with the given I/O port base.
+    ///
+    /// # Safety
+    ///
+    /// unsafe because this function must only be called once per port and the given base must
+    /// belong to a 16550 UART
+    pub unsafe fn init_at(base: u16) -> Self {
+        let mut port = unsafe { uart_16550::SerialPort::new(base) };
         port.init();
         Self { port }
     }
@@ -0,0 +1,72 @@
+use crate::kernel::{KernelValidationError, ELF_MAGIC};
+
+/// The CPU architecture that a disk image is created for.
+///
+/// The bootloader currently only supports `x86_64`. The architecture determines the file name
+/// of the kernel on the boot partition and the expected machine type of the kernel ELF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Arch {
+    /// The 64-bit x86 architecture.
+    #[default]
+    X86_64,
+}
+
+impl Arch {
+    /// Returns the name of this architecture, as used in file names.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+        }
+    }
+
+    /// Returns the file name of the kernel on the boot partition.
+    pub const fn kernel_file_name(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "kernel-x86_64",
+        }
+    }
+
+    /// Returns the expected `e_machine` value of kernel ELF files.
+    const fn elf_machine(self) -> u16 {
+        match self {
+            // EM_X86_64
+            Arch::X86_64 => 62,
+        }
+    }
+
+    /// Returns the expected `EI_CLASS` value of kernel ELF files.
+    const fn elf_class(self) -> u8 {
+        match self {
+            // ELFCLASS64
+            Arch::X86_64 => 2,
+        }
+    }
+
+    /// Checks that the given kernel executable is an ELF file for this architecture.
+    pub(crate) fn validate_kernel_header(self, kernel: &[u8]) -> Result<(), KernelValidationError> {
+        const EI_CLASS: usize = 4;
+        const E_MACHINE: usize = 18;
+
+        if !kernel.starts_with(ELF_MAGIC) {
+            return Err(KernelValidationError::NotElf);
+        }
+        let class = kernel.get(EI_CLASS).copied();
+        let machine = kernel
+            .get(E_MACHINE..E_MACHINE + 2)
+            .map(|m| u16::from_le_bytes([m[0], m[1]]));
+        match (class, machine) {
+            (Some(class), Some(machine))
+                if class == self.elf_class() && machine == self.elf_machine() =>
+            {
+                Ok(())
+            }
+            (Some(class), Some(machine)) => Err(KernelValidationError::WrongArch {
+                expected: self,
+                class,
+                machine,
+            }),
+            _ => Err(KernelValidationError::NotElf),
+        }
+    }
+}
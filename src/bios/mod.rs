@@ -29,8 +29,27 @@ impl BiosBoot {
         self
     }
 
+    /// Embeds the given config into the bootloader executable instead of creating a `boot.json`
+    /// file.
+    ///
+    /// This avoids reading the config from disk at boot time. See
+    /// [`DiskImageBuilder::set_embedded_boot_config`](crate::DiskImageBuilder::set_embedded_boot_config)
+    /// for details.
+    pub fn embed_boot_config(&mut self, config: &BootConfig) -> &mut Self {
+        self.image_builder.set_embedded_boot_config(config);
+        self
+    }
+
     /// Create a bootable BIOS disk image at the given path.
     pub fn create_disk_image(&self, out_path: &Path) -> anyhow::Result<()> {
         self.image_builder.create_bios_image(out_path)
     }
+
+    /// Create a coreboot payload at the given path.
+    ///
+    /// See [`DiskImageBuilder::create_coreboot_payload`](crate::DiskImageBuilder::create_coreboot_payload)
+    /// for details.
+    pub fn create_coreboot_payload(&self, out_path: &Path) -> anyhow::Result<()> {
+        self.image_builder.create_coreboot_payload(out_path)
+    }
 }
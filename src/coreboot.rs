@@ -0,0 +1,277 @@
+//! Creation of coreboot payloads that contain the BIOS bootloader.
+//!
+//! Coreboot loads its payloads from ELF executables, which `cbfstool add-payload` converts to
+//! the segment-based payload format of CBFS. The payload created here is the multiboot stage
+//! with an additional segment that contains the remaining stages, the kernel, and the other
+//! files. The multiboot stage finds these files through a module table in its executable,
+//! which is filled in here.
+
+use anyhow::Context;
+use bootloader_boot_config::coreboot_payload;
+use std::collections::BTreeMap;
+
+const PAGE_SIZE: u32 = 4096;
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const ELF_HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: usize = 32;
+const PT_LOAD: u32 = 1;
+/// The `PF_R` flag of program headers.
+const SEGMENT_READABLE: u32 = 4;
+
+/// A loadable segment of a 32-bit ELF executable.
+struct Segment {
+    addr: u32,
+    mem_size: u32,
+    flags: u32,
+    data: Vec<u8>,
+}
+
+/// Creates a coreboot payload from the multiboot stage and the given files.
+///
+/// The files are placed at page-aligned addresses behind the multiboot stage, in the given
+/// order.
+pub(crate) fn create_payload(
+    multiboot_stage: &[u8],
+    files: &[(&str, Vec<u8>)],
+) -> anyhow::Result<Vec<u8>> {
+    let (entry, mut segments) = parse_executable(multiboot_stage)?;
+    if files.len() > coreboot_payload::MAX_MODULES {
+        anyhow::bail!(
+            "too many files for a coreboot payload ({}, maximum is {})",
+            files.len(),
+            coreboot_payload::MAX_MODULES
+        );
+    }
+
+    let stage_end = segments
+        .iter()
+        .map(|segment| segment.addr + segment.mem_size)
+        .max()
+        .context("multiboot stage has no loadable segments")?;
+    let modules_start = align_up(stage_end, PAGE_SIZE);
+    let mut table = vec![0; coreboot_payload::SIZE];
+    table[..coreboot_payload::MAGIC.len()].copy_from_slice(&coreboot_payload::MAGIC);
+    table[coreboot_payload::COUNT_OFFSET..][..4]
+        .copy_from_slice(&u32::try_from(files.len())?.to_le_bytes());
+    let mut modules = Vec::new();
+    for (i, (name, data)) in files.iter().enumerate() {
+        if name.is_empty() || name.len() > coreboot_payload::NAME_LEN {
+            anyhow::bail!(
+                "file name `{name}` is not supported in coreboot payloads (names must be 1 to {} \
+                bytes long)",
+                coreboot_payload::NAME_LEN
+            );
+        }
+        modules.resize(
+            align_up(u32::try_from(modules.len())?, PAGE_SIZE) as usize,
+            0,
+        );
+        let addr = modules_start + u32::try_from(modules.len())?;
+        let entry = &mut table
+            [coreboot_payload::ENTRIES_OFFSET + i * coreboot_payload::ENTRY_SIZE..]
+            [..coreboot_payload::ENTRY_SIZE];
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        entry[coreboot_payload::NAME_LEN..][..4].copy_from_slice(&addr.to_le_bytes());
+        entry[coreboot_payload::NAME_LEN + 4..][..4]
+            .copy_from_slice(&u32::try_from(data.len())?.to_le_bytes());
+        modules.extend_from_slice(data);
+    }
+    modules_start
+        .checked_add(u32::try_from(modules.len())?)
+        .context("coreboot payload exceeds the 32-bit address space")?;
+
+    let mut table_locations = segments
+        .iter_mut()
+        .flat_map(|segment| find_table(&segment.data).map(move |offset| (segment, offset)));
+    let (segment, offset) = table_locations
+        .next()
+        .context("multiboot stage has no space reserved for a module table")?;
+    let reserved = &mut segment.data[offset..][..coreboot_payload::SIZE];
+    if reserved[coreboot_payload::COUNT_OFFSET..]
+        .iter()
+        .any(|&b| b != 0)
+    {
+        anyhow::bail!("multiboot stage already contains a module table");
+    }
+    reserved.copy_from_slice(&table);
+    if table_locations.next().is_some() {
+        anyhow::bail!("multiboot stage reserves space for multiple module tables");
+    }
+
+    segments.push(Segment {
+        addr: modules_start,
+        mem_size: u32::try_from(modules.len())?,
+        flags: SEGMENT_READABLE,
+        data: modules,
+    });
+    Ok(write_executable(entry, &segments))
+}
+
+/// Lists the files that are stored in the given coreboot payload.
+///
+/// Returns a map from the name of each file to its size in bytes.
+pub(crate) fn list_files(payload: &[u8]) -> anyhow::Result<BTreeMap<String, u64>> {
+    let (_, segments) = parse_executable(payload)?;
+    let (segment, offset) = segments
+        .iter()
+        .find_map(|segment| find_table(&segment.data).map(|offset| (segment, offset)))
+        .context("coreboot payload has no module table")?;
+    let table = &segment.data[offset..][..coreboot_payload::SIZE];
+    let read_u32 = |offset: usize| u32::from_le_bytes(table[offset..][..4].try_into().unwrap());
+
+    let count = read_u32(coreboot_payload::COUNT_OFFSET) as usize;
+    if count > coreboot_payload::MAX_MODULES {
+        anyhow::bail!("invalid module count {count}");
+    }
+    let mut files = BTreeMap::new();
+    for i in 0..count {
+        let entry = coreboot_payload::ENTRIES_OFFSET + i * coreboot_payload::ENTRY_SIZE;
+        let name = coreboot_payload::parse_name(
+            table[entry..][..coreboot_payload::NAME_LEN]
+                .try_into()
+                .unwrap(),
+        )
+        .with_context(|| format!("module {i} has an invalid name"))?;
+        let addr = read_u32(entry + coreboot_payload::NAME_LEN);
+        let len = read_u32(entry + coreboot_payload::NAME_LEN + 4);
+        let contained = segments.iter().any(|segment| {
+            addr >= segment.addr
+                && u64::from(addr) + u64::from(len)
+                    <= u64::from(segment.addr) + segment.data.len() as u64
+        });
+        if !contained {
+            anyhow::bail!("module `{name}` is not part of a loadable segment");
+        }
+        files.insert(name.to_owned(), u64::from(len));
+    }
+    Ok(files)
+}
+
+fn find_table(data: &[u8]) -> Option<usize> {
+    data.windows(coreboot_payload::MAGIC.len())
+        .position(|window| window == coreboot_payload::MAGIC)
+        .filter(|offset| offset + coreboot_payload::SIZE <= data.len())
+}
+
+/// Returns the entry point and the loadable segments of the given 32-bit x86 ELF executable.
+fn parse_executable(executable: &[u8]) -> anyhow::Result<(u32, Vec<Segment>)> {
+    const EI_CLASS: usize = 4;
+    const ELFCLASS32: u8 = 1;
+    const E_MACHINE: usize = 18;
+    const EM_386: u16 = 3;
+
+    let header = executable
+        .get(..ELF_HEADER_SIZE)
+        .context("executable is too small")?;
+    if header[..4] != ELF_MAGIC || header[EI_CLASS] != ELFCLASS32 {
+        anyhow::bail!("executable is not a 32-bit ELF file");
+    }
+    let read_u16 =
+        |bytes: &[u8], offset: usize| u16::from_le_bytes(bytes[offset..][..2].try_into().unwrap());
+    let read_u32 =
+        |bytes: &[u8], offset: usize| u32::from_le_bytes(bytes[offset..][..4].try_into().unwrap());
+    if read_u16(header, E_MACHINE) != EM_386 {
+        anyhow::bail!("executable is not an x86 ELF file");
+    }
+    let entry = read_u32(header, 24);
+    let program_headers = read_u32(header, 28) as usize;
+    let program_header_size = usize::from(read_u16(header, 42));
+    let program_header_count = usize::from(read_u16(header, 44));
+    if program_header_size < PROGRAM_HEADER_SIZE {
+        anyhow::bail!("invalid program header size {program_header_size}");
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..program_header_count {
+        let program_header = executable
+            .get(program_headers + i * program_header_size..)
+            .and_then(|bytes| bytes.get(..PROGRAM_HEADER_SIZE))
+            .context("program header table is truncated")?;
+        if read_u32(program_header, 0) != PT_LOAD {
+            continue;
+        }
+        let offset = read_u32(program_header, 4) as usize;
+        let file_size = read_u32(program_header, 16) as usize;
+        let data = executable
+            .get(offset..)
+            .and_then(|bytes| bytes.get(..file_size))
+            .with_context(|| format!("segment {i} is truncated"))?;
+        segments.push(Segment {
+            // coreboot loads segments to their physical address
+            addr: read_u32(program_header, 12),
+            mem_size: read_u32(program_header, 20),
+            flags: read_u32(program_header, 24),
+            data: data.to_vec(),
+        });
+    }
+    Ok((entry, segments))
+}
+
+/// Creates a 32-bit x86 ELF executable with the given entry point and segments.
+fn write_executable(entry: u32, segments: &[Segment]) -> Vec<u8> {
+    let program_headers_end = ELF_HEADER_SIZE + segments.len() * PROGRAM_HEADER_SIZE;
+
+    let mut executable = Vec::new();
+    executable.extend_from_slice(&ELF_MAGIC);
+    // 32-bit, little endian, version 1, System V ABI
+    executable.extend_from_slice(&[1, 1, 1, 0]);
+    executable.resize(16, 0);
+    let u16_fields: [u16; 2] = [2 /* ET_EXEC */, 3 /* EM_386 */];
+    for field in u16_fields {
+        executable.extend_from_slice(&field.to_le_bytes());
+    }
+    let u32_fields = [1, entry, ELF_HEADER_SIZE as u32, 0, 0];
+    for field in u32_fields {
+        executable.extend_from_slice(&field.to_le_bytes());
+    }
+    let u16_fields = [
+        ELF_HEADER_SIZE as u16,
+        PROGRAM_HEADER_SIZE as u16,
+        segments.len() as u16,
+        0, // no section headers
+        0,
+        0,
+    ];
+    for field in u16_fields {
+        executable.extend_from_slice(&field.to_le_bytes());
+    }
+    assert_eq!(executable.len(), ELF_HEADER_SIZE);
+
+    // the file offsets of the segments must be congruent to their addresses modulo the page
+    // size
+    let mut offset = program_headers_end as u32;
+    let offsets: Vec<u32> = segments
+        .iter()
+        .map(|segment| {
+            offset += segment.addr.wrapping_sub(offset) % PAGE_SIZE;
+            let segment_offset = offset;
+            offset += segment.data.len() as u32;
+            segment_offset
+        })
+        .collect();
+    for (segment, &offset) in segments.iter().zip(&offsets) {
+        let fields = [
+            PT_LOAD,
+            offset,
+            segment.addr,
+            segment.addr,
+            segment.data.len() as u32,
+            segment.mem_size,
+            segment.flags,
+            PAGE_SIZE,
+        ];
+        for field in fields {
+            executable.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    for (segment, &offset) in segments.iter().zip(&offsets) {
+        executable.resize(offset as usize, 0);
+        executable.extend_from_slice(&segment.data);
+    }
+    executable
+}
+
+fn align_up(value: u32, align: u32) -> u32 {
+    (value + align - 1) & !(align - 1)
+}
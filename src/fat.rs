@@ -2,22 +2,16 @@ use crate::file_data_source::FileDataSource;
 use anyhow::Context;
 use fatfs::Dir;
 use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::{collections::BTreeMap, fs, path::Path};
 
-use crate::KERNEL_FILE_NAME;
+const MB: u64 = 1024 * 1024;
 
 pub fn create_fat_filesystem(
     files: BTreeMap<&str, &FileDataSource>,
+    kernel_file_name: &str,
     out_fat_path: &Path,
 ) -> anyhow::Result<()> {
-    const MB: u64 = 1024 * 1024;
-
-    // calculate needed size
-    let mut needed_size = 0;
-    for source in files.values() {
-        needed_size += source.len()?;
-    }
-
     // create new filesystem image file at the given path and set its length
     let fat_file = fs::OpenOptions::new()
         .read(true)
@@ -26,14 +20,13 @@ pub fn create_fat_filesystem(
         .truncate(true)
         .open(out_fat_path)
         .unwrap();
-    let fat_size_padded_and_rounded = ((needed_size + 1024 * 64 - 1) / MB + 1) * MB + MB;
-    fat_file.set_len(fat_size_padded_and_rounded).unwrap();
+    fat_file.set_len(filesystem_size(&files)?).unwrap();
 
     // choose a file system label
     let mut label = *b"MY_RUST_OS!";
 
     // This __should__ always be a file, but maybe not. Should we allow the caller to set the volume label instead?
-    if let Some(FileDataSource::File(path)) = files.get(KERNEL_FILE_NAME) {
+    if let Some(FileDataSource::File(path)) = files.get(kernel_file_name) {
         if let Some(name) = path.file_stem() {
             let converted = name.to_string_lossy();
             let name = converted.as_bytes();
@@ -56,8 +49,62 @@ pub fn create_fat_filesystem(
     add_files_to_image(&root_dir, files)
 }
 
-pub fn add_files_to_image(
-    root_dir: &Dir<&File>,
+/// Updates the FAT filesystem in the given partition of an existing disk image in place.
+///
+/// Only files whose size or CRC differs from the given files are rewritten. Missing files are
+/// created and files that are not part of `files` are removed, so that the filesystem ends up
+/// with the same contents as one created by [`create_fat_filesystem`].
+///
+/// Returns `Ok(false)` without modifying the partition if it doesn't contain a FAT filesystem
+/// or if it is smaller than a newly created filesystem would be. In this case, the disk image
+/// needs to be recreated.
+pub fn update_fat_filesystem(
+    files: &BTreeMap<&str, &FileDataSource>,
+    disk: &File,
+    partition_start: u64,
+    partition_len: u64,
+) -> anyhow::Result<bool> {
+    if filesystem_size(files)? > partition_len {
+        return Ok(false);
+    }
+
+    let partition = PartitionSlice {
+        disk,
+        start: partition_start,
+        len: partition_len,
+        pos: 0,
+    };
+    let Ok(filesystem) = fatfs::FileSystem::new(partition, fatfs::FsOptions::new()) else {
+        return Ok(false);
+    };
+    let root_dir = filesystem.root_dir();
+
+    let wanted: Vec<String> = files.keys().map(|path| path.to_ascii_lowercase()).collect();
+    remove_stale_entries(&root_dir, "", &wanted)?;
+
+    let mut changed = BTreeMap::new();
+    for (&target_path, &source) in files {
+        let unchanged = match root_dir.open_file(target_path) {
+            Ok(mut file) => file_matches(&mut file, source).with_context(|| {
+                format!("failed to compare file at `{target_path}` with `{source:?}`")
+            })?,
+            Err(_) => false,
+        };
+        if !unchanged {
+            changed.insert(target_path, source);
+        }
+    }
+    add_files_to_image(&root_dir, changed)?;
+
+    drop(root_dir);
+    filesystem
+        .unmount()
+        .context("failed to unmount FAT file system of disk image")?;
+    Ok(true)
+}
+
+pub fn add_files_to_image<T: fatfs::ReadWriteSeek>(
+    root_dir: &Dir<T>,
     files: BTreeMap<&str, &FileDataSource>,
 ) -> anyhow::Result<()> {
     for (target_path_raw, source) in files {
@@ -91,3 +138,149 @@ pub fn add_files_to_image(
 
     Ok(())
 }
+
+/// Returns the size of a FAT filesystem that is large enough for the given files.
+fn filesystem_size(files: &BTreeMap<&str, &FileDataSource>) -> anyhow::Result<u64> {
+    let mut needed_size = 0;
+    for source in files.values() {
+        needed_size += source.len()?;
+    }
+    Ok(((needed_size + 1024 * 64 - 1) / MB + 1) * MB + MB)
+}
+
+/// Removes all files and directories below `dir` that are not part of the `wanted` paths.
+///
+/// FAT file names are case-insensitive, so the `wanted` paths must be lowercase.
+fn remove_stale_entries<T: fatfs::ReadWriteSeek>(
+    dir: &Dir<T>,
+    prefix: &str,
+    wanted: &[String],
+) -> anyhow::Result<()> {
+    let entries = dir
+        .iter()
+        .collect::<Result<Vec<_>, _>>()
+        .context("failed to read FAT directory entry")?;
+    for entry in entries {
+        let name = entry.file_name();
+        if name == "." || name == ".." {
+            continue;
+        }
+        let path = format!("{prefix}{}", name.to_ascii_lowercase());
+        if entry.is_dir() {
+            let dir_prefix = format!("{path}/");
+            remove_stale_entries(&entry.to_dir(), &dir_prefix, wanted)?;
+            if wanted.iter().any(|wanted| wanted.starts_with(&dir_prefix)) {
+                continue;
+            }
+        } else if wanted.contains(&path) {
+            continue;
+        }
+        dir.remove(&name)
+            .with_context(|| format!("failed to remove `{path}` from FAT filesystem"))?;
+    }
+    Ok(())
+}
+
+/// Checks whether the given file has the same size and CRC as the given source.
+fn file_matches<T: fatfs::ReadWriteSeek>(
+    file: &mut fatfs::File<T>,
+    source: &FileDataSource,
+) -> anyhow::Result<bool> {
+    if file.seek(SeekFrom::End(0))? != source.len()? {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::Start(0))?;
+    let mut existing = Crc32::new();
+    io::copy(file, &mut existing)?;
+    let mut new = Crc32::new();
+    source.copy_to(&mut new)?;
+    Ok(existing.finish() == new.finish())
+}
+
+/// Calculates the CRC-32 (IEEE) checksum of all data written to it.
+struct Crc32(u32);
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(!0)
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Write for Crc32 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.0 ^= u32::from(byte);
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Provides access to a partition of a disk image file, so that the FAT filesystem in it can
+/// be modified in place.
+struct PartitionSlice<'a> {
+    disk: &'a File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl PartitionSlice<'_> {
+    /// Seeks the disk image to the current position and returns the number of bytes that
+    /// can be accessed from there, at most `max`.
+    fn prepare(&mut self, max: usize) -> io::Result<usize> {
+        self.disk.seek(SeekFrom::Start(self.start + self.pos))?;
+        let remaining = self.len.saturating_sub(self.pos);
+        Ok(usize::try_from(remaining).map_or(max, |remaining| remaining.min(max)))
+    }
+}
+
+impl Read for PartitionSlice<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.prepare(buf.len())?;
+        let read = self.disk.read(&mut buf[..len])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for PartitionSlice<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.prepare(buf.len())?;
+        let written = self.disk.write(&buf[..len])?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.disk.flush()
+    }
+}
+
+impl Seek for PartitionSlice<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative offset",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
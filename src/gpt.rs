@@ -68,3 +68,26 @@ pub fn create_gpt_disk(fat_image: &Path, out_gpt_path: &Path) -> anyhow::Result<
 
     Ok(())
 }
+
+/// Returns the byte offset and length of the EFI system partition of an existing GPT disk
+/// image, so that its files can be updated in place.
+pub fn efi_partition_range(gpt_path: &Path) -> anyhow::Result<(u64, u64)> {
+    let block_size = gpt::disk::LogicalBlockSize::Lb512;
+    let disk = gpt::GptConfig::new()
+        .writable(false)
+        .logical_block_size(block_size)
+        .open(gpt_path)
+        .with_context(|| format!("failed to open GPT disk image `{}`", gpt_path.display()))?;
+    let partition = disk
+        .partitions()
+        .values()
+        .find(|partition| partition.part_type_guid == gpt::partition_types::EFI)
+        .context("disk image has no EFI system partition")?;
+    let start = partition
+        .bytes_start(block_size)
+        .context("failed to get start offset of EFI system partition")?;
+    let len = partition
+        .bytes_len(block_size)
+        .context("failed to get length of EFI system partition")?;
+    Ok((start, len))
+}
@@ -0,0 +1,107 @@
+//! Functions for inspecting the contents of created disk images, e.g. in tests.
+
+use anyhow::Context;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const SECTOR_SIZE: u64 = 512;
+
+/// Lists the files in the FAT boot partition of a BIOS disk image.
+///
+/// The image is expected to be created through
+/// [`DiskImageBuilder::create_bios_image`](crate::DiskImageBuilder::create_bios_image).
+/// Returns a map from the path of each file to its size in bytes. Files in subdirectories use
+/// `/` as path separator, e.g. `boot/kernel-x86_64`.
+#[cfg(feature = "bios")]
+pub fn list_bios_image_files(image_path: &Path) -> anyhow::Result<BTreeMap<String, u64>> {
+    let mut image = File::open(image_path)
+        .with_context(|| format!("failed to open disk image `{}`", image_path.display()))?;
+    let mbr = mbrman::MBR::read_from(&mut image, SECTOR_SIZE as u32)
+        .context("failed to read MBR partition table")?;
+    // the FAT partition follows the second stage partition (partition numbers start at 1)
+    let partition = &mbr[2];
+    if partition.is_unused() {
+        anyhow::bail!("disk image has no FAT partition");
+    }
+
+    list_fat_files(
+        &mut image,
+        u64::from(partition.starting_lba) * SECTOR_SIZE,
+        u64::from(partition.sectors) * SECTOR_SIZE,
+    )
+}
+
+/// Lists the files in the EFI system partition of a UEFI disk image.
+///
+/// The image is expected to be created through
+/// [`DiskImageBuilder::create_uefi_image`](crate::DiskImageBuilder::create_uefi_image).
+/// Returns a map from the path of each file to its size in bytes. Files in subdirectories use
+/// `/` as path separator, e.g. `efi/boot/bootx64.efi`.
+#[cfg(feature = "uefi")]
+pub fn list_uefi_image_files(image_path: &Path) -> anyhow::Result<BTreeMap<String, u64>> {
+    let (start, len) = crate::gpt::efi_partition_range(image_path)?;
+    let mut image = File::open(image_path)
+        .with_context(|| format!("failed to open disk image `{}`", image_path.display()))?;
+    list_fat_files(&mut image, start, len)
+}
+
+/// Lists the files that are stored in a coreboot payload.
+///
+/// The payload is expected to be created through
+/// [`DiskImageBuilder::create_coreboot_payload`](crate::DiskImageBuilder::create_coreboot_payload).
+/// Returns a map from the name of each file to its size in bytes.
+#[cfg(feature = "bios")]
+pub fn list_coreboot_payload_files(payload_path: &Path) -> anyhow::Result<BTreeMap<String, u64>> {
+    let payload = std::fs::read(payload_path).with_context(|| {
+        format!(
+            "failed to read coreboot payload `{}`",
+            payload_path.display()
+        )
+    })?;
+    crate::coreboot::list_files(&payload)
+}
+
+fn list_fat_files(
+    image: &mut File,
+    partition_start: u64,
+    partition_len: u64,
+) -> anyhow::Result<BTreeMap<String, u64>> {
+    let mut partition = vec![0; usize::try_from(partition_len)?];
+    image
+        .seek(SeekFrom::Start(partition_start))
+        .context("failed to seek to FAT partition")?;
+    image
+        .read_exact(&mut partition)
+        .context("failed to read FAT partition")?;
+
+    let filesystem = fatfs::FileSystem::new(Cursor::new(partition), fatfs::FsOptions::new())
+        .context("failed to open FAT file system")?;
+    let mut files = BTreeMap::new();
+    collect_files(filesystem.root_dir(), "", &mut files)?;
+    Ok(files)
+}
+
+fn collect_files<T: fatfs::ReadWriteSeek>(
+    dir: fatfs::Dir<T>,
+    prefix: &str,
+    files: &mut BTreeMap<String, u64>,
+) -> anyhow::Result<()> {
+    for entry in dir.iter() {
+        let entry = entry.context("failed to read FAT directory entry")?;
+        let name = entry.file_name();
+        if name == "." || name == ".." {
+            continue;
+        }
+        let path = format!("{prefix}{name}");
+        if entry.is_dir() {
+            collect_files(entry.to_dir(), &format!("{path}/"), files)?;
+        } else {
+            files.insert(path, entry.len());
+        }
+    }
+    Ok(())
+}
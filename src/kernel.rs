@@ -0,0 +1,305 @@
+use crate::{arch::Arch, file_data_source::FileDataSource};
+use bootloader_api::{config::ApiVersion, BootloaderConfig};
+use std::fmt;
+use xmas_elf::{
+    header,
+    program::{self, ProgramHeader},
+    ElfFile,
+};
+
+/// The name of the ELF section that contains the serialized [`BootloaderConfig`].
+const CONFIG_SECTION: &str = ".bootloader-config";
+
+/// The magic bytes at the start of every ELF file.
+pub(crate) const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// An error that makes a kernel executable unbootable, found by
+/// [`DiskImageBuilder::validate_kernel`](crate::DiskImageBuilder::validate_kernel).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum KernelValidationError {
+    /// The kernel executable could not be read.
+    Read(anyhow::Error),
+    /// The kernel executable is not an ELF file.
+    NotElf,
+    /// The kernel executable is an ELF file for a different architecture.
+    WrongArch {
+        /// The architecture of the disk image.
+        expected: Arch,
+        /// The `EI_CLASS` value of the kernel.
+        class: u8,
+        /// The `e_machine` value of the kernel.
+        machine: u16,
+    },
+    /// The ELF file could not be parsed.
+    InvalidElf(&'static str),
+    /// The kernel has no `.bootloader-config` section, i.e. it was not compiled against the
+    /// `bootloader_api` crate.
+    MissingConfigSection,
+    /// The `.bootloader-config` section was not created by a known `bootloader_api` version.
+    InvalidConfigSection,
+    /// The kernel was compiled against a `bootloader_api` version that is not compatible with
+    /// this bootloader.
+    IncompatibleApiVersion {
+        /// The `bootloader_api` version of the kernel.
+        kernel: ApiVersion,
+        /// The `bootloader_api` version that this bootloader expects.
+        expected: ApiVersion,
+    },
+    /// The `.bootloader-config` section was created by the `bootloader_api` version that this
+    /// bootloader expects, but contains an invalid config.
+    InvalidConfig(&'static str),
+    /// The kernel has no entry point, e.g. because it doesn't define a `_start` function.
+    MissingEntryPoint,
+    /// The entry point doesn't lie in an executable segment of the kernel.
+    EntryPointNotExecutable(u64),
+    /// The kernel contains no loadable segments.
+    NoLoadableSegments,
+    /// A program header of the kernel is invalid.
+    InvalidProgramHeader {
+        /// The index of the program header.
+        index: usize,
+        /// A description of the problem.
+        reason: &'static str,
+    },
+    /// The `default-kernel` file of the disk image is invalid.
+    InvalidDefaultKernelFile,
+    /// The default kernel is not part of the disk image.
+    MissingDefaultKernel(String),
+    /// The default kernel is not bootable.
+    DefaultKernel {
+        /// The file name of the default kernel.
+        name: String,
+        /// The problem of the default kernel.
+        error: Box<KernelValidationError>,
+    },
+}
+
+impl fmt::Display for KernelValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(err) => write!(f, "failed to read kernel executable: {err:#}"),
+            Self::NotElf => write!(f, "kernel executable is not an ELF file"),
+            Self::WrongArch {
+                expected,
+                class,
+                machine,
+            } => write!(
+                f,
+                "kernel executable is not an {} ELF file (class {class}, machine {machine})",
+                expected.name()
+            ),
+            Self::InvalidElf(err) => write!(f, "failed to parse kernel executable: {err}"),
+            Self::MissingConfigSection => write!(
+                f,
+                "kernel executable has no `{CONFIG_SECTION}` section, it must be compiled \
+                against the `bootloader_api` crate using the `entry_point` macro"
+            ),
+            Self::InvalidConfigSection => write!(
+                f,
+                "the `{CONFIG_SECTION}` section of the kernel executable was not created by a \
+                known `bootloader_api` version (expected version {})",
+                format_version(BootloaderConfig::default().api_version())
+            ),
+            Self::IncompatibleApiVersion { kernel, expected } => write!(
+                f,
+                "kernel was compiled against `bootloader_api` version {}, which is not \
+                compatible with this bootloader (expected version {}); update the \
+                `bootloader_api` dependency of the kernel to the version of the `bootloader` \
+                crate",
+                format_version(*kernel),
+                format_version(*expected)
+            ),
+            Self::InvalidConfig(err) => write!(
+                f,
+                "the `{CONFIG_SECTION}` section of the kernel executable contains an invalid \
+                config: {err}"
+            ),
+            Self::MissingEntryPoint => write!(
+                f,
+                "kernel executable has no entry point, it must define a `_start` function, \
+                e.g. using the `entry_point` macro of the `bootloader_api` crate"
+            ),
+            Self::EntryPointNotExecutable(addr) => write!(
+                f,
+                "the entry point {addr:#x} of the kernel doesn't lie in an executable segment"
+            ),
+            Self::NoLoadableSegments => write!(f, "kernel executable has no loadable segments"),
+            Self::InvalidProgramHeader { index, reason } => {
+                write!(
+                    f,
+                    "program header {index} of the kernel is invalid: {reason}"
+                )
+            }
+            Self::InvalidDefaultKernelFile => write!(f, "invalid `default-kernel` file"),
+            Self::MissingDefaultKernel(name) => {
+                write!(f, "default kernel `{name}` is not part of the image")
+            }
+            Self::DefaultKernel { name, error } => {
+                write!(f, "invalid default kernel `{name}`: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KernelValidationError {}
+
+/// Checks that the given kernel can be booted by this bootloader.
+///
+/// This checks the architecture, the `bootloader_api` version, the entry point, and the
+/// program headers of the kernel, so that users get an error on image creation instead of a
+/// kernel that fails to boot. If `flat_kernel` is set, files without ELF magic bytes are
+/// booted as flat binaries, which can't be validated.
+pub(crate) fn validate(
+    arch: Arch,
+    kernel: &FileDataSource,
+    flat_kernel: bool,
+) -> Result<(), KernelValidationError> {
+    let mut bytes = Vec::new();
+    kernel
+        .copy_to(&mut bytes)
+        .map_err(KernelValidationError::Read)?;
+    if flat_kernel && !bytes.starts_with(ELF_MAGIC) {
+        return Ok(());
+    }
+    arch.validate_kernel_header(&bytes)?;
+    let elf = ElfFile::new(&bytes).map_err(KernelValidationError::InvalidElf)?;
+
+    let config = check_api_version(&elf)?;
+    check_program_headers(&elf, bytes.len())?;
+    check_entry_point(&elf, config.entry_point)
+}
+
+/// Checks that the kernel was compiled against a `bootloader_api` version that is compatible
+/// with this bootloader and returns its config.
+///
+/// The bootloader reads the config from the `.bootloader-config` section at boot time and
+/// panics if it can't deserialize it. This function performs the same check on the host and
+/// additionally requires a semver-compatible version, so that version mismatches are reported
+/// when the disk image is created instead of at boot time. A config of a compatible but
+/// different version that can't be deserialized is reported as a version mismatch too, since
+/// the config layout might differ between patch versions.
+fn check_api_version(elf: &ElfFile) -> Result<BootloaderConfig, KernelValidationError> {
+    let section = elf
+        .find_section_by_name(CONFIG_SECTION)
+        .ok_or(KernelValidationError::MissingConfigSection)?;
+    let raw = section.raw_data(elf);
+
+    let expected = BootloaderConfig::default().api_version();
+    let version = BootloaderConfig::deserialize_api_version(raw)
+        .ok_or(KernelValidationError::InvalidConfigSection)?;
+    let incompatible = KernelValidationError::IncompatibleApiVersion {
+        kernel: version,
+        expected,
+    };
+    if !is_compatible(version, expected) {
+        return Err(incompatible);
+    }
+    BootloaderConfig::deserialize(raw).map_err(|err| {
+        if version == expected {
+            KernelValidationError::InvalidConfig(err)
+        } else {
+            incompatible
+        }
+    })
+}
+
+/// Checks whether the given versions are semver-compatible, i.e. whether they have the same
+/// major version and, for `0.x` versions, the same minor version.
+fn is_compatible(version: ApiVersion, expected: ApiVersion) -> bool {
+    version.version_major() == expected.version_major()
+        && (expected.version_major() != 0 || version.version_minor() == expected.version_minor())
+}
+
+/// Checks that the kernel has loadable segments and that their program headers are
+/// consistent with the file.
+fn check_program_headers(elf: &ElfFile, file_len: usize) -> Result<(), KernelValidationError> {
+    let mut loadable_segments = 0;
+    for (index, header) in elf.program_iter().enumerate() {
+        let invalid = |reason| KernelValidationError::InvalidProgramHeader { index, reason };
+        let ProgramHeader::Ph64(header) = header else {
+            return Err(invalid("not a 64-bit program header"));
+        };
+        if header.get_type() != Ok(program::Type::Load) {
+            continue;
+        }
+        loadable_segments += 1;
+        if header.file_size > header.mem_size {
+            return Err(invalid("file size is larger than memory size"));
+        }
+        match header.offset.checked_add(header.file_size) {
+            Some(end) if end <= file_len as u64 => {}
+            _ => return Err(invalid("segment data lies outside of the file")),
+        }
+        if header.virtual_addr.checked_add(header.mem_size).is_none() {
+            return Err(invalid("segment end address overflows"));
+        }
+        if header.align > 1 {
+            if !header.align.is_power_of_two() {
+                return Err(invalid("alignment is not a power of two"));
+            }
+            if header.virtual_addr % header.align != header.offset % header.align {
+                return Err(invalid("virtual address and file offset are not congruent"));
+            }
+        }
+    }
+    if loadable_segments == 0 {
+        return Err(KernelValidationError::NoLoadableSegments);
+    }
+    Ok(())
+}
+
+/// Checks that the entry point, or the configured override, lies in an executable segment.
+fn check_entry_point(elf: &ElfFile, entry_point: Option<u64>) -> Result<(), KernelValidationError> {
+    let entry_point = match entry_point {
+        Some(addr) => addr,
+        None => match elf.header.pt2.entry_point() {
+            0 => return Err(KernelValidationError::MissingEntryPoint),
+            addr => addr,
+        },
+    };
+    let executable = elf.program_iter().any(|header| {
+        header.get_type() == Ok(program::Type::Load)
+            && header.flags().is_execute()
+            && (header.virtual_addr()..header.virtual_addr() + header.mem_size())
+                .contains(&entry_point)
+    });
+    if !executable {
+        return Err(KernelValidationError::EntryPointNotExecutable(entry_point));
+    }
+    Ok(())
+}
+
+/// Returns the offset between the runtime and link-time addresses of the kernel, if it is
+/// known at build time.
+///
+/// Non-position-independent kernels are always loaded at their link-time addresses. The
+/// bootloader chooses the address of position-independent kernels at boot time, e.g.
+/// randomized if ASLR is enabled, so their offset is unknown.
+pub(crate) fn fixed_load_offset(kernel: &FileDataSource) -> anyhow::Result<u64> {
+    let mut bytes = Vec::new();
+    kernel.copy_to(&mut bytes)?;
+    let elf = ElfFile::new(&bytes).map_err(KernelValidationError::InvalidElf)?;
+    match elf.header.pt2.type_().as_type() {
+        header::Type::Executable => Ok(0),
+        header::Type::SharedObject => anyhow::bail!(
+            "the kernel is position-independent, so its load offset is chosen by the \
+            bootloader at boot time and reported in `BootInfo::kernel_image_offset`"
+        ),
+        other => anyhow::bail!("unsupported kernel ELF type `{other:?}`"),
+    }
+}
+
+fn format_version(version: ApiVersion) -> String {
+    let pre_release = if version.pre_release() {
+        " (pre-release)"
+    } else {
+        ""
+    };
+    format!(
+        "{}.{}.{}{pre_release}",
+        version.version_major(),
+        version.version_minor(),
+        version.version_patch()
+    )
+}
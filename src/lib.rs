@@ -8,6 +8,8 @@ extern crate alloc;
 
 #[cfg(feature = "bios")]
 mod bios;
+#[cfg(feature = "bios")]
+mod coreboot;
 #[cfg(feature = "uefi")]
 mod gpt;
 #[cfg(feature = "bios")]
@@ -21,8 +23,12 @@ pub use uefi::UefiBoot;
 #[cfg(feature = "bios")]
 pub use bios::BiosBoot;
 
+mod arch;
 mod fat;
 mod file_data_source;
+#[cfg(any(feature = "bios", feature = "uefi"))]
+pub mod inspect;
+mod kernel;
 
 use std::{
     borrow::Cow,
@@ -35,11 +41,15 @@ use anyhow::Context;
 use tempfile::NamedTempFile;
 
 use crate::file_data_source::FileDataSource;
-pub use bootloader_boot_config::BootConfig;
+pub use arch::Arch;
+use bootloader_boot_config::default_kernel;
+pub use bootloader_boot_config::{BootConfig, FlatKernel};
+pub use kernel::KernelValidationError;
 
-const KERNEL_FILE_NAME: &str = "kernel-x86_64";
 const RAMDISK_FILE_NAME: &str = "ramdisk";
 const CONFIG_FILE_NAME: &str = "boot.json";
+#[cfg(feature = "uefi")]
+const DEFAULT_UEFI_BOOT_PATH: &str = "efi/boot/bootx64.efi";
 
 #[cfg(feature = "uefi")]
 const UEFI_BOOTLOADER: &[u8] = include_bytes!(env!("UEFI_BOOTLOADER_PATH"));
@@ -51,12 +61,25 @@ const BIOS_STAGE_2: &[u8] = include_bytes!(env!("BIOS_STAGE_2_PATH"));
 const BIOS_STAGE_3: &[u8] = include_bytes!(env!("BIOS_STAGE_3_PATH"));
 #[cfg(feature = "bios")]
 const BIOS_STAGE_4: &[u8] = include_bytes!(env!("BIOS_STAGE_4_PATH"));
+#[cfg(feature = "bios")]
+const BIOS_MULTIBOOT: &[u8] = include_bytes!(env!("BIOS_MULTIBOOT_PATH"));
 
 /// Allows creating disk images for a specified set of files.
 ///
-/// It can currently create `MBR` (BIOS), `GPT` (UEFI), and `TFTP` (UEFI) images.
+/// It can currently create `MBR` (BIOS), `GPT` (UEFI), and `TFTP` (UEFI) images, as well as
+/// folders for direct multiboot loading (BIOS) and coreboot payloads (BIOS).
 pub struct DiskImageBuilder {
     files: BTreeMap<Cow<'static, str>, FileDataSource>,
+    embedded_boot_config: Option<Vec<u8>>,
+    arch: Arch,
+    bios_partition_offset: u64,
+    incremental: bool,
+    #[cfg(feature = "bios")]
+    bios_second_stage_partition_type: u8,
+    #[cfg(feature = "uefi")]
+    uefi_boot_path: Cow<'static, str>,
+    #[cfg(feature = "uefi")]
+    uefi_files: BTreeMap<Cow<'static, str>, FileDataSource>,
 }
 
 impl DiskImageBuilder {
@@ -71,12 +94,126 @@ impl DiskImageBuilder {
     pub fn empty() -> Self {
         Self {
             files: BTreeMap::new(),
+            embedded_boot_config: None,
+            arch: Arch::default(),
+            bios_partition_offset: 0,
+            incremental: false,
+            #[cfg(feature = "bios")]
+            bios_second_stage_partition_type: mbr::DEFAULT_SECOND_STAGE_PARTITION_TYPE,
+            #[cfg(feature = "uefi")]
+            uefi_boot_path: DEFAULT_UEFI_BOOT_PATH.into(),
+            #[cfg(feature = "uefi")]
+            uefi_files: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the CPU architecture of the disk image.
+    ///
+    /// The architecture determines the file name of the kernel and the expected machine type
+    /// of the kernel ELF file, which is validated on image creation. Defaults to
+    /// [`Arch::X86_64`], which is currently the only supported architecture.
+    pub fn set_arch(&mut self, arch: Arch) -> &mut Self {
+        let kernel = self.files.remove(self.arch.kernel_file_name());
+        self.arch = arch;
+        if let Some(kernel) = kernel {
+            self.set_file_source(arch.kernel_file_name().into(), kernel);
         }
+        self
+    }
+
+    /// Sets the minimum byte offset of the FAT boot partition in BIOS disk images.
+    ///
+    /// By default, the boot partition directly follows the second stage of the bootloader.
+    /// Larger offsets are useful for testing disks on which the boot files are located at
+    /// high sector numbers. The space before the partition is left empty; on most file
+    /// systems it doesn't occupy any disk space. The partition must end below 2 TiB, which
+    /// is the limit of the 32-bit sector numbers of MBR partition tables.
+    pub fn set_bios_partition_offset(&mut self, offset: u64) -> &mut Self {
+        self.bios_partition_offset = offset;
+        self
+    }
+
+    /// Sets whether existing BIOS and UEFI disk images are updated in place.
+    ///
+    /// If enabled, [`create_bios_image`](Self::create_bios_image) and
+    /// [`create_uefi_image`](Self::create_uefi_image) open the FAT partition of an existing
+    /// image at the given path and only rewrite the files whose size or CRC changed. This
+    /// speeds up repeated builds with large ramdisks or files. If the existing image can't be
+    /// updated, e.g. because its partition is too small or it was created by a different
+    /// bootloader version, it is recreated from scratch. Other errors while updating the
+    /// image, e.g. I/O errors, are returned. Disabled by default.
+    pub fn set_incremental(&mut self, incremental: bool) -> &mut Self {
+        self.incremental = incremental;
+        self
+    }
+
+    #[cfg(feature = "bios")]
+    /// Sets the MBR partition type byte of the partition that contains the second stage of
+    /// the BIOS bootloader.
+    ///
+    /// Defaults to `0x20`. The bootloader doesn't interpret the type, it always loads the
+    /// second stage from the first partition. Changing the type is useful if the default
+    /// collides with the conventions of other tools on a shared disk. The type must not be
+    /// `0`, which marks unused partitions.
+    pub fn set_bios_second_stage_partition_type(&mut self, partition_type: u8) -> &mut Self {
+        self.bios_second_stage_partition_type = partition_type;
+        self
+    }
+
+    #[cfg(feature = "uefi")]
+    /// Sets the path of the bootloader executable on the EFI System Partition of UEFI disk
+    /// images.
+    ///
+    /// Defaults to `efi/boot/bootx64.efi`, the removable media path that UEFI firmware boots
+    /// without any boot entries. Other paths are useful for firmware that expects a vendor
+    /// specific layout, e.g. `EFI/vendor/loader.efi` together with a boot entry. The kernel,
+    /// ramdisk, and config files are always placed in the root directory of the partition.
+    pub fn set_uefi_boot_path(&mut self, path: &str) -> &mut Self {
+        self.uefi_boot_path = path.to_owned().into();
+        self
+    }
+
+    #[cfg(feature = "uefi")]
+    /// Adds an additional EFI application or driver at the given path of the EFI System
+    /// Partition of UEFI disk images.
+    ///
+    /// In contrast to [`set_file`](Self::set_file), the file is only added to UEFI disk
+    /// images, not to BIOS images or TFTP folders.
+    pub fn add_uefi_file(&mut self, destination: String, file_path: PathBuf) -> &mut Self {
+        self.uefi_files
+            .insert(destination.into(), FileDataSource::File(file_path));
+        self
     }
 
     /// Add or replace a kernel to be included in the final image.
     pub fn set_kernel(&mut self, path: PathBuf) -> &mut Self {
-        self.set_file_source(KERNEL_FILE_NAME.into(), FileDataSource::File(path))
+        self.set_file_source(
+            self.arch.kernel_file_name().into(),
+            FileDataSource::File(path),
+        )
+    }
+
+    /// Adds an additional kernel under the given file name.
+    ///
+    /// The bootloader boots the kernel that is selected through
+    /// [`set_default_kernel`](Self::set_default_kernel), or the kernel set through
+    /// [`set_kernel`](Self::set_kernel) if no kernel is selected. This allows shipping e.g.
+    /// a debug and a release kernel in the same image.
+    pub fn add_kernel(&mut self, file_name: String, path: PathBuf) -> &mut Self {
+        self.set_file_source(file_name.into(), FileDataSource::File(path))
+    }
+
+    /// Selects the kernel that the bootloader boots by its file name.
+    ///
+    /// The name is stored in a small `default-kernel` file, which the bootloader reads on
+    /// boot. The selected kernel can thus be changed later by rewriting only this file. The
+    /// kernel must be added through [`add_kernel`](Self::add_kernel), otherwise the image
+    /// creation fails.
+    pub fn set_default_kernel(&mut self, file_name: &str) -> &mut Self {
+        self.set_file_source(
+            default_kernel::FILE_NAME.into(),
+            FileDataSource::Data(file_name.as_bytes().to_vec()),
+        )
     }
 
     /// Add or replace a ramdisk to be included in the final image.
@@ -90,6 +227,18 @@ impl DiskImageBuilder {
         self.set_file_source(CONFIG_FILE_NAME.into(), FileDataSource::Data(json))
     }
 
+    /// Embeds the given config directly into the bootloader executable.
+    ///
+    /// In contrast to [`set_boot_config`](Self::set_boot_config), no `boot.json` file needs to
+    /// be read from disk at boot time. An embedded config takes precedence over a `boot.json`
+    /// file. The serialized config must not be larger than 2 KiB, otherwise the image creation
+    /// fails.
+    pub fn set_embedded_boot_config(&mut self, boot_config: &BootConfig) -> &mut Self {
+        let json = serde_json::to_vec(boot_config).expect("failed to serialize BootConfig");
+        self.embedded_boot_config = Some(json);
+        self
+    }
+
     /// Add a file with the specified bytes to the disk image
     ///
     /// Note that the bootloader only loads the kernel and ramdisk files into memory on boot.
@@ -112,17 +261,27 @@ impl DiskImageBuilder {
         const BIOS_STAGE_3_NAME: &str = "boot-stage-3";
         const BIOS_STAGE_4_NAME: &str = "boot-stage-4";
         let stage_3 = FileDataSource::Bytes(BIOS_STAGE_3);
-        let stage_4 = FileDataSource::Bytes(BIOS_STAGE_4);
+        let stage_4 = self.bootloader_executable(BIOS_STAGE_4)?;
         let mut internal_files = BTreeMap::new();
         internal_files.insert(BIOS_STAGE_3_NAME, stage_3);
         internal_files.insert(BIOS_STAGE_4_NAME, stage_4);
+        let files = self.fat_files(&internal_files)?;
+        if self.incremental
+            && self
+                .update_bios_image(&files, image_path)
+                .context("failed to update BIOS disk image in place")?
+        {
+            return Ok(());
+        }
         let fat_partition = self
-            .create_fat_filesystem_image(internal_files)
+            .create_fat_filesystem_image(files)
             .context("failed to create FAT partition")?;
         mbr::create_mbr_disk(
             BIOS_BOOT_SECTOR,
             BIOS_STAGE_2,
+            self.bios_second_stage_partition_type,
             fat_partition.path(),
+            self.bios_partition_offset,
             image_path,
         )
         .context("failed to create BIOS MBR disk image")?;
@@ -136,12 +295,28 @@ impl DiskImageBuilder {
     #[cfg(feature = "uefi")]
     /// Create a GPT disk image for booting on UEFI systems.
     pub fn create_uefi_image(&self, image_path: &Path) -> anyhow::Result<()> {
-        const UEFI_BOOT_FILENAME: &str = "efi/boot/bootx64.efi";
+        let boot_path = self.uefi_boot_path.trim_start_matches('/');
+        if boot_path.is_empty() || boot_path.ends_with('/') {
+            anyhow::bail!("invalid UEFI boot path `{}`", self.uefi_boot_path);
+        }
 
         let mut internal_files = BTreeMap::new();
-        internal_files.insert(UEFI_BOOT_FILENAME, FileDataSource::Bytes(UEFI_BOOTLOADER));
+        internal_files.insert(boot_path, self.bootloader_executable(UEFI_BOOTLOADER)?);
+        for (path, source) in &self.uefi_files {
+            if path.eq_ignore_ascii_case(boot_path) {
+                anyhow::bail!("UEFI file `{path}` collides with the bootloader executable");
+            }
+            internal_files.insert(path, source.clone());
+        }
+        let files = self.fat_files(&internal_files)?;
+        if self.incremental
+            && update_uefi_image(&files, image_path)
+                .context("failed to update UEFI disk image in place")?
+        {
+            return Ok(());
+        }
         let fat_partition = self
-            .create_fat_filesystem_image(internal_files)
+            .create_fat_filesystem_image(files)
             .context("failed to create FAT partition")?;
         gpt::create_gpt_disk(fat_partition.path(), image_path)
             .context("failed to create UEFI GPT disk image")?;
@@ -155,6 +330,8 @@ impl DiskImageBuilder {
     #[cfg(feature = "uefi")]
     /// Create a folder containing the needed files for UEFI TFTP/PXE booting.
     pub fn create_uefi_tftp_folder(&self, tftp_path: &Path) -> anyhow::Result<()> {
+        self.validate_kernel()?;
+
         use std::{fs, ops::Deref};
 
         const UEFI_TFTP_BOOT_FILENAME: &str = "bootloader";
@@ -162,7 +339,10 @@ impl DiskImageBuilder {
             .with_context(|| format!("failed to create out dir at {}", tftp_path.display()))?;
 
         let to = tftp_path.join(UEFI_TFTP_BOOT_FILENAME);
-        fs::write(&to, UEFI_BOOTLOADER).with_context(|| {
+        let bootloader = self.bootloader_executable(UEFI_BOOTLOADER)?;
+        let mut bootloader_file = fs::File::create(&to)
+            .with_context(|| format!("failed to create bootloader file at {}", to.display()))?;
+        bootloader.copy_to(&mut bootloader_file).with_context(|| {
             format!(
                 "failed to copy bootloader from the embedded binary to {}",
                 to.display()
@@ -185,6 +365,145 @@ impl DiskImageBuilder {
         Ok(())
     }
 
+    #[cfg(feature = "bios")]
+    /// Create a folder containing the needed files for loading the BIOS bootloader through
+    /// multiboot, e.g. using QEMU's `-kernel` argument.
+    ///
+    /// The `bootloader-multiboot` file needs to be passed as multiboot kernel. All other files
+    /// of the folder need to be passed as multiboot modules, using their file name as command
+    /// line. For QEMU, this is done through the comma-separated `-initrd` argument.
+    pub fn create_multiboot_folder(&self, multiboot_path: &Path) -> anyhow::Result<()> {
+        self.validate_kernel()?;
+
+        use std::{fs, ops::Deref};
+
+        const MULTIBOOT_FILENAME: &str = "bootloader-multiboot";
+        const BIOS_STAGE_3_NAME: &str = "boot-stage-3";
+        const BIOS_STAGE_4_NAME: &str = "boot-stage-4";
+        fs::create_dir_all(multiboot_path)
+            .with_context(|| format!("failed to create out dir at {}", multiboot_path.display()))?;
+
+        let internal_files = [
+            (MULTIBOOT_FILENAME, FileDataSource::Bytes(BIOS_MULTIBOOT)),
+            (BIOS_STAGE_3_NAME, FileDataSource::Bytes(BIOS_STAGE_3)),
+            (BIOS_STAGE_4_NAME, self.bootloader_executable(BIOS_STAGE_4)?),
+        ];
+        for (name, source) in &internal_files {
+            if self.files.contains_key(*name) {
+                return Err(anyhow::Error::msg(format!(
+                    "Attempted to overwrite internal file: {name}"
+                )));
+            }
+            let to = multiboot_path.join(name);
+            let mut file = fs::File::create(&to)
+                .with_context(|| format!("failed to create file at {}", to.display()))?;
+            source.copy_to(&mut file).with_context(|| {
+                format!(
+                    "failed to copy {name} from the embedded binary to {}",
+                    to.display()
+                )
+            })?;
+        }
+
+        for f in &self.files {
+            let to = multiboot_path.join(f.0.deref());
+
+            let mut new_file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(to)?;
+
+            f.1.copy_to(&mut new_file)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "bios")]
+    /// Create a coreboot payload that contains the BIOS bootloader and all files.
+    ///
+    /// The payload is a 32-bit ELF executable, which can be added to a coreboot image through
+    /// `cbfstool <rom> add-payload -f <payload> -n fallback/payload`. It can also be added as
+    /// secondary payload, e.g. as `img/bootloader`, so that it can be chosen from the SeaBIOS
+    /// boot menu.
+    ///
+    /// The payload is based on the multiboot stage, so it doesn't rely on BIOS services. The
+    /// file names are limited to 32 bytes and at most 16 files, including the two bootloader
+    /// stages, can be stored in a payload.
+    pub fn create_coreboot_payload(&self, payload_path: &Path) -> anyhow::Result<()> {
+        self.validate_kernel()?;
+
+        const BIOS_STAGE_3_NAME: &str = "boot-stage-3";
+        const BIOS_STAGE_4_NAME: &str = "boot-stage-4";
+        let internal_files = [
+            (BIOS_STAGE_3_NAME, FileDataSource::Bytes(BIOS_STAGE_3)),
+            (BIOS_STAGE_4_NAME, self.bootloader_executable(BIOS_STAGE_4)?),
+        ];
+        let mut files = Vec::new();
+        for (name, source) in &internal_files {
+            if self.files.contains_key(*name) {
+                return Err(anyhow::Error::msg(format!(
+                    "Attempted to overwrite internal file: {name}"
+                )));
+            }
+            let mut data = Vec::new();
+            source.copy_to(&mut data)?;
+            files.push((*name, data));
+        }
+        for (name, source) in &self.files {
+            let mut data = Vec::new();
+            source
+                .copy_to(&mut data)
+                .with_context(|| format!("failed to read file `{name}`"))?;
+            files.push((name, data));
+        }
+
+        let payload = coreboot::create_payload(BIOS_MULTIBOOT, &files)
+            .context("failed to create coreboot payload")?;
+        std::fs::write(payload_path, payload).with_context(|| {
+            format!(
+                "failed to write coreboot payload to {}",
+                payload_path.display()
+            )
+        })
+    }
+
+    /// Creates a GDB script that loads the symbols of the kernel at their runtime addresses.
+    ///
+    /// The script consists of an `add-symbol-file` command with the load offset of the kernel,
+    /// so it can be loaded through `gdb -x <path>` when debugging the kernel under QEMU.
+    ///
+    /// The load offset is only known at build time for kernels that are linked to fixed
+    /// virtual addresses, i.e. non-position-independent executables, which are always loaded
+    /// at their link-time addresses. Position-independent kernels are placed dynamically by
+    /// the bootloader, so this method returns an error for them. Such kernels can read their
+    /// load offset from [`BootInfo::kernel_image_offset`] at runtime instead.
+    ///
+    /// The kernel must be set through a file path, which is referenced by the script.
+    ///
+    /// [`BootInfo::kernel_image_offset`]: bootloader_api::BootInfo::kernel_image_offset
+    pub fn create_gdb_symbols_script(&self, script_path: &Path) -> anyhow::Result<()> {
+        let kernel = self
+            .files
+            .get(self.arch.kernel_file_name())
+            .context("no kernel was set")?;
+        let FileDataSource::File(kernel_path) = kernel else {
+            anyhow::bail!("the kernel must be set through a file path to create a GDB script");
+        };
+        let offset = kernel::fixed_load_offset(kernel)?;
+        let kernel_path = kernel_path.canonicalize().with_context(|| {
+            format!("failed to resolve kernel path `{}`", kernel_path.display())
+        })?;
+        let script = format!(
+            "# load offset of the kernel: {offset:#x}\nadd-symbol-file \"{}\" -o {offset:#x}\n",
+            kernel_path.display()
+        );
+        std::fs::write(script_path, script)
+            .with_context(|| format!("failed to write GDB script to {}", script_path.display()))
+    }
+
     /// Add a file source to the disk image
     fn set_file_source(
         &mut self,
@@ -195,18 +514,85 @@ impl DiskImageBuilder {
         self
     }
 
-    fn create_fat_filesystem_image(
-        &self,
-        internal_files: BTreeMap<&str, FileDataSource>,
-    ) -> anyhow::Result<NamedTempFile> {
+    /// Checks that the kernels of the disk image can be booted by this bootloader.
+    ///
+    /// This checks the kernel and, if one is [selected](Self::set_default_kernel), the default
+    /// kernel. A kernel must be an ELF file for the configured [architecture](Self::set_arch)
+    /// that contains a `.bootloader-config` section of a compatible `bootloader_api` version,
+    /// an entry point in an executable segment, and valid program headers. If a
+    /// [flat kernel](BootConfig::flat_kernel) is configured, kernels without ELF magic bytes
+    /// are booted as flat binaries and are not checked. This check is also
+    /// performed by all `create_*` methods, so that errors are reported at build time instead
+    /// of at boot time.
+    pub fn validate_kernel(&self) -> Result<(), KernelValidationError> {
+        let flat_kernel = self
+            .boot_config()
+            .is_some_and(|config| config.flat_kernel.is_some());
+        if let Some(kernel) = self.files.get(self.arch.kernel_file_name()) {
+            kernel::validate(self.arch, kernel, flat_kernel)?;
+        }
+        if let Some(source) = self.files.get(default_kernel::FILE_NAME) {
+            let mut contents = Vec::new();
+            source
+                .copy_to(&mut contents)
+                .map_err(KernelValidationError::Read)?;
+            let name = default_kernel::parse(&contents)
+                .ok_or(KernelValidationError::InvalidDefaultKernelFile)?;
+            let kernel = self
+                .files
+                .get(name)
+                .ok_or_else(|| KernelValidationError::MissingDefaultKernel(name.into()))?;
+            kernel::validate(self.arch, kernel, flat_kernel).map_err(|error| {
+                KernelValidationError::DefaultKernel {
+                    name: name.into(),
+                    error: Box::new(error),
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns the boot config that the bootloader will use, if any.
+    ///
+    /// Like in the bootloader, an embedded config is preferred over the `boot.json` file.
+    fn boot_config(&self) -> Option<BootConfig> {
+        let json = match (&self.embedded_boot_config, self.files.get(CONFIG_FILE_NAME)) {
+            (Some(json), _) => json.clone(),
+            (None, Some(source)) => {
+                let mut json = Vec::new();
+                source.copy_to(&mut json).ok()?;
+                json
+            }
+            (None, None) => return None,
+        };
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Returns the given bootloader executable with the embedded boot config filled in.
+    fn bootloader_executable(&self, executable: &'static [u8]) -> anyhow::Result<FileDataSource> {
+        match &self.embedded_boot_config {
+            Some(json) => embed_boot_config(executable, json).map(FileDataSource::Data),
+            None => Ok(FileDataSource::Bytes(executable)),
+        }
+    }
+
+    /// Returns all files of the FAT partition, including the given bootloader files.
+    fn fat_files<'a>(
+        &'a self,
+        internal_files: &'a BTreeMap<&'a str, FileDataSource>,
+    ) -> anyhow::Result<BTreeMap<&'a str, &'a FileDataSource>> {
+        self.validate_kernel()?;
+
         let mut local_map: BTreeMap<&str, _> = BTreeMap::new();
 
         for (name, source) in &self.files {
             local_map.insert(name, source);
         }
 
-        for k in &internal_files {
-            if local_map.insert(k.0, k.1).is_some() {
+        for k in internal_files {
+            // FAT file names are case-insensitive
+            let collides = local_map.keys().any(|name| name.eq_ignore_ascii_case(k.0));
+            if collides || local_map.insert(k.0, k.1).is_some() {
                 return Err(anyhow::Error::msg(format!(
                     "Attempted to overwrite internal file: {}",
                     k.0
@@ -214,10 +600,116 @@ impl DiskImageBuilder {
             }
         }
 
+        Ok(local_map)
+    }
+
+    fn create_fat_filesystem_image(
+        &self,
+        files: BTreeMap<&str, &FileDataSource>,
+    ) -> anyhow::Result<NamedTempFile> {
         let out_file = NamedTempFile::new().context("failed to create temp file")?;
-        fat::create_fat_filesystem(local_map, out_file.path())
+        fat::create_fat_filesystem(files, self.arch.kernel_file_name(), out_file.path())
             .context("failed to create FAT filesystem")?;
 
         Ok(out_file)
     }
+
+    /// Updates the FAT partition of an existing BIOS disk image in place.
+    ///
+    /// Returns `Ok(false)` without modifying the image if there is no image at the given
+    /// path or if it can't be updated, so that it needs to be recreated.
+    #[cfg(feature = "bios")]
+    fn update_bios_image(
+        &self,
+        files: &BTreeMap<&str, &FileDataSource>,
+        image_path: &Path,
+    ) -> anyhow::Result<bool> {
+        let Some(mut image) = open_existing_image(image_path)? else {
+            return Ok(false);
+        };
+        // the image was created with different bootloader binaries or settings
+        let Ok((start, len)) = mbr::boot_partition_range(
+            &mut image,
+            BIOS_BOOT_SECTOR,
+            BIOS_STAGE_2,
+            self.bios_second_stage_partition_type,
+            self.bios_partition_offset,
+        ) else {
+            return Ok(false);
+        };
+        fat::update_fat_filesystem(files, &image, start, len)
+    }
+}
+
+/// Updates the FAT partition of an existing UEFI disk image in place.
+///
+/// Returns `Ok(false)` without modifying the image if there is no image at the given path or
+/// if it can't be updated, so that it needs to be recreated.
+#[cfg(feature = "uefi")]
+fn update_uefi_image(
+    files: &BTreeMap<&str, &FileDataSource>,
+    image_path: &Path,
+) -> anyhow::Result<bool> {
+    let Some(image) = open_existing_image(image_path)? else {
+        return Ok(false);
+    };
+    // the image is no GPT disk image with an EFI system partition
+    let Ok((start, len)) = gpt::efi_partition_range(image_path) else {
+        return Ok(false);
+    };
+    fat::update_fat_filesystem(files, &image, start, len)
+}
+
+/// Opens the disk image at the given path for updating it, or returns `None` if it doesn't
+/// exist.
+#[cfg(any(feature = "bios", feature = "uefi"))]
+fn open_existing_image(image_path: &Path) -> anyhow::Result<Option<std::fs::File>> {
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(image_path)
+    {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err)
+            .with_context(|| format!("failed to open disk image `{}`", image_path.display())),
+    }
+}
+
+/// Writes the given JSON config into the space that is reserved for it in the bootloader
+/// executable.
+fn embed_boot_config(executable: &[u8], json: &[u8]) -> anyhow::Result<Vec<u8>> {
+    use bootloader_boot_config::embedded;
+
+    if json.len() > embedded::MAX_LEN {
+        anyhow::bail!(
+            "serialized boot config is too large to embed ({} bytes, maximum is {} bytes)",
+            json.len(),
+            embedded::MAX_LEN
+        );
+    }
+
+    let mut matches = executable
+        .windows(embedded::MAGIC.len())
+        .enumerate()
+        .filter(|(_, window)| *window == embedded::MAGIC)
+        .map(|(offset, _)| offset);
+    let start = matches
+        .next()
+        .context("bootloader executable has no space reserved for an embedded boot config")?;
+    if matches.next().is_some() {
+        anyhow::bail!("bootloader executable reserves space for multiple boot configs");
+    }
+    let reserved = executable
+        .get(start..start + embedded::SIZE)
+        .context("reserved boot config space is truncated")?;
+    if reserved[embedded::LEN_OFFSET..].iter().any(|&b| b != 0) {
+        anyhow::bail!("bootloader executable already contains an embedded boot config");
+    }
+
+    let mut patched = executable.to_vec();
+    let len = u32::try_from(json.len()).unwrap();
+    patched[start + embedded::LEN_OFFSET..][..4].copy_from_slice(&len.to_le_bytes());
+    patched[start + embedded::DATA_OFFSET..][..json.len()].copy_from_slice(json);
+    Ok(patched)
 }
@@ -2,16 +2,24 @@ use anyhow::Context;
 use mbrman::BOOT_ACTIVE;
 use std::{
     fs::{self, File},
-    io::{self, Seek, SeekFrom},
+    io::{self, Read, Seek, SeekFrom},
     path::Path,
 };
 
 const SECTOR_SIZE: u32 = 512;
 
+/// The default partition type of the second stage partition.
+///
+/// The type is not interpreted by the bootloader, which always loads the second stage from
+/// the first partition.
+pub const DEFAULT_SECOND_STAGE_PARTITION_TYPE: u8 = 0x20;
+
 pub fn create_mbr_disk(
     bootsector_binary: &[u8],
     second_stage_binary: &[u8],
+    second_stage_partition_type: u8,
     boot_partition_path: &Path,
+    boot_partition_offset: u64,
     out_mbr_path: &Path,
 ) -> anyhow::Result<()> {
     use std::io::Cursor;
@@ -26,36 +34,26 @@ pub fn create_mbr_disk(
     }
 
     let mut second_stage = Cursor::new(second_stage_binary);
-    let second_stage_size = second_stage_binary.len() as u64;
-
-    let second_stage_start_sector = 1;
-    let second_stage_sectors = ((second_stage_size - 1) / u64::from(SECTOR_SIZE) + 1)
-        .try_into()
-        .context("size of second stage is larger than u32::MAX")?;
-    mbr[1] = mbrman::MBRPartitionEntry {
-        boot: BOOT_ACTIVE,
-        starting_lba: second_stage_start_sector,
-        sectors: second_stage_sectors,
-        // see BOOTLOADER_SECOND_STAGE_PARTITION_TYPE in `boot_sector` crate
-        sys: 0x20,
-
-        first_chs: mbrman::CHS::empty(),
-        last_chs: mbrman::CHS::empty(),
-    };
+    mbr[1] = second_stage_partition(second_stage_binary, second_stage_partition_type)?;
+    let second_stage_start_sector = mbr[1].starting_lba;
 
     let mut boot_partition =
         File::open(boot_partition_path).context("failed to open FAT boot partition")?;
-    let boot_partition_start_sector = second_stage_start_sector + second_stage_sectors;
+    let boot_partition_start_sector = boot_partition_start_sector(&mbr[1], boot_partition_offset)?;
     let boot_partition_size = boot_partition
         .metadata()
         .context("failed to read file metadata of FAT boot partition")?
         .len();
+    let boot_partition_sectors: u32 = ((boot_partition_size - 1) / u64::from(SECTOR_SIZE) + 1)
+        .try_into()
+        .context("size of FAT partition is larger than u32::MAX")?;
+    boot_partition_start_sector
+        .checked_add(boot_partition_sectors)
+        .context("end sector of FAT partition is larger than u32::MAX")?;
     mbr[2] = mbrman::MBRPartitionEntry {
         boot: BOOT_ACTIVE,
         starting_lba: boot_partition_start_sector,
-        sectors: ((boot_partition_size - 1) / u64::from(SECTOR_SIZE) + 1)
-            .try_into()
-            .context("size of FAT partition is larger than u32::MAX")?,
+        sectors: boot_partition_sectors,
         //TODO: is this the correct type?
         sys: 0x0c, // FAT32 with LBA
 
@@ -83,14 +81,14 @@ pub fn create_mbr_disk(
     assert_eq!(
         disk.stream_position()
             .context("failed to get disk image seek position")?,
-        u64::from(second_stage_start_sector * SECTOR_SIZE)
+        u64::from(second_stage_start_sector) * u64::from(SECTOR_SIZE)
     );
     io::copy(&mut second_stage, &mut disk)
         .context("failed to copy second stage binary to MBR disk image")?;
 
-    // fat partition
+    // fat partition (the gap before it stays a sparse hole in the image file)
     disk.seek(SeekFrom::Start(
-        (boot_partition_start_sector * SECTOR_SIZE).into(),
+        u64::from(boot_partition_start_sector) * u64::from(SECTOR_SIZE),
     ))
     .context("seek failed")?;
     io::copy(&mut boot_partition, &mut disk)
@@ -98,3 +96,95 @@ pub fn create_mbr_disk(
 
     Ok(())
 }
+
+/// Returns the byte offset and length of the FAT boot partition of an existing MBR disk
+/// image, so that its files can be updated in place.
+///
+/// Fails if the boot sector, the second stage, or the partition layout of the image differ
+/// from the ones that [`create_mbr_disk`] would create with the given arguments.
+pub fn boot_partition_range(
+    disk: &mut File,
+    bootsector_binary: &[u8],
+    second_stage_binary: &[u8],
+    second_stage_partition_type: u8,
+    boot_partition_offset: u64,
+) -> anyhow::Result<(u64, u64)> {
+    let sector_size = usize::try_from(SECTOR_SIZE).unwrap();
+    let mbr = mbrman::MBR::read_from(disk, SECTOR_SIZE).context("failed to read MBR")?;
+    // everything except the partition table must match the boot sector binary
+    let mut boot_sector = vec![0; sector_size];
+    disk.seek(SeekFrom::Start(0)).context("seek failed")?;
+    disk.read_exact(&mut boot_sector)
+        .context("failed to read boot sector")?;
+    let partition_table = 446..510;
+    if boot_sector[..partition_table.start] != bootsector_binary[..partition_table.start]
+        || boot_sector[partition_table.end..] != bootsector_binary[partition_table.end..sector_size]
+    {
+        anyhow::bail!("boot sector of disk image differs");
+    }
+
+    let second_stage = second_stage_partition(second_stage_binary, second_stage_partition_type)?;
+    if mbr[1] != second_stage {
+        anyhow::bail!("second stage partition of disk image differs");
+    }
+    let mut existing_second_stage = vec![0; second_stage_binary.len()];
+    disk.seek(SeekFrom::Start(
+        u64::from(second_stage.starting_lba) * u64::from(SECTOR_SIZE),
+    ))
+    .context("seek failed")?;
+    disk.read_exact(&mut existing_second_stage)
+        .context("failed to read second stage from disk image")?;
+    if existing_second_stage != second_stage_binary {
+        anyhow::bail!("second stage of disk image differs");
+    }
+
+    let boot_partition = &mbr[2];
+    if boot_partition.is_unused()
+        || boot_partition.starting_lba
+            != boot_partition_start_sector(&second_stage, boot_partition_offset)?
+    {
+        anyhow::bail!("FAT partition of disk image is at a different position");
+    }
+    Ok((
+        u64::from(boot_partition.starting_lba) * u64::from(SECTOR_SIZE),
+        u64::from(boot_partition.sectors) * u64::from(SECTOR_SIZE),
+    ))
+}
+
+fn second_stage_partition(
+    second_stage_binary: &[u8],
+    second_stage_partition_type: u8,
+) -> anyhow::Result<mbrman::MBRPartitionEntry> {
+    if second_stage_partition_type == 0 {
+        anyhow::bail!(
+            "partition type 0 marks unused partitions, so it can't be used for the second stage"
+        );
+    }
+
+    let second_stage_size = second_stage_binary.len() as u64;
+    let second_stage_sectors = ((second_stage_size - 1) / u64::from(SECTOR_SIZE) + 1)
+        .try_into()
+        .context("size of second stage is larger than u32::MAX")?;
+    Ok(mbrman::MBRPartitionEntry {
+        boot: BOOT_ACTIVE,
+        starting_lba: 1,
+        sectors: second_stage_sectors,
+        sys: second_stage_partition_type,
+
+        first_chs: mbrman::CHS::empty(),
+        last_chs: mbrman::CHS::empty(),
+    })
+}
+
+fn boot_partition_start_sector(
+    second_stage: &mbrman::MBRPartitionEntry,
+    boot_partition_offset: u64,
+) -> anyhow::Result<u32> {
+    // MBR partition entries use 32-bit sector numbers, so the partition must end below 2 TiB
+    u64::max(
+        u64::from(second_stage.starting_lba + second_stage.sectors),
+        boot_partition_offset.div_ceil(u64::from(SECTOR_SIZE)),
+    )
+    .try_into()
+    .context("start sector of FAT partition is larger than u32::MAX")
+}
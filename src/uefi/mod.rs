@@ -29,6 +29,17 @@ impl UefiBoot {
         self
     }
 
+    /// Embeds the given config into the bootloader executable instead of creating a `boot.json`
+    /// file.
+    ///
+    /// This avoids reading the config from disk at boot time. See
+    /// [`DiskImageBuilder::set_embedded_boot_config`](crate::DiskImageBuilder::set_embedded_boot_config)
+    /// for details.
+    pub fn embed_boot_config(&mut self, config: &BootConfig) -> &mut Self {
+        self.image_builder.set_embedded_boot_config(config);
+        self
+    }
+
     /// Create a bootable UEFI disk image at the given path.
     pub fn create_disk_image(&self, out_path: &Path) -> anyhow::Result<()> {
         self.image_builder.create_uefi_image(out_path)
@@ -1,4 +1,4 @@
-use bootloader_test_runner::run_test_kernel_internal;
+use bootloader_test_runner::{run_test_kernel_internal, run_test_kernel_with_embedded_config};
 
 use bootloader::BootConfig;
 
@@ -23,3 +23,26 @@ fn custom_boot_config() {
         Some(&config),
     );
 }
+
+#[test]
+fn rsdp_override() {
+    let mut config = BootConfig::default();
+    config.rsdp_addr = Some(0x1234_5000);
+    run_test_kernel_internal(
+        env!("CARGO_BIN_FILE_TEST_KERNEL_CONFIG_FILE_rsdp_override"),
+        None,
+        Some(&config),
+    );
+}
+
+#[test]
+fn embedded_boot_config() {
+    let mut config = BootConfig::default();
+    config.frame_buffer_logging = false;
+    config.serial_logging = true;
+    config._test_sentinel = 0xb001b001b001;
+    run_test_kernel_with_embedded_config(
+        env!("CARGO_BIN_FILE_TEST_KERNEL_CONFIG_FILE_custom_config"),
+        &config,
+    );
+}
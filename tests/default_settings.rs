@@ -1,4 +1,4 @@
-use bootloader_test_runner::run_test_kernel;
+use bootloader_test_runner::{run_test_kernel, run_test_kernel_with_stage_markers};
 
 #[test]
 fn basic_boot() {
@@ -20,3 +20,18 @@ fn check_boot_info() {
         "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_check_boot_info"
     ));
 }
+
+#[test]
+fn stage_markers() {
+    run_test_kernel_with_stage_markers(
+        env!("CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"),
+        &[
+            "config_loaded",
+            "kernel_loaded",
+            "memory_map_done",
+            "mappings_done",
+            "boot_info_done",
+            "handoff",
+        ],
+    );
+}
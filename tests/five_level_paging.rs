@@ -0,0 +1,10 @@
+use bootloader_test_runner::run_test_kernel_with_qemu_args;
+
+#[test]
+fn check_five_level_paging() {
+    // QEMU's default CPU model doesn't support 5-level paging
+    run_test_kernel_with_qemu_args(
+        env!("CARGO_BIN_FILE_TEST_KERNEL_FIVE_LEVEL_PAGING_check_five_level_paging"),
+        &["-cpu", "qemu64,+la57"],
+    );
+}
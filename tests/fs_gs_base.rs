@@ -0,0 +1,8 @@
+use bootloader_test_runner::run_test_kernel;
+
+#[test]
+fn check_fs_gs_base() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_FS_GS_BASE_check_fs_gs_base"
+    ));
+}
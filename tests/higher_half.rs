@@ -23,3 +23,15 @@ fn verify_higher_half() {
         "CARGO_BIN_FILE_TEST_KERNEL_HIGHER_HALF_verify_higher_half"
     ));
 }
+
+#[test]
+fn fixed_boot_info() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_HIGHER_HALF_fixed_boot_info"
+    ));
+}
+
+#[test]
+fn boot_time() {
+    run_test_kernel(env!("CARGO_BIN_FILE_TEST_KERNEL_HIGHER_HALF_boot_time"));
+}
@@ -0,0 +1,336 @@
+use bootloader::{inspect, BootConfig, DiskImageBuilder, FlatKernel, KernelValidationError};
+use std::path::Path;
+
+static RAMDISK_PATH: &str = "tests/ramdisk.txt";
+
+fn image_builder(kernel_path: &Path) -> DiskImageBuilder {
+    let mut image_builder = DiskImageBuilder::new(kernel_path.to_owned());
+    image_builder.set_ramdisk(RAMDISK_PATH.into());
+    image_builder.set_boot_config(&BootConfig::default());
+    image_builder.set_file_contents("modules/test.bin".into(), vec![0xaa; 1000]);
+    image_builder
+}
+
+fn check_files(files: &std::collections::BTreeMap<String, u64>, kernel_path: &Path) {
+    let kernel_len = std::fs::metadata(kernel_path).unwrap().len();
+    let ramdisk_len = std::fs::metadata(RAMDISK_PATH).unwrap().len();
+    assert_eq!(files.get("kernel-x86_64"), Some(&kernel_len));
+    assert_eq!(files.get("ramdisk"), Some(&ramdisk_len));
+    assert_eq!(files.get("modules/test.bin"), Some(&1000));
+    assert!(files.contains_key("boot.json"));
+}
+
+#[test]
+fn bios_image_contents() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let mbr_path = kernel_path.with_extension("inspect.mbr");
+    image_builder(kernel_path)
+        .create_bios_image(&mbr_path)
+        .unwrap();
+
+    let files = inspect::list_bios_image_files(&mbr_path).unwrap();
+    check_files(&files, kernel_path);
+    assert!(files.contains_key("boot-stage-3"));
+    assert!(files.contains_key("boot-stage-4"));
+}
+
+#[test]
+fn coreboot_payload_contents() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let payload_path = kernel_path.with_extension("inspect.elf");
+    image_builder(kernel_path)
+        .create_coreboot_payload(&payload_path)
+        .unwrap();
+
+    let files = inspect::list_coreboot_payload_files(&payload_path).unwrap();
+    check_files(&files, kernel_path);
+    assert!(files.contains_key("boot-stage-3"));
+    assert!(files.contains_key("boot-stage-4"));
+}
+
+#[test]
+fn uefi_image_contents() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let gpt_path = kernel_path.with_extension("inspect.gpt");
+    image_builder(kernel_path)
+        .create_uefi_image(&gpt_path)
+        .unwrap();
+
+    let files = inspect::list_uefi_image_files(&gpt_path).unwrap();
+    check_files(&files, kernel_path);
+    assert!(files.contains_key("efi/boot/bootx64.efi"));
+}
+
+#[test]
+fn default_kernel_selection() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let debug_kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_check_boot_info"
+    ));
+    let gpt_path = kernel_path.with_extension("default-kernel.gpt");
+
+    let mut image_builder = image_builder(kernel_path);
+    image_builder.add_kernel("kernel-debug".into(), debug_kernel_path.to_owned());
+    image_builder.set_default_kernel("kernel-missing");
+    assert!(image_builder.create_uefi_image(&gpt_path).is_err());
+
+    image_builder.set_default_kernel("kernel-debug");
+    image_builder.create_uefi_image(&gpt_path).unwrap();
+    let files = inspect::list_uefi_image_files(&gpt_path).unwrap();
+    let debug_kernel_len = std::fs::metadata(debug_kernel_path).unwrap().len();
+    assert_eq!(files.get("kernel-debug"), Some(&debug_kernel_len));
+    assert_eq!(files.get("default-kernel"), Some(&12));
+}
+
+#[test]
+fn custom_uefi_layout() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let gpt_path = kernel_path.with_extension("custom-layout.gpt");
+
+    let mut image_builder = image_builder(kernel_path);
+    image_builder.set_uefi_boot_path("EFI/vendor/loader.efi");
+    image_builder.add_uefi_file("EFI/vendor/driver.efi".into(), RAMDISK_PATH.into());
+    image_builder.create_uefi_image(&gpt_path).unwrap();
+
+    let files = inspect::list_uefi_image_files(&gpt_path).unwrap();
+    check_files(&files, kernel_path);
+    assert!(files.contains_key("EFI/vendor/loader.efi"));
+    assert!(files.contains_key("EFI/vendor/driver.efi"));
+    assert!(!files.contains_key("efi/boot/bootx64.efi"));
+
+    // the UEFI files are not part of BIOS images
+    let mbr_path = kernel_path.with_extension("custom-layout.mbr");
+    image_builder.create_bios_image(&mbr_path).unwrap();
+    let files = inspect::list_bios_image_files(&mbr_path).unwrap();
+    assert!(!files.contains_key("EFI/vendor/driver.efi"));
+
+    // files must not collide with the bootloader executable
+    image_builder.add_uefi_file("efi/vendor/LOADER.EFI".into(), RAMDISK_PATH.into());
+    assert!(image_builder.create_uefi_image(&gpt_path).is_err());
+}
+
+#[test]
+fn incompatible_api_version() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    const CONFIG_UUID: [u8; 16] = [
+        0x74, 0x3C, 0xA9, 0x61, 0x09, 0x36, 0x46, 0xA0, 0xBB, 0x55, 0x5C, 0x15, 0x89, 0x15, 0x25,
+        0x3D,
+    ];
+
+    // change the major version of the serialized `BootloaderConfig`
+    let mut kernel = std::fs::read(kernel_path).unwrap();
+    let config_start = kernel
+        .windows(CONFIG_UUID.len())
+        .position(|w| w == CONFIG_UUID)
+        .unwrap();
+    let major = config_start + CONFIG_UUID.len();
+    kernel[major..major + 2].copy_from_slice(&0xfffe_u16.to_le_bytes());
+    let patched_kernel_path = kernel_path.with_extension("old-api");
+    std::fs::write(&patched_kernel_path, kernel).unwrap();
+
+    let gpt_path = kernel_path.with_extension("old-api.gpt");
+    let err = image_builder(&patched_kernel_path)
+        .create_uefi_image(&gpt_path)
+        .unwrap_err();
+    let message = format!("{err:#}");
+    assert!(message.contains("65534."), "{message}");
+    assert!(message.contains("bootloader_api"), "{message}");
+}
+
+#[test]
+fn invalid_config() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    const CONFIG_UUID: [u8; 16] = [
+        0x74, 0x3C, 0xA9, 0x61, 0x09, 0x36, 0x46, 0xA0, 0xBB, 0x55, 0x5C, 0x15, 0x89, 0x15, 0x25,
+        0x3D,
+    ];
+
+    // keep the version, but write an invalid variant for the kernel stack mapping, which
+    // follows the version and the kernel stack size
+    let mut kernel = std::fs::read(kernel_path).unwrap();
+    let config_start = kernel
+        .windows(CONFIG_UUID.len())
+        .position(|w| w == CONFIG_UUID)
+        .unwrap();
+    kernel[config_start + CONFIG_UUID.len() + 7 + 8] = 0xff;
+    let patched_kernel_path = kernel_path.with_extension("invalid-config");
+    std::fs::write(&patched_kernel_path, kernel).unwrap();
+
+    let err = image_builder(&patched_kernel_path)
+        .validate_kernel()
+        .unwrap_err();
+    assert!(
+        matches!(err, KernelValidationError::InvalidConfig(_)),
+        "{err}"
+    );
+}
+
+#[test]
+fn validate_kernel() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    image_builder(kernel_path).validate_kernel().unwrap();
+
+    let err = image_builder(Path::new(RAMDISK_PATH))
+        .validate_kernel()
+        .unwrap_err();
+    assert!(matches!(err, KernelValidationError::NotElf), "{err}");
+
+    // a selected default kernel is validated too
+    let mut image_builder = image_builder(kernel_path);
+    image_builder.add_kernel("kernel-text".into(), RAMDISK_PATH.into());
+    image_builder.set_default_kernel("kernel-text");
+    let err = image_builder.validate_kernel().unwrap_err();
+    assert!(
+        matches!(&err, KernelValidationError::DefaultKernel { name, .. } if name == "kernel-text"),
+        "{err}"
+    );
+}
+
+#[test]
+fn gdb_symbols_script() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_FIXED_LOW_ADDRESS_verify_fixed_low_address"
+    ));
+    let script_path = kernel_path.with_extension("gdb");
+    image_builder(kernel_path)
+        .create_gdb_symbols_script(&script_path)
+        .unwrap();
+    let script = std::fs::read_to_string(&script_path).unwrap();
+    let kernel_path = kernel_path.canonicalize().unwrap();
+    assert!(script.contains(&format!(
+        "add-symbol-file \"{}\" -o 0x0",
+        kernel_path.display()
+    )));
+
+    // the load offset of position-independent kernels is chosen at boot time
+    let pie_kernel_path = Path::new(env!("CARGO_BIN_FILE_TEST_KERNEL_PIE_basic_boot"));
+    assert!(image_builder(pie_kernel_path)
+        .create_gdb_symbols_script(&script_path)
+        .is_err());
+}
+
+/// Writes a marker into a part of the given disk image that only a full rebuild resets.
+///
+/// The builder truncates the image file when recreating it, so the marker is lost unless the
+/// image is updated in place.
+fn write_marker(image_path: &Path, offset: u64) {
+    use std::{
+        fs::OpenOptions,
+        io::{Seek, SeekFrom, Write},
+    };
+
+    let mut image = OpenOptions::new().write(true).open(image_path).unwrap();
+    image.seek(SeekFrom::Start(offset)).unwrap();
+    image.write_all(b"MARK").unwrap();
+}
+
+fn has_marker(image_path: &Path, offset: u64) -> bool {
+    let image = std::fs::read(image_path).unwrap();
+    image.get(offset as usize..offset as usize + 4) == Some(b"MARK")
+}
+
+#[test]
+fn incremental_update() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let gpt_path = kernel_path.with_extension("incremental.gpt");
+    let mut builder = image_builder(kernel_path);
+    // the files of the update need less space, so that they fit into the existing partition
+    builder.set_file_contents("modules/stale.bin".into(), vec![0xbb; 4000]);
+    builder.create_uefi_image(&gpt_path).unwrap();
+    // the boot code of the protective MBR is not used by UEFI images
+    write_marker(&gpt_path, 0);
+
+    let mut builder = image_builder(kernel_path);
+    builder
+        .set_incremental(true)
+        .set_file_contents("modules/test.bin".into(), vec![0xcc; 3000]);
+    builder.create_uefi_image(&gpt_path).unwrap();
+
+    assert!(has_marker(&gpt_path, 0), "image was not updated in place");
+    let files = inspect::list_uefi_image_files(&gpt_path).unwrap();
+    assert_eq!(files.get("modules/test.bin"), Some(&3000));
+    assert!(!files.contains_key("modules/stale.bin"));
+    assert!(files.contains_key("efi/boot/bootx64.efi"));
+
+    builder.set_incremental(false);
+    builder.create_uefi_image(&gpt_path).unwrap();
+    assert!(!has_marker(&gpt_path, 0));
+}
+
+#[test]
+fn incremental_bios_update() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let mbr_path = kernel_path.with_extension("incremental.mbr");
+    let mut builder = image_builder(kernel_path);
+    // the files of the update need less space, so that they fit into the existing partition
+    builder.set_file_contents("modules/stale.bin".into(), vec![0xbb; 4000]);
+    builder.create_bios_image(&mbr_path).unwrap();
+    // append the marker behind the FAT partition
+    let marker_offset = std::fs::metadata(&mbr_path).unwrap().len();
+    write_marker(&mbr_path, marker_offset);
+
+    let mut builder = image_builder(kernel_path);
+    builder
+        .set_incremental(true)
+        .set_file_contents("modules/test.bin".into(), vec![0xcc; 3000]);
+    builder.create_bios_image(&mbr_path).unwrap();
+
+    assert!(
+        has_marker(&mbr_path, marker_offset),
+        "image was not updated in place"
+    );
+    let files = inspect::list_bios_image_files(&mbr_path).unwrap();
+    assert_eq!(files.get("modules/test.bin"), Some(&3000));
+    assert!(!files.contains_key("modules/stale.bin"));
+    assert!(files.contains_key("boot-stage-4"));
+
+    builder.set_incremental(false);
+    builder.create_bios_image(&mbr_path).unwrap();
+    assert!(!has_marker(&mbr_path, marker_offset));
+}
+
+#[test]
+fn flat_kernel_image() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ))
+    .with_extension("flat.bin");
+    // `cli; hlt; jmp $-2`
+    std::fs::write(&kernel_path, [0xfa, 0xf4, 0xeb, 0xfd]).unwrap();
+
+    let mut image_builder = image_builder(&kernel_path);
+    let err = image_builder.validate_kernel().unwrap_err();
+    assert!(matches!(err, KernelValidationError::NotElf), "{err}");
+
+    let mut boot_config = BootConfig::default();
+    boot_config.flat_kernel = Some(FlatKernel {
+        load_addr: 0xffff_8000_0000_0000,
+        entry_offset: 0,
+    });
+    image_builder.set_boot_config(&boot_config);
+    let gpt_path = kernel_path.with_extension("gpt");
+    image_builder.create_uefi_image(&gpt_path).unwrap();
+
+    let files = inspect::list_uefi_image_files(&gpt_path).unwrap();
+    assert_eq!(files.get("kernel-x86_64"), Some(&4));
+}
@@ -0,0 +1,23 @@
+use bootloader::DiskImageBuilder;
+use bootloader_test_runner::run_test_kernel_on_bios;
+use std::path::Path;
+
+/// Places the boot partition behind the first 4 GiB of the disk, so that the byte offsets of
+/// the boot files don't fit into 32 bits.
+const PARTITION_OFFSET: u64 = 5 << 30;
+
+#[test]
+fn boot_partition_at_high_lba() {
+    let kernel_path = Path::new(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_DEFAULT_SETTINGS_basic_boot"
+    ));
+    let mut image_builder = DiskImageBuilder::new(kernel_path.to_owned());
+    image_builder.set_bios_partition_offset(PARTITION_OFFSET);
+
+    // the image file is sparse, so the empty space before the partition takes no disk space
+    let mbr_path = kernel_path.with_extension("large.mbr");
+    image_builder.create_bios_image(&mbr_path).unwrap();
+    assert!(std::fs::metadata(&mbr_path).unwrap().len() > PARTITION_OFFSET);
+
+    run_test_kernel_on_bios(&mbr_path, &[]);
+}
@@ -0,0 +1,8 @@
+use bootloader_test_runner::run_test_kernel;
+
+#[test]
+fn many_segments() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_MANY_SEGMENTS_many_segments"
+    ));
+}
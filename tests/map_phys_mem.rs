@@ -13,3 +13,17 @@ fn access_phys_mem() {
         "CARGO_BIN_FILE_TEST_KERNEL_MAP_PHYS_MEM_access_phys_mem"
     ));
 }
+
+#[test]
+fn check_phys_mem_mapping() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_MAP_PHYS_MEM_check_phys_mem_mapping"
+    ));
+}
+
+#[test]
+fn framebuffer_in_phys_mem() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_MAP_PHYS_MEM_framebuffer_in_phys_mem"
+    ));
+}
@@ -0,0 +1,8 @@
+use bootloader_test_runner::run_test_kernel;
+
+#[test]
+fn verify_physical_load_address() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_PHYSICAL_LOAD_ADDRESS_verify_physical_load_address"
+    ));
+}
@@ -26,3 +26,11 @@ fn memory_map() {
         Some(Path::new(RAMDISK_PATH)),
     );
 }
+
+#[test]
+fn lazy_ramdisk() {
+    run_test_kernel_with_ramdisk(
+        env!("CARGO_BIN_FILE_TEST_KERNEL_RAMDISK_lazy_ramdisk"),
+        Some(Path::new(RAMDISK_PATH)),
+    );
+}
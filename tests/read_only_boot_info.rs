@@ -0,0 +1,8 @@
+use bootloader_test_runner::run_test_kernel;
+
+#[test]
+fn check_read_only_boot_info() {
+    run_test_kernel(env!(
+        "CARGO_BIN_FILE_TEST_KERNEL_READ_ONLY_BOOT_INFO_check_read_only_boot_info"
+    ));
+}
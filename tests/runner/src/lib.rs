@@ -28,6 +28,59 @@ pub fn run_test_kernel_internal(
     if let Some(cfp) = config_file_path {
         image_builder.set_boot_config(cfp);
     }
+    run_image_builder(kernel_path, &image_builder, &[], &|_| {});
+}
+
+/// Runs the given test kernel with additional QEMU arguments, e.g. to select a CPU model.
+pub fn run_test_kernel_with_qemu_args(kernel_binary_path: &str, qemu_args: &[&str]) {
+    let kernel_path = Path::new(kernel_binary_path);
+    let image_builder = DiskImageBuilder::new(kernel_path.to_owned());
+    run_image_builder(kernel_path, &image_builder, qemu_args, &|_| {});
+}
+
+pub fn run_test_kernel_with_embedded_config(kernel_binary_path: &str, config: &BootConfig) {
+    let kernel_path = Path::new(kernel_binary_path);
+    let mut image_builder = DiskImageBuilder::new(kernel_path.to_owned());
+    image_builder.set_embedded_boot_config(config);
+    run_image_builder(kernel_path, &image_builder, &[], &|_| {});
+}
+
+/// Runs the given test kernel with the `stage_markers` boot config option and checks that
+/// the serial output contains the given stage markers in order on every boot path.
+pub fn run_test_kernel_with_stage_markers(kernel_binary_path: &str, expected_stages: &[&str]) {
+    let kernel_path = Path::new(kernel_binary_path);
+    let mut config = BootConfig::default();
+    config.stage_markers = true;
+    let mut image_builder = DiskImageBuilder::new(kernel_path.to_owned());
+    image_builder.set_boot_config(&config);
+    run_image_builder(kernel_path, &image_builder, &[], &|output| {
+        let stages: Vec<_> = output
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("BOOTSTAGE:"))
+            .collect();
+        assert_eq!(stages, expected_stages, "unexpected boot stage markers");
+    });
+}
+
+/// Builds the disk images and boots them with the given additional QEMU arguments, passing
+/// the serial output of each boot to `check_output`.
+fn run_image_builder(
+    kernel_path: &Path,
+    image_builder: &DiskImageBuilder,
+    qemu_args: &[&str],
+    check_output: &dyn Fn(&str),
+) {
+    // Loading the bootloader through QEMU's `-kernel` argument skips the disk image creation
+    // and the first two BIOS stages, which makes for a faster edit-test loop.
+    #[cfg(feature = "bios")]
+    if std::env::var_os("BOOTLOADER_TEST_MULTIBOOT").is_some() {
+        let multiboot_path = kernel_path.with_extension("multiboot");
+        image_builder
+            .create_multiboot_folder(&multiboot_path)
+            .unwrap();
+        check_output(&run_test_kernel_on_multiboot(&multiboot_path, qemu_args));
+        return;
+    }
 
     #[cfg(feature = "uefi")]
     {
@@ -35,8 +88,8 @@ pub fn run_test_kernel_internal(
         let tftp_path = kernel_path.with_extension("tftp");
         image_builder.create_uefi_image(&gpt_path).unwrap();
         image_builder.create_uefi_tftp_folder(&tftp_path).unwrap();
-        run_test_kernel_on_uefi(&gpt_path);
-        run_test_kernel_on_uefi_pxe(&tftp_path);
+        check_output(&run_test_kernel_on_uefi(&gpt_path, qemu_args));
+        check_output(&run_test_kernel_on_uefi_pxe(&tftp_path, qemu_args));
     }
 
     #[cfg(feature = "bios")]
@@ -45,12 +98,12 @@ pub fn run_test_kernel_internal(
         let mbr_path = kernel_path.with_extension("mbr");
         image_builder.create_bios_image(mbr_path.as_path()).unwrap();
 
-        run_test_kernel_on_bios(&mbr_path);
+        check_output(&run_test_kernel_on_bios(&mbr_path, qemu_args));
     }
 }
 
 #[cfg(feature = "uefi")]
-pub fn run_test_kernel_on_uefi(out_gpt_path: &Path) {
+pub fn run_test_kernel_on_uefi(out_gpt_path: &Path, qemu_args: &[&str]) -> String {
     let ovmf_pure_efi = ovmf_prebuilt::ovmf_pure_efi();
     let args = [
         "-bios",
@@ -58,20 +111,41 @@ pub fn run_test_kernel_on_uefi(out_gpt_path: &Path) {
         "-drive",
         &format!("format=raw,file={}", out_gpt_path.display()),
     ];
-    run_qemu(args);
+    run_qemu(args, qemu_args)
 }
 
 #[cfg(feature = "bios")]
-pub fn run_test_kernel_on_bios(out_mbr_path: &Path) {
+pub fn run_test_kernel_on_bios(out_mbr_path: &Path, qemu_args: &[&str]) -> String {
     let args = [
         "-drive",
         &(format!("format=raw,file={}", out_mbr_path.display())),
     ];
-    run_qemu(args);
+    run_qemu(args, qemu_args)
+}
+
+#[cfg(feature = "bios")]
+pub fn run_test_kernel_on_multiboot(out_multiboot_path: &Path, qemu_args: &[&str]) -> String {
+    const MULTIBOOT_FILENAME: &str = "bootloader-multiboot";
+
+    let mut modules: Vec<_> = std::fs::read_dir(out_multiboot_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.file_name().unwrap() != MULTIBOOT_FILENAME)
+        .map(|path| path.display().to_string())
+        .collect();
+    modules.sort();
+    let kernel = out_multiboot_path.join(MULTIBOOT_FILENAME);
+    let args = [
+        "-kernel",
+        kernel.to_str().unwrap(),
+        "-initrd",
+        &modules.join(","),
+    ];
+    run_qemu(args, qemu_args)
 }
 
 #[cfg(feature = "uefi")]
-pub fn run_test_kernel_on_uefi_pxe(out_tftp_path: &Path) {
+pub fn run_test_kernel_on_uefi_pxe(out_tftp_path: &Path, qemu_args: &[&str]) -> String {
     let ovmf_pure_efi = ovmf_prebuilt::ovmf_pure_efi();
     let args = [
         "-netdev",
@@ -84,16 +158,17 @@ pub fn run_test_kernel_on_uefi_pxe(out_tftp_path: &Path) {
         "-bios",
         ovmf_pure_efi.to_str().unwrap(),
     ];
-    run_qemu(args);
+    run_qemu(args, qemu_args)
 }
 
+/// Runs QEMU with the given arguments and returns its serial output.
 #[cfg(any(feature = "uefi", feature = "bios"))]
-fn run_qemu<'a, A>(args: A)
+fn run_qemu<'a, A>(args: A, qemu_args: &[&str]) -> String
 where
     A: IntoIterator<Item = &'a str>,
 {
     use std::{
-        io::Read,
+        io::{Read, Write},
         process::{Command, Stdio},
     };
 
@@ -112,6 +187,7 @@ where
     let mut run_cmd = Command::new("qemu-system-x86_64");
     run_cmd.args(args);
     run_cmd.args(QEMU_ARGS);
+    run_cmd.args(qemu_args);
     let run_cmd_str = format!("{run_cmd:?}");
 
     run_cmd.stdout(Stdio::piped());
@@ -125,11 +201,22 @@ where
 
     let copy_stdout = std::thread::spawn(move || {
         let print_cmd = format!("\nRunning {run_cmd_str}\n\n").into_bytes();
-        let mut output = print_cmd.chain(child_stdout).chain(SEPARATOR.as_bytes());
-        std::io::copy(
-            &mut output,
-            &mut strip_ansi_escapes::Writer::new(std::io::stdout()),
-        )
+        let mut stdout = strip_ansi_escapes::Writer::new(std::io::stdout());
+        std::io::copy(&mut print_cmd.as_slice(), &mut stdout)?;
+        // forward the output while it is produced, so that hanging tests can be debugged
+        let mut captured = Vec::new();
+        let mut child_stdout = child_stdout;
+        let mut buf = [0; 4096];
+        loop {
+            let len = child_stdout.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            stdout.write_all(&buf[..len])?;
+            captured.extend_from_slice(&buf[..len]);
+        }
+        stdout.write_all(SEPARATOR.as_bytes())?;
+        std::io::Result::Ok(captured)
     });
     let copy_stderr = std::thread::spawn(move || {
         std::io::copy(
@@ -145,6 +232,7 @@ where
         other => panic!("Test failed with unexpected exit code `{other:?}`"),
     }
 
-    copy_stdout.join().unwrap().unwrap();
+    let output = copy_stdout.join().unwrap().unwrap();
     copy_stderr.join().unwrap().unwrap();
+    String::from_utf8_lossy(&strip_ansi_escapes::strip(output).unwrap()).into_owned()
 }
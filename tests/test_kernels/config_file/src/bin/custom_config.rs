@@ -1,7 +1,7 @@
 #![no_std] // don't link the Rust standard library
 #![no_main] // disable all Rust-level entry points
 
-use bootloader_api::{entry_point, BootInfo};
+use bootloader_api::{entry_point, info::LogLevelFilter, BootInfo};
 use core::fmt::Write;
 use test_kernel_config_file::{exit_qemu, serial, QemuExitCode};
 
@@ -10,6 +10,11 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     writeln!(serial(), "Entered kernel with boot info: {boot_info:?}").unwrap();
     assert_eq!(boot_info._test_sentinel, 0xb001b001b001);
+    // the custom config disables framebuffer logging
+    let boot_config = boot_info.boot_config;
+    assert!(!boot_config.frame_buffer_logging);
+    assert!(boot_config.serial_logging);
+    assert_eq!(boot_config.log_level, LogLevelFilter::Trace);
     exit_qemu(QemuExitCode::Success);
 }
 
@@ -1,7 +1,7 @@
 #![no_std] // don't link the Rust standard library
 #![no_main] // disable all Rust-level entry points
 
-use bootloader_api::{entry_point, info::PixelFormat, BootInfo};
+use bootloader_api::{entry_point, info::PixelFormat, BootInfo, BootloaderConfig};
 use test_kernel_default_settings::{exit_qemu, QemuExitCode};
 
 entry_point!(kernel_main);
@@ -28,6 +28,7 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     // check defaults for optional features
     assert_eq!(boot_info.physical_memory_offset.into_option(), None);
     assert_eq!(boot_info.recursive_index.into_option(), None);
+    assert_eq!(boot_info.kernel_stack_canary_addr.into_option(), None);
 
     // check rsdp_addr
     let rsdp = boot_info.rsdp_addr.into_option().unwrap();
@@ -36,6 +37,48 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     // the test kernel has no TLS template
     assert_eq!(boot_info.tls_template.into_option(), None);
 
+    // the random seed is always filled in
+    assert_ne!(boot_info.random_seed, [0; 32]);
+    // QEMU always reports a vendor string
+    assert_ne!(boot_info.cpu_vendor, [0; 12]);
+    // the tests run under QEMU, which sets the hypervisor bit
+    assert!(boot_info.hypervisor_vendor.into_option().is_some());
+    // the kernel is entered with its level 4 page table loaded
+    let (level_4_frame, _) = x86_64::registers::control::Cr3::read();
+    assert_eq!(
+        boot_info.kernel_page_table_phys,
+        level_4_frame.start_address().as_u64()
+    );
+
+    // the serialized config of the `entry_point` macro is reported
+    let kernel_config = boot_info.kernel_config;
+    assert_eq!(kernel_config.api_version, boot_info.api_version);
+    assert_eq!(kernel_config.kernel_stack_size, 80 * 1024);
+    assert!(!kernel_config.safe_mode);
+    let serialized = unsafe {
+        core::slice::from_raw_parts(
+            kernel_config.serialized_addr.into_option().unwrap() as *const u8,
+            kernel_config.serialized_len as usize,
+        )
+    };
+    assert_eq!(serialized, &BootloaderConfig::new_default().serialize()[..]);
+
+    // the ACPI tables range is reported as a whole
+    match boot_info.acpi_tables_addr.into_option() {
+        Some(_) => assert!(boot_info.acpi_tables_len > 0),
+        None => assert_eq!(boot_info.acpi_tables_len, 0),
+    }
+
+    // SMEP and SMAP are enabled by default if the CPU supports them
+    let enabled_cr4_flags = boot_info.enabled_cr4_flags;
+    assert_eq!(enabled_cr4_flags & !BootloaderConfig::DEFAULT_CR4_FLAGS, 0);
+    let cr4 = x86_64::registers::control::Cr4::read_raw();
+    assert_eq!(cr4 & enabled_cr4_flags, enabled_cr4_flags);
+
+    // the FS and GS base registers are left alone by default
+    assert_eq!(boot_info.fs_base.into_option(), None);
+    assert_eq!(boot_info.gs_base.into_option(), None);
+
     exit_qemu(QemuExitCode::Success);
 }
 
@@ -0,0 +1,41 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_five_level_paging::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+use x86_64::{
+    registers::control::{Cr3, Cr4, Cr4Flags},
+    structures::paging::PageTable,
+};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    assert!(boot_info.la57_supported);
+    assert!(boot_info.la57_enabled);
+    assert!(Cr4::read().contains(Cr4Flags::L5_PAGING));
+
+    // CR3 points to the level 5 table, which references the kernel's level 4 table through
+    // entries 0 and 511
+    let phys_mem_offset = boot_info.physical_memory_offset.into_option().unwrap();
+    let level_5_addr = phys_mem_offset + Cr3::read().0.start_address().as_u64();
+    let level_5_table = unsafe { &*(level_5_addr as *const PageTable) };
+    for index in [0, 511] {
+        let level_4_addr = level_5_table[index].addr().as_u64();
+        assert_eq!(level_4_addr, boot_info.kernel_page_table_phys);
+    }
+    let mut other_entries = level_5_table.iter().skip(1).take(510);
+    assert!(other_entries.all(|entry| entry.is_unused()));
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_five_level_paging::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,26 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_fixed_low_address::{exit_qemu, QemuExitCode};
+
+entry_point!(kernel_main);
+
+fn kernel_main(_boot_info: &'static mut BootInfo) -> ! {
+    // verify that the kernel is really running at the fixed low address
+    // (set through the `--image-base` link argument in the top-level `Cargo.toml`)
+    let rip = x86_64::registers::read_rip().as_u64();
+    assert_eq!(rip & 0xffff_ffff_fff0_0000, 0x2000_0000);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_fixed_low_address::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,27 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_fs_gs_base::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG, FS_BASE, GS_BASE};
+use x86_64::registers::model_specific::{FsBase, GsBase};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    assert_eq!(FsBase::read().as_u64(), FS_BASE);
+    assert_eq!(GsBase::read().as_u64(), GS_BASE);
+    assert_eq!(boot_info.fs_base.into_option(), Some(FS_BASE));
+    assert_eq!(boot_info.gs_base.into_option(), Some(GS_BASE));
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_fs_gs_base::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,32 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo, BootloaderConfig};
+use test_kernel_higher_half::{exit_qemu, QemuExitCode};
+
+pub const BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.boot_time = true;
+    config
+};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    // QEMU emulates both the UEFI time services and the CMOS real-time clock
+    let boot_time = boot_info.boot_time.into_option().unwrap();
+    assert!(boot_time.is_valid());
+    assert!(boot_time.year >= 2024);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_higher_half::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
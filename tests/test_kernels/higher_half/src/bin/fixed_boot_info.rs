@@ -0,0 +1,26 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{config::RECOMMENDED_BOOT_INFO_ADDR, entry_point, BootInfo, BootloaderConfig};
+use test_kernel_higher_half::{exit_qemu, QemuExitCode};
+
+pub const BOOTLOADER_CONFIG: BootloaderConfig = BootloaderConfig::new_with_fixed_boot_info();
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    // verify that the boot info is mapped at the recommended fixed address
+    assert_eq!(boot_info as *const _ as u64, RECOMMENDED_BOOT_INFO_ADDR);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_higher_half::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,54 @@
+use std::{env, fmt::Write, fs, path::Path};
+
+/// Number of additional `PT_LOAD` segments in the kernel executable.
+///
+/// Chosen to be larger than the limit of 32 program headers that older bootloader versions
+/// supported.
+const SEGMENT_COUNT: usize = 64;
+
+fn main() {
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+
+    // Every segment contains a single page-aligned section. We alternate between writable
+    // and read-only sections so that the linker can't merge them into a single segment.
+    let mut linker_script = String::from("SECTIONS {\n");
+    let mut statics = String::new();
+    for i in 0..SEGMENT_COUNT {
+        writeln!(
+            linker_script,
+            "  .segment_{i} ALIGN(4K) : {{ KEEP(*(.segment_{i})) }}"
+        )
+        .unwrap();
+
+        let mutability = if i % 2 == 0 { "mut " } else { "" };
+        writeln!(
+            statics,
+            "#[link_section = \".segment_{i}\"]
+            #[used]
+            static {mutability}SEGMENT_{i}: [u8; 16] = [{i}; 16];"
+        )
+        .unwrap();
+    }
+    linker_script += "}\nINSERT AFTER .text;\n";
+
+    statics += "fn segment_values() -> [u8; SEGMENT_COUNT] {\n    [";
+    for i in 0..SEGMENT_COUNT {
+        write!(
+            statics,
+            "unsafe {{ core::ptr::read_volatile(core::ptr::addr_of!(SEGMENT_{i}[15])) }},"
+        )
+        .unwrap();
+    }
+    statics += "]\n}\n";
+    statics += &format!("const SEGMENT_COUNT: usize = {SEGMENT_COUNT};\n");
+
+    let linker_script_path = Path::new(&out_dir).join("segments.ld");
+    fs::write(&linker_script_path, linker_script).unwrap();
+    fs::write(Path::new(&out_dir).join("segments.rs"), statics).unwrap();
+
+    println!(
+        "cargo:rustc-link-arg-bins=-T{}",
+        linker_script_path.display()
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,29 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_many_segments::{exit_qemu, QemuExitCode};
+
+// defines the `SEGMENT_{i}` statics, each placed in its own `PT_LOAD` segment
+include!(concat!(env!("OUT_DIR"), "/segments.rs"));
+
+entry_point!(kernel_main);
+
+fn kernel_main(_boot_info: &'static mut BootInfo) -> ! {
+    // ensure that all segments were loaded with the correct contents
+    for (i, value) in segment_values().into_iter().enumerate() {
+        assert_eq!(usize::from(value), i);
+    }
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_many_segments::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
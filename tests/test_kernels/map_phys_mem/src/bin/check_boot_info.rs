@@ -30,6 +30,15 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
         boot_info.physical_memory_offset.into_option(),
         Some(0x0000_4000_0000_0000),
     );
+    // the whole physical memory is mapped by default
+    let max_phys_addr = boot_info
+        .memory_regions
+        .iter()
+        .map(|r| r.end)
+        .max()
+        .unwrap();
+    assert!(boot_info.physical_memory_len >= max_phys_addr);
+    assert_eq!(boot_info.physical_memory_len % 0x20_0000, 0);
     assert_eq!(boot_info.recursive_index.into_option(), None);
 
     // check rsdp_addr
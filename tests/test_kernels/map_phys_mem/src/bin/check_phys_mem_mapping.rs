@@ -0,0 +1,86 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, info::MemoryRegionKind, BootInfo};
+use test_kernel_map_phys_mem::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PageTable, Translate},
+    PhysAddr, VirtAddr,
+};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    let offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let phys_mem_len = boot_info.physical_memory_len;
+    let level_4_table = offset + Cr3::read().0.start_address().as_u64();
+    let page_table =
+        unsafe { OffsetPageTable::new(&mut *level_4_table.as_mut_ptr::<PageTable>(), offset) };
+    let phys_addr = |virt: VirtAddr| {
+        page_table
+            .translate_addr(virt)
+            .unwrap_or_else(|| panic!("{:#x} is not mapped", virt.as_u64()))
+    };
+
+    // every memory region lies in the mapping, up to the highest one
+    for region in boot_info.memory_regions.iter().filter(|r| r.start < r.end) {
+        assert!(
+            region.end <= phys_mem_len,
+            "region {region:?} is not covered by the physical memory mapping"
+        );
+        for addr in [region.start, region.end - 1] {
+            assert_eq!(phys_addr(offset + addr), PhysAddr::new(addr));
+        }
+    }
+    assert_eq!(
+        phys_addr(offset + (phys_mem_len - 1)),
+        PhysAddr::new(phys_mem_len - 1)
+    );
+
+    // the kernel's own code reads the same through both mappings
+    let code = kernel_main as *const u8;
+    let code_phys = phys_addr(VirtAddr::from_ptr(code));
+    let code_through_offset = (offset + code_phys.as_u64()).as_ptr::<u8>();
+    // stay within the page, as the next page might not be physically contiguous
+    let len = 64.min(0x1000 - code as usize % 0x1000);
+    let read = |ptr: *const u8| unsafe { core::slice::from_raw_parts(ptr, len) };
+    assert_eq!(read(code), read(code_through_offset));
+
+    // the framebuffer is covered too, even if it lies above the memory map
+    let framebuffer = boot_info.framebuffer.as_mut().unwrap();
+    let framebuffer_phys = phys_addr(VirtAddr::from_ptr(framebuffer.buffer().as_ptr()));
+    let framebuffer_len = framebuffer.buffer().len() as u64;
+    assert!(framebuffer_phys.as_u64() + framebuffer_len <= phys_mem_len);
+    let through_offset = (offset + framebuffer_phys.as_u64()).as_mut_ptr::<u8>();
+    framebuffer.buffer_mut()[0] = 0x5a;
+    assert_eq!(unsafe { through_offset.read_volatile() }, 0x5a);
+    unsafe { through_offset.write_volatile(0xa5) };
+    assert_eq!(framebuffer.buffer()[0], 0xa5);
+
+    // the mapping also covers memory that the kernel can use
+    let usable = boot_info
+        .memory_regions
+        .iter()
+        .rev()
+        .find(|r| r.kind == MemoryRegionKind::Usable)
+        .unwrap();
+    let ptr = (offset + (usable.end - 8)).as_mut_ptr::<u64>();
+    unsafe {
+        ptr.write_volatile(0xdead_beef);
+        assert_eq!(ptr.read_volatile(), 0xdead_beef);
+    }
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+    use test_kernel_map_phys_mem::serial;
+
+    let _ = writeln!(serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,57 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo, BootloaderConfig};
+use test_kernel_map_phys_mem::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PageTable, Translate},
+    VirtAddr,
+};
+
+pub const CONFIG: BootloaderConfig = {
+    let mut config = BOOTLOADER_CONFIG;
+    config.mappings.framebuffer_in_physical_memory = true;
+    config
+};
+entry_point!(kernel_main, config = &CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    let offset = boot_info.physical_memory_offset.into_option().unwrap();
+    let phys_mem_len = boot_info.physical_memory_len;
+    let framebuffer = boot_info.framebuffer.as_mut().unwrap();
+    let start = framebuffer.buffer().as_ptr() as u64;
+
+    // the framebuffer lies in the physical memory mapping
+    assert!(start >= offset);
+    let framebuffer_phys = start - offset;
+    assert!(framebuffer_phys + framebuffer.buffer().len() as u64 <= phys_mem_len);
+
+    let offset = VirtAddr::new(offset);
+    let level_4_table = offset + Cr3::read().0.start_address().as_u64();
+    let page_table =
+        unsafe { OffsetPageTable::new(&mut *level_4_table.as_mut_ptr::<PageTable>(), offset) };
+    assert_eq!(
+        page_table
+            .translate_addr(VirtAddr::new(start))
+            .unwrap()
+            .as_u64(),
+        framebuffer_phys
+    );
+
+    framebuffer.buffer_mut()[0] = 0x5a;
+    assert_eq!(framebuffer.buffer()[0], 0x5a);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+    use test_kernel_map_phys_mem::serial;
+
+    let _ = writeln!(serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
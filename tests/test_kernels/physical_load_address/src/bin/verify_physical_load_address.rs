@@ -0,0 +1,61 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, info::MemoryRegionKind, BootInfo};
+use test_kernel_physical_load_address::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG, LOAD_ADDRESS};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PageTable, Translate},
+    VirtAddr,
+};
+
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+static mut DATA: u64 = 0x1234_5678;
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let page_table = {
+        let frame = Cr3::read().0;
+        let table: *mut PageTable = (phys_mem_offset + frame.start_address().as_u64()).as_mut_ptr();
+        unsafe { OffsetPageTable::new(&mut *table, phys_mem_offset) }
+    };
+
+    // the segments are mapped to the physical addresses of their program headers, which are
+    // equal to their virtual addresses
+    let rip = x86_64::registers::read_rip();
+    assert!((LOAD_ADDRESS..LOAD_ADDRESS + 0x100_0000).contains(&rip.as_u64()));
+    assert_eq!(
+        page_table.translate_addr(rip).unwrap().as_u64(),
+        rip.as_u64()
+    );
+    let data = VirtAddr::from_ptr(unsafe { core::ptr::addr_of!(DATA) });
+    assert_eq!(
+        page_table.translate_addr(data).unwrap().as_u64(),
+        data.as_u64()
+    );
+    assert_eq!(
+        unsafe { core::ptr::addr_of!(DATA).read_volatile() },
+        0x1234_5678
+    );
+
+    // the memory of the segments is reported as used
+    let region = boot_info
+        .memory_regions
+        .iter()
+        .find(|r| r.start <= LOAD_ADDRESS && LOAD_ADDRESS < r.end)
+        .unwrap();
+    assert_ne!(region.kind, MemoryRegionKind::Usable);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_physical_load_address::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -0,0 +1,42 @@
+#![no_std]
+
+use bootloader_api::{config::Mapping, BootloaderConfig};
+
+/// The address that the kernel is linked to (set through the `--image-base` link argument in
+/// the top-level `Cargo.toml`).
+///
+/// The linker sets the physical address of each segment to its virtual address.
+pub const LOAD_ADDRESS: u64 = 0x20_0000;
+
+pub const BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::Dynamic);
+    config.load_at_physical_addresses = true;
+    config
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+    use x86_64::instructions::{nop, port::Port};
+
+    unsafe {
+        let mut port = Port::new(0xf4);
+        port.write(exit_code as u32);
+    }
+
+    loop {
+        nop();
+    }
+}
+
+pub fn serial() -> uart_16550::SerialPort {
+    let mut port = unsafe { uart_16550::SerialPort::new(0x3F8) };
+    port.init();
+    port
+}
@@ -0,0 +1,53 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{config::Mapping, entry_point, BootInfo, BootloaderConfig};
+use core::{fmt::Write, slice};
+use test_kernel_ramdisk::{exit_qemu, serial, QemuExitCode, RAMDISK_CONTENTS};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PageTable, Translate},
+    VirtAddr,
+};
+
+pub const BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::Dynamic);
+    config.mappings.ramdisk_eager_pages = Some(0);
+    config
+};
+entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+
+fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
+    writeln!(serial(), "Boot info: {boot_info:?}").unwrap();
+    assert_eq!(boot_info.ramdisk_len as usize, RAMDISK_CONTENTS.len());
+    assert_eq!(boot_info.ramdisk_mapped_len, 0);
+
+    // the virtual range is reserved, but not mapped
+    let offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let level_4_table = offset + Cr3::read().0.start_address().as_u64();
+    let page_table =
+        unsafe { OffsetPageTable::new(&mut *level_4_table.as_mut_ptr::<PageTable>(), offset) };
+    let ramdisk_addr = VirtAddr::new(boot_info.ramdisk_addr.into_option().unwrap());
+    assert_eq!(page_table.translate_addr(ramdisk_addr), None);
+
+    // the contents can be read through the reported physical address
+    let ramdisk_phys_addr = boot_info.ramdisk_phys_addr.into_option().unwrap();
+    let actual_ramdisk = unsafe {
+        slice::from_raw_parts(
+            (offset + ramdisk_phys_addr).as_ptr::<u8>(),
+            boot_info.ramdisk_len as usize,
+        )
+    };
+    assert_eq!(RAMDISK_CONTENTS, actual_ramdisk);
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let _ = writeln!(test_kernel_ramdisk::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -11,6 +11,8 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     writeln!(serial(), "Boot info: {boot_info:?}").unwrap();
     assert!(boot_info.ramdisk_addr.into_option().is_some());
     assert_eq!(boot_info.ramdisk_len as usize, RAMDISK_CONTENTS.len());
+    assert_eq!(boot_info.ramdisk_mapped_len, boot_info.ramdisk_len);
+    assert!(boot_info.ramdisk_phys_addr.into_option().is_some());
     let actual_ramdisk = unsafe {
         &*slice_from_raw_parts(
             boot_info.ramdisk_addr.into_option().unwrap() as *const u8,
@@ -0,0 +1,54 @@
+#![no_std] // don't link the Rust standard library
+#![no_main] // disable all Rust-level entry points
+
+use bootloader_api::{entry_point, BootInfo};
+use test_kernel_read_only_boot_info::{exit_qemu, QemuExitCode, BOOTLOADER_CONFIG};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::TranslateResult, OffsetPageTable, PageTable, PageTableFlags, Translate,
+    },
+    VirtAddr,
+};
+
+entry_point!(
+    kernel_main,
+    config = &BOOTLOADER_CONFIG,
+    read_only_boot_info
+);
+
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let level_4_addr = phys_mem_offset + Cr3::read().0.start_address().as_u64();
+    let level_4_table = unsafe { &mut *level_4_addr.as_mut_ptr::<PageTable>() };
+    let page_table = unsafe { OffsetPageTable::new(level_4_table, phys_mem_offset) };
+
+    // both the boot info and the memory regions array must be mapped read-only
+    let memory_regions = &boot_info.memory_regions;
+    let addrs = [
+        VirtAddr::from_ptr(boot_info),
+        VirtAddr::from_ptr(memory_regions.as_ptr()),
+        VirtAddr::from_ptr(memory_regions.last().unwrap()),
+    ];
+    for addr in addrs {
+        match page_table.translate(addr) {
+            TranslateResult::Mapped { flags, .. } => {
+                assert!(flags.contains(PageTableFlags::PRESENT));
+                assert!(!flags.contains(PageTableFlags::WRITABLE));
+            }
+            other => panic!("boot info address {addr:?} is not mapped: {other:?}"),
+        }
+    }
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// This function is called on panic.
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    let _ = writeln!(test_kernel_read_only_boot_info::serial(), "PANIC: {info}");
+    exit_qemu(QemuExitCode::Failed);
+}
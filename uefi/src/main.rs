@@ -3,15 +3,19 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
 use crate::memory_descriptor::UefiMemoryDescriptor;
-use bootloader_api::info::FrameBufferInfo;
-use bootloader_boot_config::BootConfig;
+use bootloader_api::info::{BootTime, FrameBufferInfo, MemoryRegionKind};
+use bootloader_boot_config::{default_kernel, BootConfig, FilePath, Guid};
 use bootloader_x86_64_common::{
-    legacy_memory_region::LegacyFrameAllocator, Kernel, RawFrameBufferInfo, SystemInfo,
+    boot_config::{parse_boot_config, BootConfigError},
+    boot_stage::{self, BootStage},
+    embedded_boot_config,
+    legacy_memory_region::LegacyFrameAllocator,
+    Kernel, RawFrameBufferInfo, SystemInfo,
 };
 use core::{
     cell::UnsafeCell,
     ops::{Deref, DerefMut},
-    ptr, slice,
+    ptr, slice, str,
 };
 use uefi::{
     prelude::{entry, Boot, Handle, Status, SystemTable},
@@ -20,8 +24,11 @@ use uefi::{
         device_path::DevicePath,
         loaded_image::LoadedImage,
         media::{
-            file::{File, FileAttribute, FileInfo, FileMode},
+            block::BlockIO,
+            disk::DiskIo,
+            file::{File, FileAttribute, FileInfo, FileMode, RegularFile},
             fs::SimpleFileSystem,
+            partition::PartitionInfo,
         },
         network::{
             pxe::{BaseCode, DhcpV4Packet},
@@ -30,7 +37,8 @@ use uefi::{
         ProtocolPointer,
     },
     table::boot::{
-        AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+        AllocateType, LoadImageSource, MemoryType, OpenProtocolAttributes, OpenProtocolParams,
+        ScopedProtocol, SearchType,
     },
     CStr16, CStr8,
 };
@@ -74,27 +82,35 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
 
     let mut boot_mode = BootMode::Disk;
 
-    let mut kernel = load_kernel(image, &mut st, boot_mode);
-    if kernel.is_none() {
+    let (mut config, mut error_loading_config) = load_boot_config(image, &mut st, boot_mode);
+    let chainload = config.chainload;
+    if let Some(chainload) = chainload.filter(|c| !c.fallback) {
+        return chainload_image(image, &mut st, &chainload.path);
+    }
+    let mut kernel_slice = match config.kernel_partition_type {
+        Some(partition_type) => Some(
+            load_kernel_from_partition(image, &st, partition_type).unwrap_or_else(|| {
+                panic!("Failed to load kernel from partition with type {partition_type}")
+            }),
+        ),
+        None => load_kernel(image, &mut st, boot_mode),
+    };
+    if kernel_slice.is_none() {
         // Try TFTP boot
         boot_mode = BootMode::Tftp;
-        kernel = load_kernel(image, &mut st, boot_mode);
+        kernel_slice = load_kernel(image, &mut st, boot_mode);
+        (config, error_loading_config) = load_boot_config(image, &mut st, boot_mode);
     }
-    let kernel = kernel.expect("Failed to load kernel");
-
-    let config_file = load_config_file(image, &mut st, boot_mode);
-    let mut error_loading_config: Option<serde_json_core::de::Error> = None;
-    let mut config: BootConfig = match config_file
-        .as_deref()
-        .map(serde_json_core::from_slice)
-        .transpose()
-    {
-        Ok(data) => data.unwrap_or_default().0,
-        Err(err) => {
-            error_loading_config = Some(err);
-            Default::default()
-        }
-    };
+    if let (None, Some(chainload)) = (&kernel_slice, chainload) {
+        return chainload_image(image, &mut st, &chainload.path);
+    }
+    if config.stage_markers {
+        boot_stage::enable();
+    }
+    boot_stage::mark(BootStage::ConfigLoaded);
+    let kernel_slice = kernel_slice.expect("Failed to load kernel");
+    let mut kernel = Kernel::parse(kernel_slice, &config);
+    boot_stage::mark(BootStage::KernelLoaded);
 
     #[allow(deprecated)]
     if config.frame_buffer.minimum_framebuffer_height.is_none() {
@@ -106,6 +122,9 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
         config.frame_buffer.minimum_framebuffer_width =
             kernel.config.frame_buffer.minimum_framebuffer_width;
     }
+    if config.safe_mode {
+        bootloader_x86_64_common::apply_safe_mode(&mut kernel, &mut config);
+    }
     let framebuffer = init_logger(image, &st, &config);
 
     unsafe {
@@ -119,14 +138,14 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
     }
 
     if let Some(err) = error_loading_config {
-        log::warn!("Failed to deserialize the config file {:?}", err);
+        log::warn!("Failed to deserialize the config file, using the default config: {err}");
     } else {
         log::info!("Reading configuration from disk was successful");
     }
 
     log::info!("Trying to load ramdisk via {:?}", boot_mode);
     // Ramdisk must load from same source, or not at all.
-    let ramdisk = load_ramdisk(image, &mut st, boot_mode);
+    let ramdisk = load_ramdisk(image, &mut st, boot_mode, config.ramdisk_above_4gib);
 
     log::info!(
         "{}",
@@ -136,16 +155,48 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
         }
     );
 
+    // look for the RSDP while boot services are still active, so that the low memory
+    // used by the legacy BIOS scan is still identity-mapped if the firmware maps it at all
+    let rsdp_addr = config
+        .rsdp_addr
+        .map(PhysAddr::new)
+        .or_else(|| detect_rsdp(&st));
+
+    let boot_time = kernel
+        .config
+        .boot_time
+        .then(|| read_boot_time(&st))
+        .flatten();
+
     log::trace!("exiting boot services");
-    let (system_table, mut memory_map) = st.exit_boot_services();
+    let (_system_table, mut memory_map) = st.exit_boot_services();
 
     memory_map.sort();
 
     let mut frame_allocator =
         LegacyFrameAllocator::new(memory_map.entries().copied().map(UefiMemoryDescriptor));
+    if let Err(err) =
+        bootloader_x86_64_common::exclude_reserved_memory(&mut frame_allocator, &config)
+    {
+        log::warn!("{err}");
+    }
+    if let Err(err) =
+        bootloader_x86_64_common::reserve_kernel_load_range(&mut frame_allocator, &kernel)
+    {
+        panic!("requested physical load address of the kernel is not available: {err}");
+    }
 
     let max_phys_addr = frame_allocator.max_phys_addr();
+    if config.memory_test {
+        // UEFI identity-maps all physical memory
+        bootloader_x86_64_common::test_memory(
+            &mut frame_allocator,
+            VirtAddr::new(0),
+            max_phys_addr,
+        );
+    }
     let page_tables = create_page_tables(&mut frame_allocator, max_phys_addr, framebuffer.as_ref());
+    boot_stage::mark(BootStage::MemoryMapDone);
     let mut ramdisk_len = 0u64;
     let ramdisk_addr = if let Some(rd) = ramdisk {
         ramdisk_len = rd.len() as u64;
@@ -153,20 +204,18 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
     } else {
         None
     };
+    let acpi_tables = frame_allocator.region_bounds(|kind| {
+        kind == MemoryRegionKind::UnknownUefi(MemoryType::ACPI_RECLAIM.0)
+            || kind == MemoryRegionKind::UnknownUefi(MemoryType::ACPI_NON_VOLATILE.0)
+    });
+
     let system_info = SystemInfo {
         framebuffer,
-        rsdp_addr: {
-            use uefi::table::cfg;
-            let mut config_entries = system_table.config_table().iter();
-            // look for an ACPI2 RSDP first
-            let acpi2_rsdp = config_entries.find(|entry| matches!(entry.guid, cfg::ACPI2_GUID));
-            // if no ACPI2 RSDP is found, look for a ACPI1 RSDP
-            let rsdp = acpi2_rsdp
-                .or_else(|| config_entries.find(|entry| matches!(entry.guid, cfg::ACPI_GUID)));
-            rsdp.map(|entry| PhysAddr::new(entry.address as u64))
-        },
+        rsdp_addr,
         ramdisk_addr,
         ramdisk_len,
+        boot_time,
+        acpi_tables,
     };
 
     bootloader_x86_64_common::load_and_switch_to_kernel(
@@ -178,6 +227,121 @@ fn main_inner(image: Handle, mut st: SystemTable<Boot>) -> Status {
     );
 }
 
+/// Queries the current time from the UEFI runtime services.
+///
+/// Falls back to the CMOS real-time clock if the firmware doesn't report a valid time.
+fn read_boot_time(st: &SystemTable<Boot>) -> Option<BootTime> {
+    let time = st
+        .runtime_services()
+        .get_time()
+        .ok()
+        .map(|time| BootTime {
+            year: time.year(),
+            month: time.month(),
+            day: time.day(),
+            hour: time.hour(),
+            minute: time.minute(),
+            second: time.second(),
+        })
+        .filter(BootTime::is_valid);
+    time.or_else(bootloader_x86_64_common::rtc::read_cmos_time)
+}
+
+fn detect_rsdp(st: &SystemTable<Boot>) -> Option<PhysAddr> {
+    use uefi::table::cfg;
+    let mut config_entries = st.config_table().iter();
+    // look for an ACPI2 RSDP first
+    let acpi2_rsdp = config_entries.find(|entry| matches!(entry.guid, cfg::ACPI2_GUID));
+    // if no ACPI2 RSDP is found, look for a ACPI1 RSDP
+    let rsdp =
+        acpi2_rsdp.or_else(|| config_entries.find(|entry| matches!(entry.guid, cfg::ACPI_GUID)));
+    rsdp.map(|entry| PhysAddr::new(entry.address as u64))
+        .or_else(|| {
+            // some firmware doesn't report the RSDP in the config table, so fall back to
+            // the legacy BIOS scan in low memory
+            if !bios_scan_area_mapped() {
+                log::warn!("RSDP not found and legacy BIOS area is not mapped, skipping scan");
+                return None;
+            }
+            let rsdp = search_rsdp_on_bios();
+            if let Some(addr) = rsdp {
+                log::info!(
+                    "Found RSDP at {:#x} using the legacy BIOS scan",
+                    addr.as_u64()
+                );
+            }
+            rsdp
+        })
+}
+
+/// The physical memory ranges that are read by [`search_rsdp_on_bios`]: the EBDA segment
+/// pointer in the BIOS data area, all possible locations of the EBDA, and the BIOS area.
+const BIOS_SCAN_RANGES: [(u64, u64); 3] =
+    [(0x40E, 0x410), (0x8_0000, 0xA_0000), (0xE_0000, 0x10_0000)];
+
+/// Checks whether all pages of the [`BIOS_SCAN_RANGES`] are mapped in the active page table.
+///
+/// UEFI firmware doesn't necessarily map low memory, e.g. page 0 is often left unmapped to
+/// detect null pointer accesses.
+fn bios_scan_area_mapped() -> bool {
+    use x86_64::structures::paging::{Page, Translate};
+
+    let frame = x86_64::registers::control::Cr3::read().0;
+    // UEFI identity-maps all memory; the table is only used for reading
+    let level_4_table: *mut PageTable = frame.start_address().as_u64() as *mut PageTable;
+    let page_table = unsafe { OffsetPageTable::new(&mut *level_4_table, VirtAddr::new(0)) };
+    BIOS_SCAN_RANGES.iter().all(|&(start, end)| {
+        let start = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+        let end = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+        Page::range_inclusive(start, end)
+            .all(|page| page_table.translate_addr(page.start_address()).is_some())
+    })
+}
+
+/// Searches for the RSDP in the EBDA and in the `0xE0000`-`0xFFFFF` range.
+///
+/// Mirrors the RSDP detection of the BIOS bootloader. Must only be called if
+/// [`bios_scan_area_mapped`] returns `true`.
+fn search_rsdp_on_bios() -> Option<PhysAddr> {
+    use core::ptr::NonNull;
+    use rsdp::{
+        handler::{AcpiHandler, PhysicalMapping},
+        Rsdp,
+    };
+
+    #[derive(Clone)]
+    struct IdentityMapped;
+    impl AcpiHandler for IdentityMapped {
+        // see the `detect_rsdp` function of the BIOS stage-4 for why this is required
+        #[inline(never)]
+        unsafe fn map_physical_region<T>(
+            &self,
+            physical_address: usize,
+            size: usize,
+        ) -> PhysicalMapping<Self, T> {
+            unsafe {
+                PhysicalMapping::new(
+                    physical_address,
+                    NonNull::new(physical_address as *mut _).unwrap(),
+                    size,
+                    size,
+                    Self,
+                )
+            }
+        }
+
+        fn unmap_physical_region<T>(_region: &PhysicalMapping<Self, T>) {}
+    }
+
+    // UEFI identity-maps all memory while boot services are active, and the caller checked
+    // that the scanned ranges are mapped
+    unsafe {
+        Rsdp::search_for_on_bios(IdentityMapped)
+            .ok()
+            .map(|mapping| PhysAddr::new(mapping.physical_start() as u64))
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum BootMode {
     Disk,
@@ -188,8 +352,9 @@ fn load_ramdisk(
     image: Handle,
     st: &mut SystemTable<Boot>,
     boot_mode: BootMode,
+    above_4gib: bool,
 ) -> Option<&'static mut [u8]> {
-    load_file_from_boot_method(image, st, "ramdisk\0", boot_mode)
+    load_file_from_boot_method(image, st, "ramdisk\0", boot_mode, above_4gib)
 }
 
 fn load_config_file(
@@ -197,30 +362,172 @@ fn load_config_file(
     st: &mut SystemTable<Boot>,
     boot_mode: BootMode,
 ) -> Option<&'static mut [u8]> {
-    load_file_from_boot_method(image, st, "boot.json\0", boot_mode)
+    load_file_from_boot_method(image, st, "boot.json\0", boot_mode, false)
 }
 
+/// Loads and parses the boot config.
+///
+/// A config that was embedded into the bootloader binary is preferred over the `boot.json`
+/// file. If the config is invalid, the default config is returned together with the error.
+fn load_boot_config(
+    image: Handle,
+    st: &mut SystemTable<Boot>,
+    boot_mode: BootMode,
+) -> (BootConfig, Option<BootConfigError<'static>>) {
+    let config_file = match embedded_boot_config() {
+        Some(config) => Some(config),
+        None => load_config_file(image, st, boot_mode).map(|config| &*config),
+    };
+    match config_file.map(parse_boot_config).transpose() {
+        Ok(config) => (config.unwrap_or_default(), None),
+        Err(err) => (Default::default(), Some(err)),
+    }
+}
+
+/// Loads the kernel file.
+///
+/// The file name is read from the [`default_kernel::FILE_NAME`] file if it exists. Otherwise,
+/// the `kernel-x86_64` file is loaded.
 fn load_kernel(
     image: Handle,
     st: &mut SystemTable<Boot>,
     boot_mode: BootMode,
-) -> Option<Kernel<'static>> {
-    let kernel_slice = load_file_from_boot_method(image, st, "kernel-x86_64\0", boot_mode)?;
-    Some(Kernel::parse(kernel_slice))
+) -> Option<&'static mut [u8]> {
+    let mut buffer = [0; MAX_FILE_NAME_LEN];
+    let default_kernel_file = load_file_from_boot_method(
+        image,
+        st,
+        nul_terminated(default_kernel::FILE_NAME, &mut buffer),
+        boot_mode,
+        false,
+    );
+    let name = match default_kernel_file {
+        Some(contents) => default_kernel::parse(contents)
+            .unwrap_or_else(|| panic!("invalid {} file", default_kernel::FILE_NAME)),
+        None => "kernel-x86_64",
+    };
+    load_file_from_boot_method(
+        image,
+        st,
+        nul_terminated(name, &mut buffer),
+        boot_mode,
+        false,
+    )
 }
 
+/// Appends a null terminator to the given file name, as required by
+/// [`load_file_from_boot_method`].
+fn nul_terminated<'a>(name: &str, buffer: &'a mut [u8]) -> &'a str {
+    let buffer = &mut buffer[..=name.len()];
+    buffer[..name.len()].copy_from_slice(name.as_bytes());
+    buffer[name.len()] = 0;
+    str::from_utf8(buffer).unwrap()
+}
+
+/// Loads the EFI application at the given path of the boot partition and starts it, see
+/// [`BootConfig::chainload`].
+///
+/// Returns the exit status of the application.
+fn chainload_image(image: Handle, st: &mut SystemTable<Boot>, path: &FilePath) -> Status {
+    use core::fmt::Write;
+
+    let path = path.as_str();
+    let _ = writeln!(st.stdout(), "Chainloading `{path}`");
+    let mut buffer = [0; FilePath::CAPACITY + 1];
+    let data = load_file_from_disk(nul_terminated(path, &mut buffer), image, st, false)
+        .unwrap_or_else(|| panic!("Failed to load chainloaded image `{path}`"));
+    let child = st
+        .boot_services()
+        .load_image(
+            image,
+            LoadImageSource::FromBuffer {
+                buffer: data,
+                file_path: None,
+            },
+        )
+        .unwrap_or_else(|err| panic!("Failed to load chainloaded image `{path}`: {err:?}"));
+    match st.boot_services().start_image(child) {
+        Ok(()) => Status::SUCCESS,
+        Err(err) => err.status(),
+    }
+}
+
+/// Loads the given file into newly allocated pages.
+///
+/// If `above_4gib` is set, the pages are allocated above 4 GiB if possible.
 fn load_file_from_boot_method(
     image: Handle,
     st: &mut SystemTable<Boot>,
     filename: &str,
     boot_mode: BootMode,
+    above_4gib: bool,
 ) -> Option<&'static mut [u8]> {
     match boot_mode {
-        BootMode::Disk => load_file_from_disk(filename, image, st),
-        BootMode::Tftp => load_file_from_tftp_boot_server(filename, image, st),
+        BootMode::Disk => load_file_from_disk(filename, image, st, above_4gib),
+        BootMode::Tftp => load_file_from_tftp_boot_server(filename, image, st, above_4gib),
     }
 }
 
+/// Allocates loader data pages for a file of the given size.
+///
+/// If `above_4gib` is set and there is a large enough free memory region above 4 GiB, the
+/// pages are allocated in this region. Otherwise, the firmware chooses the address.
+fn allocate_file_pages(st: &SystemTable<Boot>, file_size: usize, above_4gib: bool) -> *mut u8 {
+    let pages = ((file_size - 1) / 4096) + 1;
+    let high_addr = if above_4gib {
+        let addr = find_free_pages_above_4gib(st, pages);
+        if addr.is_none() {
+            log::warn!("No free memory above 4 GiB for {pages} pages, allocating them anywhere");
+        }
+        addr
+    } else {
+        None
+    };
+    let allocate_type = match high_addr {
+        Some(addr) => AllocateType::Address(addr),
+        None => AllocateType::AnyPages,
+    };
+    st.boot_services()
+        .allocate_pages(allocate_type, MemoryType::LOADER_DATA, pages)
+        .expect("Failed to allocate memory for the file") as *mut u8
+}
+
+/// Returns the start address of the lowest free memory range above 4 GiB with the given
+/// number of pages.
+fn find_free_pages_above_4gib(st: &SystemTable<Boot>, pages: usize) -> Option<u64> {
+    const FOUR_GIB: u64 = 1 << 32;
+
+    let boot_services = st.boot_services();
+    let size = boot_services.memory_map_size();
+    // allocating the buffer might split a memory region, so reserve space for some
+    // additional entries
+    let buffer_len = size.map_size + 8 * size.entry_size;
+    let buffer = boot_services
+        .allocate_pool(MemoryType::LOADER_DATA, buffer_len)
+        .ok()?;
+
+    let addr = {
+        let buffer = unsafe { slice::from_raw_parts_mut(buffer, buffer_len) };
+        boot_services
+            .memory_map(buffer)
+            .ok()
+            .and_then(|memory_map| {
+                memory_map
+                    .entries()
+                    .filter(|descriptor| descriptor.ty == MemoryType::CONVENTIONAL)
+                    .filter_map(|descriptor| {
+                        let start = descriptor.phys_start.max(FOUR_GIB);
+                        let end = descriptor.phys_start + descriptor.page_count * 4096;
+                        (end.saturating_sub(start) >= pages as u64 * 4096).then_some(start)
+                    })
+                    .min()
+            })
+    };
+
+    let _ = boot_services.free_pool(buffer);
+    addr
+}
+
 fn open_device_path_protocol(
     image: Handle,
     st: &SystemTable<Boot>,
@@ -297,19 +604,38 @@ fn locate_and_open_protocol<P: ProtocolPointer>(
     Some(opened_handle.unwrap())
 }
 
+/// The maximum number of bytes that are read from a file in a single call.
+///
+/// Some firmware implementations fail or are very slow for large reads.
+const FILE_READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The maximum length of a file name including the null terminator, enough for any
+/// [`default_kernel`] name.
+const MAX_FILE_NAME_LEN: usize = default_kernel::MAX_NAME_LEN + 1;
+
 fn load_file_from_disk(
     name: &str,
     image: Handle,
     st: &SystemTable<Boot>,
+    above_4gib: bool,
 ) -> Option<&'static mut [u8]> {
     let mut file_system_raw = locate_and_open_protocol::<SimpleFileSystem>(image, st)?;
     let file_system = file_system_raw.deref_mut();
 
     let mut root = file_system.open_volume().unwrap();
-    let mut buf = [0u16; 256];
-    assert!(name.len() < 256);
-    let filename = CStr16::from_str_with_buf(name.trim_end_matches('\0'), &mut buf)
+    let mut buf = [0u16; MAX_FILE_NAME_LEN];
+    assert!(name.len() <= MAX_FILE_NAME_LEN);
+    CStr16::from_str_with_buf(name.trim_end_matches('\0'), &mut buf)
         .expect("Failed to convert string to utf16");
+    // UEFI file paths use backslashes as separators, so that files in subdirectories can be
+    // given as `boot/kernel-x86_64` like on BIOS
+    let len = buf.iter().position(|&c| c == 0).unwrap();
+    for c in &mut buf[..len] {
+        if *c == u16::from(b'/') {
+            *c = u16::from(b'\\');
+        }
+    }
+    let filename = CStr16::from_u16_with_nul(&buf[..=len]).unwrap();
 
     let file_handle_result = root.open(filename, FileMode::Read, FileAttribute::empty());
 
@@ -323,23 +649,179 @@ fn load_file_from_disk(
         uefi::proto::media::file::FileType::Dir(_) => panic!(),
     };
 
-    let mut buf = [0; 500];
-    let file_info: &mut FileInfo = file.get_info(&mut buf).unwrap();
-    let file_size = usize::try_from(file_info.file_size()).unwrap();
+    let file_size = usize::try_from(file_size(&mut file, st)).unwrap();
 
-    let file_ptr = st
-        .boot_services()
+    let file_ptr = allocate_file_pages(st, file_size, above_4gib);
+    unsafe { ptr::write_bytes(file_ptr, 0, file_size) };
+    let file_slice = unsafe { slice::from_raw_parts_mut(file_ptr, file_size) };
+
+    // `read` may return fewer bytes than requested, so we read in chunks until the buffer
+    // is full or the end of the file is reached
+    let mut offset = 0;
+    while offset < file_size {
+        let end = usize::min(offset + FILE_READ_CHUNK_SIZE, file_size);
+        let read = file
+            .read(&mut file_slice[offset..end])
+            .unwrap_or_else(|err| panic!("failed to read {name}: {err:?}"));
+        if read == 0 {
+            log::warn!("{name} ended after {offset:#x} of {file_size:#x} bytes");
+            break;
+        }
+        offset += read;
+        log::trace!("read {offset:#x} of {file_size:#x} bytes of {name}");
+    }
+
+    Some(&mut file_slice[..offset])
+}
+
+/// Returns the size of the given file.
+///
+/// The [`FileInfo`] contains the file name, so it is first queried into a small buffer on the
+/// stack. If the name is too long for it, the info is queried again into a pool allocation
+/// of the size that the firmware reported.
+fn file_size(file: &mut RegularFile, st: &SystemTable<Boot>) -> u64 {
+    #[repr(C, align(8))]
+    struct InfoBuffer([u8; 500]);
+
+    let mut buf = InfoBuffer([0; 500]);
+    let required_len = match file.get_info::<FileInfo>(&mut buf.0) {
+        Ok(info) => return info.file_size(),
+        Err(err) => match err.data() {
+            Some(len) => *len,
+            None => panic!("failed to query file info: {:?}", err.status()),
+        },
+    };
+
+    let boot_services = st.boot_services();
+    let buffer = boot_services
+        .allocate_pool(MemoryType::LOADER_DATA, required_len)
+        .expect("failed to allocate memory for the file info");
+    let size = {
+        let buffer = unsafe { slice::from_raw_parts_mut(buffer, required_len) };
+        file.get_info::<FileInfo>(buffer)
+            .unwrap_or_else(|err| panic!("failed to query file info: {:?}", err.status()))
+            .file_size()
+    };
+    let _ = boot_services.free_pool(buffer);
+    size
+}
+
+/// Loads the kernel from the raw GPT partition with the given partition type GUID.
+///
+/// The partition contains the kernel file directly, without a file system. For ELF files,
+/// only the part of the partition that is covered by the file is read. Other kernels, e.g.
+/// flat binaries, are read together with the rest of the partition.
+fn load_kernel_from_partition(
+    image: Handle,
+    st: &SystemTable<Boot>,
+    partition_type: Guid,
+) -> Option<&'static mut [u8]> {
+    let this = st.boot_services();
+    let open = |handle| OpenProtocolParams {
+        handle,
+        agent: image,
+        controller: None,
+    };
+
+    let handles = this
+        .locate_handle_buffer(SearchType::from_proto::<PartitionInfo>())
+        .ok()?;
+    let handle = handles.iter().copied().find(|&handle| {
+        let info = unsafe {
+            this.open_protocol::<PartitionInfo>(open(handle), OpenProtocolAttributes::GetProtocol)
+        };
+        info.ok()
+            .and_then(|info| info.gpt_partition_entry().copied())
+            .is_some_and(|entry| {
+                entry.partition_type_guid.0.to_bytes() == partition_type.to_bytes()
+            })
+    });
+    let Some(handle) = handle else {
+        log::error!("No GPT partition with type {partition_type} found");
+        return None;
+    };
+
+    let (media_id, partition_size) = {
+        let block_io = unsafe {
+            this.open_protocol::<BlockIO>(open(handle), OpenProtocolAttributes::GetProtocol)
+        }
+        .ok()?;
+        let media = block_io.media();
+        let size = (media.last_block() + 1) * u64::from(media.block_size());
+        (media.media_id(), size)
+    };
+    let disk_io =
+        unsafe { this.open_protocol::<DiskIo>(open(handle), OpenProtocolAttributes::GetProtocol) }
+            .ok()?;
+
+    let kernel_size = elf_file_size(|offset, buf| {
+        disk_io.read_disk(media_id, offset, buf).ok()?;
+        Some(())
+    })
+    .unwrap_or_else(|| {
+        log::info!("Kernel partition contains no valid ELF file, reading the whole partition");
+        partition_size
+    });
+    if kernel_size > partition_size {
+        log::error!("Kernel size {kernel_size:#x} exceeds the partition size {partition_size:#x}");
+        return None;
+    }
+    let kernel_size = usize::try_from(kernel_size).ok()?;
+
+    let kernel_ptr = this
         .allocate_pages(
             AllocateType::AnyPages,
             MemoryType::LOADER_DATA,
-            ((file_size - 1) / 4096) + 1,
+            ((kernel_size - 1) / 4096) + 1,
         )
-        .unwrap() as *mut u8;
-    unsafe { ptr::write_bytes(file_ptr, 0, file_size) };
-    let file_slice = unsafe { slice::from_raw_parts_mut(file_ptr, file_size) };
-    file.read(file_slice).unwrap();
+        .expect("Failed to allocate memory for the kernel") as *mut u8;
+    let kernel_slice = unsafe { slice::from_raw_parts_mut(kernel_ptr, kernel_size) };
+    disk_io.read_disk(media_id, 0, kernel_slice).ok()?;
 
-    Some(file_slice)
+    Some(kernel_slice)
+}
+
+/// Determines the size of the ELF file that is read through `read`.
+///
+/// The size is the end of the last section or segment, or of the header tables, whichever
+/// is largest. Returns `None` if the data is not a 64-bit ELF file or if an offset in it
+/// overflows.
+fn elf_file_size(read: impl Fn(u64, &mut [u8]) -> Option<()>) -> Option<u64> {
+    let u16_at = |buf: &[u8], offset: usize| u16::from_le_bytes([buf[offset], buf[offset + 1]]);
+    let u64_at =
+        |buf: &[u8], offset: usize| u64::from_le_bytes(buf[offset..][..8].try_into().unwrap());
+
+    let mut header = [0; 64];
+    read(0, &mut header)?;
+    // 64-bit little-endian ELF file
+    if header[..6] != *b"\x7fELF\x02\x01" {
+        return None;
+    }
+    let ph_offset = u64_at(&header, 0x20);
+    let sh_offset = u64_at(&header, 0x28);
+    let ph_entry_size = u16_at(&header, 0x36);
+    let ph_count = u16_at(&header, 0x38);
+    let sh_entry_size = u16_at(&header, 0x3a);
+    let sh_count = u16_at(&header, 0x3c);
+
+    let table_end = |offset: u64, entry_size: u16, count: u16| {
+        offset.checked_add(u64::from(entry_size).checked_mul(u64::from(count))?)
+    };
+
+    let mut size = (header.len() as u64)
+        .max(table_end(ph_offset, ph_entry_size, ph_count)?)
+        .max(table_end(sh_offset, sh_entry_size, sh_count)?);
+    let mut program_header = [0; 56];
+    for i in 0..u64::from(ph_count) {
+        read(
+            ph_offset + i * u64::from(ph_entry_size),
+            &mut program_header,
+        )?;
+        let file_offset = u64_at(&program_header, 0x08);
+        let file_size = u64_at(&program_header, 0x20);
+        size = size.max(file_offset.checked_add(file_size)?);
+    }
+    Some(size)
 }
 
 /// Try to load a kernel from a TFTP boot server.
@@ -347,6 +829,7 @@ fn load_file_from_tftp_boot_server(
     name: &str,
     image: Handle,
     st: &SystemTable<Boot>,
+    above_4gib: bool,
 ) -> Option<&'static mut [u8]> {
     let mut base_code_raw = locate_and_open_protocol::<BaseCode>(image, st)?;
     let base_code = base_code_raw.deref_mut();
@@ -356,7 +839,7 @@ fn load_file_from_tftp_boot_server(
     assert!(mode.dhcp_ack_received);
     let dhcpv4: &DhcpV4Packet = mode.dhcp_ack.as_ref();
     let server_ip = IpAddress::new_v4(dhcpv4.bootp_si_addr);
-    assert!(name.len() < 256);
+    assert!(name.len() <= MAX_FILE_NAME_LEN);
 
     let filename = CStr8::from_bytes_with_nul(name.as_bytes()).unwrap();
 
@@ -365,14 +848,7 @@ fn load_file_from_tftp_boot_server(
     let kernel_size = usize::try_from(file_size).expect("The file size should fit into usize");
 
     // Allocate some memory for the kernel file.
-    let ptr = st
-        .boot_services()
-        .allocate_pages(
-            AllocateType::AnyPages,
-            MemoryType::LOADER_DATA,
-            ((kernel_size - 1) / 4096) + 1,
-        )
-        .expect("Failed to allocate memory for the file") as *mut u8;
+    let ptr = allocate_file_pages(st, kernel_size, above_4gib);
     let slice = unsafe { slice::from_raw_parts_mut(ptr, kernel_size) };
 
     // Load the kernel file.
@@ -485,27 +961,43 @@ fn init_logger(
             .ok()?
     };
 
-    let mode = {
-        let modes = gop.modes();
-        match (
-            config
-                .frame_buffer
-                .minimum_framebuffer_height
-                .map(|v| usize::try_from(v).unwrap()),
-            config
-                .frame_buffer
-                .minimum_framebuffer_width
-                .map(|v| usize::try_from(v).unwrap()),
-        ) {
-            (Some(height), Some(width)) => modes
+    let frame_buffer = &config.frame_buffer;
+    let mut invalid_gop_mode = None;
+    let requested_mode = frame_buffer
+        .gop_mode
+        .filter(|_| !frame_buffer.keep_current_mode)
+        .and_then(|index| {
+            gop.query_mode(index)
+                .map_err(|_| invalid_gop_mode = Some(index))
+                .ok()
+        });
+    let mode = if frame_buffer.keep_current_mode {
+        None
+    } else if requested_mode.is_some() {
+        requested_mode
+    } else {
+        let bound = |v: Option<u64>| v.map(|v| usize::try_from(v).unwrap());
+        let min_height = bound(frame_buffer.minimum_framebuffer_height);
+        let min_width = bound(frame_buffer.minimum_framebuffer_width);
+        let max_height = bound(frame_buffer.maximum_framebuffer_height);
+        let max_width = bound(frame_buffer.maximum_framebuffer_width);
+        if [min_height, min_width, max_height, max_width]
+            .iter()
+            .all(Option::is_none)
+        {
+            // keep the current mode
+            None
+        } else {
+            // pick the largest mode within the bounds
+            gop.modes()
                 .filter(|m| {
-                    let res = m.info().resolution();
-                    res.1 >= height && res.0 >= width
+                    let (width, height) = m.info().resolution();
+                    min_width.is_none_or(|min| width >= min)
+                        && min_height.is_none_or(|min| height >= min)
+                        && max_width.is_none_or(|max| width <= max)
+                        && max_height.is_none_or(|max| height <= max)
                 })
-                .last(),
-            (Some(height), None) => modes.filter(|m| m.info().resolution().1 >= height).last(),
-            (None, Some(width)) => modes.filter(|m| m.info().resolution().0 >= width).last(),
-            _ => None,
+                .max_by_key(|m| m.info().resolution())
         }
     };
     if let Some(mode) = mode {
@@ -531,17 +1023,27 @@ fn init_logger(
         stride: mode_info.stride(),
     };
 
+    if config.frame_buffer.self_test {
+        bootloader_x86_64_common::framebuffer::self_test(slice, info);
+    }
+
     bootloader_x86_64_common::init_logger(
         slice,
         info,
         config.log_level,
         config.frame_buffer_logging,
         config.serial_logging,
+        config.boot_log,
     );
 
+    if let Some(index) = invalid_gop_mode {
+        log::warn!("GOP mode {index} is not available, selecting a mode by resolution instead");
+    }
+
     Some(RawFrameBufferInfo {
         addr: PhysAddr::new(framebuffer.as_mut_ptr() as u64),
         info,
+        refresh_rate: None,
     })
 }
 
@@ -20,6 +20,7 @@ impl LegacyMemoryRegion for UefiMemoryDescriptor {
     fn kind(&self) -> MemoryRegionKind {
         match self.0.ty {
             MemoryType::CONVENTIONAL => MemoryRegionKind::Usable,
+            MemoryType::UNUSABLE => MemoryRegionKind::BadMemory,
             other => MemoryRegionKind::UnknownUefi(other.0),
         }
     }